@@ -0,0 +1,16 @@
+//! Keys of the auxiliary vector a program finds on its initial stack,
+//! above the `envp` pointers, as `(key, value)` pairs of words ended by
+//! [`AT_NULL`]. The numbers are the System V ones.
+
+/// Ends the vector
+pub const AT_NULL: u64 = 0;
+/// Address of the program headers in memory
+pub const AT_PHDR: u64 = 3;
+/// Size of a program header
+pub const AT_PHENT: u64 = 4;
+/// Number of program headers
+pub const AT_PHNUM: u64 = 5;
+/// Size of a page
+pub const AT_PAGESZ: u64 = 6;
+/// The program's entry point
+pub const AT_ENTRY: u64 = 9;
@@ -0,0 +1,122 @@
+use core::fmt::{self, Display};
+
+/// Why a system call failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u16)]
+pub enum Errno {
+    /// No such system call
+    NoSys = 1,
+    /// An argument is out of range
+    Inval = 2,
+    /// A pointer argument is not mapped for the access the call makes
+    Fault = 3,
+    /// The file descriptor is not open
+    BadFd = 4,
+    /// The kernel ran out of memory or table slots
+    NoMem = 5,
+    /// The operation would block, and the caller asked not to
+    Again = 6,
+    /// No file at the path
+    NoEnt = 7,
+    /// The file is not an executable the kernel can run
+    NoExec = 8,
+    /// The process is not a child of the caller
+    Child = 9,
+    /// The call needs a file, and the path is a directory
+    IsDir = 10,
+    /// The call needs a directory, and the path is a file
+    NotDir = 11,
+}
+
+impl Errno {
+    /// Largest error number; results from `-MAX` to `-1` are errors.
+    pub const MAX: usize = 4095;
+
+    pub const fn from_raw(raw: usize) -> Option<Self> {
+        use Errno::*;
+        const ERRORS: [Errno; 11] = [
+            NoSys, Inval, Fault, BadFd, NoMem, Again, NoEnt, NoExec, Child, IsDir, NotDir,
+        ];
+
+        match raw {
+            0 => None,
+            raw => {
+                if raw <= ERRORS.len() {
+                    Some(ERRORS[raw - 1])
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Errno::NoSys => "ENOSYS",
+            Errno::Inval => "EINVAL",
+            Errno::Fault => "EFAULT",
+            Errno::BadFd => "EBADF",
+            Errno::NoMem => "ENOMEM",
+            Errno::Again => "EAGAIN",
+            Errno::NoEnt => "ENOENT",
+            Errno::NoExec => "ENOEXEC",
+            Errno::Child => "ECHILD",
+            Errno::IsDir => "EISDIR",
+            Errno::NotDir => "ENOTDIR",
+        }
+    }
+}
+
+impl Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What a system call returns.
+pub type SyscallResult = Result<usize, Errno>;
+
+/// The value of `x0` for `result`: the value itself, or the negated error
+/// number. Successful results must be below `usize::MAX - Errno::MAX`.
+pub const fn encode_result(result: SyscallResult) -> usize {
+    match result {
+        Ok(value) => value,
+        Err(errno) => (errno as usize).wrapping_neg(),
+    }
+}
+
+/// The result a system call returned in `x0`. Error numbers this version
+/// of the interface does not know decode as [`Errno::NoSys`].
+pub const fn decode_result(raw: usize) -> SyscallResult {
+    let errno = raw.wrapping_neg();
+    if errno == 0 || errno > Errno::MAX {
+        return Ok(raw);
+    }
+
+    match Errno::from_raw(errno) {
+        Some(errno) => Err(errno),
+        None => Err(Errno::NoSys),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn results_round_trip() {
+        for result in [
+            Ok(0),
+            Ok(42),
+            Ok(usize::MAX - Errno::MAX),
+            Err(Errno::NoSys),
+            Err(Errno::Again),
+            Err(Errno::NotDir),
+        ] {
+            assert_eq!(decode_result(encode_result(result)), result);
+        }
+
+        assert_eq!(encode_result(Err(Errno::Inval)), -2isize as usize);
+        assert_eq!(decode_result(-4000isize as usize), Err(Errno::NoSys));
+    }
+}
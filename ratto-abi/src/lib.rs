@@ -0,0 +1,26 @@
+//! The interface between the kernel and user programs, shared by both
+//! sides so they agree on it by construction.
+//!
+//! A program makes a system call with `svc #0`, the [`Syscall`] number in
+//! `x8` and up to six arguments in `x0` to `x5`. The result comes back in
+//! `x0`, [encoded](encode_result) so that errors are the negated
+//! [`Errno`]. Structures passed by pointer are `#[repr(C)]`.
+//!
+//! Programs start with the stack pointer on `argc`, followed by the
+//! `argv` and `envp` pointer lists and the [auxiliary vector](auxv).
+//!
+//! Any change that breaks existing programs bumps [`ABI_VERSION`], which
+//! programs can check with [`Syscall::AbiVersion`].
+#![cfg_attr(not(test), no_std)]
+
+pub mod auxv;
+pub mod errno;
+pub mod syscall;
+pub mod types;
+
+pub use errno::*;
+pub use syscall::*;
+pub use types::*;
+
+/// Version of the interface described by this crate.
+pub const ABI_VERSION: u32 = 1;
@@ -0,0 +1,154 @@
+use core::fmt::{self, Display};
+
+/// System call numbers, passed in `x8`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(usize)]
+pub enum Syscall {
+    /// `exit(code: i32) -> !`
+    Exit = 0,
+    /// `write(fd, buf: *const u8, len) -> written`
+    Write = 1,
+    /// `read(fd, buf: *mut u8, len) -> read`, waiting for at least one byte
+    Read = 2,
+    /// `getpid() -> pid`
+    GetPid = 3,
+    /// `yield() -> 0`
+    Yield = 4,
+    /// `sleep(duration: *const Timespec) -> 0`
+    Sleep = 5,
+    /// `clock_gettime(now: *mut Timespec) -> 0`, time since boot
+    ClockGetTime = 6,
+    /// `abi_version() -> ABI_VERSION`
+    AbiVersion = 7,
+    /// `mmap(len, prot) -> addr`, mapping zeroed pages anywhere; see
+    /// [`prot`](crate::prot)
+    Mmap = 8,
+    /// `munmap(addr, len) -> 0`, for whole pages
+    Munmap = 9,
+    /// `spawn(path: *const u8, path_len, args: *const u8, args_len) -> pid`,
+    /// running the executable at `path` with the arguments in `args`, each
+    /// ended by a NUL
+    Spawn = 10,
+    /// `wait(pid) -> code`, waiting for the child `pid` to exit
+    Wait = 11,
+    /// `open(path: *const u8, path_len) -> fd`, for reading a file or
+    /// listing a directory
+    Open = 12,
+    /// `close(fd) -> 0`
+    Close = 13,
+    /// `readdir(fd, entry: *mut DirEntry) -> 1`, or 0 after the last entry
+    ReadDir = 14,
+    /// `list_processes(infos: *mut ProcessInfo, count) -> total`, filling
+    /// up to `count` entries
+    ListProcesses = 15,
+    /// `meminfo(info: *mut MemInfo) -> 0`
+    MemInfo = 16,
+    /// `reboot() -> !`
+    Reboot = 17,
+}
+
+impl Syscall {
+    /// Number of system calls; numbers run from 0 to `COUNT - 1`.
+    pub const COUNT: usize = 18;
+
+    pub const ALL: [Syscall; Self::COUNT] = {
+        use Syscall::*;
+        [
+            Exit,
+            Write,
+            Read,
+            GetPid,
+            Yield,
+            Sleep,
+            ClockGetTime,
+            AbiVersion,
+            Mmap,
+            Munmap,
+            Spawn,
+            Wait,
+            Open,
+            Close,
+            ReadDir,
+            ListProcesses,
+            MemInfo,
+            Reboot,
+        ]
+    };
+
+    pub const fn from_number(number: usize) -> Option<Self> {
+        if number < Self::COUNT {
+            Some(Self::ALL[number])
+        } else {
+            None
+        }
+    }
+
+    pub const fn number(self) -> usize {
+        self as usize
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Syscall::Exit => "exit",
+            Syscall::Write => "write",
+            Syscall::Read => "read",
+            Syscall::GetPid => "getpid",
+            Syscall::Yield => "yield",
+            Syscall::Sleep => "sleep",
+            Syscall::ClockGetTime => "clock_gettime",
+            Syscall::AbiVersion => "abi_version",
+            Syscall::Mmap => "mmap",
+            Syscall::Munmap => "munmap",
+            Syscall::Spawn => "spawn",
+            Syscall::Wait => "wait",
+            Syscall::Open => "open",
+            Syscall::Close => "close",
+            Syscall::ReadDir => "readdir",
+            Syscall::ListProcesses => "list_processes",
+            Syscall::MemInfo => "meminfo",
+            Syscall::Reboot => "reboot",
+        }
+    }
+
+    /// How many of `x0` to `x5` the call reads.
+    pub const fn arg_count(self) -> usize {
+        match self {
+            Syscall::Spawn => 4,
+            Syscall::Write | Syscall::Read => 3,
+            Syscall::Mmap
+            | Syscall::Munmap
+            | Syscall::Open
+            | Syscall::ReadDir
+            | Syscall::ListProcesses => 2,
+            Syscall::Exit
+            | Syscall::Sleep
+            | Syscall::ClockGetTime
+            | Syscall::Wait
+            | Syscall::Close
+            | Syscall::MemInfo => 1,
+            Syscall::GetPid | Syscall::Yield | Syscall::AbiVersion | Syscall::Reboot => 0,
+        }
+    }
+}
+
+impl Display for Syscall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbers_round_trip() {
+        for (number, syscall) in Syscall::ALL.into_iter().enumerate() {
+            assert_eq!(syscall.number(), number);
+            assert_eq!(Syscall::from_number(number), Some(syscall));
+        }
+
+        assert_eq!(Syscall::from_number(Syscall::COUNT), None);
+        assert!(Syscall::ALL.iter().all(|syscall| syscall.arg_count() <= 6));
+    }
+}
@@ -0,0 +1,184 @@
+use core::time::Duration;
+
+/// A point in time, or a length of time, as passed to
+/// [`Syscall::Sleep`](crate::Syscall::Sleep) and
+/// [`Syscall::ClockGetTime`](crate::Syscall::ClockGetTime).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
+pub struct Timespec {
+    pub secs: u64,
+    /// Always below 1 000 000 000. As wide as `secs`, so the structure has
+    /// no padding.
+    pub nanos: u64,
+}
+
+impl Timespec {
+    pub const fn from_duration(duration: Duration) -> Self {
+        Timespec {
+            secs: duration.as_secs(),
+            nanos: duration.subsec_nanos() as u64,
+        }
+    }
+
+    /// The duration, or `None` if `nanos` is out of range.
+    pub const fn to_duration(self) -> Option<Duration> {
+        if self.nanos >= 1_000_000_000 {
+            return None;
+        }
+
+        Some(Duration::new(self.secs, self.nanos as u32))
+    }
+}
+
+/// File descriptors every process starts with, all on the serial console.
+/// Files opened with [`Syscall::Open`](crate::Syscall::Open) get the
+/// numbers after them.
+pub mod fd {
+    pub const STDIN: usize = 0;
+    pub const STDOUT: usize = 1;
+    pub const STDERR: usize = 2;
+}
+
+/// Access to pages mapped by [`Syscall::Mmap`](crate::Syscall::Mmap).
+pub mod prot {
+    pub const READ: usize = 1 << 0;
+    pub const WRITE: usize = 1 << 1;
+    pub const EXEC: usize = 1 << 2;
+}
+
+/// Size of a page, the unit [`Syscall::Mmap`](crate::Syscall::Mmap) maps.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Longest path [`Syscall::Spawn`](crate::Syscall::Spawn) and
+/// [`Syscall::Open`](crate::Syscall::Open) take, in bytes.
+pub const PATH_MAX: usize = 256;
+
+/// Most bytes of arguments [`Syscall::Spawn`](crate::Syscall::Spawn) takes,
+/// the NULs ending them included.
+pub const ARG_MAX: usize = 1024;
+
+/// One entry of a directory, from [`Syscall::ReadDir`](crate::Syscall::ReadDir).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct DirEntry {
+    /// Size of a file's contents, 0 for a directory
+    pub size: u64,
+    /// See [`file_kind`]
+    pub kind: u8,
+    pub name_len: u8,
+    pub name: [u8; DirEntry::NAME_MAX],
+}
+
+impl DirEntry {
+    /// Longest name an entry holds; longer names are cut short.
+    pub const NAME_MAX: usize = 54;
+
+    pub const fn new() -> Self {
+        DirEntry {
+            size: 0,
+            kind: 0,
+            name_len: 0,
+            name: [0; Self::NAME_MAX],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        text(&self.name, self.name_len)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == file_kind::DIR
+    }
+}
+
+impl Default for DirEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Kinds of [`DirEntry`].
+pub mod file_kind {
+    pub const FILE: u8 = 1;
+    pub const DIR: u8 = 2;
+}
+
+/// One process, from [`Syscall::ListProcesses`](crate::Syscall::ListProcesses).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct ProcessInfo {
+    pub pid: u64,
+    /// 0 for processes the kernel started, or whose parent has exited
+    pub parent: u64,
+    /// See [`process_state`]
+    pub state: u8,
+    pub name_len: u8,
+    pub name: [u8; ProcessInfo::NAME_MAX],
+}
+
+impl ProcessInfo {
+    /// Longest name an entry holds; longer names are cut short.
+    pub const NAME_MAX: usize = 46;
+
+    pub const fn new() -> Self {
+        ProcessInfo {
+            pid: 0,
+            parent: 0,
+            state: 0,
+            name_len: 0,
+            name: [0; Self::NAME_MAX],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        text(&self.name, self.name_len)
+    }
+}
+
+impl Default for ProcessInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// States of a [`ProcessInfo`].
+pub mod process_state {
+    /// Being loaded
+    pub const STARTING: u8 = 1;
+    pub const RUNNING: u8 = 2;
+    /// Exited, and not waited for yet
+    pub const EXITED: u8 = 3;
+
+    pub const fn name(state: u8) -> &'static str {
+        match state {
+            STARTING => "starting",
+            RUNNING => "running",
+            EXITED => "exited",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Memory use, from [`Syscall::MemInfo`](crate::Syscall::MemInfo).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[repr(C)]
+pub struct MemInfo {
+    pub page_size: u64,
+    /// Pages of memory the kernel hands out
+    pub total_pages: u64,
+    /// Those of them not in use
+    pub free_pages: u64,
+}
+
+// The structures have no padding, so every byte the kernel copies is set
+const _: () = {
+    assert!(size_of::<DirEntry>() == 64);
+    assert!(size_of::<ProcessInfo>() == 64);
+    assert!(size_of::<MemInfo>() == 24);
+};
+
+/// The first `len` bytes of `bytes`, or nothing if they are not UTF-8.
+fn text(bytes: &[u8], len: u8) -> &str {
+    let bytes = &bytes[..(len as usize).min(bytes.len())];
+    core::str::from_utf8(bytes).unwrap_or_default()
+}
@@ -0,0 +1,25 @@
+//! Feed arbitrary images to the ELF reader, which must reject them or hand
+//! out segments that lie within them. Run with `cargo fuzz run elf` from
+//! `ratto-core`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ratto_core::elf::{Elf, initial_stack};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(elf) = Elf::new(data) else {
+        return;
+    };
+
+    let image = data.as_ptr_range();
+    for segment in elf.segments() {
+        assert!(segment.data.len() as u64 <= segment.mem_size);
+        assert!(segment.vaddr.checked_add(segment.mem_size).is_some());
+        assert!(segment.data.is_empty() || image.contains(&segment.data.as_ptr()));
+    }
+
+    let mut stack = [0; 512];
+    let auxv = [(5, elf.program_header_count() as u64), (9, elf.entry())];
+    let _ = initial_stack(&mut stack, 0x1000, &["fuzz"], &[], &auxv);
+    let _ = elf.program_headers_vaddr();
+});
@@ -1,3 +1,8 @@
 use core::fmt::Debug;
+use core::ops::Range;
 
-pub trait BootInfo: Debug + Clone {}
+pub trait BootInfo: Debug + Clone {
+    /// Physical memory holding the initial ramdisk the bootloader loaded,
+    /// if any.
+    fn initrd(&self) -> Option<Range<u64>>;
+}
@@ -0,0 +1,101 @@
+//! Allocation-free containers for kernel objects.
+//!
+//! [`List`] and [`AvlTree`] are intrusive: items carry their own [`Link`] or
+//! [`TreeLink`] and are borrowed, not owned, by the container, so putting a
+//! task on a run queue or a timer in a timer tree never allocates. An
+//! [`Adapter`], usually written with [`intrusive_adapter!`], tells a
+//! container which field of the item is its link, so one item can sit on
+//! several containers through several links.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+mod array_vec;
+mod avl_tree;
+mod list;
+
+pub use array_vec::*;
+pub use avl_tree::*;
+pub use list::*;
+
+/// Ties an item type to one of its embedded links.
+///
+/// # Safety
+/// `OFFSET` must be the offset in bytes of a field of type `Link` inside
+/// `Item`. [`intrusive_adapter!`] guarantees this.
+pub unsafe trait Adapter {
+    type Item;
+    type Link;
+
+    const OFFSET: usize;
+}
+
+/// Define an [`Adapter`] for the link field `field` of `item`:
+///
+/// ```ignore
+/// intrusive_adapter!(pub RunQueueAdapter = Task { run_link: Link });
+/// ```
+#[macro_export]
+macro_rules! intrusive_adapter {
+    ($vis:vis $name:ident = $item:ty { $field:ident: $link:ty }) => {
+        $vis struct $name;
+
+        unsafe impl $crate::collections::Adapter for $name {
+            type Item = $item;
+            type Link = $link;
+
+            const OFFSET: usize = {
+                // Rejects a field whose type is not `$link`
+                let _: fn(&$item) -> &$link = |item| &item.$field;
+                core::mem::offset_of!($item, $field)
+            };
+        }
+    };
+}
+
+fn link_of<A: Adapter>(item: &A::Item) -> &A::Link {
+    unsafe {
+        &*(item as *const A::Item)
+            .byte_add(A::OFFSET)
+            .cast::<A::Link>()
+    }
+}
+
+/// # Safety
+/// `link` must be the `A` link of a live `A::Item` borrowed for `'a`.
+unsafe fn item_of<'a, A: Adapter>(link: *const A::Link) -> &'a A::Item {
+    unsafe { &*link.byte_sub(A::OFFSET).cast::<A::Item>() }
+}
+
+/// Identifies a container, so that a link records which one it is on.
+/// Assigned on first use, since containers are built in const contexts.
+#[derive(Debug, Default)]
+struct OwnerId(usize);
+
+impl OwnerId {
+    const NONE: usize = 0;
+
+    const fn new() -> Self {
+        OwnerId(Self::NONE)
+    }
+
+    fn get(&mut self) -> usize {
+        static NEXT: AtomicUsize = AtomicUsize::new(1);
+
+        if self.0 == Self::NONE {
+            self.0 = NEXT.fetch_add(1, Ordering::Relaxed);
+        }
+        self.0
+    }
+}
+
+/// Claim `owner` for a container, panicking if the link is already on one.
+fn claim(owner: &AtomicUsize, id: usize) {
+    let claimed = owner
+        .compare_exchange(OwnerId::NONE, id, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok();
+    assert!(claimed, "Item is already linked into a container");
+}
+
+fn release(owner: &AtomicUsize) {
+    owner.store(OwnerId::NONE, Ordering::Release);
+}
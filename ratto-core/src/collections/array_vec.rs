@@ -0,0 +1,281 @@
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+/// A vector with inline storage for up to `N` items.
+///
+/// Running out of room is an error rather than a reallocation: [`push`]
+/// and [`insert`] hand the rejected item back in a [`CapacityError`].
+///
+/// [`push`]: ArrayVec::push
+/// [`insert`]: ArrayVec::insert
+pub struct ArrayVec<T, const N: usize> {
+    items: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+/// An item that did not fit into an [`ArrayVec`].
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct CapacityError<T>(pub T);
+
+impl<T> fmt::Debug for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CapacityError(..)")
+    }
+}
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ArrayVec is full")
+    }
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    pub const fn new() -> Self {
+        ArrayVec {
+            items: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn push(&mut self, item: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError(item));
+        }
+
+        self.items[self.len].write(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Insert `item` at `index`, shifting later items up.
+    ///
+    /// # Panics
+    /// If `index > len`.
+    pub fn insert(&mut self, index: usize, item: T) -> Result<(), CapacityError<T>> {
+        assert!(index <= self.len, "Insertion index out of bounds");
+        if self.is_full() {
+            return Err(CapacityError(item));
+        }
+
+        unsafe {
+            let at = self.as_mut_ptr().add(index);
+            ptr::copy(at, at.add(1), self.len - index);
+            at.write(item);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.len -= 1;
+        Some(unsafe { self.items[self.len].assume_init_read() })
+    }
+
+    /// Remove the item at `index`, shifting later items down.
+    ///
+    /// # Panics
+    /// If `index >= len`.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "Removal index out of bounds");
+
+        self.len -= 1;
+        unsafe {
+            let at = self.as_mut_ptr().add(index);
+            let item = at.read();
+            ptr::copy(at.add(1), at, self.len - index);
+            item
+        }
+    }
+
+    /// Remove the item at `index`, moving the last item into its place.
+    ///
+    /// # Panics
+    /// If `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "Removal index out of bounds");
+
+        let last = self.len - 1;
+        self.swap(index, last);
+        self.pop().unwrap()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            drop(self.pop());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Keep only the items for which `keep` returns true, preserving order.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        let mut index = 0;
+        while index < self.len {
+            if keep(&self[index]) {
+                index += 1;
+            } else {
+                drop(self.remove(index));
+            }
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.items.as_ptr().cast(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.items.as_mut_ptr().cast()
+    }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut clone = Self::new();
+        for item in self {
+            // Cannot overflow: `self` holds at most `N` items
+            let _ = clone.push(item.clone());
+        }
+        clone
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for ArrayVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for ArrayVec<T, N> {}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a ArrayVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut ArrayVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = core::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overflow_hands_the_item_back() {
+        let mut vec = ArrayVec::<u32, 3>::new();
+        for value in 0..3 {
+            vec.push(value).unwrap();
+        }
+
+        assert!(vec.is_full());
+        assert_eq!(vec.push(3), Err(CapacityError(3)));
+        assert_eq!(vec.insert(0, 4), Err(CapacityError(4)));
+        assert_eq!(vec.as_slice(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn insert_and_remove_keep_order() {
+        let mut vec = ArrayVec::<u32, 8>::new();
+        vec.push(1).unwrap();
+        vec.push(3).unwrap();
+        vec.insert(0, 0).unwrap();
+        vec.insert(2, 2).unwrap();
+        vec.insert(4, 4).unwrap();
+        assert_eq!(*vec, [0, 1, 2, 3, 4]);
+
+        assert_eq!(vec.remove(1), 1);
+        assert_eq!(vec.swap_remove(0), 0);
+        assert_eq!(*vec, [4, 2, 3]);
+
+        vec.retain(|&value| value != 2);
+        assert_eq!(*vec, [4, 3]);
+        assert_eq!(vec.pop(), Some(3));
+        assert_eq!(vec.pop(), Some(4));
+        assert_eq!(vec.pop(), None);
+    }
+
+    #[test]
+    fn items_are_dropped() {
+        let marker = std::rc::Rc::new(());
+        let mut vec = ArrayVec::<_, 4>::new();
+        for _ in 0..4 {
+            vec.push(marker.clone()).unwrap();
+        }
+
+        vec.truncate(2);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 3);
+        let clone = vec.clone();
+        drop(vec);
+        drop(clone);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 1);
+    }
+}
@@ -0,0 +1,505 @@
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Adapter, OwnerId, claim, item_of, link_of, release};
+
+/// An [`Adapter`] for an [`AvlTree`], which also knows the item's sort key.
+pub trait KeyAdapter: Adapter<Link = TreeLink> {
+    type Key: Ord;
+
+    fn key(item: &Self::Item) -> Self::Key;
+}
+
+/// The part of an item that puts it in an [`AvlTree`].
+///
+/// A link is in at most one tree at a time; inserting it into a second one
+/// panics.
+#[derive(Debug)]
+pub struct TreeLink {
+    /// The tree the link is in, or `OwnerId::NONE`.
+    owner: AtomicUsize,
+    parent: Cell<*const TreeLink>,
+    left: Cell<*const TreeLink>,
+    right: Cell<*const TreeLink>,
+    height: Cell<u8>,
+}
+
+// As for `Link`: the pointers are only touched through the owning tree
+unsafe impl Send for TreeLink {}
+unsafe impl Sync for TreeLink {}
+
+impl TreeLink {
+    pub const fn new() -> Self {
+        TreeLink {
+            owner: AtomicUsize::new(OwnerId::NONE),
+            parent: Cell::new(ptr::null()),
+            left: Cell::new(ptr::null()),
+            right: Cell::new(ptr::null()),
+            height: Cell::new(0),
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.owner.load(Ordering::Relaxed) != OwnerId::NONE
+    }
+}
+
+impl Default for TreeLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Dereference a node pointer of the tree.
+fn node<'n>(link: *const TreeLink) -> Option<&'n TreeLink> {
+    unsafe { link.as_ref() }
+}
+
+fn height(link: *const TreeLink) -> i32 {
+    node(link).map_or(0, |link| link.height.get().into())
+}
+
+/// Left height minus right height.
+fn balance(link: &TreeLink) -> i32 {
+    height(link.left.get()) - height(link.right.get())
+}
+
+fn update_height(link: &TreeLink) {
+    let height = 1 + height(link.left.get()).max(height(link.right.get()));
+    link.height.set(height as u8);
+}
+
+fn leftmost(mut link: *const TreeLink) -> *const TreeLink {
+    while let Some(left) = node(link)
+        .map(|link| link.left.get())
+        .filter(|l| !l.is_null())
+    {
+        link = left;
+    }
+    link
+}
+
+fn rightmost(mut link: *const TreeLink) -> *const TreeLink {
+    while let Some(right) = node(link)
+        .map(|link| link.right.get())
+        .filter(|r| !r.is_null())
+    {
+        link = right;
+    }
+    link
+}
+
+/// The in-order successor of `link`.
+fn successor(link: &TreeLink) -> *const TreeLink {
+    if !link.right.get().is_null() {
+        return leftmost(link.right.get());
+    }
+
+    let mut child: *const TreeLink = link;
+    let mut parent = link.parent.get();
+    while let Some(up) = node(parent) {
+        if up.left.get() == child {
+            break;
+        }
+        child = parent;
+        parent = up.parent.get();
+    }
+    parent
+}
+
+/// An intrusive AVL tree of items borrowed for `'a`, ordered by
+/// [`KeyAdapter::key`]. Items with equal keys are kept in insertion order,
+/// so the tree also works as a priority queue, e.g. of timer deadlines.
+///
+/// Items are unlinked when the tree is dropped, so they can be reused.
+pub struct AvlTree<'a, A: KeyAdapter> {
+    root: *const TreeLink,
+    len: usize,
+    id: OwnerId,
+    _phantom: PhantomData<&'a A::Item>,
+}
+
+unsafe impl<'a, A: KeyAdapter> Send for AvlTree<'a, A> where A::Item: Sync {}
+unsafe impl<'a, A: KeyAdapter> Sync for AvlTree<'a, A> where A::Item: Sync {}
+
+impl<'a, A: KeyAdapter> AvlTree<'a, A> {
+    pub const fn new() -> Self {
+        AvlTree {
+            root: ptr::null(),
+            len: 0,
+            id: OwnerId::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The item with the smallest key.
+    pub fn first(&self) -> Option<&'a A::Item> {
+        node(leftmost(self.root)).map(|link| unsafe { item_of::<A>(link) })
+    }
+
+    /// The item with the largest key.
+    pub fn last(&self) -> Option<&'a A::Item> {
+        node(rightmost(self.root)).map(|link| unsafe { item_of::<A>(link) })
+    }
+
+    /// The first-inserted item with key `key`.
+    pub fn find(&self, key: &A::Key) -> Option<&'a A::Item> {
+        let mut found = None;
+        let mut link = self.root;
+        while let Some(current) = node(link) {
+            let item = unsafe { item_of::<A>(current) };
+            link = match key.cmp(&A::key(item)) {
+                core::cmp::Ordering::Less => current.left.get(),
+                core::cmp::Ordering::Greater => current.right.get(),
+                core::cmp::Ordering::Equal => {
+                    found = Some(item);
+                    current.left.get()
+                }
+            };
+        }
+        found
+    }
+
+    pub fn insert(&mut self, item: &'a A::Item) {
+        let link = link_of::<A>(item);
+        claim(&link.owner, self.id.get());
+        link.left.set(ptr::null());
+        link.right.set(ptr::null());
+        link.height.set(1);
+
+        let key = A::key(item);
+        let mut parent = ptr::null();
+        let mut current = self.root;
+        let mut left = false;
+        while let Some(up) = node(current) {
+            parent = current;
+            left = key < A::key(unsafe { item_of::<A>(up) });
+            current = if left { up.left.get() } else { up.right.get() };
+        }
+
+        link.parent.set(parent);
+        match node(parent) {
+            Some(parent) if left => parent.left.set(link),
+            Some(parent) => parent.right.set(link),
+            None => self.root = link,
+        }
+        self.len += 1;
+        self.rebalance_from(parent);
+    }
+
+    pub fn pop_first(&mut self) -> Option<&'a A::Item> {
+        let item = self.first()?;
+        self.unlink(link_of::<A>(item));
+        Some(item)
+    }
+
+    pub fn contains(&mut self, item: &A::Item) -> bool {
+        let id = self.id.get();
+        link_of::<A>(item).owner.load(Ordering::Relaxed) == id
+    }
+
+    /// Unlink `item`, returning false if it was not in this tree.
+    pub fn remove(&mut self, item: &A::Item) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+
+        self.unlink(link_of::<A>(item));
+        true
+    }
+
+    pub fn clear(&mut self) {
+        let mut link = leftmost(self.root);
+        while let Some(current) = node(link) {
+            link = successor(current);
+            release(&current.owner);
+        }
+
+        self.root = ptr::null();
+        self.len = 0;
+    }
+
+    /// Iterate in key order.
+    pub fn iter(&self) -> TreeIter<'_, 'a, A> {
+        TreeIter {
+            next: leftmost(self.root),
+            _tree: PhantomData,
+        }
+    }
+
+    /// Unlink `link`, which must be in this tree.
+    fn unlink(&mut self, link: &TreeLink) {
+        let (left, right) = (link.left.get(), link.right.get());
+        let rebalance = if left.is_null() || right.is_null() {
+            let child = if left.is_null() { right } else { left };
+            self.replace_child(link.parent.get(), link, child);
+            link.parent.get()
+        } else {
+            // Splice the successor, which has no left child, into `link`'s place
+            let next = node(leftmost(right)).unwrap();
+            let rebalance = if ptr::eq(next.parent.get(), link) {
+                next as *const _
+            } else {
+                let next_parent = next.parent.get();
+                self.replace_child(next_parent, next, next.right.get());
+                next.right.set(right);
+                node(right).unwrap().parent.set(next);
+                next_parent
+            };
+
+            self.replace_child(link.parent.get(), link, next);
+            next.left.set(left);
+            node(left).unwrap().parent.set(next);
+            next.height.set(link.height.get());
+            rebalance
+        };
+
+        self.len -= 1;
+        release(&link.owner);
+        self.rebalance_from(rebalance);
+    }
+
+    /// Point `parent`'s link to `old`, or the root if `parent` is null, at
+    /// `new` instead.
+    fn replace_child(
+        &mut self,
+        parent: *const TreeLink,
+        old: *const TreeLink,
+        new: *const TreeLink,
+    ) {
+        match node(parent) {
+            Some(parent) if parent.left.get() == old => parent.left.set(new),
+            Some(parent) => parent.right.set(new),
+            None => self.root = new,
+        }
+        if let Some(new) = node(new) {
+            new.parent.set(parent);
+        }
+    }
+
+    /// Make `link`'s right child its parent, returning the new subtree root.
+    fn rotate_left(&mut self, link: &TreeLink) -> *const TreeLink {
+        let pivot = node(link.right.get()).unwrap();
+        link.right.set(pivot.left.get());
+        if let Some(inner) = node(pivot.left.get()) {
+            inner.parent.set(link);
+        }
+
+        self.replace_child(link.parent.get(), link, pivot);
+        pivot.left.set(link);
+        link.parent.set(pivot);
+        update_height(link);
+        update_height(pivot);
+        pivot
+    }
+
+    /// Make `link`'s left child its parent, returning the new subtree root.
+    fn rotate_right(&mut self, link: &TreeLink) -> *const TreeLink {
+        let pivot = node(link.left.get()).unwrap();
+        link.left.set(pivot.right.get());
+        if let Some(inner) = node(pivot.right.get()) {
+            inner.parent.set(link);
+        }
+
+        self.replace_child(link.parent.get(), link, pivot);
+        pivot.right.set(link);
+        link.parent.set(pivot);
+        update_height(link);
+        update_height(pivot);
+        pivot
+    }
+
+    /// Fix heights and balance on the path from `link` up to the root.
+    fn rebalance_from(&mut self, mut link: *const TreeLink) {
+        while let Some(mut current) = node(link) {
+            update_height(current);
+            match balance(current) {
+                2.. => {
+                    let left = node(current.left.get()).unwrap();
+                    if balance(left) < 0 {
+                        self.rotate_left(left);
+                    }
+                    current = node(self.rotate_right(current)).unwrap();
+                }
+                ..=-2 => {
+                    let right = node(current.right.get()).unwrap();
+                    if balance(right) > 0 {
+                        self.rotate_right(right);
+                    }
+                    current = node(self.rotate_left(current)).unwrap();
+                }
+                _ => {}
+            }
+            link = current.parent.get();
+        }
+    }
+}
+
+impl<'a, A: KeyAdapter> Default for AvlTree<'a, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, A: KeyAdapter> Drop for AvlTree<'a, A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<'a, A: KeyAdapter> core::fmt::Debug for AvlTree<'a, A>
+where
+    A::Item: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'t, 'a, A: KeyAdapter> IntoIterator for &'t AvlTree<'a, A> {
+    type Item = &'a A::Item;
+    type IntoIter = TreeIter<'t, 'a, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// In-order iterator over an [`AvlTree`].
+pub struct TreeIter<'t, 'a, A: KeyAdapter> {
+    next: *const TreeLink,
+    _tree: PhantomData<&'t AvlTree<'a, A>>,
+}
+
+impl<'t, 'a, A: KeyAdapter> Iterator for TreeIter<'t, 'a, A> {
+    type Item = &'a A::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let link = node(self.next)?;
+        self.next = successor(link);
+        Some(unsafe { item_of::<A>(link) })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::intrusive_adapter;
+
+    struct Timer {
+        deadline: u32,
+        seq: usize,
+        link: TreeLink,
+    }
+
+    intrusive_adapter!(TimerAdapter = Timer { link: TreeLink });
+
+    impl KeyAdapter for TimerAdapter {
+        type Key = u32;
+
+        fn key(timer: &Timer) -> u32 {
+            timer.deadline
+        }
+    }
+
+    /// Check parent links, heights and balance, returning the height.
+    fn check(link: *const TreeLink, parent: *const TreeLink) -> i32 {
+        let Some(current) = node(link) else {
+            return 0;
+        };
+
+        assert_eq!(current.parent.get(), parent);
+        let left = check(current.left.get(), link);
+        let right = check(current.right.get(), link);
+        assert!((left - right).abs() <= 1, "Tree is unbalanced");
+        assert_eq!(i32::from(current.height.get()), 1 + left.max(right));
+        1 + left.max(right)
+    }
+
+    /// A small LCG, so the test is reproducible without extra dependencies.
+    fn random(state: &mut u64) -> u32 {
+        *state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*state >> 33) as u32
+    }
+
+    #[test]
+    fn matches_a_sorted_vec() {
+        let timers: Vec<_> = (0..300)
+            .scan(1, |state, seq| {
+                Some(Timer {
+                    deadline: random(state) % 50,
+                    seq,
+                    link: TreeLink::new(),
+                })
+            })
+            .collect();
+
+        let mut tree = AvlTree::<TimerAdapter>::new();
+        let mut expected: Vec<(u32, usize)> = Vec::new();
+        let mut state = 7;
+        for _ in 0..3_000 {
+            let timer = &timers[random(&mut state) as usize % timers.len()];
+            if tree.remove(timer) {
+                expected.retain(|&(_, seq)| seq != timer.seq);
+            } else {
+                tree.insert(timer);
+                let at = expected.partition_point(|&(deadline, _)| deadline <= timer.deadline);
+                expected.insert(at, (timer.deadline, timer.seq));
+            }
+
+            check(tree.root, ptr::null());
+            assert_eq!(tree.len(), expected.len());
+        }
+
+        let contents: Vec<_> = tree
+            .iter()
+            .map(|timer| (timer.deadline, timer.seq))
+            .collect();
+        assert_eq!(contents, expected);
+        assert_eq!(
+            tree.last().map(|timer| timer.seq),
+            expected.last().map(|e| e.1)
+        );
+
+        // Popping in order, each item is the first left with its deadline
+        for &(deadline, seq) in &expected {
+            assert_eq!(tree.find(&deadline).unwrap().seq, seq);
+            assert_eq!(tree.pop_first().unwrap().seq, seq);
+        }
+        assert!(tree.is_empty());
+        assert!(tree.find(&0).is_none());
+    }
+
+    #[test]
+    fn dropping_the_tree_unlinks_items() {
+        let timers: Vec<_> = (0..10)
+            .map(|seq| Timer {
+                deadline: 10 - seq as u32,
+                seq,
+                link: TreeLink::new(),
+            })
+            .collect();
+
+        let mut tree = AvlTree::<TimerAdapter>::new();
+        for timer in &timers {
+            tree.insert(timer);
+        }
+        assert_eq!(tree.first().unwrap().seq, 9);
+        check(tree.root, ptr::null());
+
+        drop(tree);
+        assert!(timers.iter().all(|timer| !timer.link.is_linked()));
+    }
+}
@@ -0,0 +1,316 @@
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Adapter, OwnerId, claim, item_of, link_of, release};
+
+/// The part of an item that puts it on a [`List`].
+///
+/// A link is on at most one list at a time; linking it into a second one
+/// panics.
+#[derive(Debug)]
+pub struct Link {
+    /// The list the link is on, or `OwnerId::NONE`.
+    owner: AtomicUsize,
+    prev: Cell<*const Link>,
+    next: Cell<*const Link>,
+}
+
+// `prev` and `next` are only touched through the owning list, which needs
+// `&mut` to change them; ownership itself is claimed atomically
+unsafe impl Send for Link {}
+unsafe impl Sync for Link {}
+
+impl Link {
+    pub const fn new() -> Self {
+        Link {
+            owner: AtomicUsize::new(OwnerId::NONE),
+            prev: Cell::new(ptr::null()),
+            next: Cell::new(ptr::null()),
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.owner.load(Ordering::Relaxed) != OwnerId::NONE
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An intrusive doubly-linked list of items borrowed for `'a`.
+///
+/// Items are unlinked when the list is dropped, so they can be reused.
+pub struct List<'a, A: Adapter<Link = Link>> {
+    head: *const Link,
+    tail: *const Link,
+    len: usize,
+    id: OwnerId,
+    _phantom: PhantomData<&'a A::Item>,
+}
+
+unsafe impl<'a, A: Adapter<Link = Link>> Send for List<'a, A> where A::Item: Sync {}
+unsafe impl<'a, A: Adapter<Link = Link>> Sync for List<'a, A> where A::Item: Sync {}
+
+impl<'a, A: Adapter<Link = Link>> List<'a, A> {
+    pub const fn new() -> Self {
+        List {
+            head: ptr::null(),
+            tail: ptr::null(),
+            len: 0,
+            id: OwnerId::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn front(&self) -> Option<&'a A::Item> {
+        unsafe { self.head.as_ref() }.map(|link| unsafe { item_of::<A>(link) })
+    }
+
+    pub fn back(&self) -> Option<&'a A::Item> {
+        unsafe { self.tail.as_ref() }.map(|link| unsafe { item_of::<A>(link) })
+    }
+
+    pub fn push_front(&mut self, item: &'a A::Item) {
+        let link = self.claim(item);
+        link.next.set(self.head);
+        match unsafe { self.head.as_ref() } {
+            Some(head) => head.prev.set(link),
+            None => self.tail = link,
+        }
+        self.head = link;
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, item: &'a A::Item) {
+        let link = self.claim(item);
+        link.prev.set(self.tail);
+        match unsafe { self.tail.as_ref() } {
+            Some(tail) => tail.next.set(link),
+            None => self.head = link,
+        }
+        self.tail = link;
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<&'a A::Item> {
+        let item = self.front()?;
+        self.unlink(link_of::<A>(item));
+        Some(item)
+    }
+
+    pub fn pop_back(&mut self) -> Option<&'a A::Item> {
+        let item = self.back()?;
+        self.unlink(link_of::<A>(item));
+        Some(item)
+    }
+
+    pub fn contains(&mut self, item: &A::Item) -> bool {
+        let id = self.id.get();
+        link_of::<A>(item).owner.load(Ordering::Relaxed) == id
+    }
+
+    /// Unlink `item`, returning false if it was not on this list.
+    pub fn remove(&mut self, item: &A::Item) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+
+        self.unlink(link_of::<A>(item));
+        true
+    }
+
+    /// Keep only the items for which `keep` returns true.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&'a A::Item) -> bool,
+    {
+        let mut link = self.head;
+        while let Some(current) = unsafe { link.as_ref() } {
+            link = current.next.get();
+            if !keep(unsafe { item_of::<A>(current) }) {
+                self.unlink(current);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn iter(&self) -> ListIter<'_, 'a, A> {
+        ListIter {
+            next: self.head,
+            _list: PhantomData,
+        }
+    }
+
+    fn claim(&mut self, item: &'a A::Item) -> &'a Link {
+        let link = link_of::<A>(item);
+        claim(&link.owner, self.id.get());
+        link.prev.set(ptr::null());
+        link.next.set(ptr::null());
+        link
+    }
+
+    /// Unlink `link`, which must be on this list.
+    fn unlink(&mut self, link: &Link) {
+        let (prev, next) = (link.prev.get(), link.next.get());
+        match unsafe { prev.as_ref() } {
+            Some(prev) => prev.next.set(next),
+            None => self.head = next,
+        }
+        match unsafe { next.as_ref() } {
+            Some(next) => next.prev.set(prev),
+            None => self.tail = prev,
+        }
+
+        self.len -= 1;
+        release(&link.owner);
+    }
+}
+
+impl<'a, A: Adapter<Link = Link>> Default for List<'a, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, A: Adapter<Link = Link>> Drop for List<'a, A> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<'a, A: Adapter<Link = Link>> core::fmt::Debug for List<'a, A>
+where
+    A::Item: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'l, 'a, A: Adapter<Link = Link>> IntoIterator for &'l List<'a, A> {
+    type Item = &'a A::Item;
+    type IntoIter = ListIter<'l, 'a, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Front-to-back iterator over a [`List`].
+pub struct ListIter<'l, 'a, A: Adapter<Link = Link>> {
+    next: *const Link,
+    _list: PhantomData<&'l List<'a, A>>,
+}
+
+impl<'l, 'a, A: Adapter<Link = Link>> Iterator for ListIter<'l, 'a, A> {
+    type Item = &'a A::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let link = unsafe { self.next.as_ref() }?;
+        self.next = link.next.get();
+        Some(unsafe { item_of::<A>(link) })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::intrusive_adapter;
+
+    struct Task {
+        id: u32,
+        run_link: Link,
+        wait_link: Link,
+    }
+
+    impl Task {
+        fn new(id: u32) -> Self {
+            Task {
+                id,
+                run_link: Link::new(),
+                wait_link: Link::new(),
+            }
+        }
+    }
+
+    intrusive_adapter!(RunAdapter = Task { run_link: Link });
+    intrusive_adapter!(WaitAdapter = Task { wait_link: Link });
+
+    fn ids<A: Adapter<Link = Link, Item = Task>>(list: &List<'_, A>) -> Vec<u32> {
+        list.iter().map(|task| task.id).collect()
+    }
+
+    #[test]
+    fn push_pop_and_remove() {
+        let tasks: Vec<_> = (0..4).map(Task::new).collect();
+        let mut list = List::<RunAdapter>::new();
+
+        list.push_back(&tasks[1]);
+        list.push_back(&tasks[2]);
+        list.push_front(&tasks[0]);
+        list.push_back(&tasks[3]);
+        assert_eq!(ids(&list), [0, 1, 2, 3]);
+        assert_eq!(list.len(), 4);
+
+        assert!(list.remove(&tasks[2]));
+        assert!(!list.remove(&tasks[2]));
+        assert!(!tasks[2].run_link.is_linked());
+        assert_eq!(list.pop_back().unwrap().id, 3);
+        assert_eq!(list.pop_front().unwrap().id, 0);
+        assert_eq!(ids(&list), [1]);
+
+        list.retain(|_| false);
+        assert!(list.is_empty());
+        assert!(list.front().is_none());
+    }
+
+    #[test]
+    fn items_can_sit_on_several_lists() {
+        let tasks: Vec<_> = (0..3).map(Task::new).collect();
+        let mut run = List::<RunAdapter>::new();
+        let mut wait = List::<WaitAdapter>::new();
+
+        for task in &tasks {
+            run.push_back(task);
+            wait.push_front(task);
+        }
+        assert_eq!(ids(&run), [0, 1, 2]);
+        assert_eq!(ids(&wait), [2, 1, 0]);
+
+        let mut other = List::<RunAdapter>::new();
+        assert!(!other.remove(&tasks[0]));
+        assert!(run.contains(&tasks[0]));
+
+        drop(run);
+        assert!(tasks.iter().all(|task| !task.run_link.is_linked()));
+        other.push_back(&tasks[0]);
+        assert_eq!(ids(&other), [0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already linked")]
+    fn linking_twice_panics() {
+        let task = Task::new(0);
+        let mut first = List::<RunAdapter>::new();
+        let mut second = List::<RunAdapter>::new();
+
+        first.push_back(&task);
+        second.push_back(&task);
+    }
+}
@@ -0,0 +1,296 @@
+//! A minimal, allocation-free reader of cpio archives in the "newc" format,
+//! as used for initramfs images.
+//!
+//! Each entry is a 110-byte ASCII header, the NUL-terminated name and the
+//! contents, the latter two padded to 4 bytes. The archive ends with an
+//! entry named [`TRAILER`].
+
+pub const MAGIC: &[u8; 6] = b"070701";
+/// Same layout, with a checksum of the contents in the last field
+pub const MAGIC_CRC: &[u8; 6] = b"070702";
+pub const HEADER_SIZE: usize = 110;
+
+/// Name of the entry ending the archive.
+pub const TRAILER: &str = "TRAILER!!!";
+
+/// The file type bits of a mode.
+pub const S_IFMT: u32 = 0o170_000;
+pub const S_IFDIR: u32 = 0o040_000;
+pub const S_IFREG: u32 = 0o100_000;
+pub const S_IFLNK: u32 = 0o120_000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CpioError {
+    BadMagic,
+    /// A header field is not hexadecimal
+    BadHeader,
+    /// A name is empty, unterminated or not UTF-8
+    BadName,
+    /// An entry runs past the end, or the trailer is missing
+    Truncated,
+}
+
+impl CpioError {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            CpioError::BadMagic => "Not a newc cpio archive",
+            CpioError::BadHeader => "Bad cpio header",
+            CpioError::BadName => "Bad cpio entry name",
+            CpioError::Truncated => "cpio archive truncated",
+        }
+    }
+}
+
+/// A validated archive.
+#[derive(Debug, Copy, Clone)]
+pub struct Cpio<'a> {
+    /// The entries, without the trailer
+    data: &'a [u8],
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> Cpio<'a> {
+    /// Check every entry of the archive in `data`, up to the trailer. Any
+    /// padding after it is ignored.
+    pub fn new(data: &'a [u8]) -> Result<Self, CpioError> {
+        let mut offset = 0;
+        loop {
+            let (entry, next) = read_entry(data, offset)?;
+            if entry.name == TRAILER {
+                return Ok(Cpio {
+                    data: &data[..offset],
+                });
+            }
+
+            offset = next;
+        }
+    }
+
+    /// Size of the archive up to its trailer.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The entries in archive order.
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'a>> + 'a {
+        let data = self.data;
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            if offset >= data.len() {
+                return None;
+            }
+
+            // Checked by `new`
+            let (entry, next) = read_entry(data, offset).ok()?;
+            offset = next;
+            Some(entry)
+        })
+    }
+}
+
+impl Entry<'_> {
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+/// The entry at `offset`, and the offset of the next one.
+fn read_entry(data: &[u8], offset: usize) -> Result<(Entry<'_>, usize), CpioError> {
+    let header = offset
+        .checked_add(HEADER_SIZE)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(CpioError::Truncated)?;
+
+    let magic = &header[..6];
+    if magic != MAGIC && magic != MAGIC_CRC {
+        return Err(CpioError::BadMagic);
+    }
+
+    // Thirteen 8-digit fields follow the magic
+    let field = |index: usize| hex_u32(&header[6 + index * 8..][..8]).ok_or(CpioError::BadHeader);
+    let mode = field(1)?;
+    let file_size = field(6)? as usize;
+    let name_size = field(11)? as usize;
+
+    let name_start = offset + HEADER_SIZE;
+    let name = name_start
+        .checked_add(name_size)
+        .and_then(|end| data.get(name_start..end))
+        .ok_or(CpioError::Truncated)?;
+    let name = match name.split_last() {
+        Some((0, name)) if !name.is_empty() => {
+            core::str::from_utf8(name).map_err(|_| CpioError::BadName)?
+        }
+        _ => return Err(CpioError::BadName),
+    };
+
+    let data_start = align4(name_start + name_size);
+    let contents = data_start
+        .checked_add(file_size)
+        .and_then(|end| data.get(data_start..end))
+        .ok_or(CpioError::Truncated)?;
+
+    let entry = Entry {
+        name,
+        mode,
+        data: contents,
+    };
+    Ok((entry, align4(data_start + file_size)))
+}
+
+fn hex_u32(digits: &[u8]) -> Option<u32> {
+    let digits = core::str::from_utf8(digits).ok()?;
+    if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(digits, 16).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+#[cfg(all(test, not(loom)))]
+pub(crate) mod test {
+    use super::*;
+
+    /// Builds an archive of `(name, mode, contents)` entries, with the trailer.
+    pub(crate) fn archive(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let trailer = [(TRAILER, 0, &[][..])];
+        for (index, (name, mode, contents)) in entries.iter().chain(&trailer).enumerate() {
+            let fields = [
+                index as u32,
+                *mode,
+                0,
+                0,
+                1,
+                0,
+                contents.len() as u32,
+                0,
+                0,
+                0,
+                0,
+                name.len() as u32 + 1,
+                0,
+            ];
+
+            archive.extend_from_slice(MAGIC);
+            for field in fields {
+                archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+            }
+            archive.extend_from_slice(name.as_bytes());
+            archive.push(0);
+            archive.resize(align4(archive.len()), 0);
+            archive.extend_from_slice(contents);
+            archive.resize(align4(archive.len()), 0);
+        }
+
+        archive
+    }
+
+    #[test]
+    fn reads_entries() {
+        let data = archive(&[
+            ("bin", S_IFDIR | 0o755, b""),
+            ("bin/hello", S_IFREG | 0o755, b"\x7fELF..."),
+            ("etc/motd", S_IFREG | 0o644, b"Hi\n"),
+        ]);
+
+        let cpio = Cpio::new(&data).unwrap();
+        let entries: Vec<_> = cpio.entries().collect();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].is_dir());
+        assert_eq!(entries[1].name, "bin/hello");
+        assert!(entries[1].is_file());
+        assert_eq!(entries[1].data, b"\x7fELF...");
+        assert_eq!(entries[2].data, b"Hi\n");
+
+        // Padding after the trailer is ignored
+        let mut padded = data.clone();
+        padded.resize(data.len() + 512, 0);
+        assert_eq!(Cpio::new(&padded).unwrap().entries().count(), 3);
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        let data = archive(&[("file", S_IFREG, b"contents")]);
+        let patched = |offset: usize, bytes: &[u8]| {
+            let mut data = data.clone();
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Cpio::new(&data).unwrap_err()
+        };
+
+        assert_eq!(Cpio::new(&[]).unwrap_err(), CpioError::Truncated);
+        assert_eq!(
+            Cpio::new(&data[..HEADER_SIZE + 4]).unwrap_err(),
+            CpioError::Truncated
+        );
+        // No trailer
+        assert_eq!(Cpio::new(&data[..120]).unwrap_err(), CpioError::Truncated);
+        assert_eq!(patched(0, b"070707"), CpioError::BadMagic);
+        assert_eq!(patched(6 + 8, b"0000000G"), CpioError::BadHeader);
+        assert_eq!(patched(6 + 6 * 8, b"+0000008"), CpioError::BadHeader);
+        assert_eq!(patched(6 + 6 * 8, b"FFFFFFFF"), CpioError::Truncated);
+        assert_eq!(patched(6 + 11 * 8, b"00000001"), CpioError::BadName);
+        assert_eq!(patched(6 + 11 * 8, b"00000003"), CpioError::BadName);
+        assert_eq!(patched(HEADER_SIZE, b"\xff"), CpioError::BadName);
+    }
+
+    /// Randomly corrupted and truncated archives are rejected, or read as
+    /// entries within the archive.
+    #[test]
+    fn survives_fuzzed_archives() {
+        let data = archive(&[
+            ("dir", S_IFDIR, b""),
+            ("dir/a", S_IFREG, b"aaaa"),
+            ("b", S_IFREG, b"bbbbbbbbb"),
+        ]);
+        let mut state = 0x853c_49e6_748f_ea9bu64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..20_000 {
+            let mut fuzzed = data.clone();
+            for _ in 0..random() % 4 + 1 {
+                let index = random() as usize % fuzzed.len();
+                fuzzed[index] = match random() % 3 {
+                    0 => b"0123456789abcdefF"[random() as usize % 17],
+                    _ => random() as u8,
+                };
+            }
+            fuzzed.truncate(fuzzed.len() - random() as usize % 8);
+
+            if let Ok(cpio) = Cpio::new(&fuzzed) {
+                let range = fuzzed.as_ptr_range();
+                for entry in cpio.entries() {
+                    assert!(entry.data.is_empty() || range.contains(&entry.data.as_ptr()));
+                    assert!(!entry.name.is_empty());
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,120 @@
+use crate::percpu::PerCpuArea;
+
 pub trait CpuOps {
     type InterruptState: Copy;
 
     fn disable_interrupts() -> Self::InterruptState;
     fn enable_interrupts(state: Self::InterruptState);
 
+    /// Whether interrupts were unmasked in `state`, as returned by
+    /// [`disable_interrupts`](CpuOps::disable_interrupts).
+    fn interrupts_were_enabled(state: Self::InterruptState) -> bool;
+
+    /// Unmask interrupts regardless of the current state, returning the previous state.
+    fn unmask_interrupts() -> Self::InterruptState;
+
+    /// Nanoseconds since an arbitrary point in the past, from a monotonic clock.
+    fn monotonic_ns() -> u64;
+
+    /// Index of the executing core, read from the hardware.
+    fn current_cpu_id() -> usize;
+
+    /// The executing core's per-CPU area, once installed.
+    fn per_cpu_area() -> Option<&'static PerCpuArea>;
+
+    /// Install the executing core's per-CPU area.
+    ///
+    /// # Safety
+    /// The area must belong to the executing core and must not be shared
+    /// with any other core.
+    unsafe fn set_per_cpu_area(area: &'static PerCpuArea);
+
+    /// Sleep until an interrupt is pending, which may be one that is
+    /// masked. Can return early.
+    fn wait_for_interrupt() {
+        core::hint::spin_loop();
+    }
+
     fn wait_forever() -> ! {
         loop {
             core::hint::spin_loop();
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::cell::Cell;
+
+    use super::CpuOps;
+    use crate::percpu::PerCpuArea;
+
+    #[cfg(not(loom))]
+    thread_local! {
+        static AREA: Cell<Option<&'static PerCpuArea>> = const { Cell::new(None) };
+        static INTERRUPTS_ENABLED: Cell<bool> = const { Cell::new(true) };
+    }
+
+    // Model threads share one OS thread, so they need loom's thread locals
+    #[cfg(loom)]
+    loom::thread_local! {
+        static AREA: Cell<Option<&'static PerCpuArea>> = Cell::new(None);
+        static INTERRUPTS_ENABLED: Cell<bool> = Cell::new(true);
+    }
+
+    /// Host stand-in for a core: each thread (or loom model thread) plays one
+    /// CPU, chosen with [`TestCpu::bind`], and tracks its own interrupt mask.
+    pub struct TestCpu;
+
+    impl TestCpu {
+        /// Make the calling thread act as core `cpu`.
+        pub fn bind(cpu: usize) {
+            let area = Box::leak(Box::new(PerCpuArea::new(cpu)));
+            unsafe { Self::set_per_cpu_area(area) };
+        }
+
+        pub fn interrupts_enabled() -> bool {
+            INTERRUPTS_ENABLED.with(Cell::get)
+        }
+    }
+
+    impl CpuOps for TestCpu {
+        type InterruptState = bool;
+
+        fn disable_interrupts() -> Self::InterruptState {
+            INTERRUPTS_ENABLED.with(|enabled| enabled.replace(false))
+        }
+
+        fn enable_interrupts(state: Self::InterruptState) {
+            INTERRUPTS_ENABLED.with(|enabled| enabled.set(state));
+        }
+
+        fn interrupts_were_enabled(state: Self::InterruptState) -> bool {
+            state
+        }
+
+        fn unmask_interrupts() -> Self::InterruptState {
+            INTERRUPTS_ENABLED.with(|enabled| enabled.replace(true))
+        }
+
+        fn monotonic_ns() -> u64 {
+            static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+            START
+                .get_or_init(std::time::Instant::now)
+                .elapsed()
+                .as_nanos() as u64
+        }
+
+        fn current_cpu_id() -> usize {
+            Self::per_cpu_area().map_or(0, |area| area.cpu_id())
+        }
+
+        fn per_cpu_area() -> Option<&'static PerCpuArea> {
+            AREA.with(Cell::get)
+        }
+
+        unsafe fn set_per_cpu_area(area: &'static PerCpuArea) {
+            AREA.with(|current| current.set(Some(area)));
+        }
+    }
+}
@@ -0,0 +1,624 @@
+//! A minimal, allocation-free reader of ELF64 executables, and the initial
+//! stack a program starts with.
+//!
+//! Only what loading a static executable needs is read: the file header and
+//! the program headers. [`Elf::new`] checks every field the loader relies
+//! on, so a malformed image is rejected up front rather than half loaded.
+
+use bitflags::bitflags;
+
+use crate::mem::MapFlags;
+
+mod stack;
+
+pub use stack::*;
+
+/// `e_machine` of AArch64 executables.
+pub const EM_AARCH64: u16 = 183;
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+
+const ET_EXEC: u16 = 2;
+
+const EHDR_SIZE: usize = 64;
+/// Size of a program header, for `AT_PHENT`.
+pub const PHDR_SIZE: usize = 56;
+
+/// Pages segments are loaded in. Each page is mapped with the permissions of
+/// one segment, so no two segments may share one.
+pub const LOAD_PAGE_SIZE: u64 = 4096;
+
+pub const PT_NULL: u32 = 0;
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_INTERP: u32 = 3;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ElfError {
+    BadMagic,
+    /// Not a 64-bit little-endian image of the current ELF version
+    BadClass,
+    /// Not a static executable
+    BadType,
+    Truncated,
+    /// The program header table is malformed
+    BadProgramHeaders,
+    /// A `PT_LOAD` segment lies outside the image, overflows, overlaps
+    /// another or is misaligned
+    BadSegment,
+    /// Two `PT_LOAD` segments share a page, which needs a linker script
+    /// that page-aligns them
+    SharedPage,
+    /// The image needs an interpreter or dynamic linking
+    Dynamic,
+    /// The entry point is not in an executable segment
+    BadEntry,
+}
+
+impl ElfError {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ElfError::BadMagic => "Not an ELF image",
+            ElfError::BadClass => "Not a 64-bit little-endian ELF image",
+            ElfError::BadType => "Not an ELF executable",
+            ElfError::Truncated => "ELF image truncated",
+            ElfError::BadProgramHeaders => "Bad ELF program headers",
+            ElfError::BadSegment => "Bad ELF segment",
+            ElfError::SharedPage => "ELF segments share a page",
+            ElfError::Dynamic => "Dynamically linked ELF image",
+            ElfError::BadEntry => "ELF entry point not executable",
+        }
+    }
+}
+
+/// A validated executable image.
+#[derive(Debug, Copy, Clone)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    machine: u16,
+    entry: u64,
+    phoff: usize,
+    phnum: usize,
+}
+
+bitflags! {
+    /// `p_flags` of a segment.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct SegmentFlags: u32 {
+        const EXEC  = 1 << 0;
+        const WRITE = 1 << 1;
+        const READ  = 1 << 2;
+    }
+}
+
+/// An entry of the program header table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: SegmentFlags,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+    pub align: u64,
+}
+
+/// A `PT_LOAD` segment: `mem_size` bytes at `vaddr`, starting with `data`
+/// and zero after it.
+#[derive(Debug, Copy, Clone)]
+pub struct Segment<'a> {
+    pub vaddr: u64,
+    pub mem_size: u64,
+    pub data: &'a [u8],
+    pub flags: SegmentFlags,
+}
+
+impl<'a> Elf<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.get(..4) != Some(&ELF_MAGIC[..]) {
+            return Err(ElfError::BadMagic);
+        }
+
+        let ident = data.get(4..7).ok_or(ElfError::Truncated)?;
+        if ident != [ELFCLASS64, ELFDATA2LSB, EV_CURRENT] {
+            return Err(ElfError::BadClass);
+        }
+
+        if data.len() < EHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+
+        if le_u16(data, 16) != Some(ET_EXEC) {
+            return Err(ElfError::BadType);
+        }
+
+        let phoff = le_u64(data, 32).ok_or(ElfError::Truncated)?;
+        let phentsize = le_u16(data, 54).ok_or(ElfError::Truncated)? as usize;
+        let phnum = le_u16(data, 56).ok_or(ElfError::Truncated)? as usize;
+        if phentsize != PHDR_SIZE || phnum == 0 {
+            return Err(ElfError::BadProgramHeaders);
+        }
+
+        let phoff = usize::try_from(phoff).map_err(|_| ElfError::Truncated)?;
+        phoff
+            .checked_add(phnum * PHDR_SIZE)
+            .filter(|end| *end <= data.len())
+            .ok_or(ElfError::Truncated)?;
+
+        let elf = Elf {
+            data,
+            machine: le_u16(data, 18).ok_or(ElfError::Truncated)?,
+            entry: le_u64(data, 24).ok_or(ElfError::Truncated)?,
+            phoff,
+            phnum,
+        };
+
+        elf.check_segments()?;
+        Ok(elf)
+    }
+
+    /// `e_machine`, which the loader must match against the CPU.
+    pub fn machine(&self) -> u16 {
+        self.machine
+    }
+
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Number of program headers, for `AT_PHNUM`.
+    pub fn program_header_count(&self) -> usize {
+        self.phnum
+    }
+
+    /// Where the program header table is once the image is loaded, for
+    /// `AT_PHDR`, if a segment loads it.
+    pub fn program_headers_vaddr(&self) -> Option<u64> {
+        let offset = self.phoff as u64;
+        self.program_headers()
+            .filter(|header| header.kind == PT_LOAD)
+            .find(|header| offset >= header.offset && offset - header.offset < header.file_size)
+            .map(|header| header.vaddr + (offset - header.offset))
+    }
+
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let table = &self.data[self.phoff..self.phoff + self.phnum * PHDR_SIZE];
+        table.chunks_exact(PHDR_SIZE).map(|header| ProgramHeader {
+            kind: le_u32(header, 0).unwrap(),
+            flags: SegmentFlags::from_bits_truncate(le_u32(header, 4).unwrap()),
+            offset: le_u64(header, 8).unwrap(),
+            vaddr: le_u64(header, 16).unwrap(),
+            file_size: le_u64(header, 32).unwrap(),
+            mem_size: le_u64(header, 40).unwrap(),
+            align: le_u64(header, 48).unwrap(),
+        })
+    }
+
+    /// The `PT_LOAD` segments, in ascending address order.
+    pub fn segments(&self) -> impl Iterator<Item = Segment<'a>> + 'a {
+        let data = self.data;
+        self.program_headers()
+            .filter(|header| header.kind == PT_LOAD)
+            .map(move |header| Segment {
+                vaddr: header.vaddr,
+                mem_size: header.mem_size,
+                // Checked by `check_segments`
+                data: &data[header.offset as usize..][..header.file_size as usize],
+                flags: header.flags,
+            })
+    }
+
+    fn check_segments(&self) -> Result<(), ElfError> {
+        let mut previous_end = None;
+        let mut previous_last_page = None;
+        let mut entry_executable = false;
+
+        for header in self.program_headers() {
+            match header.kind {
+                PT_LOAD => {}
+                PT_DYNAMIC | PT_INTERP => return Err(ElfError::Dynamic),
+                _ => continue,
+            }
+
+            let file_end = header
+                .offset
+                .checked_add(header.file_size)
+                .ok_or(ElfError::BadSegment)?;
+            let end = header
+                .vaddr
+                .checked_add(header.mem_size)
+                .ok_or(ElfError::BadSegment)?;
+
+            if file_end > self.data.len() as u64 || header.file_size > header.mem_size {
+                return Err(ElfError::BadSegment);
+            }
+
+            // Loadable segments are sorted by address, so each must start
+            // after the one before ends
+            if previous_end.is_some_and(|previous_end| header.vaddr < previous_end) {
+                return Err(ElfError::BadSegment);
+            }
+            previous_end = Some(end);
+
+            if header.mem_size > 0 {
+                let first_page = header.vaddr / LOAD_PAGE_SIZE;
+                if previous_last_page.is_some_and(|last_page| first_page <= last_page) {
+                    return Err(ElfError::SharedPage);
+                }
+                previous_last_page = Some((end - 1) / LOAD_PAGE_SIZE);
+            }
+
+            if header.align > 1
+                && (!header.align.is_power_of_two()
+                    || header.vaddr % header.align != header.offset % header.align)
+            {
+                return Err(ElfError::BadSegment);
+            }
+
+            if header.flags.contains(SegmentFlags::EXEC)
+                && (header.vaddr..end).contains(&self.entry)
+            {
+                entry_executable = true;
+            }
+        }
+
+        if previous_end.is_none() {
+            return Err(ElfError::BadSegment);
+        }
+        if !entry_executable {
+            return Err(ElfError::BadEntry);
+        }
+
+        Ok(())
+    }
+}
+
+impl Segment<'_> {
+    /// How to map the segment's pages for user mode.
+    pub fn map_flags(&self) -> MapFlags {
+        let mut flags = MapFlags::USER;
+        if self.flags.contains(SegmentFlags::READ) {
+            flags |= MapFlags::READ;
+        }
+        if self.flags.contains(SegmentFlags::WRITE) {
+            flags |= MapFlags::WRITE;
+        }
+        if self.flags.contains(SegmentFlags::EXEC) {
+            flags |= MapFlags::EXEC;
+        }
+
+        flags
+    }
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset.checked_add(8)?)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    const TEXT: u64 = 0x40_0000;
+    const DATA: u64 = 0x41_0000;
+
+    /// Builds an image with a header, program headers and segment contents,
+    /// in that order.
+    struct Builder {
+        headers: Vec<ProgramHeader>,
+        contents: Vec<u8>,
+        entry: u64,
+    }
+
+    impl Builder {
+        fn new(entry: u64) -> Self {
+            Builder {
+                headers: Vec::new(),
+                contents: Vec::new(),
+                entry,
+            }
+        }
+
+        fn segment(
+            mut self,
+            kind: u32,
+            flags: SegmentFlags,
+            vaddr: u64,
+            data: &[u8],
+            mem_size: u64,
+        ) -> Self {
+            self.headers.push(ProgramHeader {
+                kind,
+                flags,
+                offset: self.contents.len() as u64,
+                vaddr,
+                file_size: data.len() as u64,
+                mem_size,
+                align: 0,
+            });
+            self.contents.extend_from_slice(data);
+            self
+        }
+
+        fn build(self) -> Vec<u8> {
+            let contents_offset = (EHDR_SIZE + self.headers.len() * PHDR_SIZE) as u64;
+
+            let mut image = Vec::new();
+            image.extend_from_slice(&ELF_MAGIC);
+            image.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, EV_CURRENT]);
+            image.resize(16, 0);
+            image.extend_from_slice(&ET_EXEC.to_le_bytes());
+            image.extend_from_slice(&EM_AARCH64.to_le_bytes());
+            image.extend_from_slice(&1u32.to_le_bytes());
+            image.extend_from_slice(&self.entry.to_le_bytes());
+            image.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+            image.resize(52, 0);
+            image.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+            image.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+            image.extend_from_slice(&(self.headers.len() as u16).to_le_bytes());
+            image.resize(EHDR_SIZE, 0);
+
+            for header in &self.headers {
+                image.extend_from_slice(&header.kind.to_le_bytes());
+                image.extend_from_slice(&header.flags.bits().to_le_bytes());
+                image.extend_from_slice(&(header.offset + contents_offset).to_le_bytes());
+                image.extend_from_slice(&header.vaddr.to_le_bytes());
+                image.extend_from_slice(&header.vaddr.to_le_bytes());
+                image.extend_from_slice(&header.file_size.to_le_bytes());
+                image.extend_from_slice(&header.mem_size.to_le_bytes());
+                image.extend_from_slice(&header.align.to_le_bytes());
+            }
+
+            image.extend_from_slice(&self.contents);
+            image
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        Builder::new(TEXT + 4)
+            .segment(
+                PT_LOAD,
+                SegmentFlags::READ | SegmentFlags::EXEC,
+                TEXT,
+                b"code",
+                4 + 4,
+            )
+            .segment(
+                0x6474_e551,
+                SegmentFlags::READ | SegmentFlags::WRITE,
+                0,
+                &[],
+                0,
+            )
+            .segment(
+                PT_LOAD,
+                SegmentFlags::READ | SegmentFlags::WRITE,
+                DATA,
+                b"data",
+                0x100,
+            )
+            .build()
+    }
+
+    #[test]
+    fn reads_load_segments() {
+        let image = sample();
+        let elf = Elf::new(&image).unwrap();
+
+        assert_eq!(elf.machine(), EM_AARCH64);
+        assert_eq!(elf.entry(), TEXT + 4);
+        assert_eq!(elf.program_header_count(), 3);
+        assert_eq!(elf.program_headers_vaddr(), None);
+
+        let segments: Vec<_> = elf.segments().collect();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].vaddr, TEXT);
+        assert_eq!(segments[0].data, b"code");
+        assert_eq!(
+            segments[0].map_flags(),
+            MapFlags::USER | MapFlags::READ | MapFlags::EXEC
+        );
+        assert_eq!(segments[1].vaddr, DATA);
+        assert_eq!(segments[1].mem_size, 0x100);
+        assert_eq!(segments[1].data, b"data");
+        assert_eq!(
+            segments[1].map_flags(),
+            MapFlags::USER | MapFlags::READ | MapFlags::WRITE
+        );
+    }
+
+    #[test]
+    fn finds_loaded_program_headers() {
+        // A segment loading the whole file, headers included
+        let mut image = Builder::new(TEXT + 0x100)
+            .segment(
+                PT_LOAD,
+                SegmentFlags::READ | SegmentFlags::EXEC,
+                TEXT,
+                &[],
+                0x1000,
+            )
+            .build();
+        // Offset 0 and a file size covering the headers
+        let image_size = image.len() as u64;
+        image[EHDR_SIZE + 8..EHDR_SIZE + 16].copy_from_slice(&0u64.to_le_bytes());
+        image[EHDR_SIZE + 32..EHDR_SIZE + 40].copy_from_slice(&image_size.to_le_bytes());
+
+        let elf = Elf::new(&image).unwrap();
+        assert_eq!(elf.program_headers_vaddr(), Some(TEXT + EHDR_SIZE as u64));
+        assert_eq!(elf.segments().next().unwrap().data, &image[..]);
+    }
+
+    #[test]
+    fn rejects_malformed_images() {
+        let image = sample();
+        let patched = |offset: usize, bytes: &[u8]| {
+            let mut image = image.clone();
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Elf::new(&image).unwrap_err()
+        };
+        let phdr = |index: usize, field: usize| EHDR_SIZE + index * PHDR_SIZE + field;
+
+        assert_eq!(Elf::new(&image[..3]).unwrap_err(), ElfError::BadMagic);
+        assert_eq!(Elf::new(&image[..40]).unwrap_err(), ElfError::Truncated);
+        assert_eq!(
+            Elf::new(&image[..image.len() - 1]).unwrap_err(),
+            ElfError::BadSegment
+        );
+        assert_eq!(patched(0, b"\x7fELG"), ElfError::BadMagic);
+        assert_eq!(patched(4, &[1]), ElfError::BadClass);
+        assert_eq!(patched(5, &[2]), ElfError::BadClass);
+        assert_eq!(patched(16, &3u16.to_le_bytes()), ElfError::BadType);
+        assert_eq!(
+            patched(54, &32u16.to_le_bytes()),
+            ElfError::BadProgramHeaders
+        );
+        assert_eq!(
+            patched(56, &0u16.to_le_bytes()),
+            ElfError::BadProgramHeaders
+        );
+        assert_eq!(patched(56, &100u16.to_le_bytes()), ElfError::Truncated);
+        assert_eq!(patched(32, &u64::MAX.to_le_bytes()), ElfError::Truncated);
+        assert_eq!(patched(24, &DATA.to_le_bytes()), ElfError::BadEntry);
+        assert_eq!(
+            patched(phdr(0, 0), &PT_INTERP.to_le_bytes()),
+            ElfError::Dynamic
+        );
+
+        // File size above memory size, a segment overflowing the address
+        // space, overlapping segments and misalignment
+        assert_eq!(
+            patched(phdr(0, 40), &2u64.to_le_bytes()),
+            ElfError::BadSegment
+        );
+        assert_eq!(
+            patched(phdr(2, 16), &(u64::MAX - 8).to_le_bytes()),
+            ElfError::BadSegment
+        );
+        assert_eq!(
+            patched(phdr(2, 16), &(TEXT + 4).to_le_bytes()),
+            ElfError::BadSegment
+        );
+        assert_eq!(
+            patched(phdr(0, 48), &0x1000u64.to_le_bytes()),
+            ElfError::BadSegment
+        );
+        assert_eq!(
+            patched(phdr(2, 8), &u64::MAX.to_le_bytes()),
+            ElfError::BadSegment
+        );
+    }
+
+    #[test]
+    fn rejects_segments_sharing_a_page() {
+        let image = |data_vaddr: u64| {
+            Builder::new(TEXT)
+                .segment(
+                    PT_LOAD,
+                    SegmentFlags::READ | SegmentFlags::EXEC,
+                    TEXT,
+                    b"code",
+                    0x800,
+                )
+                .segment(
+                    PT_LOAD,
+                    SegmentFlags::READ | SegmentFlags::WRITE,
+                    data_vaddr,
+                    b"data",
+                    0x100,
+                )
+                .build()
+        };
+
+        // Apart by bytes, but on the same page
+        assert_eq!(
+            Elf::new(&image(TEXT + 0x800)).unwrap_err(),
+            ElfError::SharedPage
+        );
+        assert_eq!(
+            Elf::new(&image(TEXT + 0xf00)).unwrap_err(),
+            ElfError::SharedPage
+        );
+        assert!(Elf::new(&image(TEXT + LOAD_PAGE_SIZE)).is_ok());
+    }
+
+    /// Throws randomly corrupted and truncated images at the parser, which
+    /// must reject them or only ever read within the image.
+    #[test]
+    fn survives_fuzzed_images() {
+        let image = sample();
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut accepted = 0;
+        for _ in 0..20_000 {
+            let mut fuzzed = image.clone();
+            for _ in 0..random() % 8 + 1 {
+                let index = random() as usize % fuzzed.len();
+                fuzzed[index] = match random() % 4 {
+                    0 => 0,
+                    1 => 0xff,
+                    _ => random() as u8,
+                };
+            }
+            fuzzed.truncate(fuzzed.len() - random() as usize % 16);
+
+            let Ok(elf) = Elf::new(&fuzzed) else {
+                continue;
+            };
+
+            accepted += 1;
+            let range = fuzzed.as_ptr_range();
+            let within = |bytes: &[u8]| {
+                let bytes = bytes.as_ptr_range();
+                range.start <= bytes.start && bytes.end <= range.end
+            };
+
+            // The table `program_headers_vaddr` and `segments` read
+            assert!(elf.phoff + elf.phnum * PHDR_SIZE <= fuzzed.len());
+            assert_eq!(elf.program_headers().count(), elf.phnum);
+            if let Some(vaddr) = elf.program_headers_vaddr() {
+                let segment = elf
+                    .segments()
+                    .find(|segment| {
+                        vaddr >= segment.vaddr && vaddr - segment.vaddr < segment.data.len() as u64
+                    })
+                    .unwrap();
+                let offset = (vaddr - segment.vaddr) as usize;
+                assert!(within(&segment.data[offset..]));
+            }
+
+            assert!(elf.segments().count() > 0);
+            for segment in elf.segments() {
+                assert!(segment.data.len() as u64 <= segment.mem_size);
+                assert!(segment.vaddr.checked_add(segment.mem_size).is_some());
+                assert!(segment.data.is_empty() || within(segment.data));
+            }
+        }
+
+        // Changes to the contents alone leave the image valid
+        assert!(accepted > 0);
+    }
+}
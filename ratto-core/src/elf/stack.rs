@@ -0,0 +1,133 @@
+/// Lay out the stack a program starts with, as the System V ABI describes,
+/// in `stack`, which is the memory just below `top` in the program's
+/// address space.
+///
+/// From the returned stack pointer up, the stack holds `argc`, the `argv`
+/// pointers and a null, the `envp` pointers and a null, and the `auxv`
+/// pairs ended by an `AT_NULL` pair. The strings they point to are at the
+/// top. The stack pointer is 16-byte aligned.
+///
+/// Returns `None` if it does not all fit, or if a string holds a NUL.
+pub fn initial_stack(
+    stack: &mut [u8],
+    top: u64,
+    argv: &[&str],
+    envp: &[&str],
+    auxv: &[(u64, u64)],
+) -> Option<u64> {
+    let strings = || argv.iter().chain(envp);
+    if strings().any(|string| string.contains('\0')) {
+        return None;
+    }
+
+    let strings_size: usize = strings().map(|string| string.len() + 1).sum();
+    let words = 1 + (argv.len() + 1) + (envp.len() + 1) + 2 * (auxv.len() + 1);
+    let size = (strings_size + words * 8).next_multiple_of(16);
+    if size > stack.len() || !top.is_multiple_of(16) {
+        return None;
+    }
+
+    let sp = top.checked_sub(size as u64)?;
+    let base = stack.len() - size;
+    let (mut vector, mut string_area) = stack[base..].split_at_mut(size - strings_size);
+    let mut string_addr = top - strings_size as u64;
+
+    let mut push_word = |word: u64| {
+        let (head, rest) = core::mem::take(&mut vector).split_at_mut(8);
+        head.copy_from_slice(&word.to_le_bytes());
+        vector = rest;
+    };
+
+    let mut push_string = |string: &str| {
+        let (head, rest) = core::mem::take(&mut string_area).split_at_mut(string.len() + 1);
+        head[..string.len()].copy_from_slice(string.as_bytes());
+        head[string.len()] = 0;
+        string_area = rest;
+
+        let addr = string_addr;
+        string_addr += head.len() as u64;
+        addr
+    };
+
+    push_word(argv.len() as u64);
+    for list in [argv, envp] {
+        for string in list {
+            push_word(push_string(string));
+        }
+        push_word(0);
+    }
+
+    for &(key, value) in auxv.iter().chain(&[(0, 0)]) {
+        push_word(key);
+        push_word(value);
+    }
+
+    // Padding up to the strings
+    vector.fill(0);
+    Some(sp)
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    const TOP: u64 = 0x80_0000;
+
+    fn word(stack: &[u8], addr: u64) -> u64 {
+        let offset = (addr - (TOP - stack.len() as u64)) as usize;
+        u64::from_le_bytes(stack[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn string(stack: &[u8], addr: u64) -> &str {
+        let offset = (addr - (TOP - stack.len() as u64)) as usize;
+        let len = stack[offset..].iter().position(|&byte| byte == 0).unwrap();
+        core::str::from_utf8(&stack[offset..offset + len]).unwrap()
+    }
+
+    #[test]
+    fn lays_out_arguments_environment_and_auxv() {
+        let mut stack = [0xaa; 256];
+        let sp = initial_stack(
+            &mut stack,
+            TOP,
+            &["prog", "-v"],
+            &["HOME=/"],
+            &[(6, 0x1000), (9, 0x40_0000)],
+        )
+        .unwrap();
+
+        assert_eq!(sp % 16, 0);
+        assert_eq!(word(&stack, sp), 2);
+        assert_eq!(string(&stack, word(&stack, sp + 8)), "prog");
+        assert_eq!(string(&stack, word(&stack, sp + 16)), "-v");
+        assert_eq!(word(&stack, sp + 24), 0);
+        assert_eq!(string(&stack, word(&stack, sp + 32)), "HOME=/");
+        assert_eq!(word(&stack, sp + 40), 0);
+
+        let auxv: Vec<_> = (0..3)
+            .map(|index| {
+                let pair = sp + 48 + index * 16;
+                (word(&stack, pair), word(&stack, pair + 8))
+            })
+            .collect();
+        assert_eq!(auxv, [(6, 0x1000), (9, 0x40_0000), (0, 0)]);
+
+        // The strings end at the top
+        assert_eq!(&stack[stack.len() - 7..], b"HOME=/\0");
+    }
+
+    #[test]
+    fn rejects_what_does_not_fit() {
+        let mut stack = [0; 64];
+        assert_eq!(
+            initial_stack(&mut stack, TOP, &[], &[], &[]),
+            Some(TOP - 48)
+        );
+        assert_eq!(
+            initial_stack(&mut stack, TOP, &["a".repeat(40).as_str()], &[], &[]),
+            None
+        );
+        assert_eq!(initial_stack(&mut stack, TOP, &["a\0b"], &[], &[]), None);
+        assert_eq!(initial_stack(&mut stack, TOP - 8, &[], &[], &[]), None);
+    }
+}
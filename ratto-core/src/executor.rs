@@ -0,0 +1,425 @@
+//! A cooperative executor for `async` code that needs no allocator: spawned
+//! futures live in a fixed number of fixed-size slots.
+//!
+//! Wakers may be used from any core and from interrupt handlers. While
+//! nothing is ready, the thread running the executor parks through
+//! [`SchedOps`], so the executor can run on a thread of its own next to
+//! preemptive threads, or be the only thing a core runs.
+
+use core::cell::UnsafeCell;
+use core::convert::Infallible;
+use core::future::Future;
+use core::mem::MaybeUninit;
+use core::pin::{Pin, pin};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use crate::sched::SchedOps;
+use crate::sync::{MpscConsumer, MpscRing, SpinLockIrq};
+
+mod signal;
+mod timer;
+mod waker_cell;
+
+pub use signal::*;
+pub use timer::*;
+pub use waker_cell::*;
+
+/// Default size of the slot each spawned future is stored in.
+pub const DEFAULT_SLOT_SIZE: usize = 1024;
+
+/// Alignment of the future slots; no future may need more.
+pub const SLOT_ALIGN: usize = 16;
+
+const FREE: u8 = 0;
+/// Claimed by [`Executor::spawn`], which is moving a future in
+const CLAIMED: u8 = 1;
+/// Holds a future that has not completed
+const LIVE: u8 = 2;
+
+/// Runs up to `N` spawned futures of at most `SIZE` bytes each, plus the one
+/// passed to [`block_on`](Executor::block_on). `N` must be a power of two.
+pub struct Executor<S: SchedOps, const N: usize, const SIZE: usize = DEFAULT_SLOT_SIZE> {
+    slots: [Slot<SIZE>; N],
+    /// Indices of the slots whose futures were woken
+    ready: MpscRing<usize, N>,
+    /// Whether the future passed to `block_on` was woken
+    main_woken: AtomicBool,
+    /// The task running the executor, unparked when a future is woken
+    runner: SpinLockIrq<Option<S::Task>, S::Cpu>,
+}
+
+// Slot contents are only touched by the spawner that claimed the slot and
+// then by the runner, and spawned futures are `Send`
+unsafe impl<S: SchedOps, const N: usize, const SIZE: usize> Sync for Executor<S, N, SIZE> {}
+
+struct Slot<const SIZE: usize> {
+    index: usize,
+    state: AtomicU8,
+    /// Set while the slot's index is on the ready ring
+    queued: AtomicBool,
+    vtable: UnsafeCell<Option<FutureVTable>>,
+    storage: UnsafeCell<MaybeUninit<Storage<SIZE>>>,
+}
+
+#[repr(C, align(16))]
+struct Storage<const SIZE: usize>([u8; SIZE]);
+
+/// How to poll and drop the future in a slot, whose type is erased.
+#[derive(Copy, Clone)]
+struct FutureVTable {
+    poll: unsafe fn(*mut u8, &mut Context<'_>) -> Poll<()>,
+    drop: unsafe fn(*mut u8),
+}
+
+impl FutureVTable {
+    fn of<F: Future<Output = ()>>() -> Self {
+        unsafe fn poll<F: Future<Output = ()>>(future: *mut u8, cx: &mut Context<'_>) -> Poll<()> {
+            // Slots never move, so their futures are pinned
+            unsafe { Pin::new_unchecked(&mut *future.cast::<F>()).poll(cx) }
+        }
+
+        unsafe fn drop<F>(future: *mut u8) {
+            unsafe { future.cast::<F>().drop_in_place() };
+        }
+
+        FutureVTable {
+            poll: poll::<F>,
+            drop: drop::<F>,
+        }
+    }
+}
+
+impl<const SIZE: usize> Slot<SIZE> {
+    const fn new(index: usize) -> Self {
+        Slot {
+            index,
+            state: AtomicU8::new(FREE),
+            queued: AtomicBool::new(false),
+            vtable: UnsafeCell::new(None),
+            storage: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    fn future(&self) -> *mut u8 {
+        self.storage.get().cast()
+    }
+}
+
+impl<S: SchedOps, const N: usize, const SIZE: usize> Executor<S, N, SIZE> {
+    const SLOT_WAKER: RawWakerVTable = RawWakerVTable::new(
+        Self::clone_slot,
+        Self::wake_slot,
+        Self::wake_slot,
+        Self::drop_waker,
+    );
+
+    const MAIN_WAKER: RawWakerVTable = RawWakerVTable::new(
+        Self::clone_main,
+        Self::wake_main,
+        Self::wake_main,
+        Self::drop_waker,
+    );
+
+    #[track_caller]
+    pub const fn new() -> Self {
+        let mut slots = [const { Slot::new(0) }; N];
+        let mut index = 0;
+        while index < N {
+            slots[index] = Slot::new(index);
+            index += 1;
+        }
+
+        Executor {
+            slots,
+            ready: MpscRing::new(),
+            main_woken: AtomicBool::new(false),
+            runner: SpinLockIrq::new(None),
+        }
+    }
+
+    /// Start running `future` alongside the others. Fails if every slot is
+    /// taken. May be called from any core, but not from interrupt handlers.
+    pub fn spawn<F>(&'static self, future: F) -> Result<(), &'static str>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        const {
+            assert!(
+                size_of::<F>() <= SIZE,
+                "Future too large for an executor slot"
+            );
+            assert!(align_of::<F>() <= SLOT_ALIGN, "Future too strictly aligned");
+        };
+
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| {
+                slot.state
+                    .compare_exchange(FREE, CLAIMED, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or("Too many async tasks")?;
+
+        unsafe {
+            slot.future().cast::<F>().write(future);
+            *slot.vtable.get() = Some(FutureVTable::of::<F>());
+        }
+
+        slot.state.store(LIVE, Ordering::Release);
+        self.wake(slot);
+        Ok(())
+    }
+
+    /// Number of spawned futures that have not completed.
+    pub fn len(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| slot.state.load(Ordering::Relaxed) != FREE)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run the spawned futures forever on the calling thread.
+    pub fn run(&'static self) -> ! {
+        match self.block_on(core::future::pending::<Infallible>()) {}
+    }
+
+    /// Run the spawned futures on the calling thread until `future`
+    /// completes, and return its output.
+    ///
+    /// # Panics
+    /// If the executor is already running on another thread.
+    pub fn block_on<F: Future>(&'static self, future: F) -> F::Output {
+        let mut ready = self.ready.consumer().expect("Executor is already running");
+        self.runner
+            .lock_with(|runner| *runner = Some(S::current_task()));
+
+        let mut future = pin!(future);
+        let waker = unsafe { Waker::from_raw(self.main_waker()) };
+        self.main_woken.store(true, Ordering::Relaxed);
+
+        loop {
+            if self.main_woken.swap(false, Ordering::AcqRel)
+                && let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker))
+            {
+                self.runner.lock_with(|runner| *runner = None);
+                return output;
+            }
+
+            // A wakeup after these checks leaves the runner's wake token set,
+            // so parking returns straight away
+            if !self.poll_ready(&mut ready) && !self.main_woken.load(Ordering::Acquire) {
+                S::park();
+            }
+        }
+    }
+
+    /// Poll every spawned future that was woken. Returns whether there were
+    /// any.
+    fn poll_ready(&self, ready: &mut MpscConsumer<'_, usize, N>) -> bool {
+        let mut polled = false;
+        while let Some(index) = ready.pop() {
+            polled = true;
+
+            let slot = &self.slots[index];
+            // Wakes from here on queue the future again
+            slot.queued.swap(false, Ordering::AcqRel);
+
+            // A waker can outlive its future
+            if slot.state.load(Ordering::Acquire) != LIVE {
+                continue;
+            }
+
+            let waker = unsafe { Waker::from_raw(Self::slot_waker(slot)) };
+            let vtable = unsafe { (*slot.vtable.get()).expect("Live slot without a future") };
+            let poll = unsafe { (vtable.poll)(slot.future(), &mut Context::from_waker(&waker)) };
+            if poll.is_ready() {
+                unsafe {
+                    (vtable.drop)(slot.future());
+                    *slot.vtable.get() = None;
+                }
+
+                slot.state.store(FREE, Ordering::Release);
+            }
+        }
+
+        polled
+    }
+
+    fn wake(&self, slot: &Slot<SIZE>) {
+        if slot.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        // Each slot is queued at most once, so the ring cannot fill up
+        self.ready
+            .push(slot.index)
+            .unwrap_or_else(|_| unreachable!("Executor ready ring overflowed"));
+        self.unpark_runner();
+    }
+
+    fn unpark_runner(&self) {
+        if let Some(runner) = self.runner.lock_with(|runner| runner.clone()) {
+            S::unpark(&runner);
+        }
+    }
+
+    fn slot_waker(slot: &Slot<SIZE>) -> RawWaker {
+        RawWaker::new(ptr::from_ref(slot).cast(), &Self::SLOT_WAKER)
+    }
+
+    fn main_waker(&self) -> RawWaker {
+        RawWaker::new(ptr::from_ref(self).cast(), &Self::MAIN_WAKER)
+    }
+
+    /// The executor owning `slot`, which must be one of its slots.
+    unsafe fn of_slot(slot: &Slot<SIZE>) -> &Self {
+        let slots = ptr::from_ref(slot).wrapping_sub(slot.index);
+        let offset = core::mem::offset_of!(Self, slots);
+        unsafe { &*slots.byte_sub(offset).cast::<Self>() }
+    }
+
+    unsafe fn clone_slot(slot: *const ()) -> RawWaker {
+        Self::slot_waker(unsafe { &*slot.cast::<Slot<SIZE>>() })
+    }
+
+    unsafe fn wake_slot(slot: *const ()) {
+        let slot = unsafe { &*slot.cast::<Slot<SIZE>>() };
+        unsafe { Self::of_slot(slot) }.wake(slot);
+    }
+
+    unsafe fn clone_main(executor: *const ()) -> RawWaker {
+        unsafe { &*executor.cast::<Self>() }.main_waker()
+    }
+
+    unsafe fn wake_main(executor: *const ()) {
+        let executor = unsafe { &*executor.cast::<Self>() };
+        executor.main_woken.store(true, Ordering::Release);
+        executor.unpark_runner();
+    }
+
+    /// Wakers only point into the executor, so there is nothing to release.
+    unsafe fn drop_waker(_: *const ()) {}
+}
+
+impl<S: SchedOps, const N: usize, const SIZE: usize> Default for Executor<S, N, SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+    use crate::sched::test::TestSched;
+
+    type TestExecutor = Executor<TestSched, 4, 256>;
+
+    fn leak() -> &'static TestExecutor {
+        Box::leak(Box::new(TestExecutor::new()))
+    }
+
+    /// Completes after being polled `polls` times, waking itself each time.
+    async fn yield_times(polls: usize) {
+        let mut left = polls;
+        core::future::poll_fn(|cx| match left {
+            0 => Poll::Ready(()),
+            _ => {
+                left -= 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    #[test]
+    fn runs_spawned_futures_to_completion() {
+        let executor = leak();
+        let done = Arc::new(AtomicUsize::new(0));
+        for polls in 0..4 {
+            let done = done.clone();
+            executor
+                .spawn(async move {
+                    yield_times(polls).await;
+                    done.fetch_add(1, Ordering::Relaxed);
+                })
+                .unwrap();
+        }
+
+        assert!(executor.spawn(async {}).is_err());
+        let output = executor.block_on(async {
+            while done.load(Ordering::Relaxed) < 4 {
+                yield_times(1).await;
+            }
+            42
+        });
+
+        assert_eq!(output, 42);
+        assert!(executor.is_empty());
+        executor.spawn(async {}).unwrap();
+    }
+
+    #[test]
+    fn wakes_from_other_threads() {
+        let executor = leak();
+        let signal: &'static Signal<u32, TestCpu> = Box::leak(Box::new(Signal::new()));
+
+        executor
+            .spawn(async move {
+                let value = signal.wait().await;
+                signal.signal(value + 1);
+            })
+            .unwrap();
+
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            signal.signal(1);
+        });
+
+        let output = executor.block_on(async {
+            // Let the spawned future take the first value
+            while !executor.is_empty() {
+                yield_times(1).await;
+            }
+            signal.wait().await
+        });
+
+        sender.join().unwrap();
+        assert_eq!(output, 2);
+    }
+
+    #[test]
+    fn stale_wakers_are_harmless() {
+        let executor = leak();
+        let stash: &'static WakerCell<TestCpu> = Box::leak(Box::new(WakerCell::new()));
+
+        executor
+            .spawn(async move {
+                core::future::poll_fn(|cx| {
+                    stash.register(cx.waker());
+                    Poll::Ready(())
+                })
+                .await
+            })
+            .unwrap();
+
+        executor.block_on(yield_times(2));
+        assert!(executor.is_empty());
+
+        // The future is gone, so waking it must not poll anything
+        stash.wake();
+        executor.block_on(yield_times(1));
+        assert!(executor.is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+use core::future::Future;
+use core::task::{Poll, Waker};
+
+use crate::cpu::CpuOps;
+use crate::sync::SpinLockIrq;
+
+/// Hands a value from an interrupt handler, or any other context, to the
+/// one future waiting for it, such as the completion status of an I/O
+/// request. A value signalled before anyone waits is kept; a second one
+/// replaces it.
+pub struct Signal<T, Cpu: CpuOps> {
+    state: SpinLockIrq<State<T>, Cpu>,
+}
+
+struct State<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T, Cpu: CpuOps> Signal<T, Cpu> {
+    #[track_caller]
+    pub const fn new() -> Self {
+        Signal {
+            state: SpinLockIrq::new(State {
+                value: None,
+                waker: None,
+            }),
+        }
+    }
+
+    /// Store `value` and wake the waiting future.
+    pub fn signal(&self, value: T) {
+        let waker = self.state.lock_with(|state| {
+            state.value = Some(value);
+            state.waker.take()
+        });
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Take the signalled value without waiting.
+    pub fn try_take(&self) -> Option<T> {
+        self.state.lock_with(|state| state.value.take())
+    }
+
+    /// Wait for a value and take it.
+    pub fn wait(&self) -> impl Future<Output = T> + '_ {
+        core::future::poll_fn(|cx| {
+            self.state.lock_with(|state| match state.value.take() {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+        })
+    }
+
+    /// Forget a value that was signalled but not taken.
+    pub fn reset(&self) {
+        self.state.lock_with(|state| state.value = None);
+    }
+}
+
+impl<T, Cpu: CpuOps> Default for Signal<T, Cpu> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
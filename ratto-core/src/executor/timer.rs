@@ -0,0 +1,237 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use crate::collections::ArrayVec;
+use crate::cpu::CpuOps;
+use crate::sync::SpinLockIrq;
+
+/// Wakes futures once their deadline on [`CpuOps::monotonic_ns`] has passed.
+/// Holds up to `N` pending deadlines; futures that find it full poll again
+/// until there is room.
+///
+/// Nothing expires on its own: something, usually the timer tick, must call
+/// [`expire`](TimerQueue::expire), so deadlines are only as precise as its
+/// period.
+pub struct TimerQueue<Cpu: CpuOps, const N: usize> {
+    timers: SpinLockIrq<ArrayVec<Timer, N>, Cpu>,
+    next_id: AtomicU64,
+}
+
+struct Timer {
+    id: u64,
+    deadline: u64,
+    waker: Waker,
+}
+
+impl<Cpu: CpuOps, const N: usize> TimerQueue<Cpu, N> {
+    #[track_caller]
+    pub const fn new() -> Self {
+        TimerQueue {
+            timers: SpinLockIrq::new(ArrayVec::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// A future that completes `duration` from now.
+    pub fn sleep(&self, duration: Duration) -> Sleep<'_, Cpu, N> {
+        let duration = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.sleep_until(Cpu::monotonic_ns().saturating_add(duration))
+    }
+
+    /// A future that completes once [`CpuOps::monotonic_ns`] reaches
+    /// `deadline`.
+    pub fn sleep_until(&self, deadline: u64) -> Sleep<'_, Cpu, N> {
+        Sleep {
+            queue: self,
+            deadline,
+            id: None,
+        }
+    }
+
+    /// Wake the futures whose deadline has passed, returning how many.
+    pub fn expire(&self) -> usize {
+        let now = Cpu::monotonic_ns();
+        let mut expired = ArrayVec::<Waker, N>::new();
+        self.timers.lock_with(|timers| {
+            let mut index = 0;
+            while index < timers.len() {
+                if timers[index].deadline <= now {
+                    let timer = timers.swap_remove(index);
+                    // At most `N` timers are queued, so there is room
+                    let _ = expired.push(timer.waker);
+                } else {
+                    index += 1;
+                }
+            }
+        });
+
+        let count = expired.len();
+        while let Some(waker) = expired.pop() {
+            waker.wake();
+        }
+
+        count
+    }
+
+    /// The earliest pending deadline.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.timers
+            .lock_with(|timers| timers.iter().map(|timer| timer.deadline).min())
+    }
+
+    /// Number of pending deadlines.
+    pub fn len(&self) -> usize {
+        self.timers.lock_with(|timers| timers.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queue or update the timer `id`, returning its id, or `None` if the
+    /// queue is full.
+    fn arm(&self, id: Option<u64>, deadline: u64, waker: &Waker) -> Option<u64> {
+        self.timers.lock_with(|timers| {
+            if let Some(timer) = id.and_then(|id| timers.iter_mut().find(|timer| timer.id == id)) {
+                timer.waker.clone_from(waker);
+                return Some(timer.id);
+            }
+
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let timer = Timer {
+                id,
+                deadline,
+                waker: waker.clone(),
+            };
+            timers.push(timer).ok().map(|()| id)
+        })
+    }
+
+    fn cancel(&self, id: u64) {
+        self.timers
+            .lock_with(|timers| timers.retain(|timer| timer.id != id));
+    }
+}
+
+impl<Cpu: CpuOps, const N: usize> Default for TimerQueue<Cpu, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Completes at a deadline; see [`TimerQueue::sleep`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct Sleep<'a, Cpu: CpuOps, const N: usize> {
+    queue: &'a TimerQueue<Cpu, N>,
+    deadline: u64,
+    /// The queued timer, once polled
+    id: Option<u64>,
+}
+
+impl<'a, Cpu: CpuOps, const N: usize> Sleep<'a, Cpu, N> {
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+}
+
+impl<'a, Cpu: CpuOps, const N: usize> Future for Sleep<'a, Cpu, N> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Cpu::monotonic_ns() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                self.queue.cancel(id);
+            }
+            return Poll::Ready(());
+        }
+
+        self.id = self.queue.arm(self.id, self.deadline, cx.waker());
+        if self.id.is_none() {
+            cx.waker().wake_by_ref();
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<'a, Cpu: CpuOps, const N: usize> Drop for Sleep<'a, Cpu, N> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.queue.cancel(id);
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+    use crate::executor::Executor;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn sleepers_wake_in_deadline_order() {
+        static TIMERS: TimerQueue<TestCpu, 4> = TimerQueue::new();
+        static EXECUTOR: Executor<TestSched, 4, 256> = Executor::new();
+        static ORDER: SpinLockIrq<Vec<u64>, TestCpu> = SpinLockIrq::new(Vec::new());
+
+        for millis in [30, 10, 20] {
+            EXECUTOR
+                .spawn(async move {
+                    TIMERS.sleep(Duration::from_millis(millis)).await;
+                    ORDER.lock_with(|order| order.push(millis));
+                })
+                .unwrap();
+        }
+
+        // Ticks until `block_on` returns, since its own sleeps need expiring
+        // after the last sleeper is done
+        let done = std::sync::atomic::AtomicBool::new(false);
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    std::thread::sleep(Duration::from_millis(1));
+                    TIMERS.expire();
+                }
+            });
+
+            EXECUTOR.block_on(async {
+                while !EXECUTOR.is_empty() {
+                    TIMERS.sleep(Duration::from_millis(1)).await;
+                }
+            });
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(ORDER.lock_with(|order| order.clone()), [10, 20, 30]);
+        assert!(TIMERS.is_empty());
+    }
+
+    #[test]
+    fn dropped_sleep_cancels_its_timer() {
+        let timers = TimerQueue::<TestCpu, 2>::new();
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+
+        let mut sleep = Box::pin(timers.sleep(Duration::from_secs(60)));
+        assert!(sleep.as_mut().poll(&mut cx).is_pending());
+        assert!(sleep.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers.next_deadline(), Some(sleep.deadline()));
+
+        drop(sleep);
+        assert!(timers.is_empty());
+        assert_eq!(timers.expire(), 0);
+
+        let mut due = Box::pin(timers.sleep_until(0));
+        assert!(due.as_mut().poll(&mut cx).is_ready());
+        assert!(timers.is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+use core::task::Waker;
+
+use crate::cpu::CpuOps;
+use crate::sync::SpinLockIrq;
+
+/// Holds the waker of the one future waiting for an event, typically an
+/// interrupt, so that the event's handler can wake it.
+pub struct WakerCell<Cpu: CpuOps> {
+    waker: SpinLockIrq<Option<Waker>, Cpu>,
+}
+
+impl<Cpu: CpuOps> WakerCell<Cpu> {
+    #[track_caller]
+    pub const fn new() -> Self {
+        WakerCell {
+            waker: SpinLockIrq::new(None),
+        }
+    }
+
+    /// Store `waker`, replacing the one stored before.
+    pub fn register(&self, waker: &Waker) {
+        self.waker.lock_with(|stored| match stored {
+            Some(stored) if stored.will_wake(waker) => {}
+            stored => *stored = Some(waker.clone()),
+        });
+    }
+
+    /// Wake the stored waker, if any, and forget it.
+    pub fn wake(&self) {
+        // Woken outside the lock, since waking may take other locks
+        if let Some(waker) = self.waker.lock_with(Option::take) {
+            waker.wake();
+        }
+    }
+}
+
+impl<Cpu: CpuOps> Default for WakerCell<Cpu> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,543 @@
+//! A minimal, allocation-free flattened device tree (DTB) reader.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FdtError {
+    BadMagic,
+    BadVersion,
+    Truncated,
+}
+
+/// A parsed device tree blob.
+#[derive(Debug, Copy, Clone)]
+pub struct Fdt<'a> {
+    data: &'a [u8],
+    structure: &'a [u8],
+    strings: &'a [u8],
+    reserve_map: &'a [u8],
+}
+
+/// A node of the tree. Properties and children are read lazily from the
+/// structure block.
+#[derive(Debug, Copy, Clone)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset of the node's first property or child token
+    body: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+/// A `(address, size)` pair from a `reg` property or the reserve map.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Region {
+    pub address: u64,
+    pub size: u64,
+}
+
+enum Token<'a> {
+    BeginNode(&'a str),
+    EndNode,
+    Prop(Property<'a>),
+    End,
+}
+
+impl<'a> Fdt<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, FdtError> {
+        let header = |index: usize| be_u32(data, index * 4).ok_or(FdtError::Truncated);
+
+        if header(0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+
+        // Word 6 is the oldest version this blob is backwards compatible with
+        if header(6)? > FDT_LAST_COMPATIBLE_VERSION {
+            return Err(FdtError::BadVersion);
+        }
+
+        let total_size = header(1)? as usize;
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        let section = |offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .and_then(|end| data.get(offset..end))
+                .ok_or(FdtError::Truncated)
+        };
+
+        let (struct_offset, struct_size) = (header(2)? as usize, header(9)? as usize);
+        let (strings_offset, strings_size) = (header(3)? as usize, header(8)? as usize);
+        let reserve_offset = header(4)? as usize;
+
+        Ok(Fdt {
+            data,
+            structure: section(struct_offset, struct_size)?,
+            strings: section(strings_offset, strings_size)?,
+            reserve_map: data.get(reserve_offset..).ok_or(FdtError::Truncated)?,
+        })
+    }
+
+    /// Parse the blob at `ptr`, trusting the size in its header.
+    ///
+    /// # Safety
+    /// `ptr` must point to readable memory holding a device tree blob, which
+    /// must stay valid and unmodified for `'a`.
+    pub unsafe fn from_ptr(ptr: *const u8) -> Result<Self, FdtError> {
+        let header = unsafe { core::slice::from_raw_parts(ptr, FDT_HEADER_SIZE) };
+        if be_u32(header, 0) != Some(FDT_MAGIC) {
+            return Err(FdtError::BadMagic);
+        }
+
+        let total_size = be_u32(header, 4).ok_or(FdtError::Truncated)? as usize;
+        Self::new(unsafe { core::slice::from_raw_parts(ptr, total_size) })
+    }
+
+    /// Size of the whole blob in bytes.
+    pub fn total_size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn root(&self) -> Option<Node<'a>> {
+        let mut offset = 0;
+        loop {
+            match self.token(&mut offset)? {
+                Token::BeginNode(name) => {
+                    return Some(Node {
+                        fdt: *self,
+                        name,
+                        body: offset,
+                    });
+                }
+                Token::End => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Find a node by absolute path, such as `/cpus/cpu@1`. A path component
+    /// without a unit address also matches nodes that have one.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(self.root()?, |node, component| node.child(component))
+    }
+
+    /// Entries of the memory reservation block.
+    pub fn reserved_regions(&self) -> impl Iterator<Item = Region> + 'a {
+        self.reserve_map
+            .chunks_exact(16)
+            .map(|entry| Region {
+                address: be_u64(entry, 0).unwrap_or(0),
+                size: be_u64(entry, 8).unwrap_or(0),
+            })
+            .take_while(|region| region.address != 0 || region.size != 0)
+    }
+
+    fn token(&self, offset: &mut usize) -> Option<Token<'a>> {
+        loop {
+            let token = be_u32(self.structure, *offset)?;
+            *offset += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(self.structure.get(*offset..)?)?;
+                    *offset = align4(*offset + name.len() + 1);
+                    return Some(Token::BeginNode(name));
+                }
+                FDT_END_NODE => return Some(Token::EndNode),
+                FDT_PROP => {
+                    let len = be_u32(self.structure, *offset)? as usize;
+                    let name_offset = be_u32(self.structure, *offset + 4)? as usize;
+                    let start = *offset + 8;
+                    let value = self.structure.get(start..start.checked_add(len)?)?;
+                    *offset = align4(start + len);
+
+                    let name = cstr(self.strings.get(name_offset..)?)?;
+                    return Some(Token::Prop(Property { name, value }));
+                }
+                FDT_NOP => continue,
+                FDT_END => return Some(Token::End),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Skip over the rest of a node whose body starts at `offset`, returning
+    /// the offset just past its end token.
+    fn skip_node(&self, mut offset: usize) -> Option<usize> {
+        let mut depth = 1usize;
+        while depth > 0 {
+            match self.token(&mut offset)? {
+                Token::BeginNode(_) => depth += 1,
+                Token::EndNode => depth -= 1,
+                Token::Prop(_) => {}
+                Token::End => return None,
+            }
+        }
+
+        Some(offset)
+    }
+}
+
+impl<'a> Node<'a> {
+    /// The node name, including any unit address.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The node name without its unit address.
+    pub fn base_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap_or(self.name)
+    }
+
+    pub fn properties(&self) -> impl Iterator<Item = Property<'a>> + 'a {
+        let fdt = self.fdt;
+        let mut offset = self.body;
+        core::iter::from_fn(move || match fdt.token(&mut offset)? {
+            Token::Prop(property) => Some(property),
+            _ => None,
+        })
+    }
+
+    pub fn property(&self, name: &str) -> Option<Property<'a>> {
+        self.properties().find(|property| property.name == name)
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        let fdt = self.fdt;
+        let mut offset = self.body;
+        core::iter::from_fn(move || {
+            loop {
+                match fdt.token(&mut offset)? {
+                    Token::Prop(_) => continue,
+                    Token::BeginNode(name) => {
+                        let child = Node {
+                            fdt,
+                            name,
+                            body: offset,
+                        };
+                        offset = fdt.skip_node(offset)?;
+                        return Some(child);
+                    }
+                    Token::EndNode | Token::End => return None,
+                }
+            }
+        })
+    }
+
+    /// Find a direct child by name. A name without a unit address also
+    /// matches children that have one.
+    pub fn child(&self, name: &str) -> Option<Node<'a>> {
+        self.children()
+            .find(|child| child.name == name || (!name.contains('@') && child.base_name() == name))
+    }
+
+    /// The `#address-cells` and `#size-cells` this node specifies for its
+    /// children, with the spec defaults of 2 and 1.
+    pub fn cell_sizes(&self) -> (usize, usize) {
+        let cells = |name, default| {
+            self.property(name)
+                .and_then(|property| property.as_u32())
+                .map_or(default, |cells| cells as usize)
+        };
+
+        (cells("#address-cells", 2), cells("#size-cells", 1))
+    }
+}
+
+impl<'a> Property<'a> {
+    /// The value as a single nul-terminated string.
+    pub fn as_str(&self) -> Option<&'a str> {
+        cstr(self.value)
+    }
+
+    /// The value as a list of nul-terminated strings.
+    pub fn as_str_list(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.value
+            .split(|&byte| byte == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| core::str::from_utf8(s).ok())
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self.value.len() {
+            4 => be_u32(self.value, 0),
+            _ => None,
+        }
+    }
+
+    /// The value as one 32-bit or 64-bit integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.value.len() {
+            4 => be_u32(self.value, 0).map(u64::from),
+            8 => be_u64(self.value, 0),
+            _ => None,
+        }
+    }
+
+    /// Read the `index`th value of `cells` 32-bit cells (1 or 2).
+    pub fn cells(&self, index: usize, cells: usize) -> Option<u64> {
+        read_cells(self.value, index * cells * 4, cells)
+    }
+
+    /// The value as a `reg`-style list of `(address, size)` pairs.
+    pub fn regions(
+        &self,
+        address_cells: usize,
+        size_cells: usize,
+    ) -> impl Iterator<Item = Region> + 'a {
+        let stride = (address_cells + size_cells) * 4;
+        let value = self.value;
+        (0..value.len().checked_div(stride).unwrap_or(0)).filter_map(move |index| {
+            let offset = index * stride;
+            Some(Region {
+                address: read_cells(value, offset, address_cells)?,
+                size: read_cells(value, offset + address_cells * 4, size_cells)?,
+            })
+        })
+    }
+}
+
+fn read_cells(bytes: &[u8], offset: usize, cells: usize) -> Option<u64> {
+    match cells {
+        0 => Some(0),
+        1 => be_u32(bytes, offset).map(u64::from),
+        2 => be_u64(bytes, offset),
+        _ => None,
+    }
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        bytes.get(offset..offset.checked_add(8)?)?.try_into().ok()?,
+    ))
+}
+
+fn cstr(bytes: &[u8]) -> Option<&str> {
+    let len = bytes.iter().position(|&byte| byte == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a blob from begin/prop/end calls, in the order they appear.
+    struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Builder {
+                structure: Vec::new(),
+                strings: Vec::new(),
+            }
+        }
+
+        fn begin(mut self, name: &str) -> Self {
+            self.structure
+                .extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad()
+        }
+
+        fn prop(mut self, name: &str, value: &[u8]) -> Self {
+            let name_offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+
+            self.structure.extend_from_slice(&FDT_PROP.to_be_bytes());
+            self.structure
+                .extend_from_slice(&(value.len() as u32).to_be_bytes());
+            self.structure.extend_from_slice(&name_offset.to_be_bytes());
+            self.structure.extend_from_slice(value);
+            self.pad()
+        }
+
+        fn end(mut self) -> Self {
+            self.structure
+                .extend_from_slice(&FDT_END_NODE.to_be_bytes());
+            self
+        }
+
+        fn pad(mut self) -> Self {
+            while !self.structure.len().is_multiple_of(4) {
+                self.structure.push(0);
+            }
+            self
+        }
+
+        fn build(mut self, reserved: &[(u64, u64)]) -> Vec<u8> {
+            self.structure.extend_from_slice(&FDT_END.to_be_bytes());
+
+            let reserve_offset = FDT_HEADER_SIZE;
+            let struct_offset = reserve_offset + (reserved.len() + 1) * 16;
+            let strings_offset = struct_offset + self.structure.len();
+            let total_size = strings_offset + self.strings.len();
+
+            let mut blob = Vec::new();
+            for word in [
+                FDT_MAGIC,
+                total_size as u32,
+                struct_offset as u32,
+                strings_offset as u32,
+                reserve_offset as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structure.len() as u32,
+            ] {
+                blob.extend_from_slice(&word.to_be_bytes());
+            }
+
+            for (address, size) in reserved.iter().chain(&[(0, 0)]) {
+                blob.extend_from_slice(&address.to_be_bytes());
+                blob.extend_from_slice(&size.to_be_bytes());
+            }
+
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    fn cells(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+
+    fn sample() -> Vec<u8> {
+        Builder::new()
+            .begin("")
+            .prop("#address-cells", &cells(&[1]))
+            .prop("#size-cells", &cells(&[1]))
+            .begin("memory@0")
+            .prop("reg", &cells(&[0x0, 0x3b00_0000]))
+            .end()
+            .begin("cpus")
+            .prop("#address-cells", &cells(&[1]))
+            .prop("#size-cells", &cells(&[0]))
+            .begin("cpu@0")
+            .prop("reg", &cells(&[0]))
+            .prop("enable-method", b"spin-table\0")
+            .prop("cpu-release-addr", &cells(&[0, 0xd8]))
+            .end()
+            .begin("cpu@1")
+            .prop("reg", &cells(&[1]))
+            .prop("enable-method", b"psci\0")
+            .end()
+            .end()
+            .begin("chosen")
+            .prop("bootargs", b"console=serial0\0")
+            .end()
+            .end()
+            .build(&[(0x1000, 0x2000)])
+    }
+
+    #[test]
+    fn finds_nodes_and_properties() {
+        let blob = sample();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        assert_eq!(fdt.total_size(), blob.len());
+        assert_eq!(
+            fdt.find_node("/chosen")
+                .and_then(|chosen| chosen.property("bootargs"))
+                .and_then(|bootargs| bootargs.as_str()),
+            Some("console=serial0")
+        );
+
+        let cpu = fdt.find_node("/cpus/cpu@0").unwrap();
+        assert_eq!(cpu.base_name(), "cpu");
+        assert_eq!(
+            cpu.property("enable-method").unwrap().as_str(),
+            Some("spin-table")
+        );
+        assert_eq!(
+            cpu.property("cpu-release-addr").unwrap().as_u64(),
+            Some(0xd8)
+        );
+
+        let names: Vec<_> = fdt
+            .find_node("/cpus")
+            .unwrap()
+            .children()
+            .map(|child| child.name())
+            .collect();
+        assert_eq!(names, ["cpu@0", "cpu@1"]);
+
+        assert!(fdt.find_node("/cpus/cpu@2").is_none());
+    }
+
+    #[test]
+    fn reads_regions_with_cell_sizes() {
+        let blob = sample();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        let (address_cells, size_cells) = fdt.root().unwrap().cell_sizes();
+        let memory = fdt.find_node("/memory").unwrap();
+        let regions: Vec<_> = memory
+            .property("reg")
+            .unwrap()
+            .regions(address_cells, size_cells)
+            .collect();
+        assert_eq!(
+            regions,
+            [Region {
+                address: 0,
+                size: 0x3b00_0000
+            }]
+        );
+
+        let reserved: Vec<_> = fdt.reserved_regions().collect();
+        assert_eq!(
+            reserved,
+            [Region {
+                address: 0x1000,
+                size: 0x2000
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_blobs() {
+        let mut blob = sample();
+        assert_eq!(Fdt::new(&blob[..8]).unwrap_err(), FdtError::Truncated);
+        assert_eq!(
+            Fdt::new(&blob[..blob.len() - 1]).unwrap_err(),
+            FdtError::Truncated
+        );
+
+        blob[0] = 0;
+        assert_eq!(Fdt::new(&blob).unwrap_err(), FdtError::BadMagic);
+    }
+}
@@ -0,0 +1,175 @@
+//! The embedded kernel symbol table.
+//!
+//! `ratto-xtask` writes the table into the kernel's reserved `.ksyms` section
+//! after linking, and the kernel reads it back to symbolize addresses.
+//!
+//! Layout, all integers little-endian:
+//!
+//! ```text
+//! Header    magic: [u8; 4], count: u32, base: u64
+//! Entry     offset: u32, size: u32, name_offset: u32, name_len: u32   (x count)
+//! Names     UTF-8 bytes referenced by the entries
+//! ```
+//!
+//! Entries are sorted by `offset`, which is relative to `base`.
+
+use core::fmt::Display;
+
+pub const MAGIC: [u8; 4] = *b"KSYM";
+pub const HEADER_SIZE: usize = 16;
+pub const ENTRY_SIZE: usize = 16;
+
+/// A symbol table parsed from the `.ksyms` section.
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolTable<'a> {
+    base: u64,
+    entries: &'a [u8],
+    names: &'a [u8],
+}
+
+/// An address resolved to the function containing it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub offset: u64,
+}
+
+impl Display for Symbol<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}+{:#x}", self.name, self.offset)
+    }
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Parse a table, returning `None` if the magic is missing (for example,
+    /// when the section was never filled in) or the counts are out of range.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let header = bytes.get(..HEADER_SIZE)?;
+        if header[0..4] != MAGIC {
+            return None;
+        }
+
+        let count = read_u32(header, 4)? as usize;
+        let base = u64::from_le_bytes(header[8..16].try_into().ok()?);
+
+        let entries_end = HEADER_SIZE.checked_add(count.checked_mul(ENTRY_SIZE)?)?;
+        let entries = bytes.get(HEADER_SIZE..entries_end)?;
+        let names = bytes.get(entries_end..)?;
+
+        Some(SymbolTable {
+            base,
+            entries,
+            names,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Find the function containing `addr`.
+    pub fn lookup(&self, addr: u64) -> Option<Symbol<'a>> {
+        let offset = u32::try_from(addr.checked_sub(self.base)?).ok()?;
+
+        // Index of the last entry starting at or before `offset`
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.entry(mid)?.0 <= offset {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let (start, size, name_offset, name_len) = self.entry(lo.checked_sub(1)?)?;
+        if size != 0 && offset - start >= size {
+            return None;
+        }
+
+        let name_start = name_offset as usize;
+        let name = self
+            .names
+            .get(name_start..name_start.checked_add(name_len as usize)?)?;
+
+        Some(Symbol {
+            name: core::str::from_utf8(name).ok()?,
+            offset: (offset - start) as u64,
+        })
+    }
+
+    fn entry(&self, index: usize) -> Option<(u32, u32, u32, u32)> {
+        let entry = self
+            .entries
+            .get(index * ENTRY_SIZE..(index + 1) * ENTRY_SIZE)?;
+
+        Some((
+            read_u32(entry, 0)?,
+            read_u32(entry, 4)?,
+            read_u32(entry, 8)?,
+            read_u32(entry, 12)?,
+        ))
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(base: u64, symbols: &[(u32, u32, &str)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&base.to_le_bytes());
+
+        let mut names = Vec::new();
+        for (offset, size, name) in symbols {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&size.to_le_bytes());
+            bytes.extend_from_slice(&(names.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            names.extend_from_slice(name.as_bytes());
+        }
+
+        bytes.extend_from_slice(&names);
+        bytes
+    }
+
+    #[test]
+    fn lookup_resolves_function_and_offset() {
+        let bytes = table(0x80000, &[(0x0, 0x10, "_start"), (0x40, 0x20, "main")]);
+        let table = SymbolTable::parse(&bytes).unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.lookup(0x80004),
+            Some(Symbol {
+                name: "_start",
+                offset: 4
+            })
+        );
+        assert_eq!(table.lookup(0x8005f).unwrap().to_string(), "main+0x1f");
+
+        // Gaps between functions, and addresses outside the table
+        assert_eq!(table.lookup(0x80010), None);
+        assert_eq!(table.lookup(0x80060), None);
+        assert_eq!(table.lookup(0x7ffff), None);
+    }
+
+    #[test]
+    fn parse_rejects_unfilled_section() {
+        assert!(SymbolTable::parse(&[0u8; 64]).is_none());
+
+        let mut bytes = table(0, &[(0, 4, "f")]);
+        bytes.truncate(HEADER_SIZE + 4);
+        assert!(SymbolTable::parse(&bytes).is_none());
+    }
+}
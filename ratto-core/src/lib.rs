@@ -1,8 +1,18 @@
 #![cfg_attr(not(test), no_std)]
 
 pub mod boot;
+pub mod collections;
+pub mod cpio;
 pub mod cpu;
+pub mod elf;
+#[cfg(not(loom))]
+pub mod executor;
+pub mod fdt;
+pub mod ksyms;
 pub mod mem;
+pub mod percpu;
+pub mod ramfs;
+pub mod sched;
 pub mod sync;
 
 #[cfg(test)]
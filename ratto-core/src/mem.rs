@@ -23,8 +23,34 @@ pub trait MemoryMapper {
         virt: VirtualAddress,
     ) -> Result<PhysicalFrame, &'static str>;
 
+    /// The frame mapped at the page of `virt` in `space`, and how it is
+    /// mapped, if it is.
+    fn translate(
+        &self,
+        space: &Self::AddressSpace,
+        virt: VirtualAddress,
+    ) -> Option<(PhysicalFrame, MapFlags)>;
+
     /// Activate an address space (load page table root).
     unsafe fn activate(&self, space: &Self::AddressSpace);
+
+    /// Stop translating user addresses, which then fault until an address
+    /// space is activated again.
+    ///
+    /// # Safety
+    /// Nothing may still be using addresses of the active address space.
+    unsafe fn deactivate(&self);
+
+    /// Free the page tables of an address space, passing every page still
+    /// mapped in it to `unmapped`.
+    ///
+    /// # Safety
+    /// The address space must not be active on any core.
+    unsafe fn destroy_address_space(
+        &mut self,
+        space: Self::AddressSpace,
+        unmapped: &mut dyn FnMut(VirtualAddress, PhysicalFrame),
+    );
 }
 
 pub trait FrameAllocator {
@@ -38,6 +64,12 @@ pub trait FrameAllocator {
     /// # Safety
     /// Caller must ensure the frame is no longer mapped or in use.
     unsafe fn free_frame(&mut self, frame: PhysicalFrame);
+
+    /// Number of frames the allocator hands out, in use or not.
+    fn total_frames(&self) -> usize;
+
+    /// Number of those frames not in use.
+    fn free_frames(&self) -> usize;
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -52,6 +84,7 @@ pub struct PhysicalAddress(pub u64);
 pub struct VirtualAddress(pub u64);
 
 bitflags::bitflags! {
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
     pub struct MapFlags: u32 {
         const READ    = 1 << 0;
         const WRITE   = 1 << 1;
@@ -0,0 +1,413 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::cpu::CpuOps;
+use crate::sync::lockdep::HeldLocks;
+
+/// Per-core bookkeeping, found through the core's per-CPU base register
+/// (`TPIDR_EL1` on aarch64).
+#[repr(C)]
+#[derive(Debug)]
+pub struct PerCpuArea {
+    cpu_id: usize,
+    preempt_count: AtomicUsize,
+    irq_depth: AtomicUsize,
+    irq_enabled_before: AtomicBool,
+    bh_count: AtomicUsize,
+    pub(crate) held_locks: HeldLocks,
+}
+
+impl PerCpuArea {
+    pub const fn new(cpu_id: usize) -> Self {
+        PerCpuArea {
+            cpu_id,
+            preempt_count: AtomicUsize::new(0),
+            irq_depth: AtomicUsize::new(0),
+            irq_enabled_before: AtomicBool::new(false),
+            bh_count: AtomicUsize::new(0),
+            held_locks: HeldLocks::new(),
+        }
+    }
+
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id
+    }
+
+    /// How many [`PreemptGuard`]s are live on this core.
+    pub fn preempt_count(&self) -> usize {
+        self.preempt_count.load(Ordering::Relaxed)
+    }
+
+    /// How many [`IrqGuard`]s are live on this core.
+    pub fn irq_depth(&self) -> usize {
+        self.irq_depth.load(Ordering::Relaxed)
+    }
+
+    /// How many [`BhGuard`]s are live on this core.
+    pub fn bh_count(&self) -> usize {
+        self.bh_count.load(Ordering::Relaxed)
+    }
+}
+
+/// Index of the executing core, from its per-CPU area if one is installed and
+/// from the hardware otherwise.
+pub fn cpu_id<Cpu: CpuOps>() -> usize {
+    match Cpu::per_cpu_area() {
+        Some(area) => area.cpu_id,
+        None => Cpu::current_cpu_id(),
+    }
+}
+
+/// Whether the executing core may switch tasks right now.
+pub fn preemptible<Cpu: CpuOps>() -> bool {
+    Cpu::per_cpu_area().is_none_or(|area| area.preempt_count() == 0)
+}
+
+/// Disable preemption until the returned guard is dropped. Guards nest.
+pub fn preempt_disable<Cpu: CpuOps>() -> PreemptGuard<Cpu> {
+    // Without interrupts masked the task could move to another core between
+    // finding the area and counting itself in it, and pin the wrong core
+    let irq = irq_save::<Cpu>();
+    let area = Cpu::per_cpu_area();
+    if let Some(area) = area {
+        area.preempt_count.fetch_add(1, Ordering::Relaxed);
+    }
+    drop(irq);
+
+    PreemptGuard {
+        area,
+        _phantom: PhantomData,
+    }
+}
+
+/// Keeps the owning task on the current core. Not `Send`, since it must be
+/// dropped on the core that created it.
+#[must_use]
+pub struct PreemptGuard<Cpu: CpuOps> {
+    area: Option<&'static PerCpuArea>,
+    _phantom: PhantomData<(Cpu, *const ())>,
+}
+
+impl<Cpu: CpuOps> PreemptGuard<Cpu> {
+    /// The core the guard pins the caller to.
+    pub fn cpu_id(&self) -> usize {
+        match self.area {
+            Some(area) => area.cpu_id,
+            None => Cpu::current_cpu_id(),
+        }
+    }
+}
+
+impl<Cpu: CpuOps> Drop for PreemptGuard<Cpu> {
+    fn drop(&mut self) {
+        if let Some(area) = self.area {
+            area.preempt_count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Mask interrupts until the returned guard is dropped.
+///
+/// Guards nest and may be dropped in any order: the core counts live guards
+/// and only restores the interrupt state seen by the outermost one when the
+/// last guard goes away. Before the per-CPU area is installed each guard
+/// restores the state it saw itself, which is only correct in LIFO order.
+pub fn irq_save<Cpu: CpuOps>() -> IrqGuard<Cpu> {
+    let state = Cpu::disable_interrupts();
+    let area = Cpu::per_cpu_area();
+    if let Some(area) = area
+        && area.irq_depth.fetch_add(1, Ordering::Relaxed) == 0
+    {
+        area.irq_enabled_before
+            .store(Cpu::interrupts_were_enabled(state), Ordering::Relaxed);
+    }
+
+    IrqGuard {
+        area,
+        state,
+        _phantom: PhantomData,
+    }
+}
+
+/// Keeps interrupts masked on the core that created it.
+#[must_use]
+pub struct IrqGuard<Cpu: CpuOps> {
+    area: Option<&'static PerCpuArea>,
+    state: Cpu::InterruptState,
+    _phantom: PhantomData<(Cpu, *const ())>,
+}
+
+impl<Cpu: CpuOps> Drop for IrqGuard<Cpu> {
+    fn drop(&mut self) {
+        match self.area {
+            Some(area) => {
+                if area.irq_depth.fetch_sub(1, Ordering::Relaxed) == 1
+                    && area.irq_enabled_before.load(Ordering::Relaxed)
+                {
+                    Cpu::unmask_interrupts();
+                }
+            }
+            None => Cpu::enable_interrupts(self.state),
+        }
+    }
+}
+
+/// Whether softirqs may run on the executing core.
+pub fn bh_enabled<Cpu: CpuOps>() -> bool {
+    Cpu::per_cpu_area().is_none_or(|area| area.bh_count() == 0)
+}
+
+/// Hold off softirqs (bottom halves) and preemption on the executing core
+/// until the returned guard is dropped. Guards nest. Softirqs raised in the
+/// meantime stay pending until the next IRQ exit.
+pub fn bh_disable<Cpu: CpuOps>() -> BhGuard<Cpu> {
+    let preempt = preempt_disable::<Cpu>();
+    let area = Cpu::per_cpu_area();
+    if let Some(area) = area {
+        area.bh_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    BhGuard {
+        area,
+        _preempt: preempt,
+    }
+}
+
+/// Keeps softirqs off on the core that created it.
+#[must_use]
+pub struct BhGuard<Cpu: CpuOps> {
+    area: Option<&'static PerCpuArea>,
+    _preempt: PreemptGuard<Cpu>,
+}
+
+impl<Cpu: CpuOps> Drop for BhGuard<Cpu> {
+    fn drop(&mut self) {
+        if let Some(area) = self.area {
+            area.bh_count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A variable with one instance per core.
+///
+/// The local instance is reached through [`PerCpu::get`], which disables
+/// preemption for as long as the reference is held so that the caller cannot
+/// migrate to another core halfway through. Instances can be shared with
+/// interrupt handlers and remote cores, so mutation goes through interior
+/// mutability (atomics or locks).
+pub struct PerCpu<T, Cpu: CpuOps, const N: usize> {
+    values: [T; N],
+    _phantom: PhantomData<Cpu>,
+}
+
+unsafe impl<T: Sync, Cpu: CpuOps, const N: usize> Sync for PerCpu<T, Cpu, N> {}
+
+impl<T, Cpu: CpuOps, const N: usize> PerCpu<T, Cpu, N> {
+    pub const fn new(values: [T; N]) -> Self {
+        PerCpu {
+            values,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The executing core's instance.
+    pub fn get(&self) -> PerCpuRef<'_, T, Cpu> {
+        let guard = preempt_disable::<Cpu>();
+        PerCpuRef {
+            value: &self.values[guard.cpu_id()],
+            _guard: guard,
+        }
+    }
+
+    pub fn with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.get())
+    }
+
+    /// The instance of another core.
+    pub fn for_cpu(&self, cpu: usize) -> &T {
+        &self.values[cpu]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+}
+
+/// The executing core's instance of a [`PerCpu`], pinned for its lifetime.
+#[must_use]
+pub struct PerCpuRef<'a, T, Cpu: CpuOps> {
+    value: &'a T,
+    _guard: PreemptGuard<Cpu>,
+}
+
+impl<'a, T, Cpu: CpuOps> core::ops::Deref for PerCpuRef<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    #[test]
+    fn each_core_sees_its_own_instance() {
+        static COUNTERS: PerCpu<AtomicUsize, TestCpu, 2> =
+            PerCpu::new([const { AtomicUsize::new(0) }; 2]);
+
+        std::thread::scope(|scope| {
+            for cpu in 0..2 {
+                scope.spawn(move || {
+                    TestCpu::bind(cpu);
+                    for _ in 0..=cpu {
+                        COUNTERS.with(|counter| counter.fetch_add(1, Ordering::Relaxed));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(COUNTERS.for_cpu(0).load(Ordering::Relaxed), 1);
+        assert_eq!(COUNTERS.for_cpu(1).load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn references_disable_preemption() {
+        static VALUES: PerCpu<usize, TestCpu, 2> = PerCpu::new([10, 20]);
+
+        std::thread::spawn(|| {
+            TestCpu::bind(1);
+            let area = TestCpu::per_cpu_area().unwrap();
+            assert!(preemptible::<TestCpu>());
+
+            let value = VALUES.get();
+            assert_eq!(*value, 20);
+            assert_eq!(area.preempt_count(), 1);
+            assert!(!preemptible::<TestCpu>());
+
+            let nested = preempt_disable::<TestCpu>();
+            assert_eq!(area.preempt_count(), 2);
+
+            drop(nested);
+            drop(value);
+            assert!(preemptible::<TestCpu>());
+        })
+        .join()
+        .unwrap();
+    }
+
+    /// [`TestCpu`], recording whether interrupts were masked each time the
+    /// per-CPU area was looked up.
+    struct RecordingCpu;
+
+    thread_local! {
+        static LOOKUPS: std::cell::RefCell<Vec<bool>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl CpuOps for RecordingCpu {
+        type InterruptState = bool;
+
+        fn disable_interrupts() -> bool {
+            TestCpu::disable_interrupts()
+        }
+
+        fn enable_interrupts(state: bool) {
+            TestCpu::enable_interrupts(state)
+        }
+
+        fn interrupts_were_enabled(state: bool) -> bool {
+            TestCpu::interrupts_were_enabled(state)
+        }
+
+        fn unmask_interrupts() -> bool {
+            TestCpu::unmask_interrupts()
+        }
+
+        fn monotonic_ns() -> u64 {
+            TestCpu::monotonic_ns()
+        }
+
+        fn current_cpu_id() -> usize {
+            TestCpu::current_cpu_id()
+        }
+
+        fn per_cpu_area() -> Option<&'static PerCpuArea> {
+            LOOKUPS.with(|lookups| lookups.borrow_mut().push(TestCpu::interrupts_enabled()));
+            TestCpu::per_cpu_area()
+        }
+
+        unsafe fn set_per_cpu_area(area: &'static PerCpuArea) {
+            unsafe { TestCpu::set_per_cpu_area(area) }
+        }
+    }
+
+    #[test]
+    fn preempt_disable_finds_its_core_with_interrupts_masked() {
+        std::thread::spawn(|| {
+            TestCpu::bind(1);
+
+            // An interrupt between the lookup and the count could move the
+            // task, so no lookup may happen with interrupts enabled
+            let guard = preempt_disable::<RecordingCpu>();
+            let lookups = LOOKUPS.with(|lookups| lookups.take());
+            assert!(!lookups.is_empty());
+            assert!(lookups.iter().all(|enabled| !enabled), "{:?}", lookups);
+
+            let area = TestCpu::per_cpu_area().unwrap();
+            assert_eq!(area.preempt_count(), 1);
+            assert_eq!(area.irq_depth(), 0);
+            assert!(TestCpu::interrupts_enabled());
+
+            drop(guard);
+            assert_eq!(area.preempt_count(), 0);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn irq_guards_restore_in_any_order() {
+        TestCpu::bind(0);
+
+        let outer = irq_save::<TestCpu>();
+        let inner = irq_save::<TestCpu>();
+        assert!(!TestCpu::interrupts_enabled());
+
+        drop(outer);
+        assert!(!TestCpu::interrupts_enabled());
+
+        drop(inner);
+        assert!(TestCpu::interrupts_enabled());
+        assert_eq!(TestCpu::per_cpu_area().unwrap().irq_depth(), 0);
+    }
+
+    #[test]
+    fn irq_guards_keep_interrupts_masked_if_they_were() {
+        TestCpu::bind(0);
+        let state = TestCpu::disable_interrupts();
+
+        drop(irq_save::<TestCpu>());
+        assert!(!TestCpu::interrupts_enabled());
+
+        TestCpu::enable_interrupts(state);
+    }
+
+    #[test]
+    fn bh_guards_disable_softirqs_and_preemption() {
+        TestCpu::bind(0);
+
+        let guard = bh_disable::<TestCpu>();
+        assert!(!bh_enabled::<TestCpu>());
+        assert!(!preemptible::<TestCpu>());
+        assert!(TestCpu::interrupts_enabled());
+
+        drop(guard);
+        assert!(bh_enabled::<TestCpu>());
+        assert!(preemptible::<TestCpu>());
+    }
+}
@@ -0,0 +1,237 @@
+//! A read-only, allocation-free filesystem tree over borrowed file contents,
+//! such as the files of an initramfs archive.
+
+use crate::collections::ArrayVec;
+use crate::cpio::Cpio;
+
+/// Index of a node in its [`RamFs`].
+pub type NodeId = usize;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RamFsError {
+    /// There is no room for another node
+    Full,
+    /// A path goes through a file
+    NotADirectory,
+    /// A file and a directory have the same path
+    Exists,
+    /// A path holds `..`
+    BadPath,
+}
+
+impl RamFsError {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            RamFsError::Full => "Too many files",
+            RamFsError::NotADirectory => "Not a directory",
+            RamFsError::Exists => "File exists",
+            RamFsError::BadPath => "Bad path",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NodeKind<'a> {
+    Dir,
+    File(&'a [u8]),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Node<'a> {
+    /// The last path component, empty for the root
+    pub name: &'a str,
+    pub parent: NodeId,
+    pub kind: NodeKind<'a>,
+}
+
+/// A tree of up to `N` nodes, the root included. Paths are `/`-separated,
+/// and relative paths are taken from the root.
+#[derive(Debug, Clone)]
+pub struct RamFs<'a, const N: usize> {
+    nodes: ArrayVec<Node<'a>, N>,
+}
+
+impl<'a, const N: usize> RamFs<'a, N> {
+    pub const ROOT: NodeId = 0;
+
+    /// An empty tree, holding the root directory.
+    pub fn new() -> Self {
+        const { assert!(N > 0) };
+
+        let mut nodes = ArrayVec::new();
+        let root = Node {
+            name: "",
+            parent: Self::ROOT,
+            kind: NodeKind::Dir,
+        };
+        let _ = nodes.push(root);
+        RamFs { nodes }
+    }
+
+    /// The directories and regular files of `cpio`. Other entries, such as
+    /// symlinks and device nodes, are skipped.
+    pub fn from_cpio(cpio: &Cpio<'a>) -> Result<Self, RamFsError> {
+        let mut fs = Self::new();
+        for entry in cpio.entries() {
+            if entry.is_dir() {
+                fs.insert(entry.name, NodeKind::Dir)?;
+            } else if entry.is_file() {
+                fs.insert(entry.name, NodeKind::File(entry.data))?;
+            }
+        }
+
+        Ok(fs)
+    }
+
+    /// Add a node at `path`, creating missing parent directories. A file
+    /// replaces an earlier file at the same path.
+    pub fn insert(&mut self, path: &'a str, kind: NodeKind<'a>) -> Result<NodeId, RamFsError> {
+        let mut components = components(path).peekable();
+        let mut current = Self::ROOT;
+        while let Some(name) = components.next() {
+            if name == ".." {
+                return Err(RamFsError::BadPath);
+            }
+
+            let last = components.peek().is_none();
+            let wanted = if last { kind } else { NodeKind::Dir };
+            current = match (self.child(current, name), wanted) {
+                (Some(id), NodeKind::Dir) if self.nodes[id].kind == NodeKind::Dir => id,
+                (Some(id), NodeKind::File(_)) if last => match self.nodes[id].kind {
+                    NodeKind::File(_) => {
+                        self.nodes[id].kind = wanted;
+                        id
+                    }
+                    NodeKind::Dir => return Err(RamFsError::Exists),
+                },
+                (Some(_), _) if last => return Err(RamFsError::Exists),
+                (Some(_), _) => return Err(RamFsError::NotADirectory),
+                (None, kind) => {
+                    let node = Node {
+                        name,
+                        parent: current,
+                        kind,
+                    };
+                    self.nodes.push(node).map_err(|_| RamFsError::Full)?;
+                    self.nodes.len() - 1
+                }
+            };
+        }
+
+        Ok(current)
+    }
+
+    /// The node at `path`, following `.` and `..`.
+    pub fn lookup(&self, path: &str) -> Option<NodeId> {
+        components(path).try_fold(Self::ROOT, |current, name| match name {
+            ".." => Some(self.nodes[current].parent),
+            _ if self.nodes[current].kind != NodeKind::Dir => None,
+            _ => self.child(current, name),
+        })
+    }
+
+    /// The contents of the file at `path`.
+    pub fn read(&self, path: &str) -> Option<&'a [u8]> {
+        match self.nodes[self.lookup(path)?].kind {
+            NodeKind::File(data) => Some(data),
+            NodeKind::Dir => None,
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<'a> {
+        &self.nodes[id]
+    }
+
+    /// The nodes directly in the directory `id`, in insertion order.
+    pub fn children(&self, id: NodeId) -> impl Iterator<Item = (NodeId, &Node<'a>)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(move |(_, node)| node.parent == id)
+    }
+
+    /// The number of nodes, the root included.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    fn child(&self, parent: NodeId, name: &str) -> Option<NodeId> {
+        self.children(parent)
+            .find(|(_, node)| node.name == name)
+            .map(|(id, _)| id)
+    }
+}
+
+impl<const N: usize> Default for RamFs<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpio::{self, S_IFDIR, S_IFLNK, S_IFREG};
+
+    type Fs<'a> = RamFs<'a, 16>;
+
+    fn names<'a>(fs: &'a Fs, path: &str) -> Vec<&'a str> {
+        let dir = fs.lookup(path).unwrap();
+        fs.children(dir).map(|(_, node)| node.name).collect()
+    }
+
+    #[test]
+    fn builds_tree_from_cpio() {
+        let data = cpio::test::archive(&[
+            (".", S_IFDIR, b""),
+            ("bin", S_IFDIR, b""),
+            ("bin/init", S_IFREG, b"init"),
+            ("./etc/motd", S_IFREG, b"Hello\n"),
+            ("bin/sh", S_IFLNK, b"init"),
+        ]);
+        let cpio = Cpio::new(&data).unwrap();
+        let fs = Fs::from_cpio(&cpio).unwrap();
+
+        assert_eq!(names(&fs, "/"), ["bin", "etc"]);
+        assert_eq!(names(&fs, "bin"), ["init"]);
+        assert_eq!(fs.read("/bin/init"), Some(&b"init"[..]));
+        assert_eq!(fs.read("/etc//motd"), Some(&b"Hello\n"[..]));
+        assert_eq!(fs.read("/bin/../etc/./motd"), Some(&b"Hello\n"[..]));
+        assert_eq!(fs.read("/bin/sh"), None);
+        assert_eq!(fs.read("/bin"), None);
+        assert_eq!(fs.lookup("/.."), Some(Fs::ROOT));
+        assert_eq!(fs.lookup("/bin/init/x"), None);
+    }
+
+    #[test]
+    fn insert_checks_kinds_and_room() {
+        let mut fs = RamFs::<4>::new();
+        assert!(fs.is_empty());
+        let file = fs.insert("a/b", NodeKind::File(b"1")).unwrap();
+        assert_eq!(fs.node(file).parent, fs.lookup("a").unwrap());
+
+        assert_eq!(fs.insert("a/b", NodeKind::File(b"2")), Ok(file));
+        assert_eq!(fs.read("a/b"), Some(&b"2"[..]));
+        assert_eq!(fs.insert("a/b", NodeKind::Dir), Err(RamFsError::Exists));
+        assert_eq!(fs.insert("a", NodeKind::File(b"")), Err(RamFsError::Exists));
+        assert_eq!(
+            fs.insert("a/b/c", NodeKind::Dir),
+            Err(RamFsError::NotADirectory)
+        );
+        assert_eq!(fs.insert("../c", NodeKind::Dir), Err(RamFsError::BadPath));
+
+        fs.insert("c", NodeKind::Dir).unwrap();
+        assert_eq!(fs.insert("d", NodeKind::Dir), Err(RamFsError::Full));
+        assert_eq!(fs.len(), 4);
+    }
+}
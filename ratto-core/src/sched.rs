@@ -0,0 +1,142 @@
+//! Scheduling: the hooks the blocking primitives need ([`SchedOps`]) and
+//! the policies that decide which task runs next ([`Scheduler`]).
+
+use crate::cpu::CpuOps;
+
+mod cpu_mask;
+mod entity;
+mod fair_share;
+mod priority;
+mod round_robin;
+
+pub use cpu_mask::*;
+pub use entity::*;
+pub use fair_share::*;
+pub use priority::*;
+pub use round_robin::*;
+
+/// What the blocking primitives in [`sync`](crate::sync) need from the
+/// scheduler: a handle on the running task and a way to put it to sleep until
+/// another task, core or interrupt handler wakes it.
+///
+/// Parking follows a token protocol, so a wakeup is never lost: [`unpark`]
+/// leaves a token with the task, and [`park`] consumes it, returning at once
+/// if it was already there.
+///
+/// [`park`]: SchedOps::park
+/// [`unpark`]: SchedOps::unpark
+pub trait SchedOps {
+    type Cpu: CpuOps;
+
+    /// A handle that can wake a task, even from another core.
+    type Task: Clone + Send + Sync;
+
+    fn current_task() -> Self::Task;
+
+    /// Block the running task until its wake token is set, then clear it.
+    /// May return spuriously, so callers re-check what they wait for.
+    fn park();
+
+    /// Set the wake token of `task`, making it runnable if it is parked.
+    fn unpark(task: &Self::Task);
+}
+
+/// A scheduling policy: a run queue of [`SchedEntity`]s and the rules for
+/// which one runs next and for how long.
+///
+/// The caller serializes all calls, typically with the run queue lock, and
+/// keeps the running task out of the queue until it stops running.
+pub trait Scheduler<'a> {
+    /// Queue a task that is new or that stopped running while still
+    /// runnable, by yielding or being preempted.
+    fn enqueue(&mut self, task: &'a SchedEntity);
+
+    /// Take the task that should run next off the queue.
+    fn pick_next(&mut self) -> Option<&'a SchedEntity>;
+
+    /// Account a timer tick to the running task `current`, returning
+    /// whether it should be preempted.
+    fn tick(&mut self, current: &'a SchedEntity) -> bool;
+
+    /// Queue a task that was blocked and has been woken.
+    fn task_wakeup(&mut self, task: &'a SchedEntity);
+
+    /// Take the queued task that would wait longest among those `movable`
+    /// accepts, to hand it to another scheduler, which adds it with
+    /// [`enqueue`](Scheduler::enqueue). Used to balance load between cores.
+    fn steal<F>(&mut self, movable: F) -> Option<&'a SchedEntity>
+    where
+        F: FnMut(&'a SchedEntity) -> bool;
+
+    /// Number of queued tasks.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    #[cfg(loom)]
+    use loom::thread;
+    #[cfg(not(loom))]
+    use std::thread;
+
+    use super::SchedOps;
+    #[cfg(not(loom))]
+    use super::{SchedEntity, Scheduler};
+    use crate::cpu::test::TestCpu;
+
+    /// Run `ticks` simulated timer ticks, starting with the first task
+    /// picked and switching whenever the policy preempts. Returns how many
+    /// ticks each of `tasks` ran for.
+    #[cfg(not(loom))]
+    pub fn simulate<'a, S: Scheduler<'a>>(
+        scheduler: &mut S,
+        tasks: &'a [SchedEntity],
+        ticks: usize,
+    ) -> Vec<usize> {
+        let mut ran = vec![0; tasks.len()];
+        let mut current = scheduler.pick_next().expect("Nothing to run");
+        for _ in 0..ticks {
+            ran[index_of(tasks, current)] += 1;
+            if scheduler.tick(current) {
+                scheduler.enqueue(current);
+                current = scheduler.pick_next().unwrap();
+            }
+        }
+
+        ran
+    }
+
+    /// Index of `task` in `tasks`.
+    #[cfg(not(loom))]
+    pub fn index_of(tasks: &[SchedEntity], task: &SchedEntity) -> usize {
+        tasks
+            .iter()
+            .position(|entry| core::ptr::eq(entry, task))
+            .expect("Task is not in the simulation")
+    }
+
+    /// Host stand-in for the scheduler: every thread is a task and parks
+    /// with the standard library's (or loom's) thread parking.
+    pub struct TestSched;
+
+    impl SchedOps for TestSched {
+        type Cpu = TestCpu;
+        type Task = thread::Thread;
+
+        fn current_task() -> Self::Task {
+            thread::current()
+        }
+
+        fn park() {
+            thread::park();
+        }
+
+        fn unpark(task: &Self::Task) {
+            task.unpark();
+        }
+    }
+}
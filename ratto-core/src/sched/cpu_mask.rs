@@ -0,0 +1,170 @@
+use core::fmt::Debug;
+use core::ops::{BitAnd, BitOr, Not};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A set of cores, such as those a task may run on. Core `n` is bit `n`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct CpuMask(u64);
+
+impl CpuMask {
+    /// Most cores a mask can name.
+    pub const CAPACITY: usize = u64::BITS as usize;
+
+    pub const EMPTY: CpuMask = CpuMask(0);
+    pub const ALL: CpuMask = CpuMask(u64::MAX);
+
+    pub const fn from_bits(bits: u64) -> Self {
+        CpuMask(bits)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// The mask holding only `cpu`.
+    pub const fn single(cpu: usize) -> Self {
+        assert!(cpu < Self::CAPACITY, "Core index out of range");
+        CpuMask(1 << cpu)
+    }
+
+    /// The first `count` cores.
+    pub const fn first_n(count: usize) -> Self {
+        match count {
+            0 => Self::EMPTY,
+            count if count >= Self::CAPACITY => Self::ALL,
+            count => CpuMask((1 << count) - 1),
+        }
+    }
+
+    pub const fn contains(self, cpu: usize) -> bool {
+        cpu < Self::CAPACITY && self.0 & (1 << cpu) != 0
+    }
+
+    pub fn insert(&mut self, cpu: usize) {
+        *self = *self | Self::single(cpu);
+    }
+
+    pub fn remove(&mut self, cpu: usize) {
+        *self = *self & !Self::single(cpu);
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn count(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// The lowest-numbered core in the mask.
+    pub const fn first(self) -> Option<usize> {
+        match self.0 {
+            0 => None,
+            bits => Some(bits.trailing_zeros() as usize),
+        }
+    }
+
+    /// The cores in the mask, lowest first.
+    pub fn iter(self) -> impl Iterator<Item = usize> {
+        let mut bits = self.0;
+        core::iter::from_fn(move || {
+            let cpu = CpuMask(bits).first()?;
+            bits &= bits - 1;
+            Some(cpu)
+        })
+    }
+}
+
+impl BitAnd for CpuMask {
+    type Output = CpuMask;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        CpuMask(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for CpuMask {
+    type Output = CpuMask;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        CpuMask(self.0 | rhs.0)
+    }
+}
+
+impl Not for CpuMask {
+    type Output = CpuMask;
+
+    fn not(self) -> Self::Output {
+        CpuMask(!self.0)
+    }
+}
+
+impl Default for CpuMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl Debug for CpuMask {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// A [`CpuMask`] that can be shared between cores.
+#[derive(Debug)]
+pub struct AtomicCpuMask(AtomicU64);
+
+impl AtomicCpuMask {
+    pub const fn new(mask: CpuMask) -> Self {
+        AtomicCpuMask(AtomicU64::new(mask.0))
+    }
+
+    pub fn load(&self) -> CpuMask {
+        CpuMask(self.0.load(Ordering::Acquire))
+    }
+
+    pub fn store(&self, mask: CpuMask) {
+        self.0.store(mask.0, Ordering::Release);
+    }
+}
+
+impl Default for AtomicCpuMask {
+    fn default() -> Self {
+        Self::new(CpuMask::ALL)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_operations() {
+        let mut mask = CpuMask::EMPTY;
+        assert!(mask.is_empty());
+        assert_eq!(mask.first(), None);
+
+        mask.insert(3);
+        mask.insert(0);
+        mask.insert(63);
+        assert!(mask.contains(0) && mask.contains(3) && mask.contains(63));
+        assert!(!mask.contains(1) && !mask.contains(64));
+        assert_eq!(mask.count(), 3);
+        assert_eq!(mask.first(), Some(0));
+        assert_eq!(mask.iter().collect::<Vec<_>>(), [0, 3, 63]);
+
+        mask.remove(0);
+        assert_eq!(mask & CpuMask::first_n(4), CpuMask::single(3));
+        assert_eq!((!mask).count(), 62);
+        assert_eq!(format!("{:?}", mask), "{3, 63}");
+    }
+
+    #[test]
+    fn first_n_covers_the_whole_range() {
+        assert_eq!(CpuMask::first_n(0), CpuMask::EMPTY);
+        assert_eq!(CpuMask::first_n(4).bits(), 0b1111);
+        assert_eq!(CpuMask::first_n(64), CpuMask::ALL);
+        assert_eq!(CpuMask::first_n(100), CpuMask::ALL);
+    }
+}
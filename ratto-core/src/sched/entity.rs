@@ -0,0 +1,97 @@
+use core::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+
+use crate::collections::{KeyAdapter, Link, TreeLink};
+use crate::intrusive_adapter;
+
+/// Number of priority levels, `0` being the most important.
+pub const PRIORITY_LEVELS: usize = 8;
+
+pub const DEFAULT_PRIORITY: u8 = 4;
+
+/// The part of a task the scheduling policies work with.
+///
+/// Apart from the priority, the fields belong to whichever [`Scheduler`]
+/// the entity is queued on, which only touches them under its caller's
+/// lock; they are atomics so that entities can be shared between cores.
+///
+/// [`Scheduler`]: super::Scheduler
+#[derive(Debug)]
+pub struct SchedEntity {
+    pub(super) run_link: Link,
+    pub(super) tree_link: TreeLink,
+    priority: AtomicU8,
+    /// Ticks left of the current time slice
+    pub(super) slice_left: AtomicU32,
+    /// Weighted time run, for [`FairShare`](super::FairShare)
+    pub(super) vruntime: AtomicU64,
+}
+
+intrusive_adapter!(pub(super) RunLinkAdapter = SchedEntity { run_link: Link });
+intrusive_adapter!(pub(super) TreeLinkAdapter = SchedEntity { tree_link: TreeLink });
+
+impl KeyAdapter for TreeLinkAdapter {
+    type Key = u64;
+
+    fn key(entity: &SchedEntity) -> u64 {
+        entity.vruntime()
+    }
+}
+
+impl SchedEntity {
+    pub const fn new() -> Self {
+        SchedEntity {
+            run_link: Link::new(),
+            tree_link: TreeLink::new(),
+            priority: AtomicU8::new(DEFAULT_PRIORITY),
+            slice_left: AtomicU32::new(0),
+            vruntime: AtomicU64::new(0),
+        }
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    /// Change the priority, which takes effect the next time the entity is
+    /// queued.
+    ///
+    /// # Panics
+    /// If `priority` is not below [`PRIORITY_LEVELS`].
+    pub fn set_priority(&self, priority: u8) {
+        assert!(
+            usize::from(priority) < PRIORITY_LEVELS,
+            "Priority {} out of range",
+            priority
+        );
+        self.priority.store(priority, Ordering::Relaxed);
+    }
+
+    /// Forget the scheduling history, for an entity that is reused.
+    pub fn reset(&self) {
+        self.priority.store(DEFAULT_PRIORITY, Ordering::Relaxed);
+        self.slice_left.store(0, Ordering::Relaxed);
+        self.vruntime.store(0, Ordering::Relaxed);
+    }
+
+    pub(super) fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    /// Count down the time slice by one tick, refilling it with `slice`
+    /// ticks if it was empty. Returns whether it has run out.
+    pub(super) fn consume_slice(&self, slice: u32) -> bool {
+        let left = match self.slice_left.load(Ordering::Relaxed) {
+            0 => slice,
+            left => left,
+        };
+
+        self.slice_left.store(left - 1, Ordering::Relaxed);
+        left == 1
+    }
+}
+
+impl Default for SchedEntity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
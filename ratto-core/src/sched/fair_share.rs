@@ -0,0 +1,202 @@
+use core::sync::atomic::Ordering;
+
+use super::{DEFAULT_PRIORITY, PRIORITY_LEVELS, SchedEntity, Scheduler, TreeLinkAdapter};
+use crate::collections::AvlTree;
+
+/// Weight of each priority level: each level gets 1.25 times the CPU time
+/// of the next less important one.
+const WEIGHTS: [u64; PRIORITY_LEVELS] = [2500, 2000, 1600, 1280, 1024, 819, 655, 524];
+
+/// Virtual time a tick is worth to a task at the default priority.
+const TICK_VRUNTIME: u64 = 1 << 20;
+
+/// How far the running task may get ahead of the most deserving waiting
+/// one before it is preempted.
+const GRANULARITY: u64 = TICK_VRUNTIME;
+
+/// How far behind the queue a woken task may be placed, so that tasks that
+/// sleep a lot get some priority without starving the rest.
+const SLEEPER_CREDIT: u64 = 3 * TICK_VRUNTIME;
+
+/// Shares CPU time between tasks in proportion to the weight of their
+/// priority, by always running the task that has had the least weighted
+/// ("virtual") time.
+///
+/// Nothing starves: a less important task runs less, but still runs.
+pub struct FairShare<'a> {
+    tree: AvlTree<'a, TreeLinkAdapter>,
+    /// Lower bound of the queued and running tasks' virtual time, which
+    /// only ever grows
+    min_vruntime: u64,
+}
+
+impl<'a> FairShare<'a> {
+    pub const fn new() -> Self {
+        FairShare {
+            tree: AvlTree::new(),
+            min_vruntime: 0,
+        }
+    }
+
+    fn update_min_vruntime(&mut self, current: Option<&SchedEntity>) {
+        let queued = self.tree.first().map(SchedEntity::vruntime);
+        let running = current.map(SchedEntity::vruntime);
+        let lowest = match (queued, running) {
+            (Some(queued), Some(running)) => queued.min(running),
+            (Some(lowest), None) | (None, Some(lowest)) => lowest,
+            (None, None) => return,
+        };
+
+        self.min_vruntime = self.min_vruntime.max(lowest);
+    }
+
+    /// Queue `task` no earlier than `floor` in virtual time.
+    fn insert(&mut self, task: &'a SchedEntity, floor: u64) {
+        task.vruntime
+            .store(task.vruntime().max(floor), Ordering::Relaxed);
+        self.tree.insert(task);
+    }
+}
+
+impl<'a> Default for FairShare<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Scheduler<'a> for FairShare<'a> {
+    fn enqueue(&mut self, task: &'a SchedEntity) {
+        // A new task starts level with the others rather than at zero
+        self.insert(task, self.min_vruntime);
+    }
+
+    fn pick_next(&mut self) -> Option<&'a SchedEntity> {
+        let task = self.tree.pop_first()?;
+        self.update_min_vruntime(Some(task));
+        Some(task)
+    }
+
+    fn tick(&mut self, current: &'a SchedEntity) -> bool {
+        let weight = WEIGHTS[usize::from(current.priority())];
+        let delta = TICK_VRUNTIME * WEIGHTS[usize::from(DEFAULT_PRIORITY)] / weight;
+        current.vruntime.fetch_add(delta, Ordering::Relaxed);
+        self.update_min_vruntime(Some(current));
+
+        self.tree
+            .first()
+            .is_some_and(|next| current.vruntime() > next.vruntime() + GRANULARITY)
+    }
+
+    fn task_wakeup(&mut self, task: &'a SchedEntity) {
+        self.insert(task, self.min_vruntime.saturating_sub(SLEEPER_CREDIT));
+    }
+
+    fn steal<F>(&mut self, mut movable: F) -> Option<&'a SchedEntity>
+    where
+        F: FnMut(&'a SchedEntity) -> bool,
+    {
+        let task = self.tree.iter().filter(|&task| movable(task)).last()?;
+        self.tree.remove(task);
+
+        // Virtual times of different queues are unrelated, so the task
+        // joins its new queue level with the tasks there
+        task.vruntime.store(0, Ordering::Relaxed);
+        Some(task)
+    }
+
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::{index_of, simulate};
+
+    #[test]
+    fn equal_priorities_get_equal_shares() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        let mut scheduler = FairShare::new();
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        let ran = simulate(&mut scheduler, &tasks, 3000);
+        assert!(
+            ran.iter().all(|&ticks| ticks.abs_diff(1000) <= 3),
+            "{ran:?}"
+        );
+    }
+
+    #[test]
+    fn shares_follow_priority_weights() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        tasks[0].set_priority(2);
+        tasks[2].set_priority(7);
+
+        let mut scheduler = FairShare::new();
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        const TICKS: usize = 10_000;
+        let ran = simulate(&mut scheduler, &tasks, TICKS);
+        let total: u64 = [1600, 1024, 524].iter().sum();
+        for (ticks, weight) in ran.iter().zip([1600, 1024, 524]) {
+            let expected = TICKS as u64 * weight / total;
+            assert!((*ticks as u64).abs_diff(expected) <= 10, "{ran:?}");
+        }
+    }
+
+    #[test]
+    fn woken_sleeper_cannot_monopolize() {
+        let tasks: Vec<_> = (0..2).map(|_| SchedEntity::new()).collect();
+        let mut scheduler = FairShare::new();
+        scheduler.enqueue(&tasks[0]);
+
+        // Task 0 runs alone for a long time while task 1 sleeps
+        let running = scheduler.pick_next().unwrap();
+        for _ in 0..1000 {
+            assert!(!scheduler.tick(running));
+        }
+        scheduler.enqueue(running);
+
+        scheduler.task_wakeup(&tasks[1]);
+        let woken = scheduler.pick_next().unwrap();
+        assert!(core::ptr::eq(woken, &tasks[1]));
+
+        // It gets ahead by its sleeper credit, then they alternate
+        let mut ticks = 0;
+        while !scheduler.tick(woken) {
+            ticks += 1;
+        }
+        assert!(ticks <= (SLEEPER_CREDIT + GRANULARITY) / TICK_VRUNTIME);
+    }
+
+    #[test]
+    fn stolen_task_joins_the_new_queue_level() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        let mut busy = FairShare::new();
+        for task in &tasks[..2] {
+            busy.enqueue(task);
+        }
+        simulate(&mut busy, &tasks[..2], 100);
+
+        let mut other = FairShare::new();
+        other.enqueue(&tasks[2]);
+        let running = other.pick_next().unwrap();
+        for _ in 0..10 {
+            other.tick(running);
+        }
+        other.enqueue(running);
+
+        // The simulation leaves one task running and the other queued
+        let stolen = busy.steal(|_| true).unwrap();
+        assert!(busy.is_empty());
+        assert!(stolen.vruntime() < other.min_vruntime);
+        other.enqueue(stolen);
+        assert_eq!(stolen.vruntime(), other.min_vruntime);
+        assert_eq!(index_of(&tasks, other.pick_next().unwrap()), 2);
+    }
+}
@@ -0,0 +1,169 @@
+use super::{PRIORITY_LEVELS, RunLinkAdapter, SchedEntity, Scheduler};
+use crate::collections::List;
+
+/// Always runs the most important ready task, round-robin within a
+/// priority level. A task becoming ready at a higher priority preempts the
+/// running one at the next tick.
+///
+/// Lower priorities starve for as long as higher ones stay busy.
+pub struct PriorityScheduler<'a> {
+    levels: [List<'a, RunLinkAdapter>; PRIORITY_LEVELS],
+    /// Bit `n` is set when level `n` is not empty
+    ready: u32,
+    len: usize,
+    slice: u32,
+}
+
+impl<'a> PriorityScheduler<'a> {
+    pub const fn new(slice: u32) -> Self {
+        assert!(slice > 0, "Time slice must be at least one tick");
+
+        PriorityScheduler {
+            levels: [const { List::new() }; PRIORITY_LEVELS],
+            ready: 0,
+            len: 0,
+            slice,
+        }
+    }
+
+    /// The most important non-empty level.
+    fn top_level(&self) -> Option<usize> {
+        (self.ready != 0).then(|| self.ready.trailing_zeros() as usize)
+    }
+}
+
+impl<'a> Scheduler<'a> for PriorityScheduler<'a> {
+    fn enqueue(&mut self, task: &'a SchedEntity) {
+        let level = usize::from(task.priority());
+        self.levels[level].push_back(task);
+        self.ready |= 1 << level;
+        self.len += 1;
+    }
+
+    fn pick_next(&mut self) -> Option<&'a SchedEntity> {
+        let level = self.top_level()?;
+        let task = self.levels[level].pop_front();
+        if self.levels[level].is_empty() {
+            self.ready &= !(1 << level);
+        }
+
+        self.len -= 1;
+        task
+    }
+
+    fn tick(&mut self, current: &'a SchedEntity) -> bool {
+        let expired = current.consume_slice(self.slice);
+        let current_level = usize::from(current.priority());
+
+        match self.top_level() {
+            Some(level) if level < current_level => true,
+            Some(level) => level == current_level && expired,
+            None => false,
+        }
+    }
+
+    fn task_wakeup(&mut self, task: &'a SchedEntity) {
+        self.enqueue(task);
+    }
+
+    fn steal<F>(&mut self, mut movable: F) -> Option<&'a SchedEntity>
+    where
+        F: FnMut(&'a SchedEntity) -> bool,
+    {
+        // The least important levels wait longest
+        for level in (0..PRIORITY_LEVELS).rev() {
+            let queue = &mut self.levels[level];
+            let Some(task) = queue.iter().filter(|&task| movable(task)).last() else {
+                continue;
+            };
+
+            queue.remove(task);
+            if queue.is_empty() {
+                self.ready &= !(1 << level);
+            }
+
+            self.len -= 1;
+            return Some(task);
+        }
+
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::{index_of, simulate};
+
+    #[test]
+    fn higher_priority_runs_first() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        tasks[0].set_priority(5);
+        tasks[1].set_priority(1);
+        tasks[2].set_priority(3);
+
+        let mut scheduler = PriorityScheduler::new(1);
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        let order: Vec<_> = core::iter::from_fn(|| scheduler.pick_next())
+            .map(|task| index_of(&tasks, task))
+            .collect();
+        assert_eq!(order, [1, 2, 0]);
+    }
+
+    #[test]
+    fn equal_priorities_share_and_lower_ones_starve() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        tasks[2].set_priority(7);
+
+        let mut scheduler = PriorityScheduler::new(2);
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        assert_eq!(simulate(&mut scheduler, &tasks, 100), [50, 50, 0]);
+    }
+
+    #[test]
+    fn wakeup_at_higher_priority_preempts() {
+        let tasks: Vec<_> = (0..2).map(|_| SchedEntity::new()).collect();
+        tasks[1].set_priority(0);
+
+        let mut scheduler = PriorityScheduler::new(10);
+        scheduler.enqueue(&tasks[0]);
+        let current = scheduler.pick_next().unwrap();
+        assert!(!scheduler.tick(current));
+
+        scheduler.task_wakeup(&tasks[1]);
+        assert!(scheduler.tick(current));
+        scheduler.enqueue(current);
+        assert!(core::ptr::eq(scheduler.pick_next().unwrap(), &tasks[1]));
+    }
+
+    #[test]
+    fn steals_from_the_least_important_level() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        tasks[0].set_priority(6);
+        tasks[1].set_priority(1);
+
+        let mut scheduler = PriorityScheduler::new(1);
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        let stolen = scheduler.steal(|_| true).unwrap();
+        assert_eq!(index_of(&tasks, stolen), 0);
+        let stolen = scheduler.steal(|task| task.priority() < 4).unwrap();
+        assert_eq!(index_of(&tasks, stolen), 1);
+
+        assert_eq!(scheduler.len(), 1);
+        assert_eq!(index_of(&tasks, scheduler.pick_next().unwrap()), 2);
+        assert!(scheduler.pick_next().is_none());
+    }
+}
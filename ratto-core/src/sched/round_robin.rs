@@ -0,0 +1,118 @@
+use super::{RunLinkAdapter, SchedEntity, Scheduler};
+use crate::collections::List;
+
+/// Runs tasks in FIFO order, each for up to `slice` ticks at a time.
+/// Priorities are ignored.
+pub struct RoundRobin<'a> {
+    queue: List<'a, RunLinkAdapter>,
+    slice: u32,
+}
+
+impl<'a> RoundRobin<'a> {
+    pub const fn new(slice: u32) -> Self {
+        assert!(slice > 0, "Time slice must be at least one tick");
+
+        RoundRobin {
+            queue: List::new(),
+            slice,
+        }
+    }
+}
+
+impl<'a> Scheduler<'a> for RoundRobin<'a> {
+    fn enqueue(&mut self, task: &'a SchedEntity) {
+        self.queue.push_back(task);
+    }
+
+    fn pick_next(&mut self) -> Option<&'a SchedEntity> {
+        self.queue.pop_front()
+    }
+
+    fn tick(&mut self, current: &'a SchedEntity) -> bool {
+        current.consume_slice(self.slice) && !self.queue.is_empty()
+    }
+
+    fn task_wakeup(&mut self, task: &'a SchedEntity) {
+        self.enqueue(task);
+    }
+
+    fn steal<F>(&mut self, mut movable: F) -> Option<&'a SchedEntity>
+    where
+        F: FnMut(&'a SchedEntity) -> bool,
+    {
+        let task = self.queue.iter().filter(|&task| movable(task)).last()?;
+        self.queue.remove(task);
+        Some(task)
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::{index_of, simulate};
+
+    #[test]
+    fn tasks_take_turns_by_slice() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        let mut scheduler = RoundRobin::new(2);
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        let mut current = scheduler.pick_next().unwrap();
+        let mut order = Vec::new();
+        for _ in 0..12 {
+            order.push(index_of(&tasks, current));
+            if scheduler.tick(current) {
+                scheduler.enqueue(current);
+                current = scheduler.pick_next().unwrap();
+            }
+        }
+
+        assert_eq!(order, [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn lone_task_is_not_preempted() {
+        let tasks = [SchedEntity::new()];
+        let mut scheduler = RoundRobin::new(1);
+        scheduler.enqueue(&tasks[0]);
+
+        assert_eq!(simulate(&mut scheduler, &tasks, 10), [10]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn shares_are_equal_regardless_of_priority() {
+        let tasks: Vec<_> = (0..4).map(|_| SchedEntity::new()).collect();
+        tasks[0].set_priority(0);
+        let mut scheduler = RoundRobin::new(3);
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        assert_eq!(simulate(&mut scheduler, &tasks, 1200), [300; 4]);
+    }
+
+    #[test]
+    fn steals_the_last_movable_task() {
+        let tasks: Vec<_> = (0..3).map(|_| SchedEntity::new()).collect();
+        let mut scheduler = RoundRobin::new(1);
+        for task in &tasks {
+            scheduler.enqueue(task);
+        }
+
+        let stolen = scheduler.steal(|task| !core::ptr::eq(task, &tasks[2]));
+        assert_eq!(index_of(&tasks, stolen.unwrap()), 1);
+        assert!(scheduler.steal(|_| false).is_none());
+
+        let left: Vec<_> = core::iter::from_fn(|| scheduler.pick_next())
+            .map(|task| index_of(&tasks, task))
+            .collect();
+        assert_eq!(left, [0, 2]);
+    }
+}
@@ -1,5 +1,44 @@
+/// Define a `const fn` that is a plain `fn` under `cfg(loom)`, whose atomics
+/// cannot be built in a const context.
+macro_rules! loom_const_fn {
+    ($(#[$attr:meta])* $vis:vis const fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])*
+        $vis const fn $($rest)*
+
+        #[cfg(loom)]
+        $(#[$attr])*
+        $vis fn $($rest)*
+    };
+}
+
+mod atomic;
+mod cell;
+mod channel;
+mod condvar;
+mod event;
+pub mod lockdep;
+mod mcs_lock;
+mod mpsc;
+mod mutex;
 mod once_lock;
+mod rw_spin_lock;
+mod semaphore;
 mod spin_lock;
+mod spsc;
+mod ticket_lock;
+mod wait_queue;
 
+pub use channel::*;
+pub use condvar::*;
+pub use event::*;
+pub use mcs_lock::*;
+pub use mpsc::*;
+pub use mutex::*;
 pub use once_lock::*;
+pub use rw_spin_lock::*;
+pub use semaphore::*;
 pub use spin_lock::*;
+pub use spsc::*;
+pub use ticket_lock::*;
+pub use wait_queue::*;
@@ -0,0 +1,13 @@
+//! The atomics and spin hint used by the primitives in this module. Under
+//! `cfg(loom)` they are loom's, so that model tests can explore every
+//! interleaving and memory ordering the primitives allow.
+
+#[cfg(not(loom))]
+pub(crate) use core::hint::spin_loop;
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+
+#[cfg(loom)]
+pub(crate) use loom::hint::spin_loop;
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
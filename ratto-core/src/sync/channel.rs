@@ -0,0 +1,185 @@
+use super::{MpscConsumer, MpscRing, WaitQueue};
+use crate::sched::SchedOps;
+
+/// A bounded channel of capacity `N` (a power of two, at least 2) with any number of
+/// senders and one [`Receiver`].
+///
+/// Blocking calls sleep through [`SchedOps`]; the `try_` variants never do,
+/// so interrupt handlers can use [`try_send`](Channel::try_send).
+pub struct Channel<T, S: SchedOps, const N: usize> {
+    ring: MpscRing<T, N>,
+    not_empty: WaitQueue<S>,
+    not_full: WaitQueue<S>,
+}
+
+impl<T, S: SchedOps, const N: usize> Channel<T, S, N> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new() -> Self {
+            Channel {
+                ring: MpscRing::new(),
+                not_empty: WaitQueue::new(),
+                not_full: WaitQueue::new(),
+            }
+        }
+    }
+
+    /// Send `value`, sleeping while the channel is full.
+    pub fn send(&self, mut value: T) {
+        loop {
+            match self.try_send(value) {
+                Ok(()) => return,
+                Err(rejected) => value = rejected,
+            }
+            self.not_full.wait_until(|| !self.ring.is_full());
+        }
+    }
+
+    /// Send `value` unless the channel is full, in which case it is handed
+    /// back.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.ring.push(value)?;
+        self.not_empty.wake_one();
+        Ok(())
+    }
+
+    /// The receiving end, unless it is already taken.
+    pub fn receiver(&self) -> Option<Receiver<'_, T, S, N>> {
+        Some(Receiver {
+            consumer: self.ring.consumer()?,
+            channel: self,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+impl<T, S: SchedOps, const N: usize> Default for Channel<T, S, N> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The receiving end of a [`Channel`]; dropping it frees the end again.
+pub struct Receiver<'a, T, S: SchedOps, const N: usize> {
+    consumer: MpscConsumer<'a, T, N>,
+    channel: &'a Channel<T, S, N>,
+}
+
+impl<'a, T, S: SchedOps, const N: usize> Receiver<'a, T, S, N> {
+    /// Receive the next value, sleeping while the channel is empty.
+    pub fn recv(&mut self) -> T {
+        loop {
+            if let Some(value) = self.try_recv() {
+                return value;
+            }
+            self.channel
+                .not_empty
+                .wait_until(|| self.channel.ring.is_ready());
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        let value = self.consumer.pop()?;
+        self.channel.not_full.wake_one();
+        Some(value)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn try_variants_never_block() {
+        let channel = Channel::<u8, TestSched, 2>::new();
+        let mut receiver = channel.receiver().unwrap();
+        assert!(channel.receiver().is_none());
+
+        assert_eq!(receiver.try_recv(), None);
+        channel.try_send(1).unwrap();
+        channel.try_send(2).unwrap();
+        assert_eq!(channel.try_send(3), Err(3));
+        assert_eq!(receiver.recv(), 1);
+        assert_eq!(receiver.try_recv(), Some(2));
+    }
+
+    #[test]
+    fn senders_block_until_received() {
+        const SENDERS: usize = 3;
+        const ITEMS: usize = 2_000;
+
+        let channel = Channel::<usize, TestSched, 4>::new();
+        std::thread::scope(|scope| {
+            for _ in 0..SENDERS {
+                scope.spawn(|| {
+                    for value in 1..=ITEMS {
+                        channel.send(value);
+                    }
+                });
+            }
+
+            let mut receiver = channel.receiver().unwrap();
+            let sum: usize = (0..SENDERS * ITEMS).map(|_| receiver.recv()).sum();
+            assert_eq!(sum, SENDERS * ITEMS * (ITEMS + 1) / 2);
+        });
+
+        assert!(channel.is_empty());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn blocked_sender_is_woken() {
+        // Three receives through two wait queues; bound the search so it
+        // finishes in seconds
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(|| {
+            let channel = Arc::new(Channel::<usize, TestSched, 2>::new());
+            channel.try_send(1).unwrap();
+            channel.try_send(2).unwrap();
+
+            let sender = {
+                let channel = channel.clone();
+                thread::spawn(move || channel.send(3))
+            };
+
+            let mut receiver = channel.receiver().unwrap();
+            for value in 1..=3 {
+                assert_eq!(receiver.recv(), value);
+            }
+            sender.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn blocked_receiver_is_woken() {
+        loom::model(|| {
+            let channel = Arc::new(Channel::<usize, TestSched, 2>::new());
+
+            let sender = {
+                let channel = channel.clone();
+                thread::spawn(move || channel.send(1))
+            };
+
+            assert_eq!(channel.receiver().unwrap().recv(), 1);
+            sender.join().unwrap();
+        });
+    }
+}
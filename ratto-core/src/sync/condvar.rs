@@ -0,0 +1,156 @@
+use super::atomic::{AtomicUsize, Ordering};
+use super::{MutexGuard, WaitQueue};
+use crate::sched::SchedOps;
+
+/// A condition variable for [`Mutex`](super::Mutex)-protected state.
+///
+/// Waits may end spuriously, so callers re-check their condition in a loop
+/// or use [`wait_while`](Condvar::wait_while).
+pub struct Condvar<S: SchedOps> {
+    /// Bumped by every notification, so that a waiter can tell whether one
+    /// arrived after it let go of the mutex.
+    generation: AtomicUsize,
+    waiters: WaitQueue<S>,
+}
+
+impl<S: SchedOps> Condvar<S> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new() -> Self {
+            Condvar {
+                generation: AtomicUsize::new(0),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    /// Unlock the mutex, sleep until notified, then lock it again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T, S>) -> MutexGuard<'a, T, S> {
+        let mutex = guard.mutex;
+        let generation = self.generation.load(Ordering::Relaxed);
+        drop(guard);
+
+        self.waiters
+            .wait_until(|| self.generation.load(Ordering::Relaxed) != generation);
+        mutex.lock()
+    }
+
+    /// Wait for as long as `condition` holds on the protected value.
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T, S>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T, S>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.waiters.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.waiters.wake_all();
+    }
+}
+
+impl<S: SchedOps> Default for Condvar<S> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::TestSched;
+    use crate::sync::Mutex;
+
+    #[test]
+    fn notify_all_wakes_every_waiter() {
+        let state = Mutex::<(bool, usize), TestSched>::new((false, 0));
+        let condvar = Condvar::<TestSched>::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| {
+                    let mut guard = condvar.wait_while(state.lock(), |(ready, _)| !*ready);
+                    guard.1 += 1;
+                });
+            }
+
+            state.lock().0 = true;
+            condvar.notify_all();
+        });
+
+        assert_eq!(state.lock().1, 3);
+    }
+
+    #[test]
+    fn handoff_with_notify_one() {
+        let slot = Mutex::<Option<u32>, TestSched>::new(None);
+        let condvar = Condvar::<TestSched>::new();
+
+        std::thread::scope(|scope| {
+            let consumer = scope.spawn(|| {
+                let mut received = Vec::new();
+                while received.len() < 100 {
+                    let mut guard = condvar.wait_while(slot.lock(), |slot| slot.is_none());
+                    received.push(guard.take().unwrap());
+                    condvar.notify_one();
+                }
+                received
+            });
+
+            for value in 0..100 {
+                let mut guard = condvar.wait_while(slot.lock(), |slot| slot.is_some());
+                *guard = Some(value);
+                drop(guard);
+                condvar.notify_one();
+            }
+
+            assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+        });
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+    use crate::sync::Mutex;
+
+    #[test]
+    fn notification_is_not_lost() {
+        loom::model(|| {
+            let shared = Arc::new((
+                Mutex::<bool, TestSched>::new(false),
+                Condvar::<TestSched>::new(),
+            ));
+
+            let notifier = {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    *shared.0.lock() = true;
+                    shared.1.notify_one();
+                })
+            };
+
+            let guard = shared.1.wait_while(shared.0.lock(), |ready| !*ready);
+            assert!(*guard);
+            drop(guard);
+            notifier.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,224 @@
+use super::WaitQueue;
+use super::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::sched::SchedOps;
+
+/// A flag tasks can sleep on. Once [`set`](Event::set) it releases every
+/// waiter, present and future, until [`reset`](Event::reset).
+pub struct Event<S: SchedOps> {
+    set: AtomicBool,
+    waiters: WaitQueue<S>,
+}
+
+impl<S: SchedOps> Event<S> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new() -> Self {
+            Event {
+                set: AtomicBool::new(false),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    pub fn wait(&self) {
+        if !self.is_set() {
+            self.waiters.wait_until(|| self.is_set());
+        }
+    }
+
+    /// Set the flag and wake all waiters. Safe from interrupt handlers.
+    pub fn set(&self) {
+        self.set.store(true, Ordering::Release);
+        self.waiters.wake_all();
+    }
+
+    pub fn reset(&self) {
+        self.set.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.set.load(Ordering::Acquire)
+    }
+}
+
+impl<S: SchedOps> Default for Event<S> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Marks completions of some piece of work, such as a device request.
+///
+/// Each [`complete`](Completion::complete) lets one [`wait`](Completion::wait)
+/// through, whether it comes before or after, and
+/// [`complete_all`](Completion::complete_all) lets every wait through for good.
+pub struct Completion<S: SchedOps> {
+    /// Completions not yet consumed, or `ALL`.
+    done: AtomicUsize,
+    waiters: WaitQueue<S>,
+}
+
+impl<S: SchedOps> Completion<S> {
+    const ALL: usize = usize::MAX;
+
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new() -> Self {
+            Completion {
+                done: AtomicUsize::new(0),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    pub fn wait(&self) {
+        if !self.try_wait() {
+            self.waiters.wait_until(|| self.try_wait());
+        }
+    }
+
+    /// Consume a completion if one is pending, without sleeping.
+    pub fn try_wait(&self) -> bool {
+        self.done
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |done| match done {
+                0 => None,
+                Self::ALL => Some(Self::ALL),
+                done => Some(done - 1),
+            })
+            .is_ok()
+    }
+
+    /// Record one completion and wake one waiter. Safe from interrupt
+    /// handlers.
+    pub fn complete(&self) {
+        let _ = self
+            .done
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |done| {
+                (done != Self::ALL).then(|| done + 1)
+            });
+        self.waiters.wake_one();
+    }
+
+    /// Let every current and future waiter through. Safe from interrupt
+    /// handlers.
+    pub fn complete_all(&self) {
+        self.done.store(Self::ALL, Ordering::Release);
+        self.waiters.wake_all();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Relaxed) != 0
+    }
+
+    /// Forget all completions, so the object can be reused.
+    pub fn reset(&self) {
+        self.done.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<S: SchedOps> Default for Completion<S> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn event_releases_all_until_reset() {
+        let event = Event::<TestSched>::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| event.wait());
+            }
+            event.set();
+        });
+
+        event.wait();
+        event.reset();
+        assert!(!event.is_set());
+    }
+
+    #[test]
+    fn each_completion_releases_one_wait() {
+        let completion = Completion::<TestSched>::new();
+
+        completion.complete();
+        completion.complete();
+        assert!(completion.try_wait());
+        completion.wait();
+        assert!(!completion.try_wait());
+
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| completion.wait());
+            completion.complete();
+            waiter.join().unwrap();
+        });
+        assert!(!completion.is_done());
+    }
+
+    #[test]
+    fn complete_all_is_sticky() {
+        let completion = Completion::<TestSched>::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| completion.wait());
+            }
+            completion.complete_all();
+        });
+
+        completion.complete();
+        assert!(completion.try_wait());
+        assert!(completion.try_wait());
+
+        completion.reset();
+        assert!(!completion.is_done());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn completion_wakes_its_waiter() {
+        loom::model(|| {
+            let completion = Arc::new(Completion::<TestSched>::new());
+
+            let worker = {
+                let completion = completion.clone();
+                thread::spawn(move || completion.complete())
+            };
+
+            completion.wait();
+            worker.join().unwrap();
+            assert!(!completion.is_done());
+        });
+    }
+
+    #[test]
+    fn event_wakes_its_waiter() {
+        loom::model(|| {
+            let event = Arc::new(Event::<TestSched>::new());
+
+            let setter = {
+                let event = event.clone();
+                thread::spawn(move || event.set())
+            };
+
+            event.wait();
+            setter.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,434 @@
+//! Lock debugging for debug builds.
+//!
+//! Every [`RawSpinLock`](super::RawSpinLock) (and so every `SpinLockIrq` and
+//! `SpinLockBh`) records the core and call site that holds it. Taking a lock
+//! the core already holds panics instead of spinning forever. Locks are
+//! grouped into classes by the call site that created them, and the order in
+//! which each core nests classes is kept in a global graph: taking `B` while
+//! holding `A` after `A` was once taken while holding `B` is reported as a
+//! potential deadlock, even if the two paths never actually raced. Locks held
+//! longer than [`HOLD_TIME_WARNING_NS`] are reported when released.
+//!
+//! Reports go to the function installed with [`set_reporter`]. Tracking only
+//! happens on cores with a per-CPU area, and compiles away in release builds.
+
+#![cfg_attr(not(debug_assertions), allow(dead_code))]
+
+use core::fmt;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use crate::cpu::CpuOps;
+
+/// Locks held longer than this are reported on release.
+pub const HOLD_TIME_WARNING_NS: u64 = 10_000_000;
+
+/// Lock classes tracked in the order graph. Classes beyond this go unchecked,
+/// which is reported once.
+const MAX_CLASSES: usize = 64;
+
+/// Locks a core can hold at once and still have their order checked.
+const MAX_HELD: usize = 8;
+
+const NO_CLASS: usize = usize::MAX;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static REPORTER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static REPORTING: AtomicBool = AtomicBool::new(false);
+static CLASSES_FULL: AtomicBool = AtomicBool::new(false);
+
+/// Creation sites of the known classes, in registration order.
+static CLASSES: [AtomicPtr<Location<'static>>; MAX_CLASSES] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CLASSES];
+
+/// `ORDER[a]` has bit `b` set once class `b` was taken while holding `a`.
+static ORDER: [AtomicU64; MAX_CLASSES] = [const { AtomicU64::new(0) }; MAX_CLASSES];
+
+/// Install the function that prints lock debugging reports.
+pub fn set_reporter(reporter: fn(fmt::Arguments)) {
+    REPORTER.store(reporter as *mut (), Ordering::Release);
+}
+
+/// Stop all lock debugging, for example once the kernel is panicking and
+/// only wants to get its message out.
+pub fn turn_off() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    cfg!(debug_assertions) && ENABLED.load(Ordering::Relaxed)
+}
+
+fn report(args: fmt::Arguments) {
+    let reporter = REPORTER.load(Ordering::Acquire);
+    // Printing takes locks of its own, which must not report recursively
+    if reporter.is_null() || REPORTING.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    let reporter: fn(fmt::Arguments) = unsafe { core::mem::transmute(reporter) };
+    reporter(args);
+    REPORTING.store(false, Ordering::Release);
+}
+
+/// Index of the class created at `site`, registering it on first use.
+fn class_index(site: &'static Location<'static>) -> Option<usize> {
+    let site_ptr = site as *const Location as *mut Location;
+    for (index, slot) in CLASSES.iter().enumerate() {
+        let current = slot.load(Ordering::Acquire);
+        let current = if current.is_null() {
+            match slot.compare_exchange(current, site_ptr, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Some(index),
+                Err(other) => other,
+            }
+        } else {
+            current
+        };
+
+        // The same call site may have several `Location`s after inlining
+        if current == site_ptr || unsafe { *current == *site } {
+            return Some(index);
+        }
+    }
+
+    if !CLASSES_FULL.swap(true, Ordering::Relaxed) {
+        report(format_args!(
+            "More than {} lock classes: locks created at {} and other new sites go unchecked",
+            MAX_CLASSES, site
+        ));
+    }
+    None
+}
+
+/// Whether the order graph has a path from class `from` to class `to`.
+fn reaches(from: usize, to: usize) -> bool {
+    let mut visited = 0u64;
+    let mut frontier = ORDER[from].load(Ordering::Relaxed);
+    while frontier & !visited != 0 {
+        if frontier & (1 << to) != 0 {
+            return true;
+        }
+
+        let mut next = 0;
+        let mut bits = frontier & !visited;
+        while bits != 0 {
+            let class = bits.trailing_zeros() as usize;
+            next |= ORDER[class].load(Ordering::Relaxed);
+            bits &= bits - 1;
+        }
+
+        visited |= frontier;
+        frontier = next;
+    }
+
+    false
+}
+
+fn class_site(class: usize) -> &'static Location<'static> {
+    unsafe { &*CLASSES[class].load(Ordering::Acquire) }
+}
+
+/// Classes held by one core, innermost last. Lives in the per-CPU area.
+#[derive(Debug)]
+pub(crate) struct HeldLocks {
+    count: AtomicUsize,
+    classes: [AtomicUsize; MAX_HELD],
+}
+
+impl HeldLocks {
+    pub(crate) const fn new() -> Self {
+        HeldLocks {
+            count: AtomicUsize::new(0),
+            classes: [const { AtomicUsize::new(NO_CLASS) }; MAX_HELD],
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let count = self.count.load(Ordering::Relaxed).min(MAX_HELD);
+        self.classes[..count]
+            .iter()
+            .map(|class| class.load(Ordering::Relaxed))
+    }
+
+    fn push(&self, class: usize) {
+        let count = self.count.fetch_add(1, Ordering::Relaxed);
+        if count < MAX_HELD {
+            self.classes[count].store(class, Ordering::Relaxed);
+        }
+    }
+
+    /// Remove the innermost entry for `class`; locks need not be released
+    /// in the order they were taken.
+    fn remove(&self, class: usize) {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return;
+        }
+
+        let tracked = count.min(MAX_HELD);
+        if let Some(position) = self.classes[..tracked]
+            .iter()
+            .rposition(|held| held.load(Ordering::Relaxed) == class)
+        {
+            for index in position..tracked - 1 {
+                let next = self.classes[index + 1].load(Ordering::Relaxed);
+                self.classes[index].store(next, Ordering::Relaxed);
+            }
+            self.classes[tracked - 1].store(NO_CLASS, Ordering::Relaxed);
+        }
+
+        self.count.store(count - 1, Ordering::Relaxed);
+    }
+}
+
+/// Per-lock debugging state. Empty in release builds.
+#[derive(Debug)]
+pub(crate) struct LockDebug {
+    #[cfg(debug_assertions)]
+    site: &'static Location<'static>,
+    /// Index of the holding core plus one, or zero when free.
+    #[cfg(debug_assertions)]
+    owner: AtomicUsize,
+    #[cfg(debug_assertions)]
+    owner_location: AtomicPtr<Location<'static>>,
+    #[cfg(debug_assertions)]
+    acquired_at: AtomicU64,
+}
+
+#[cfg(debug_assertions)]
+impl LockDebug {
+    #[track_caller]
+    pub(crate) const fn new() -> Self {
+        LockDebug {
+            site: Location::caller(),
+            owner: AtomicUsize::new(0),
+            owner_location: AtomicPtr::new(core::ptr::null_mut()),
+            acquired_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Check a blocking acquisition from `caller` before it starts spinning.
+    pub(crate) fn before_lock<Cpu: CpuOps>(&self, caller: &'static Location<'static>) {
+        let Some(area) = Cpu::per_cpu_area() else {
+            return;
+        };
+        if !is_enabled() {
+            return;
+        }
+
+        if self.owner.load(Ordering::Relaxed) == area.cpu_id() + 1 {
+            let holder = self.owner_location.load(Ordering::Relaxed);
+            match unsafe { holder.as_ref() } {
+                Some(holder) => panic!(
+                    "Recursive acquisition of lock created at {} on core {}: taken at {}, already held from {}",
+                    self.site,
+                    area.cpu_id(),
+                    caller,
+                    holder
+                ),
+                None => panic!(
+                    "Recursive acquisition of lock created at {} on core {}: taken at {}",
+                    self.site,
+                    area.cpu_id(),
+                    caller
+                ),
+            }
+        }
+
+        let Some(class) = class_index(self.site) else {
+            return;
+        };
+
+        for held in area.held_locks.iter() {
+            if held == class || held == NO_CLASS {
+                continue;
+            }
+
+            let edge = 1 << class;
+            let known = ORDER[held].fetch_or(edge, Ordering::Relaxed) & edge != 0;
+            if !known && reaches(class, held) {
+                report(format_args!(
+                    "Possible deadlock: lock created at {} taken at {} while holding lock created at {}, which was previously taken while holding it",
+                    self.site,
+                    caller,
+                    class_site(held)
+                ));
+            }
+        }
+    }
+
+    /// Record that the executing core now holds the lock.
+    pub(crate) fn locked<Cpu: CpuOps>(&self, caller: &'static Location<'static>) {
+        let Some(area) = Cpu::per_cpu_area() else {
+            return;
+        };
+        if !is_enabled() {
+            return;
+        }
+
+        self.owner.store(area.cpu_id() + 1, Ordering::Relaxed);
+        self.owner_location.store(
+            caller as *const Location as *mut Location,
+            Ordering::Relaxed,
+        );
+        self.acquired_at
+            .store(Cpu::monotonic_ns(), Ordering::Relaxed);
+        area.held_locks
+            .push(class_index(self.site).unwrap_or(NO_CLASS));
+    }
+
+    /// Record the release of the lock, just before it is unlocked. A long
+    /// hold is only reported by [`LongHold::report`] once the lock is free,
+    /// since the reporter may need the very same lock to print.
+    pub(crate) fn unlocking<Cpu: CpuOps>(&self) -> Option<LongHold> {
+        if self.owner.swap(0, Ordering::Relaxed) == 0 {
+            return None;
+        }
+
+        if let Some(area) = Cpu::per_cpu_area() {
+            area.held_locks
+                .remove(class_index(self.site).unwrap_or(NO_CLASS));
+        }
+
+        let held_ns = Cpu::monotonic_ns().saturating_sub(self.acquired_at.load(Ordering::Relaxed));
+        let holder = unsafe { self.owner_location.load(Ordering::Relaxed).as_ref()? };
+        (held_ns > HOLD_TIME_WARNING_NS && is_enabled()).then_some(LongHold {
+            site: self.site,
+            holder,
+            held_ns,
+        })
+    }
+}
+
+/// A lock that was held for too long, to be reported after its release.
+pub(crate) struct LongHold {
+    site: &'static Location<'static>,
+    holder: &'static Location<'static>,
+    held_ns: u64,
+}
+
+impl LongHold {
+    pub(crate) fn report(self) {
+        report(format_args!(
+            "Lock created at {} held for {} us from {}",
+            self.site,
+            self.held_ns / 1000,
+            self.holder
+        ));
+    }
+}
+
+#[cfg(not(debug_assertions))]
+#[allow(clippy::extra_unused_type_parameters)]
+impl LockDebug {
+    #[track_caller]
+    pub(crate) const fn new() -> Self {
+        LockDebug {}
+    }
+
+    #[inline(always)]
+    pub(crate) fn before_lock<Cpu: CpuOps>(&self, _caller: &'static Location<'static>) {}
+
+    #[inline(always)]
+    pub(crate) fn locked<Cpu: CpuOps>(&self, _caller: &'static Location<'static>) {}
+
+    #[inline(always)]
+    pub(crate) fn unlocking<Cpu: CpuOps>(&self) -> Option<LongHold> {
+        None
+    }
+}
+
+#[cfg(all(test, debug_assertions, not(loom)))]
+mod test {
+    use std::string::{String, ToString};
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    use crate::cpu::test::TestCpu;
+    use crate::sync::RawSpinLock;
+
+    static REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn capture_reports() {
+        super::set_reporter(|args| REPORTS.lock().unwrap().push(args.to_string()));
+    }
+
+    fn reported(site: u32) -> Vec<String> {
+        let site = std::format!("{}:{}:", file!(), site);
+        REPORTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|report| report.contains(&site))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    #[should_panic(expected = "Recursive acquisition")]
+    fn recursive_acquisition_panics() {
+        TestCpu::bind(0);
+        let lock = RawSpinLock::<(), TestCpu>::new(());
+
+        let _outer = lock.lock();
+        let _inner = lock.lock();
+    }
+
+    #[test]
+    fn other_cores_may_wait_for_the_lock() {
+        TestCpu::bind(0);
+        let lock = RawSpinLock::<u32, TestCpu>::new(0);
+
+        let guard = lock.lock();
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                TestCpu::bind(1);
+                lock.lock_with(|value| *value += 1);
+            });
+
+            std::thread::yield_now();
+            drop(guard);
+            waiter.join().unwrap();
+        });
+
+        assert_eq!(*lock.lock(), 1);
+    }
+
+    #[test]
+    fn order_inversion_is_reported() {
+        capture_reports();
+        TestCpu::bind(0);
+        let (first, first_site) = (RawSpinLock::<(), TestCpu>::new(()), line!());
+        let (second, second_site) = (RawSpinLock::<(), TestCpu>::new(()), line!());
+
+        {
+            let _first = first.lock();
+            let _second = second.lock();
+        }
+        assert!(reported(first_site).is_empty());
+
+        let _second = second.lock();
+        let _first = first.lock();
+        let reports = reported(first_site);
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].contains("Possible deadlock"));
+        assert!(reports[0].contains(&std::format!("{}:{}:", file!(), second_site)));
+    }
+
+    #[test]
+    fn long_holds_are_reported() {
+        capture_reports();
+        TestCpu::bind(0);
+        let (lock, site) = (RawSpinLock::<(), TestCpu>::new(()), line!());
+
+        lock.lock_with(|_| {
+            std::thread::sleep(std::time::Duration::from_nanos(
+                2 * super::HOLD_TIME_WARNING_NS,
+            ))
+        });
+
+        let reports = reported(site);
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].contains("held for"));
+    }
+}
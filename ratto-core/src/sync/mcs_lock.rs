@@ -0,0 +1,300 @@
+use core::ptr;
+
+use super::atomic::{AtomicBool, AtomicPtr, Ordering, spin_loop};
+use crate::cpu::CpuOps;
+use crate::percpu::{self, IrqGuard};
+
+/// A queue entry of an [`McsLock`], padded to a cache line so that each
+/// waiter spins on memory no other core writes until the handoff.
+#[repr(align(64))]
+struct McsNode {
+    next: AtomicPtr<McsNode>,
+    waiting: AtomicBool,
+}
+
+impl McsNode {
+    loom_const_fn! {
+        const fn new() -> Self {
+            McsNode {
+                next: AtomicPtr::new(ptr::null_mut()),
+                waiting: AtomicBool::new(false),
+            }
+        }
+    }
+}
+
+/// A fair, queued spinlock (Mellor-Crummey and Scott).
+///
+/// Waiters form a FIFO queue and each spins on its own node rather than on the
+/// lock word, so a contended handoff touches only two cores' cache lines. The
+/// lock embeds one node per core; since interrupts are disabled from the
+/// moment a core starts waiting until it releases the lock, a core can never
+/// need its node twice at once. `N` is the number of cores.
+pub struct McsLock<T: Sized, Cpu: CpuOps, const N: usize> {
+    data: core::cell::UnsafeCell<T>,
+    tail: AtomicPtr<McsNode>,
+    nodes: [McsNode; N],
+    _phantom: core::marker::PhantomData<Cpu>,
+}
+
+unsafe impl<T: Sized + Send, Cpu: CpuOps, const N: usize> Sync for McsLock<T, Cpu, N> {}
+unsafe impl<T: Sized + Send, Cpu: CpuOps, const N: usize> Send for McsLock<T, Cpu, N> {}
+
+impl<T, Cpu: CpuOps, const N: usize> McsLock<T, Cpu, N> {
+    loom_const_fn! {
+        pub const fn new(data: T) -> Self {
+            McsLock {
+                data: core::cell::UnsafeCell::new(data),
+                tail: AtomicPtr::new(ptr::null_mut()),
+                #[cfg(not(loom))]
+                nodes: [const { McsNode::new() }; N],
+                #[cfg(loom)]
+                nodes: core::array::from_fn(|_| McsNode::new()),
+                _phantom: core::marker::PhantomData,
+            }
+        }
+    }
+}
+
+impl<T: Sized, Cpu: CpuOps, const N: usize> McsLock<T, Cpu, N> {
+    pub fn lock(&self) -> McsLockGuard<'_, T, Cpu, N> {
+        let irq = percpu::irq_save::<Cpu>();
+        let node = self.local_node();
+        node.next.store(ptr::null_mut(), Ordering::Relaxed);
+        node.waiting.store(true, Ordering::Relaxed);
+
+        let node_ptr = node as *const McsNode as *mut McsNode;
+        let prev = self.tail.swap(node_ptr, Ordering::AcqRel);
+        if let Some(prev) = unsafe { prev.as_ref() } {
+            prev.next.store(node_ptr, Ordering::Release);
+            while node.waiting.load(Ordering::Acquire) {
+                spin_loop();
+            }
+        }
+
+        McsLockGuard {
+            lock: self,
+            node,
+            _irq: irq,
+        }
+    }
+
+    /// Take the lock only if nobody holds or is waiting for it.
+    pub fn try_lock(&self) -> Option<McsLockGuard<'_, T, Cpu, N>> {
+        let irq = percpu::irq_save::<Cpu>();
+        let node = self.local_node();
+        node.next.store(ptr::null_mut(), Ordering::Relaxed);
+
+        let node_ptr = node as *const McsNode as *mut McsNode;
+        match self.tail.compare_exchange(
+            ptr::null_mut(),
+            node_ptr,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(McsLockGuard {
+                lock: self,
+                node,
+                _irq: irq,
+            }),
+            Err(_) => None,
+        }
+    }
+
+    /// The executing core's node. Panics if the lock was built for fewer
+    /// cores than are running.
+    fn local_node(&self) -> &McsNode {
+        let cpu = percpu::cpu_id::<Cpu>();
+        self.nodes
+            .get(cpu)
+            .unwrap_or_else(|| panic!("McsLock has no node for core {}", cpu))
+    }
+
+    pub fn lock_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.lock();
+        f(&mut *guard)
+    }
+
+    /// Whether the lock is held or contended.
+    pub fn is_locked(&self) -> bool {
+        !self.tail.load(Ordering::Relaxed).is_null()
+    }
+}
+
+#[must_use]
+pub struct McsLockGuard<'a, T: Sized, Cpu: CpuOps, const N: usize> {
+    lock: &'a McsLock<T, Cpu, N>,
+    node: &'a McsNode,
+    _irq: IrqGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps, const N: usize> core::ops::Deref for McsLockGuard<'a, T, Cpu, N> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps, const N: usize> core::ops::DerefMut
+    for McsLockGuard<'a, T, Cpu, N>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps, const N: usize> Drop for McsLockGuard<'a, T, Cpu, N> {
+    fn drop(&mut self) {
+        let node_ptr = self.node as *const McsNode as *mut McsNode;
+        let mut next = self.node.next.load(Ordering::Acquire);
+
+        if next.is_null() {
+            // No known successor: try to mark the lock free
+            if self
+                .lock
+                .tail
+                .compare_exchange(
+                    node_ptr,
+                    ptr::null_mut(),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return;
+            }
+
+            // A successor swapped itself in but has not linked to us yet
+            loop {
+                next = self.node.next.load(Ordering::Acquire);
+                if !next.is_null() {
+                    break;
+                }
+
+                spin_loop();
+            }
+        }
+
+        unsafe { (*next).waiting.store(false, Ordering::Release) };
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    const THREADS: usize = 4;
+
+    #[test]
+    fn contended_increments_are_not_lost() {
+        const ITERATIONS: usize = 1_000;
+
+        let lock = McsLock::<usize, TestCpu, THREADS>::new(0);
+        std::thread::scope(|scope| {
+            for cpu in 0..THREADS {
+                let lock = &lock;
+                scope.spawn(move || {
+                    TestCpu::bind(cpu);
+                    for _ in 0..ITERATIONS {
+                        lock.lock_with(|count| *count += 1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*lock.lock(), THREADS * ITERATIONS);
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    #[should_panic(expected = "McsLock has no node for core 2")]
+    fn too_few_nodes_panic_clearly() {
+        let lock = McsLock::<(), TestCpu, 2>::new(());
+        TestCpu::bind(2);
+        let _guard = lock.lock();
+    }
+
+    #[test]
+    fn try_lock_fails_while_held() {
+        let lock = McsLock::<(), TestCpu, THREADS>::new(());
+
+        let guard = lock.lock();
+        assert!(!TestCpu::interrupts_enabled());
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                TestCpu::bind(1);
+                assert!(lock.try_lock().is_none());
+            });
+        });
+
+        drop(guard);
+        assert!(TestCpu::interrupts_enabled());
+        assert!(lock.try_lock().is_some());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    fn increment(count: &mut UnsafeCell<usize>) {
+        count.with_mut(|count| unsafe { *count += 1 });
+    }
+
+    #[test]
+    fn lock_is_mutually_exclusive() {
+        loom::model(|| {
+            let lock = Arc::new(McsLock::<_, TestCpu, 2>::new(UnsafeCell::new(0)));
+
+            let other = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    lock.lock_with(increment);
+                    assert!(TestCpu::interrupts_enabled());
+                })
+            };
+
+            TestCpu::bind(0);
+            lock.lock_with(increment);
+            other.join().unwrap();
+
+            let count = lock.lock().with(|count| unsafe { *count });
+            assert_eq!(count, 2);
+            assert!(!lock.is_locked());
+        });
+    }
+
+    #[test]
+    fn node_is_reused_after_handoff() {
+        loom::model(|| {
+            let lock = Arc::new(McsLock::<_, TestCpu, 2>::new(UnsafeCell::new(0)));
+
+            let other = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    lock.lock_with(increment);
+                })
+            };
+
+            TestCpu::bind(0);
+            lock.lock_with(increment);
+            if let Some(mut guard) = lock.try_lock() {
+                increment(&mut guard);
+            }
+            other.join().unwrap();
+            assert!(TestCpu::interrupts_enabled());
+        });
+    }
+}
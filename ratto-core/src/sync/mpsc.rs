@@ -0,0 +1,294 @@
+use core::mem::MaybeUninit;
+
+use super::atomic::{AtomicBool, AtomicUsize, Ordering, spin_loop};
+use super::cell::UnsafeCell;
+use super::spsc::claim;
+
+/// A fixed-capacity, lock-free queue that any number of cores and interrupt
+/// handlers push to and a single consumer, claimed with
+/// [`consumer`](MpscRing::consumer), pops from. `N` must be a power of two
+/// and at least 2.
+///
+/// Producers reserve a slot and then fill it (Vyukov's bounded queue), so
+/// the consumer may briefly see the queue as empty while an earlier push is
+/// still in progress.
+pub struct MpscRing<T, const N: usize> {
+    slots: [Slot<T>; N],
+    /// Next slot to pop, only advanced by the consumer.
+    head: AtomicUsize,
+    /// Next slot to reserve for a push.
+    tail: AtomicUsize,
+    consumer_claimed: AtomicBool,
+}
+
+/// A slot tagged with the lap of the ring it was last used in. For position
+/// `pos` with `lap = pos - pos % N`, a stamp of `lap` means the slot is free
+/// for a push at `pos` and `lap + 1` that it holds the value pushed there.
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    loom_const_fn! {
+        const fn new() -> Self {
+            Slot {
+                stamp: AtomicUsize::new(0),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+    }
+}
+
+unsafe impl<T: Send, const N: usize> Sync for MpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for MpscRing<T, N> {}
+
+impl<T, const N: usize> MpscRing<T, N> {
+    loom_const_fn! {
+        pub const fn new() -> Self {
+            // With a single slot the stamp of a full slot would read as free
+            // for the next lap
+            const {
+                assert!(
+                    N.is_power_of_two() && N >= 2,
+                    "Ring capacity must be a power of two of at least 2"
+                )
+            };
+
+            MpscRing {
+                #[cfg(not(loom))]
+                slots: [const { Slot::new() }; N],
+                #[cfg(loom)]
+                slots: core::array::from_fn(|_| Slot::new()),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                consumer_claimed: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Queue `value`, handing it back if the ring is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % N];
+            let lap = pos - pos % N;
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            match (stamp.wrapping_sub(lap) as isize).signum() {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        slot.value.with_mut(|cell| unsafe { (*cell).write(value) });
+                        slot.stamp.store(lap.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                // The slot still holds, or is being filled with, a value from
+                // the previous lap
+                -1 => return Err(value),
+                // Another producer took `pos` first
+                _ => {
+                    spin_loop();
+                    pos = self.tail.load(Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// The consuming end, unless it is already taken.
+    pub fn consumer(&self) -> Option<MpscConsumer<'_, T, N>> {
+        claim(&self.consumer_claimed).then_some(MpscConsumer { ring: self })
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of queued items, counting pushes still in progress. Only a
+    /// snapshot while the ring is in use.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        self.tail.load(Ordering::Acquire).wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= N
+    }
+
+    /// Whether the next pop would return a value.
+    pub(super) fn is_ready(&self) -> bool {
+        let pos = self.head.load(Ordering::Acquire);
+        let lap = pos - pos % N;
+        self.slots[pos % N].stamp.load(Ordering::Acquire) == lap.wrapping_add(1)
+    }
+
+    /// Pop the next value.
+    ///
+    /// # Safety
+    /// The caller must be the ring's only consumer.
+    unsafe fn pop(&self) -> Option<T> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[pos % N];
+        let lap = pos - pos % N;
+        if slot.stamp.load(Ordering::Acquire) != lap.wrapping_add(1) {
+            return None;
+        }
+
+        let value = slot
+            .value
+            .with(|cell| unsafe { (*cell).assume_init_read() });
+        slot.stamp.store(lap.wrapping_add(N), Ordering::Release);
+        self.head.store(pos.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Drop for MpscRing<T, N> {
+    fn drop(&mut self) {
+        // With `&mut self` nobody else can be consuming
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+impl<T, const N: usize> Default for MpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The consuming end of an [`MpscRing`]; dropping it frees the end again.
+pub struct MpscConsumer<'a, T, const N: usize> {
+    ring: &'a MpscRing<T, N>,
+}
+
+impl<'a, T, const N: usize> MpscConsumer<'a, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        unsafe { self.ring.pop() }
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for MpscConsumer<'a, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+impl<'a, T, const N: usize> Drop for MpscConsumer<'a, T, N> {
+    fn drop(&mut self) {
+        self.ring.consumer_claimed.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fills_and_drains_in_order() {
+        let ring = MpscRing::<u32, 2>::new();
+        let mut consumer = ring.consumer().unwrap();
+        assert!(ring.consumer().is_none());
+
+        for lap in 0..3 {
+            ring.push(lap * 2).unwrap();
+            ring.push(lap * 2 + 1).unwrap();
+            assert_eq!(ring.push(99), Err(99));
+            assert!(ring.is_full());
+
+            assert_eq!(consumer.pop(), Some(lap * 2));
+            assert_eq!(consumer.pop(), Some(lap * 2 + 1));
+            assert_eq!(consumer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn queued_items_are_dropped_with_the_ring() {
+        let marker = std::rc::Rc::new(());
+        let ring = MpscRing::<_, 4>::new();
+        ring.push(marker.clone()).unwrap();
+        ring.push(marker.clone()).unwrap();
+
+        drop(ring);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn stress_keeps_each_producers_order() {
+        const PRODUCERS: usize = 4;
+        const ITEMS: usize = 25_000;
+
+        let ring = MpscRing::<(usize, usize), 32>::new();
+        std::thread::scope(|scope| {
+            for producer in 0..PRODUCERS {
+                let ring = &ring;
+                scope.spawn(move || {
+                    for mut item in (0..ITEMS).map(|value| (producer, value)) {
+                        while let Err(rejected) = ring.push(item) {
+                            item = rejected;
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+
+            let mut consumer = ring.consumer().unwrap();
+            let mut next = [0; PRODUCERS];
+            let mut received = 0;
+            while received < PRODUCERS * ITEMS {
+                match consumer.pop() {
+                    Some((producer, value)) => {
+                        assert_eq!(value, next[producer]);
+                        next[producer] += 1;
+                        received += 1;
+                    }
+                    None => std::thread::yield_now(),
+                }
+            }
+        });
+
+        assert!(ring.is_empty());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_pushes_are_all_received() {
+        loom::model(|| {
+            let ring = Arc::new(MpscRing::<usize, 2>::new());
+
+            let producers: Vec<_> = (1..=2)
+                .map(|value| {
+                    let ring = ring.clone();
+                    thread::spawn(move || ring.push(value).unwrap())
+                })
+                .collect();
+
+            let mut consumer = ring.consumer().unwrap();
+            let mut sum = consumer.pop().unwrap_or(0);
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            sum += consumer.by_ref().sum::<usize>();
+
+            assert_eq!(sum, 3);
+        });
+    }
+}
@@ -0,0 +1,174 @@
+use core::cell::UnsafeCell;
+
+use super::WaitQueue;
+use super::atomic::{AtomicBool, Ordering};
+use crate::sched::SchedOps;
+
+/// A lock that puts contending tasks to sleep instead of spinning, for data
+/// held across long operations such as I/O. Only usable from task context.
+///
+/// The lock is not fair: a task that finds it free takes it even if others
+/// are waiting, which keeps the uncontended path to a single atomic.
+pub struct Mutex<T: Sized, S: SchedOps> {
+    data: UnsafeCell<T>,
+    locked: AtomicBool,
+    waiters: WaitQueue<S>,
+}
+
+unsafe impl<T: Sized + Send, S: SchedOps> Sync for Mutex<T, S> {}
+unsafe impl<T: Sized + Send, S: SchedOps> Send for Mutex<T, S> {}
+
+impl<T, S: SchedOps> Mutex<T, S> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new(data: T) -> Self {
+            Mutex {
+                data: UnsafeCell::new(data),
+                locked: AtomicBool::new(false),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+}
+
+impl<T: Sized, S: SchedOps> Mutex<T, S> {
+    pub fn lock(&self) -> MutexGuard<'_, T, S> {
+        if !self.acquire() {
+            self.waiters.wait_until(|| self.acquire());
+        }
+
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, S>> {
+        self.acquire().then_some(MutexGuard { mutex: self })
+    }
+
+    pub fn lock_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.lock();
+        f(&mut *guard)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    fn acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+#[must_use]
+pub struct MutexGuard<'a, T: Sized, S: SchedOps> {
+    pub(super) mutex: &'a Mutex<T, S>,
+}
+
+impl<'a, T: Sized, S: SchedOps> core::ops::Deref for MutexGuard<'a, T, S> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: Sized, S: SchedOps> core::ops::DerefMut for MutexGuard<'a, T, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: Sized, S: SchedOps> Drop for MutexGuard<'a, T, S> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.waiters.wake_one();
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpu::test::TestCpu;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn lock_excludes_and_unlocks() {
+        let mut mutex = Mutex::<u32, TestSched>::new(1);
+
+        let mut guard = mutex.lock();
+        *guard += 1;
+        assert!(mutex.is_locked());
+        assert!(mutex.try_lock().is_none());
+        assert!(TestCpu::interrupts_enabled());
+
+        drop(guard);
+        *mutex.get_mut() += 1;
+        assert_eq!(mutex.into_inner(), 3);
+    }
+
+    #[test]
+    fn contended_increments_are_not_lost() {
+        const THREADS: usize = 4;
+        const ITERATIONS: usize = 1_000;
+
+        let mutex = Mutex::<usize, TestSched>::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..ITERATIONS {
+                        mutex.lock_with(|count| {
+                            *count += 1;
+                            std::thread::yield_now();
+                        });
+                    }
+                });
+            }
+        });
+
+        assert_eq!(mutex.into_inner(), THREADS * ITERATIONS);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    fn increment(count: &mut UnsafeCell<usize>) {
+        count.with_mut(|count| unsafe { *count += 1 });
+    }
+
+    #[test]
+    fn lock_is_mutually_exclusive() {
+        loom::model(|| {
+            let mutex = Arc::new(Mutex::<_, TestSched>::new(UnsafeCell::new(0)));
+
+            let other = {
+                let mutex = mutex.clone();
+                thread::spawn(move || mutex.lock_with(increment))
+            };
+
+            mutex.lock_with(increment);
+            other.join().unwrap();
+
+            let count = mutex.lock().with(|count| unsafe { *count });
+            assert_eq!(count, 2);
+        });
+    }
+}
@@ -1,51 +1,410 @@
-use crate::{cpu::CpuOps, sync::SpinLock};
+use core::cell::UnsafeCell;
+use core::convert::Infallible;
+use core::mem::MaybeUninit;
 
+use super::atomic::{AtomicU8, Ordering, spin_loop};
+use crate::cpu::CpuOps;
+use crate::percpu;
+
+const UNINIT: u8 = 0;
+const INITIALISING: u8 = 1;
+const READY: u8 = 2;
+const POISONED: u8 = 3;
+
+/// A cell written at most once, then readable without locking.
+///
+/// The first caller of [`get_or_init`](OnceLock::get_or_init) (or one of its
+/// variants) runs the initialiser with interrupts masked, so an IRQ handler on
+/// the same core cannot wait for it forever; callers on other cores spin until
+/// it is done. If the initialiser panics the cell is poisoned and every later
+/// initialisation attempt panics too.
 pub struct OnceLock<T, Cpu: CpuOps> {
-    inner: SpinLock<Option<T>, Cpu>,
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+    _phantom: core::marker::PhantomData<Cpu>,
 }
 
+unsafe impl<T: Send + Sync, Cpu: CpuOps> Sync for OnceLock<T, Cpu> {}
+unsafe impl<T: Send, Cpu: CpuOps> Send for OnceLock<T, Cpu> {}
+
 impl<T, Cpu: CpuOps> OnceLock<T, Cpu> {
-    pub const fn new() -> Self {
-        OnceLock {
-            inner: SpinLock::new(None),
+    loom_const_fn! {
+        pub const fn new() -> Self {
+            OnceLock {
+                state: AtomicU8::new(UNINIT),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+                _phantom: core::marker::PhantomData,
+            }
         }
     }
 
     pub fn get(&self) -> Option<&T> {
-        let guard = self.inner.lock();
-        if let Some(ref value) = *guard {
-            // Safe because the lock is held, so the value cannot be modified or removed.
-            let ptr = value as *const T;
-            unsafe { Some(&*ptr) }
+        if self.is_initialized() {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
         } else {
             None
         }
     }
 
-    pub fn get_mut(&self) -> Option<&mut T> {
-        let mut guard = self.inner.lock();
-        if let Some(ref mut value) = *guard {
-            // Safe because the lock is held, so the value cannot be modified or removed.
-            let ptr = value as *mut T;
-            unsafe { Some(&mut *ptr) }
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.state.load(Ordering::Relaxed) == READY {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
         } else {
             None
         }
     }
 
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == READY
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == POISONED
+    }
+
+    /// Store `value` unless the cell already holds one, in which case `value`
+    /// is handed back. If another caller is initialising the cell this waits
+    /// for it, fails only if that initialiser stored a value, and panics if
+    /// it poisoned the cell.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
     pub fn get_or_init<F>(&self, init: F) -> &T
     where
         F: FnOnce() -> T,
     {
-        let mut guard = self.inner.lock();
-        if guard.is_none() {
-            *guard = Some(init());
+        match self.get_or_try_init(|| Ok::<T, Infallible>(init())) {
+            Ok(value) => value,
+        }
+    }
+
+    /// Like [`get_or_init`](OnceLock::get_or_init), but a failing initialiser
+    /// leaves the cell empty so that a later call can try again.
+    pub fn get_or_try_init<F, E>(&self, init: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        loop {
+            match self.state.compare_exchange_weak(
+                UNINIT,
+                INITIALISING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return self.initialise(init),
+                Err(READY) => return Ok(self.get().unwrap()),
+                Err(POISONED) => panic!("OnceLock poisoned by a panicking initialiser"),
+                Err(_) => spin_loop(),
+            }
+        }
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    /// Empty the cell, returning its value if it had one.
+    pub fn take(&mut self) -> Option<T> {
+        if self.state.load(Ordering::Relaxed) == READY {
+            self.state.store(UNINIT, Ordering::Relaxed);
+            Some(unsafe { self.value.get_mut().assume_init_read() })
+        } else {
+            None
+        }
+    }
+
+    /// Run `init` after winning the race to `INITIALISING`.
+    fn initialise<F, E>(&self, init: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        /// Poisons the cell unless defused, i.e. if `init` unwinds.
+        struct Poison<'a>(&'a AtomicU8);
+
+        impl Drop for Poison<'_> {
+            fn drop(&mut self) {
+                self.0.store(POISONED, Ordering::Release);
+            }
+        }
+
+        let _irq = percpu::irq_save::<Cpu>();
+        let poison = Poison(&self.state);
+        let result = init();
+        core::mem::forget(poison);
+
+        match result {
+            Ok(value) => {
+                unsafe { (*self.value.get()).write(value) };
+                self.state.store(READY, Ordering::Release);
+                Ok(self.get().unwrap())
+            }
+            Err(error) => {
+                self.state.store(UNINIT, Ordering::Release);
+                Err(error)
+            }
         }
-        // Safe because the lock is held, so the value cannot be modified or removed.
-        let ptr = guard.as_ref().unwrap() as *const T;
-        unsafe { &*ptr }
     }
 }
 
-unsafe impl<T: Send, Cpu: CpuOps> Sync for OnceLock<T, Cpu> {}
-unsafe impl<T: Send, Cpu: CpuOps> Send for OnceLock<T, Cpu> {}
+impl<T, Cpu: CpuOps> Drop for OnceLock<T, Cpu> {
+    fn drop(&mut self) {
+        if self.state.load(Ordering::Relaxed) == READY {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<T: core::fmt::Debug, Cpu: CpuOps> core::fmt::Debug for OnceLock<T, Cpu> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceLock").field(value).finish(),
+            None => f.write_str("OnceLock(<uninit>)"),
+        }
+    }
+}
+
+/// A value computed by `F` on first access, for statics that cannot be built
+/// in a const context. Initialisation follows [`OnceLock`], including
+/// poisoning if `F` panics.
+pub struct Lazy<T, Cpu: CpuOps, F = fn() -> T> {
+    cell: OnceLock<T, Cpu>,
+    init: UnsafeCell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, Cpu: CpuOps, F: Send> Sync for Lazy<T, Cpu, F> {}
+
+impl<T, Cpu: CpuOps, F: FnOnce() -> T> Lazy<T, Cpu, F> {
+    loom_const_fn! {
+        pub const fn new(init: F) -> Self {
+            Lazy {
+                cell: OnceLock::new(),
+                init: UnsafeCell::new(Some(init)),
+            }
+        }
+    }
+
+    /// The value, computing it first if needed.
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| {
+            // Only the core that won the race to initialise gets here
+            let init = unsafe { (*this.init.get()).take() };
+            init.expect("Lazy initialiser already consumed")()
+        })
+    }
+
+    /// The value, if it has been computed.
+    pub fn get(this: &Self) -> Option<&T> {
+        this.cell.get()
+    }
+}
+
+impl<T, Cpu: CpuOps, F: FnOnce() -> T> core::ops::Deref for Lazy<T, Cpu, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        Lazy::force(self)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+    use std::sync::atomic::AtomicUsize;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    #[test]
+    fn set_only_succeeds_once() {
+        let mut cell = OnceLock::<u32, TestCpu>::new();
+        assert!(cell.get().is_none());
+
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get(), Some(&1));
+
+        *cell.get_mut().unwrap() = 3;
+        assert_eq!(cell.into_inner(), Some(3));
+    }
+
+    #[test]
+    fn racing_initialisers_run_once() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let cell = OnceLock::<usize, TestCpu>::new();
+
+        let values: Vec<usize> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|cpu| {
+                    let cell = &cell;
+                    scope.spawn(move || {
+                        TestCpu::bind(cpu);
+                        *cell.get_or_init(|| {
+                            CALLS.fetch_add(1, Ordering::Relaxed);
+                            std::thread::yield_now();
+                            cpu
+                        })
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert!(values.iter().all(|&value| value == values[0]));
+    }
+
+    #[test]
+    fn failed_initialiser_can_be_retried() {
+        let cell = OnceLock::<u32, TestCpu>::new();
+
+        assert_eq!(cell.get_or_try_init(|| Err("not yet")), Err("not yet"));
+        assert!(cell.get().is_none());
+        assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>(5)), Ok(&5));
+        assert!(TestCpu::interrupts_enabled());
+    }
+
+    #[test]
+    fn panicking_initialiser_poisons() {
+        let cell = OnceLock::<u32, TestCpu>::new();
+
+        let result = catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!("boom"))));
+        assert!(result.is_err());
+        assert!(cell.is_poisoned());
+        assert!(cell.get().is_none());
+
+        let retry = catch_unwind(AssertUnwindSafe(|| *cell.get_or_init(|| 1)));
+        assert!(retry.is_err());
+    }
+
+    #[test]
+    fn value_is_dropped_with_the_cell() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        drop(OnceLock::<Counted, TestCpu>::new());
+        let cell = OnceLock::<Counted, TestCpu>::new();
+        cell.get_or_init(|| Counted);
+        drop(cell);
+
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn lazy_computes_on_first_access() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: Lazy<u64, TestCpu> = Lazy::new(|| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            42
+        });
+
+        assert!(Lazy::get(&VALUE).is_none());
+        assert_eq!(*VALUE, 42);
+        assert_eq!(*VALUE + 1, 43);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::sync::atomic::AtomicUsize;
+    use loom::thread;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    #[test]
+    fn racing_initialisers_run_once() {
+        loom::model(|| {
+            let cell = Arc::new(OnceLock::<usize, TestCpu>::new());
+            let calls = Arc::new(AtomicUsize::new(0));
+
+            let other = {
+                let (cell, calls) = (cell.clone(), calls.clone());
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    let value = *cell.get_or_init(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        1
+                    });
+                    assert!(TestCpu::interrupts_enabled());
+                    value
+                })
+            };
+
+            TestCpu::bind(0);
+            let value = *cell.get_or_init(|| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                0
+            });
+
+            assert_eq!(other.join().unwrap(), value);
+            assert_eq!(calls.load(Ordering::Relaxed), 1);
+            assert!(TestCpu::interrupts_enabled());
+        });
+    }
+
+    #[test]
+    fn readers_see_a_published_value() {
+        loom::model(|| {
+            let cell = Arc::new(OnceLock::<Arc<usize>, TestCpu>::new());
+
+            let reader = {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    if let Some(value) = cell.get() {
+                        assert_eq!(**value, 7);
+                    }
+                })
+            };
+
+            TestCpu::bind(0);
+            assert!(cell.set(Arc::new(7)).is_ok());
+            reader.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn lazy_forces_once() {
+        loom::model(|| {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let lazy = {
+                let calls = calls.clone();
+                Arc::new(Lazy::<usize, TestCpu, _>::new(move || {
+                    calls.fetch_add(1, Ordering::Relaxed) + 5
+                }))
+            };
+
+            let other = {
+                let lazy = lazy.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    *Lazy::force(&lazy)
+                })
+            };
+
+            TestCpu::bind(0);
+            assert_eq!(**lazy, 5);
+            assert_eq!(other.join().unwrap(), 5);
+            assert_eq!(calls.load(Ordering::Relaxed), 1);
+        });
+    }
+}
@@ -0,0 +1,521 @@
+use core::mem::ManuallyDrop;
+use core::ptr;
+
+use super::atomic::{AtomicUsize, Ordering, spin_loop};
+use crate::cpu::CpuOps;
+use crate::percpu::{self, IrqGuard};
+
+const WRITER: usize = 1 << 0;
+const UPGRADABLE: usize = 1 << 1;
+const WRITER_WAITING: usize = 1 << 2;
+const READER: usize = 1 << 3;
+
+/// A reader-writer spinlock for read-mostly data.
+///
+/// Any number of readers, or one writer, may hold the lock. One upgradable
+/// reader may hold it alongside plain readers and later turn into a writer
+/// without letting another writer in between. The lock prefers writers: once
+/// a writer is waiting, new readers hold off until it has been served, so a
+/// steady stream of readers cannot starve it. As a consequence a core must not
+/// take a read lock it already holds. Interrupts are disabled while any guard
+/// is live, like [`SpinLock`](super::SpinLock).
+pub struct RwSpinLock<T: Sized, Cpu: CpuOps> {
+    data: core::cell::UnsafeCell<T>,
+    state: AtomicUsize,
+    _phantom: core::marker::PhantomData<Cpu>,
+}
+
+unsafe impl<T: Sized + Send + Sync, Cpu: CpuOps> Sync for RwSpinLock<T, Cpu> {}
+unsafe impl<T: Sized + Send, Cpu: CpuOps> Send for RwSpinLock<T, Cpu> {}
+
+impl<T, Cpu: CpuOps> RwSpinLock<T, Cpu> {
+    loom_const_fn! {
+        pub const fn new(data: T) -> Self {
+            RwSpinLock {
+                data: core::cell::UnsafeCell::new(data),
+                state: AtomicUsize::new(0),
+                _phantom: core::marker::PhantomData,
+            }
+        }
+    }
+}
+
+impl<T: Sized, Cpu: CpuOps> RwSpinLock<T, Cpu> {
+    pub fn read(&self) -> RwSpinLockReadGuard<'_, T, Cpu> {
+        let irq = percpu::irq_save::<Cpu>();
+        while !self.try_acquire_read() {
+            spin_loop();
+        }
+
+        RwSpinLockReadGuard {
+            lock: self,
+            _irq: irq,
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwSpinLockReadGuard<'_, T, Cpu>> {
+        let irq = percpu::irq_save::<Cpu>();
+        if self.try_acquire_read() {
+            Some(RwSpinLockReadGuard {
+                lock: self,
+                _irq: irq,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn write(&self) -> RwSpinLockWriteGuard<'_, T, Cpu> {
+        let irq = percpu::irq_save::<Cpu>();
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & !WRITER_WAITING == 0 {
+                // Acquiring clears the waiting flag; other waiting writers set it again
+                if self
+                    .state
+                    .compare_exchange_weak(state, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    break;
+                }
+            } else if state & WRITER_WAITING == 0 {
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+
+            spin_loop();
+        }
+
+        RwSpinLockWriteGuard {
+            lock: self,
+            _irq: irq,
+        }
+    }
+
+    pub fn try_write(&self) -> Option<RwSpinLockWriteGuard<'_, T, Cpu>> {
+        let irq = percpu::irq_save::<Cpu>();
+        let state = self.state.load(Ordering::Relaxed);
+        if state & !WRITER_WAITING == 0
+            && self
+                .state
+                .compare_exchange(state, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            Some(RwSpinLockWriteGuard {
+                lock: self,
+                _irq: irq,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Take a read lock that can later be upgraded to a write lock. Only one
+    /// upgradable guard exists at a time, but it shares the lock with readers.
+    pub fn upgradable_read(&self) -> RwSpinLockUpgradableGuard<'_, T, Cpu> {
+        let irq = percpu::irq_save::<Cpu>();
+        while !self.try_acquire_upgradable() {
+            spin_loop();
+        }
+
+        RwSpinLockUpgradableGuard {
+            lock: self,
+            _irq: irq,
+        }
+    }
+
+    pub fn try_upgradable_read(&self) -> Option<RwSpinLockUpgradableGuard<'_, T, Cpu>> {
+        let irq = percpu::irq_save::<Cpu>();
+        if self.try_acquire_upgradable() {
+            Some(RwSpinLockUpgradableGuard {
+                lock: self,
+                _irq: irq,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn read_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let guard = self.read();
+        f(&*guard)
+    }
+
+    pub fn write_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.write();
+        f(&mut *guard)
+    }
+
+    /// Number of readers currently holding the lock, not counting an
+    /// upgradable reader.
+    pub fn reader_count(&self) -> usize {
+        self.state.load(Ordering::Relaxed) / READER
+    }
+
+    fn try_acquire_read(&self) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        state & (WRITER | WRITER_WAITING) == 0
+            && self
+                .state
+                .compare_exchange_weak(state, state + READER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    fn try_acquire_upgradable(&self) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        state & (WRITER | UPGRADABLE | WRITER_WAITING) == 0
+            && self
+                .state
+                .compare_exchange_weak(
+                    state,
+                    state | UPGRADABLE,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+    }
+}
+
+#[must_use]
+pub struct RwSpinLockReadGuard<'a, T: Sized, Cpu: CpuOps> {
+    lock: &'a RwSpinLock<T, Cpu>,
+    _irq: IrqGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for RwSpinLockReadGuard<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> Drop for RwSpinLockReadGuard<'a, T, Cpu> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+#[must_use]
+pub struct RwSpinLockWriteGuard<'a, T: Sized, Cpu: CpuOps> {
+    lock: &'a RwSpinLock<T, Cpu>,
+    _irq: IrqGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> RwSpinLockWriteGuard<'a, T, Cpu> {
+    /// Turn the write lock into a read lock without letting a writer in.
+    pub fn downgrade(self) -> RwSpinLockReadGuard<'a, T, Cpu> {
+        let this = ManuallyDrop::new(self);
+        // Adds a reader and clears WRITER in one step
+        this.lock
+            .state
+            .fetch_add(READER - WRITER, Ordering::Release);
+
+        RwSpinLockReadGuard {
+            lock: this.lock,
+            // The IRQ guard moves to the new guard; `this` is never dropped
+            _irq: unsafe { ptr::read(&this._irq) },
+        }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for RwSpinLockWriteGuard<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::DerefMut for RwSpinLockWriteGuard<'a, T, Cpu> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> Drop for RwSpinLockWriteGuard<'a, T, Cpu> {
+    fn drop(&mut self) {
+        // Keep WRITER_WAITING so that queued writers still hold readers off
+        self.lock.state.fetch_and(!WRITER, Ordering::Release);
+    }
+}
+
+#[must_use]
+pub struct RwSpinLockUpgradableGuard<'a, T: Sized, Cpu: CpuOps> {
+    lock: &'a RwSpinLock<T, Cpu>,
+    _irq: IrqGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> RwSpinLockUpgradableGuard<'a, T, Cpu> {
+    /// Wait for the remaining readers to leave and take the write lock.
+    pub fn upgrade(self) -> RwSpinLockWriteGuard<'a, T, Cpu> {
+        let this = ManuallyDrop::new(self);
+        let state = &this.lock.state;
+        loop {
+            let current = state.load(Ordering::Relaxed);
+            if current & !WRITER_WAITING == UPGRADABLE {
+                if state
+                    .compare_exchange_weak(current, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    break;
+                }
+            } else if current & WRITER_WAITING == 0 {
+                // Stop new readers from arriving while we drain the old ones
+                state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+
+            spin_loop();
+        }
+
+        RwSpinLockWriteGuard {
+            lock: this.lock,
+            // The IRQ guard moves to the new guard; `this` is never dropped
+            _irq: unsafe { ptr::read(&this._irq) },
+        }
+    }
+
+    /// Take the write lock if no readers hold the lock.
+    pub fn try_upgrade(self) -> Result<RwSpinLockWriteGuard<'a, T, Cpu>, Self> {
+        let state = self.lock.state.load(Ordering::Relaxed);
+        if state & !WRITER_WAITING == UPGRADABLE
+            && self
+                .lock
+                .state
+                .compare_exchange(state, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            let this = ManuallyDrop::new(self);
+            Ok(RwSpinLockWriteGuard {
+                lock: this.lock,
+                // The IRQ guard moves to the new guard; `this` is never dropped
+                _irq: unsafe { ptr::read(&this._irq) },
+            })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Give up the right to upgrade, keeping a plain read lock.
+    pub fn downgrade(self) -> RwSpinLockReadGuard<'a, T, Cpu> {
+        let this = ManuallyDrop::new(self);
+        // Adds a reader and clears UPGRADABLE in one step
+        this.lock
+            .state
+            .fetch_add(READER - UPGRADABLE, Ordering::Release);
+
+        RwSpinLockReadGuard {
+            lock: this.lock,
+            // The IRQ guard moves to the new guard; `this` is never dropped
+            _irq: unsafe { ptr::read(&this._irq) },
+        }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for RwSpinLockUpgradableGuard<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> Drop for RwSpinLockUpgradableGuard<'a, T, Cpu> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(UPGRADABLE, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    #[test]
+    fn readers_share_and_writers_exclude() {
+        let lock = RwSpinLock::<u32, TestCpu>::new(1);
+
+        let first = lock.read();
+        let second = lock.read();
+        assert_eq!(lock.reader_count(), 2);
+        assert!(lock.try_write().is_none());
+
+        drop(second);
+        drop(first);
+        let mut writer = lock.write();
+        *writer += 1;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_upgradable_read().is_none());
+
+        drop(writer);
+        assert_eq!(*lock.read(), 2);
+        assert!(TestCpu::interrupts_enabled());
+    }
+
+    #[test]
+    fn waiting_writer_holds_off_new_readers() {
+        let lock = RwSpinLock::<u32, TestCpu>::new(0);
+
+        let reader = lock.read();
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                TestCpu::bind(1);
+                lock.write_with(|value| *value = 7);
+            });
+
+            while lock.state.load(Ordering::Relaxed) & WRITER_WAITING == 0 {
+                std::thread::yield_now();
+            }
+            assert!(lock.try_read().is_none());
+
+            drop(reader);
+            writer.join().unwrap();
+        });
+
+        assert_eq!(*lock.read(), 7);
+    }
+
+    #[test]
+    fn upgrade_and_downgrade() {
+        let lock = RwSpinLock::<u32, TestCpu>::new(0);
+
+        let upgradable = lock.upgradable_read();
+        let reader = lock.read();
+        assert!(lock.try_upgradable_read().is_none());
+        let upgradable = upgradable.try_upgrade().err().unwrap();
+
+        drop(reader);
+        let mut writer = upgradable.upgrade();
+        *writer = 3;
+        assert!(!TestCpu::interrupts_enabled());
+
+        let reader = writer.downgrade();
+        assert_eq!(*reader, 3);
+        assert_eq!(*lock.try_upgradable_read().unwrap().downgrade(), 3);
+        drop(reader);
+
+        assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+        assert!(TestCpu::interrupts_enabled());
+    }
+
+    #[test]
+    fn contended_writes_are_not_lost() {
+        const THREADS: usize = 4;
+        const ITERATIONS: usize = 1_000;
+
+        let lock = RwSpinLock::<(usize, usize), TestCpu>::new((0, 0));
+        std::thread::scope(|scope| {
+            for cpu in 0..THREADS {
+                let lock = &lock;
+                scope.spawn(move || {
+                    TestCpu::bind(cpu);
+                    for i in 0..ITERATIONS {
+                        if i % 2 == 0 {
+                            lock.write_with(|(a, b)| {
+                                *a += 1;
+                                *b += 1;
+                            });
+                        } else {
+                            let (a, b) = *lock.read();
+                            assert_eq!(a, b);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            *lock.read(),
+            (THREADS * ITERATIONS / 2, THREADS * ITERATIONS / 2)
+        );
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    type Pair = (UnsafeCell<usize>, UnsafeCell<usize>);
+
+    fn bump(pair: &mut Pair) {
+        pair.0.with_mut(|a| unsafe { *a += 1 });
+        pair.1.with_mut(|b| unsafe { *b += 1 });
+    }
+
+    fn read(pair: &Pair) -> (usize, usize) {
+        (
+            pair.0.with(|a| unsafe { *a }),
+            pair.1.with(|b| unsafe { *b }),
+        )
+    }
+
+    #[test]
+    fn readers_see_whole_writes() {
+        loom::model(|| {
+            let lock = Arc::new(RwSpinLock::<Pair, TestCpu>::new((
+                UnsafeCell::new(0),
+                UnsafeCell::new(0),
+            )));
+
+            let writer = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    lock.write_with(bump);
+                    assert!(TestCpu::interrupts_enabled());
+                })
+            };
+
+            TestCpu::bind(0);
+            let (a, b) = lock.read_with(read);
+            assert_eq!(a, b);
+            assert!(TestCpu::interrupts_enabled());
+
+            writer.join().unwrap();
+            assert_eq!(lock.read_with(read), (1, 1));
+        });
+    }
+
+    #[test]
+    fn upgrade_excludes_writers() {
+        loom::model(|| {
+            let lock = Arc::new(RwSpinLock::<Pair, TestCpu>::new((
+                UnsafeCell::new(0),
+                UnsafeCell::new(0),
+            )));
+
+            let writer = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    lock.write_with(bump);
+                })
+            };
+
+            TestCpu::bind(0);
+            let upgradable = lock.upgradable_read();
+            let before = read(&upgradable);
+            let mut writer_guard = upgradable.upgrade();
+            assert_eq!(read(&writer_guard), before);
+            bump(&mut writer_guard);
+            let reader = writer_guard.downgrade();
+            assert_eq!(read(&reader), (before.0 + 1, before.1 + 1));
+            drop(reader);
+            assert!(TestCpu::interrupts_enabled());
+
+            writer.join().unwrap();
+            assert_eq!(lock.read_with(read), (2, 2));
+            assert_eq!(lock.reader_count(), 0);
+        });
+    }
+}
@@ -0,0 +1,114 @@
+use super::WaitQueue;
+use super::atomic::{AtomicUsize, Ordering};
+use crate::sched::SchedOps;
+
+/// A counting semaphore: [`acquire`](Semaphore::acquire) takes a permit,
+/// sleeping until one is available, and [`release`](Semaphore::release)
+/// returns one. Releasing is safe from interrupt handlers.
+pub struct Semaphore<S: SchedOps> {
+    permits: AtomicUsize,
+    waiters: WaitQueue<S>,
+}
+
+impl<S: SchedOps> Semaphore<S> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new(permits: usize) -> Self {
+            Semaphore {
+                permits: AtomicUsize::new(permits),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    pub fn acquire(&self) {
+        if !self.try_acquire() {
+            self.waiters.wait_until(|| self.try_acquire());
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| {
+                permits.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        self.waiters.wake_one();
+    }
+
+    /// Permits currently available.
+    pub fn available(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn permits_are_counted() {
+        let semaphore = Semaphore::<TestSched>::new(2);
+
+        assert!(semaphore.try_acquire());
+        semaphore.acquire();
+        assert!(!semaphore.try_acquire());
+
+        semaphore.release();
+        assert_eq!(semaphore.available(), 1);
+    }
+
+    #[test]
+    fn holders_never_exceed_permits() {
+        const PERMITS: usize = 2;
+
+        let semaphore = Semaphore::<TestSched>::new(PERMITS);
+        let holders = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..200 {
+                        semaphore.acquire();
+                        let held = holders.fetch_add(1, Ordering::Relaxed) + 1;
+                        assert!(held <= PERMITS);
+                        std::thread::yield_now();
+                        holders.fetch_sub(1, Ordering::Relaxed);
+                        semaphore.release();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(semaphore.available(), PERMITS);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn release_wakes_a_waiter() {
+        loom::model(|| {
+            let semaphore = Arc::new(Semaphore::<TestSched>::new(0));
+
+            let releaser = {
+                let semaphore = semaphore.clone();
+                thread::spawn(move || semaphore.release())
+            };
+
+            semaphore.acquire();
+            releaser.join().unwrap();
+            assert_eq!(semaphore.available(), 0);
+        });
+    }
+}
@@ -1,30 +1,49 @@
-use core::hint::spin_loop;
-use core::sync::atomic::Ordering;
+use core::panic::Location;
 
+use super::atomic::{AtomicBool, Ordering, spin_loop};
 use crate::cpu::CpuOps;
+use crate::percpu::{self, BhGuard, IrqGuard, PreemptGuard};
+use crate::sync::lockdep::LockDebug;
 
-pub struct SpinLock<T: Sized, Cpu: CpuOps> {
+/// The default kernel spinlock, safe to share with interrupt handlers.
+pub type SpinLock<T, Cpu> = SpinLockIrq<T, Cpu>;
+pub type SpinLockGuard<'a, T, Cpu> = SpinLockIrqGuard<'a, T, Cpu>;
+
+/// A spinlock that leaves interrupts alone and only disables preemption.
+/// Data behind it must never be touched from IRQ or softirq context, or a
+/// handler interrupting the holder deadlocks its own core.
+///
+/// Debug builds check how the lock is used; see [`lockdep`](super::lockdep).
+pub struct RawSpinLock<T: Sized, Cpu: CpuOps> {
     data: core::cell::UnsafeCell<T>,
-    lock: core::sync::atomic::AtomicBool,
+    lock: AtomicBool,
+    debug: LockDebug,
     _phantom: core::marker::PhantomData<Cpu>,
 }
 
-unsafe impl<T: Sized + Send, Cpu: CpuOps> Sync for SpinLock<T, Cpu> {}
-unsafe impl<T: Sized + Send, Cpu: CpuOps> Send for SpinLock<T, Cpu> {}
+unsafe impl<T: Sized + Send, Cpu: CpuOps> Sync for RawSpinLock<T, Cpu> {}
+unsafe impl<T: Sized + Send, Cpu: CpuOps> Send for RawSpinLock<T, Cpu> {}
 
-impl<T, Cpu: CpuOps> SpinLock<T, Cpu> {
-    pub const fn new(data: T) -> Self {
-        SpinLock {
-            data: core::cell::UnsafeCell::new(data),
-            lock: core::sync::atomic::AtomicBool::new(false),
-            _phantom: core::marker::PhantomData,
+impl<T, Cpu: CpuOps> RawSpinLock<T, Cpu> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new(data: T) -> Self {
+            RawSpinLock {
+                data: core::cell::UnsafeCell::new(data),
+                lock: AtomicBool::new(false),
+                debug: LockDebug::new(),
+                _phantom: core::marker::PhantomData,
+            }
         }
     }
 }
 
-impl<T: Sized, Cpu: CpuOps> SpinLock<T, Cpu> {
-    pub fn lock(&self) -> SpinLockGuard<'_, T, Cpu> {
-        let interrupt_state = Cpu::disable_interrupts();
+impl<T: Sized, Cpu: CpuOps> RawSpinLock<T, Cpu> {
+    #[track_caller]
+    pub fn lock(&self) -> RawSpinLockGuard<'_, T, Cpu> {
+        let preempt = percpu::preempt_disable::<Cpu>();
+        let caller = Location::caller();
+        self.debug.before_lock::<Cpu>(caller);
         while self
             .lock
             .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
@@ -35,12 +54,28 @@ impl<T: Sized, Cpu: CpuOps> SpinLock<T, Cpu> {
             }
         }
 
-        SpinLockGuard {
+        self.debug.locked::<Cpu>(caller);
+        RawSpinLockGuard {
             lock: self,
-            interrupt_state,
+            _preempt: preempt,
         }
     }
 
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<RawSpinLockGuard<'_, T, Cpu>> {
+        let preempt = percpu::preempt_disable::<Cpu>();
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        self.debug.locked::<Cpu>(Location::caller());
+        Some(RawSpinLockGuard {
+            lock: self,
+            _preempt: preempt,
+        })
+    }
+
+    #[track_caller]
     pub fn lock_with<R, F>(&self, f: F) -> R
     where
         F: FnOnce(&mut T) -> R,
@@ -48,15 +83,19 @@ impl<T: Sized, Cpu: CpuOps> SpinLock<T, Cpu> {
         let mut guard = self.lock();
         f(&mut *guard)
     }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock.load(Ordering::Relaxed)
+    }
 }
 
 #[must_use]
-pub struct SpinLockGuard<'a, T: Sized, Cpu: CpuOps> {
-    lock: &'a SpinLock<T, Cpu>,
-    interrupt_state: Cpu::InterruptState,
+pub struct RawSpinLockGuard<'a, T: Sized, Cpu: CpuOps> {
+    lock: &'a RawSpinLock<T, Cpu>,
+    _preempt: PreemptGuard<Cpu>,
 }
 
-impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for SpinLockGuard<'a, T, Cpu> {
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for RawSpinLockGuard<'a, T, Cpu> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -64,15 +103,330 @@ impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for SpinLockGuard<'a, T, Cpu> {
     }
 }
 
-impl<'a, T: Sized, Cpu: CpuOps> core::ops::DerefMut for SpinLockGuard<'a, T, Cpu> {
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::DerefMut for RawSpinLockGuard<'a, T, Cpu> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.lock.data.get() }
     }
 }
 
-impl<'a, T: Sized, Cpu: CpuOps> Drop for SpinLockGuard<'a, T, Cpu> {
+impl<'a, T: Sized, Cpu: CpuOps> Drop for RawSpinLockGuard<'a, T, Cpu> {
     fn drop(&mut self) {
+        let long_hold = self.lock.debug.unlocking::<Cpu>();
         self.lock.lock.store(false, Ordering::Release);
-        Cpu::enable_interrupts(self.interrupt_state);
+        if let Some(long_hold) = long_hold {
+            long_hold.report();
+        }
+    }
+}
+
+/// A spinlock that masks interrupts on the holding core, for data shared with
+/// IRQ handlers. Guards can be released in any order; see
+/// [`percpu::irq_save`].
+pub struct SpinLockIrq<T: Sized, Cpu: CpuOps> {
+    raw: RawSpinLock<T, Cpu>,
+}
+
+impl<T, Cpu: CpuOps> SpinLockIrq<T, Cpu> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new(data: T) -> Self {
+            SpinLockIrq {
+                raw: RawSpinLock::new(data),
+            }
+        }
+    }
+}
+
+impl<T: Sized, Cpu: CpuOps> SpinLockIrq<T, Cpu> {
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockIrqGuard<'_, T, Cpu> {
+        let irq = percpu::irq_save::<Cpu>();
+        SpinLockIrqGuard {
+            raw: self.raw.lock(),
+            _irq: irq,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinLockIrqGuard<'_, T, Cpu>> {
+        let irq = percpu::irq_save::<Cpu>();
+        self.raw
+            .try_lock()
+            .map(|raw| SpinLockIrqGuard { raw, _irq: irq })
+    }
+
+    #[track_caller]
+    pub fn lock_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.lock();
+        f(&mut *guard)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.is_locked()
+    }
+}
+
+/// Unlocks, then lets interrupts back in (fields drop in declaration order).
+#[must_use]
+pub struct SpinLockIrqGuard<'a, T: Sized, Cpu: CpuOps> {
+    raw: RawSpinLockGuard<'a, T, Cpu>,
+    _irq: IrqGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for SpinLockIrqGuard<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.raw
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::DerefMut for SpinLockIrqGuard<'a, T, Cpu> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.raw
+    }
+}
+
+/// A spinlock that holds off softirqs on the holding core, for data shared
+/// between process context and softirqs, tasklets or work items but never
+/// hard IRQ handlers. Interrupts stay enabled.
+pub struct SpinLockBh<T: Sized, Cpu: CpuOps> {
+    raw: RawSpinLock<T, Cpu>,
+}
+
+impl<T, Cpu: CpuOps> SpinLockBh<T, Cpu> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new(data: T) -> Self {
+            SpinLockBh {
+                raw: RawSpinLock::new(data),
+            }
+        }
+    }
+}
+
+impl<T: Sized, Cpu: CpuOps> SpinLockBh<T, Cpu> {
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockBhGuard<'_, T, Cpu> {
+        let bh = percpu::bh_disable::<Cpu>();
+        SpinLockBhGuard {
+            raw: self.raw.lock(),
+            _bh: bh,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinLockBhGuard<'_, T, Cpu>> {
+        let bh = percpu::bh_disable::<Cpu>();
+        self.raw
+            .try_lock()
+            .map(|raw| SpinLockBhGuard { raw, _bh: bh })
+    }
+
+    #[track_caller]
+    pub fn lock_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.lock();
+        f(&mut *guard)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.is_locked()
+    }
+}
+
+/// Unlocks, then lets softirqs back in (fields drop in declaration order).
+#[must_use]
+pub struct SpinLockBhGuard<'a, T: Sized, Cpu: CpuOps> {
+    raw: RawSpinLockGuard<'a, T, Cpu>,
+    _bh: BhGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for SpinLockBhGuard<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.raw
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::DerefMut for SpinLockBhGuard<'a, T, Cpu> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.raw
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    #[test]
+    fn raw_lock_leaves_interrupts_alone() {
+        TestCpu::bind(0);
+        let lock = RawSpinLock::<u32, TestCpu>::new(0);
+
+        let guard = lock.lock();
+        assert!(TestCpu::interrupts_enabled());
+        assert!(!percpu::preemptible::<TestCpu>());
+        assert!(lock.try_lock().is_none());
+
+        drop(guard);
+        assert!(percpu::preemptible::<TestCpu>());
+    }
+
+    #[test]
+    fn irq_locks_released_out_of_order() {
+        TestCpu::bind(0);
+        let first = SpinLockIrq::<u32, TestCpu>::new(1);
+        let second = SpinLockIrq::<u32, TestCpu>::new(2);
+
+        let a = first.lock();
+        let b = second.lock();
+        assert!(!TestCpu::interrupts_enabled());
+
+        drop(a);
+        assert!(!first.is_locked());
+        assert!(!TestCpu::interrupts_enabled());
+
+        drop(b);
+        assert!(TestCpu::interrupts_enabled());
+    }
+
+    #[test]
+    fn bh_lock_disables_softirqs_only() {
+        TestCpu::bind(0);
+        let lock = SpinLockBh::<u32, TestCpu>::new(0);
+
+        lock.lock_with(|value| {
+            *value += 1;
+            assert!(TestCpu::interrupts_enabled());
+            assert!(!percpu::bh_enabled::<TestCpu>());
+        });
+        assert!(percpu::bh_enabled::<TestCpu>());
+    }
+
+    #[test]
+    fn contended_increments_are_not_lost() {
+        const THREADS: usize = 4;
+        const ITERATIONS: usize = 1_000;
+
+        let lock = SpinLock::<usize, TestCpu>::new(0);
+        std::thread::scope(|scope| {
+            for cpu in 0..THREADS {
+                let lock = &lock;
+                scope.spawn(move || {
+                    TestCpu::bind(cpu);
+                    for _ in 0..ITERATIONS {
+                        lock.lock_with(|count| *count += 1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*lock.lock(), THREADS * ITERATIONS);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    fn increment(count: &mut UnsafeCell<usize>) {
+        count.with_mut(|count| unsafe { *count += 1 });
+    }
+
+    #[test]
+    fn lock_is_mutually_exclusive() {
+        loom::model(|| {
+            let lock = Arc::new(SpinLock::<_, TestCpu>::new(UnsafeCell::new(0)));
+
+            let other = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    lock.lock_with(increment);
+                    assert!(TestCpu::interrupts_enabled());
+                })
+            };
+
+            TestCpu::bind(0);
+            lock.lock_with(increment);
+            other.join().unwrap();
+
+            let count = lock.lock().with(|count| unsafe { *count });
+            assert_eq!(count, 2);
+            assert!(TestCpu::interrupts_enabled());
+        });
+    }
+
+    #[test]
+    fn failed_try_lock_leaves_no_trace() {
+        loom::model(|| {
+            let lock = Arc::new(RawSpinLock::<_, TestCpu>::new(UnsafeCell::new(0)));
+
+            let other = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    if let Some(mut guard) = lock.try_lock() {
+                        increment(&mut guard);
+                    }
+                    assert!(percpu::preemptible::<TestCpu>());
+                })
+            };
+
+            TestCpu::bind(0);
+            lock.lock_with(increment);
+            other.join().unwrap();
+            assert!(percpu::preemptible::<TestCpu>());
+        });
+    }
+
+    #[test]
+    fn guards_dropped_out_of_order() {
+        loom::model(|| {
+            let locks = Arc::new((
+                SpinLockIrq::<_, TestCpu>::new(UnsafeCell::new(0)),
+                SpinLockBh::<_, TestCpu>::new(UnsafeCell::new(0)),
+            ));
+
+            let other = {
+                let locks = locks.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    let mut bh = locks.1.lock();
+                    let mut irq = locks.0.lock();
+                    increment(&mut irq);
+                    increment(&mut bh);
+
+                    drop(bh);
+                    assert!(percpu::bh_enabled::<TestCpu>());
+                    assert!(!TestCpu::interrupts_enabled());
+                    drop(irq);
+                    assert!(TestCpu::interrupts_enabled());
+                })
+            };
+
+            TestCpu::bind(0);
+            let mut irq = locks.0.lock();
+            increment(&mut irq);
+            drop(irq);
+            assert!(TestCpu::interrupts_enabled());
+            locks.1.lock_with(increment);
+            assert!(percpu::bh_enabled::<TestCpu>());
+
+            other.join().unwrap();
+        });
     }
 }
@@ -0,0 +1,252 @@
+use core::mem::MaybeUninit;
+
+use super::atomic::{AtomicBool, AtomicUsize, Ordering};
+use super::cell::UnsafeCell;
+
+/// A fixed-capacity, lock-free queue with one producer and one consumer,
+/// for example an IRQ handler feeding a task.
+///
+/// Each end is claimed through [`producer`](SpscRing::producer) or
+/// [`consumer`](SpscRing::consumer), which hand out at most one handle per
+/// end at a time. `N` must be a power of two.
+pub struct SpscRing<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Next slot to pop, only advanced by the consumer.
+    head: AtomicUsize,
+    /// Next slot to push, only advanced by the producer.
+    tail: AtomicUsize,
+    producer_claimed: AtomicBool,
+    consumer_claimed: AtomicBool,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for SpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for SpscRing<T, N> {}
+
+impl<T, const N: usize> SpscRing<T, N> {
+    loom_const_fn! {
+        pub const fn new() -> Self {
+            const { assert!(N.is_power_of_two(), "Ring capacity must be a power of two") };
+
+            SpscRing {
+                #[cfg(not(loom))]
+                slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+                #[cfg(loom)]
+                slots: core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                producer_claimed: AtomicBool::new(false),
+                consumer_claimed: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// The producing end, unless it is already taken.
+    pub fn producer(&self) -> Option<SpscProducer<'_, T, N>> {
+        claim(&self.producer_claimed).then_some(SpscProducer { ring: self })
+    }
+
+    /// The consuming end, unless it is already taken.
+    pub fn consumer(&self) -> Option<SpscConsumer<'_, T, N>> {
+        claim(&self.consumer_claimed).then_some(SpscConsumer { ring: self })
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of queued items. Only a snapshot while the ends are in use.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        self.tail.load(Ordering::Acquire).wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+}
+
+impl<T, const N: usize> Drop for SpscRing<T, N> {
+    fn drop(&mut self) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let mut head = self.head.load(Ordering::Relaxed);
+        while head != tail {
+            self.slots[head % N].with_mut(|slot| unsafe { (*slot).assume_init_drop() });
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+impl<T, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Claim one end of a ring, returning whether it was free.
+pub(super) fn claim(claimed: &AtomicBool) -> bool {
+    claimed
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+}
+
+/// The producing end of an [`SpscRing`]; dropping it frees the end again.
+pub struct SpscProducer<'a, T, const N: usize> {
+    ring: &'a SpscRing<T, N>,
+}
+
+impl<'a, T, const N: usize> SpscProducer<'a, T, N> {
+    /// Queue `value`, handing it back if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let ring = self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(ring.head.load(Ordering::Acquire)) == N {
+            return Err(value);
+        }
+
+        ring.slots[tail % N].with_mut(|slot| unsafe { (*slot).write(value) });
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<'a, T, const N: usize> Drop for SpscProducer<'a, T, N> {
+    fn drop(&mut self) {
+        self.ring.producer_claimed.store(false, Ordering::Release);
+    }
+}
+
+/// The consuming end of an [`SpscRing`]; dropping it frees the end again.
+pub struct SpscConsumer<'a, T, const N: usize> {
+    ring: &'a SpscRing<T, N>,
+}
+
+impl<'a, T, const N: usize> SpscConsumer<'a, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        if head == ring.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = ring.slots[head % N].with(|slot| unsafe { (*slot).assume_init_read() });
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for SpscConsumer<'a, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+impl<'a, T, const N: usize> Drop for SpscConsumer<'a, T, N> {
+    fn drop(&mut self) {
+        self.ring.consumer_claimed.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fills_and_drains_in_order() {
+        let ring = SpscRing::<u32, 4>::new();
+        let mut producer = ring.producer().unwrap();
+        let mut consumer = ring.consumer().unwrap();
+        assert!(ring.producer().is_none());
+
+        for value in 0..4 {
+            producer.push(value).unwrap();
+        }
+        assert!(ring.is_full());
+        assert_eq!(producer.push(4), Err(4));
+
+        assert_eq!(consumer.pop(), Some(0));
+        producer.push(4).unwrap();
+        assert_eq!(consumer.by_ref().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert!(ring.is_empty());
+
+        drop(producer);
+        assert!(ring.producer().is_some());
+    }
+
+    #[test]
+    fn queued_items_are_dropped_with_the_ring() {
+        let marker = std::rc::Rc::new(());
+        let ring = SpscRing::<_, 2>::new();
+        ring.producer().unwrap().push(marker.clone()).unwrap();
+
+        drop(ring);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn stress_preserves_order() {
+        const ITEMS: usize = 100_000;
+
+        let ring = SpscRing::<usize, 16>::new();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut producer = ring.producer().unwrap();
+                for mut value in 0..ITEMS {
+                    while let Err(rejected) = producer.push(value) {
+                        value = rejected;
+                        std::thread::yield_now();
+                    }
+                }
+            });
+
+            let mut consumer = ring.consumer().unwrap();
+            let mut expected = 0;
+            while expected < ITEMS {
+                match consumer.pop() {
+                    Some(value) => {
+                        assert_eq!(value, expected);
+                        expected += 1;
+                    }
+                    None => std::thread::yield_now(),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+
+    #[test]
+    fn items_arrive_in_order() {
+        loom::model(|| {
+            let ring = Arc::new(SpscRing::<usize, 2>::new());
+
+            let producer = {
+                let ring = ring.clone();
+                thread::spawn(move || {
+                    let mut producer = ring.producer().unwrap();
+                    let pushed = (0..3).take_while(|&value| producer.push(value).is_ok());
+                    pushed.count()
+                })
+            };
+
+            let mut consumer = ring.consumer().unwrap();
+            let mut received = Vec::new();
+            received.extend(consumer.pop());
+            let pushed = producer.join().unwrap();
+            received.extend(consumer.by_ref());
+
+            assert_eq!(received, (0..pushed).collect::<Vec<_>>());
+        });
+    }
+}
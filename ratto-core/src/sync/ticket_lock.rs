@@ -0,0 +1,189 @@
+use super::atomic::{AtomicUsize, Ordering, spin_loop};
+use crate::cpu::CpuOps;
+use crate::percpu::{self, IrqGuard};
+
+/// A fair spinlock: waiters take a ticket and are served in arrival order, so
+/// no core can be starved under contention. Interrupts are disabled while the
+/// lock is held, like [`SpinLock`](super::SpinLock).
+pub struct TicketLock<T: Sized, Cpu: CpuOps> {
+    data: core::cell::UnsafeCell<T>,
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    _phantom: core::marker::PhantomData<Cpu>,
+}
+
+unsafe impl<T: Sized + Send, Cpu: CpuOps> Sync for TicketLock<T, Cpu> {}
+unsafe impl<T: Sized + Send, Cpu: CpuOps> Send for TicketLock<T, Cpu> {}
+
+impl<T, Cpu: CpuOps> TicketLock<T, Cpu> {
+    loom_const_fn! {
+        pub const fn new(data: T) -> Self {
+            TicketLock {
+                data: core::cell::UnsafeCell::new(data),
+                next_ticket: AtomicUsize::new(0),
+                now_serving: AtomicUsize::new(0),
+                _phantom: core::marker::PhantomData,
+            }
+        }
+    }
+}
+
+impl<T: Sized, Cpu: CpuOps> TicketLock<T, Cpu> {
+    pub fn lock(&self) -> TicketLockGuard<'_, T, Cpu> {
+        let irq = percpu::irq_save::<Cpu>();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+
+        TicketLockGuard {
+            lock: self,
+            _irq: irq,
+        }
+    }
+
+    /// Take the lock only if nobody holds or is waiting for it.
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T, Cpu>> {
+        let irq = percpu::irq_save::<Cpu>();
+        // Acquire pairs with the previous holder's release of `now_serving`
+        let serving = self.now_serving.load(Ordering::Acquire);
+        match self.next_ticket.compare_exchange(
+            serving,
+            serving.wrapping_add(1),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(TicketLockGuard {
+                lock: self,
+                _irq: irq,
+            }),
+            Err(_) => None,
+        }
+    }
+
+    pub fn lock_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.lock();
+        f(&mut *guard)
+    }
+
+    /// Whether the lock is held or contended.
+    pub fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+}
+
+#[must_use]
+pub struct TicketLockGuard<'a, T: Sized, Cpu: CpuOps> {
+    lock: &'a TicketLock<T, Cpu>,
+    _irq: IrqGuard<Cpu>,
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::Deref for TicketLockGuard<'a, T, Cpu> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> core::ops::DerefMut for TicketLockGuard<'a, T, Cpu> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Sized, Cpu: CpuOps> Drop for TicketLockGuard<'a, T, Cpu> {
+    fn drop(&mut self) {
+        // Only the holder advances `now_serving`
+        let serving = self.lock.now_serving.load(Ordering::Relaxed);
+        self.lock
+            .now_serving
+            .store(serving.wrapping_add(1), Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    #[test]
+    fn contended_increments_are_not_lost() {
+        const THREADS: usize = 4;
+        const ITERATIONS: usize = 1_000;
+
+        let lock = TicketLock::<usize, TestCpu>::new(0);
+        std::thread::scope(|scope| {
+            for cpu in 0..THREADS {
+                let lock = &lock;
+                scope.spawn(move || {
+                    TestCpu::bind(cpu);
+                    for _ in 0..ITERATIONS {
+                        lock.lock_with(|count| *count += 1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*lock.lock(), THREADS * ITERATIONS);
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn try_lock_fails_while_held() {
+        let lock = TicketLock::<(), TestCpu>::new(());
+
+        let guard = lock.lock();
+        assert!(!TestCpu::interrupts_enabled());
+        assert!(lock.try_lock().is_none());
+        assert!(!TestCpu::interrupts_enabled());
+
+        drop(guard);
+        assert!(TestCpu::interrupts_enabled());
+        assert!(lock.try_lock().is_some());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::cpu::test::TestCpu;
+
+    fn increment(count: &mut UnsafeCell<usize>) {
+        count.with_mut(|count| unsafe { *count += 1 });
+    }
+
+    #[test]
+    fn lock_is_mutually_exclusive() {
+        loom::model(|| {
+            let lock = Arc::new(TicketLock::<_, TestCpu>::new(UnsafeCell::new(0)));
+
+            let other = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    TestCpu::bind(1);
+                    match lock.try_lock() {
+                        Some(mut guard) => increment(&mut guard),
+                        None => lock.lock_with(increment),
+                    }
+                    assert!(TestCpu::interrupts_enabled());
+                })
+            };
+
+            TestCpu::bind(0);
+            lock.lock_with(increment);
+            other.join().unwrap();
+
+            let count = lock.lock().with(|count| unsafe { *count });
+            assert_eq!(count, 2);
+            assert!(TestCpu::interrupts_enabled());
+        });
+    }
+}
@@ -0,0 +1,261 @@
+use core::cell::Cell;
+use core::ptr;
+
+use super::SpinLock;
+use super::atomic::{AtomicBool, Ordering};
+use crate::percpu;
+use crate::sched::SchedOps;
+
+/// A FIFO of sleeping tasks, the building block of the blocking primitives.
+///
+/// Each waiter's queue entry lives on its own stack, so waiting never
+/// allocates. The queue is guarded by an IRQ-safe spinlock: interrupt
+/// handlers may wake tasks, but only task context may wait.
+pub struct WaitQueue<S: SchedOps> {
+    waiters: SpinLock<Waiters<S>, S::Cpu>,
+}
+
+impl<S: SchedOps> WaitQueue<S> {
+    loom_const_fn! {
+        #[track_caller]
+        pub const fn new() -> Self {
+            WaitQueue {
+                waiters: SpinLock::new(Waiters {
+                    head: ptr::null(),
+                    tail: ptr::null(),
+                }),
+            }
+        }
+    }
+
+    /// Sleep until `condition` returns true.
+    ///
+    /// `condition` runs with the queue locked, so a waker that makes it true
+    /// and then calls [`wake_one`](WaitQueue::wake_one) or
+    /// [`wake_all`](WaitQueue::wake_all) cannot slip in between the check and
+    /// the sleep. It runs with interrupts masked and must not block.
+    pub fn wait_until<F>(&self, mut condition: F)
+    where
+        F: FnMut() -> bool,
+    {
+        debug_assert!(
+            percpu::preemptible::<S::Cpu>(),
+            "Sleeping in atomic context"
+        );
+
+        loop {
+            let waiter = Waiter::<S> {
+                task: S::current_task(),
+                next: Cell::new(ptr::null()),
+                woken: AtomicBool::new(false),
+            };
+
+            {
+                let mut waiters = self.waiters.lock();
+                if condition() {
+                    return;
+                }
+                waiters.push(&waiter);
+            }
+
+            // Whoever dequeued us sets `woken` last, after which the entry
+            // may go out of scope
+            while !waiter.woken.load(Ordering::Acquire) {
+                S::park();
+            }
+        }
+    }
+
+    /// Wake the task that has waited longest. Returns whether there was one.
+    pub fn wake_one(&self) -> bool {
+        let waiter = self.waiters.lock().pop();
+        if waiter.is_null() {
+            return false;
+        }
+
+        unsafe { Waiter::wake(waiter) };
+        true
+    }
+
+    /// Wake every waiting task, returning how many there were.
+    pub fn wake_all(&self) -> usize {
+        let mut waiter = {
+            let mut waiters = self.waiters.lock();
+            waiters.tail = ptr::null();
+            core::mem::replace(&mut waiters.head, ptr::null())
+        };
+
+        let mut woken = 0;
+        while !waiter.is_null() {
+            let next = unsafe { (*waiter).next.get() };
+            unsafe { Waiter::wake(waiter) };
+            waiter = next;
+            woken += 1;
+        }
+        woken
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().head.is_null()
+    }
+}
+
+impl<S: SchedOps> Default for WaitQueue<S> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Waiter<S: SchedOps> {
+    task: S::Task,
+    next: Cell<*const Waiter<S>>,
+    woken: AtomicBool,
+}
+
+impl<S: SchedOps> Waiter<S> {
+    /// # Safety
+    /// `waiter` must have been dequeued by the caller and not yet woken.
+    unsafe fn wake(waiter: *const Self) {
+        let waiter = unsafe { &*waiter };
+        let task = waiter.task.clone();
+        waiter.woken.store(true, Ordering::Release);
+        S::unpark(&task);
+    }
+}
+
+/// The queued waiters, linked through their `next` fields.
+struct Waiters<S: SchedOps> {
+    head: *const Waiter<S>,
+    tail: *const Waiter<S>,
+}
+
+// The entries are only touched with the queue locked, or by the task that
+// dequeued them
+unsafe impl<S: SchedOps> Send for Waiters<S> {}
+
+impl<S: SchedOps> Waiters<S> {
+    fn push(&mut self, waiter: &Waiter<S>) {
+        match unsafe { self.tail.as_ref() } {
+            Some(tail) => tail.next.set(waiter),
+            None => self.head = waiter,
+        }
+        self.tail = waiter;
+    }
+
+    fn pop(&mut self) -> *const Waiter<S> {
+        let waiter = self.head;
+        if let Some(head) = unsafe { waiter.as_ref() } {
+            self.head = head.next.get();
+            if self.head.is_null() {
+                self.tail = ptr::null();
+            }
+        }
+        waiter
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn wakes_in_arrival_order() {
+        const TASKS: usize = 3;
+
+        let queue = WaitQueue::<TestSched>::new();
+        let turn = AtomicUsize::new(0);
+        let order = std::sync::Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for task in 0..TASKS {
+                let (queue, turn, order) = (&queue, &turn, &order);
+                scope.spawn(move || {
+                    // Queue up one at a time so the arrival order is known
+                    while turn.load(Ordering::Acquire) != task {
+                        std::thread::yield_now();
+                    }
+                    let mut queued = false;
+                    queue.wait_until(|| {
+                        if !queued {
+                            queued = true;
+                            turn.store(task + 1, Ordering::Release);
+                            return false;
+                        }
+                        true
+                    });
+                    order.lock().unwrap().push(task);
+                });
+            }
+
+            while turn.load(Ordering::Acquire) != TASKS {
+                std::thread::yield_now();
+            }
+            for woken in 1..=TASKS {
+                assert!(queue.wake_one());
+                while order.lock().unwrap().len() != woken {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        assert_eq!(*order.lock().unwrap(), [0, 1, 2]);
+        assert!(queue.is_empty());
+        assert!(!queue.wake_one());
+    }
+
+    #[test]
+    fn wake_all_releases_everyone() {
+        let queue = WaitQueue::<TestSched>::new();
+        let open = AtomicBool::new(false);
+        let waiting = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    waiting.fetch_add(1, Ordering::Relaxed);
+                    queue.wait_until(|| open.load(Ordering::Relaxed));
+                });
+            }
+
+            while waiting.load(Ordering::Relaxed) != 4 {
+                std::thread::yield_now();
+            }
+            open.store(true, Ordering::Relaxed);
+            queue.wake_all();
+        });
+
+        assert!(queue.is_empty());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod model {
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::*;
+    use crate::sched::test::TestSched;
+
+    #[test]
+    fn wakeup_is_not_lost() {
+        loom::model(|| {
+            let shared = Arc::new((WaitQueue::<TestSched>::new(), AtomicBool::new(false)));
+
+            let waker = {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    shared.1.store(true, Ordering::Relaxed);
+                    shared.0.wake_one();
+                })
+            };
+
+            shared.0.wait_until(|| shared.1.load(Ordering::Relaxed));
+            waker.join().unwrap();
+            assert!(shared.0.is_empty());
+        });
+    }
+}
@@ -1,10 +1,22 @@
 use core::cell::SyncUnsafeCell;
 
+use ratto_core::collections::{ArrayVec, CapacityError};
+use ratto_core::fdt::Fdt;
 use ratto_kernel::{
-    arch::aarch64::mem::{MemoryRegion, MemoryRegionType},
-    klog,
+    arch::{
+        self,
+        aarch64::{
+            mem::{self, MemoryRegion, MemoryRegionType},
+            smp::{CpuInfo, EnableMethod, PsciConduit},
+        },
+    },
+    kerr, klog,
 };
 
+/// Spin-table release addresses used by the Raspberry Pi 3 firmware stub,
+/// for when the DTB does not describe the cores.
+const RASPI3_RELEASE_ADDRS: [u64; 4] = [0xd8, 0xe0, 0xe8, 0xf0];
+
 unsafe extern "C" {
     unsafe static __kernel_start: u8;
     unsafe static __kernel_end: u8;
@@ -14,69 +26,147 @@ unsafe extern "C" {
 #[unsafe(no_mangle)]
 static mut __dtb_ptr: u64 = 0;
 
-static MEMORY_REGIONS: SyncUnsafeCell<[MemoryRegion; 16]> = SyncUnsafeCell::new(
-    [MemoryRegion {
-        start: 0,
-        size: 0,
-        kind: MemoryRegionType::Reserved,
-    }; 16],
-);
-
-#[derive(Debug)]
-pub struct Dtb {
-    base: *const u8,
-}
+/// Most memory map entries the boot code keeps before giving up.
+const MAX_MEMORY_REGIONS: usize = 16;
 
-impl Dtb {
-    pub unsafe fn from_phys_addr(addr: u64) -> Option<Self> {
-        Some(Dtb {
-            base: addr as *const u8,
-        })
-    }
-}
+static MEMORY_REGIONS: SyncUnsafeCell<ArrayVec<MemoryRegion, MAX_MEMORY_REGIONS>> =
+    SyncUnsafeCell::new(ArrayVec::new());
+
+static CPUS: SyncUnsafeCell<ArrayVec<CpuInfo, { arch::MAX_CPUS }>> =
+    SyncUnsafeCell::new(ArrayVec::new());
 
 fn kernel_phys_range() -> (u64, u64) {
     unsafe {
         (
-            &__kernel_start as *const _ as u64,
-            &__kernel_end as *const _ as u64,
+            mem::virt_to_phys(&__kernel_start as *const _ as usize),
+            mem::virt_to_phys(&__kernel_end as *const _ as usize),
         )
     }
 }
 
 // TODO: Proper DTB parsing
-unsafe fn parse_memory_map(_dtb: &Dtb) -> &'static [MemoryRegion] {
-    // TODO: remove this, just to avoid unused variable warning
-    _ = _dtb.base;
-
+unsafe fn parse_memory_map(
+    _dtb: Option<&Fdt>,
+) -> Result<&'static [MemoryRegion], CapacityError<MemoryRegion>> {
     let regions = unsafe { &mut *MEMORY_REGIONS.get() };
-    let mut count = 0;
+    regions.clear();
 
     // Example: RAM (from DTB /memory)
-    regions[count] = MemoryRegion {
+    regions.push(MemoryRegion {
         start: 0x0000_0000,
         size: 0x3B00_0000,
         kind: MemoryRegionType::Usable,
-    };
-    count += 1;
+    })?;
 
     // Firmware reserved
-    regions[count] = MemoryRegion {
+    regions.push(MemoryRegion {
         start: 0x3B00_0000,
         size: 0x0500_0000,
         kind: MemoryRegionType::Firmware,
-    };
-    count += 1;
+    })?;
 
     // MMIO
-    regions[count] = MemoryRegion {
+    regions.push(MemoryRegion {
         start: 0xFE00_0000,
         size: 0x0200_0000,
         kind: MemoryRegionType::Mmio,
+    })?;
+
+    Ok(regions)
+}
+
+/// Read the cores and their `enable-method` from `/cpus`, falling back to the
+/// Raspberry Pi 3 spin table if the DTB has no usable description.
+unsafe fn parse_cpus(dtb: Option<&Fdt>) -> &'static [CpuInfo] {
+    let cpus = unsafe { &mut *CPUS.get() };
+    cpus.clear();
+
+    let psci_conduit = dtb
+        .and_then(|dtb| dtb.find_node("/psci"))
+        .and_then(|psci| psci.property("method"))
+        .and_then(|method| match method.as_str()? {
+            "hvc" => Some(PsciConduit::Hvc),
+            "smc" => Some(PsciConduit::Smc),
+            _ => None,
+        });
+
+    if let Some(cpus_node) = dtb.and_then(|dtb| dtb.find_node("/cpus")) {
+        let (address_cells, _) = cpus_node.cell_sizes();
+
+        for node in cpus_node
+            .children()
+            .filter(|node| node.base_name() == "cpu")
+        {
+            let Some(mpidr) = node
+                .property("reg")
+                .and_then(|reg| reg.cells(0, address_cells))
+            else {
+                continue;
+            };
+
+            let method = node
+                .property("enable-method")
+                .and_then(|method| method.as_str());
+            let enable_method = match method {
+                Some("spin-table") => node
+                    .property("cpu-release-addr")
+                    .and_then(|addr| addr.as_u64())
+                    .map(|release_addr| EnableMethod::SpinTable { release_addr }),
+                Some("psci") => psci_conduit.map(|conduit| EnableMethod::Psci { conduit }),
+                _ => None,
+            };
+
+            let Some(enable_method) = enable_method else {
+                kerr!("No usable enable-method for {}", node.name());
+                continue;
+            };
+
+            let cpu = CpuInfo {
+                mpidr,
+                enable_method,
+            };
+            if cpus.push(cpu).is_err() {
+                kerr!(
+                    "Ignoring {}: more than {} cores",
+                    node.name(),
+                    cpus.capacity()
+                );
+            }
+        }
+    }
+
+    if cpus.is_empty() {
+        for (index, release_addr) in RASPI3_RELEASE_ADDRS.into_iter().enumerate() {
+            let cpu = CpuInfo {
+                mpidr: index as u64,
+                enable_method: EnableMethod::SpinTable { release_addr },
+            };
+            if cpus.push(cpu).is_err() {
+                break;
+            }
+        }
+    }
+
+    cpus
+}
+
+/// Where the bootloader loaded the initramfs, from `/chosen`, or `(0, 0)`.
+fn parse_initrd(dtb: Option<&Fdt>) -> (u64, u64) {
+    let chosen = dtb.and_then(|dtb| dtb.find_node("/chosen"));
+    let property = |name| {
+        chosen
+            .and_then(|chosen| chosen.property(name))
+            .and_then(|property| property.as_u64())
     };
-    count += 1;
 
-    &regions[..count]
+    match (property("linux,initrd-start"), property("linux,initrd-end")) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        (None, None) => (0, 0),
+        _ => {
+            kerr!("Ignoring malformed initrd range in /chosen");
+            (0, 0)
+        }
+    }
 }
 
 pub fn boot_info() -> ratto_kernel::arch::aarch64::boot::BootInfo {
@@ -88,10 +178,19 @@ pub fn boot_info() -> ratto_kernel::arch::aarch64::boot::BootInfo {
 
     klog!("DTB physical address: {:#x}", dtb_phys);
 
-    let dtb = unsafe { Dtb::from_phys_addr(dtb_phys).expect("invalid DTB") };
+    let dtb = unsafe { Fdt::from_ptr(mem::phys_to_virt(dtb_phys) as *const u8) };
+    if let Err(error) = dtb {
+        kerr!("Unable to parse DTB: {:?}", error);
+    }
 
-    let memory_map = unsafe { parse_memory_map(&dtb) };
+    let memory_map = match unsafe { parse_memory_map(dtb.as_ref().ok()) } {
+        Ok(memory_map) => memory_map,
+        Err(_) => panic!("Memory map has more than {MAX_MEMORY_REGIONS} regions"),
+    };
+    let cpus = unsafe { parse_cpus(dtb.as_ref().ok()) };
     let kernel_range = kernel_phys_range();
+    let dtb_size = dtb.as_ref().map_or(0, |dtb| dtb.total_size() as u64);
+    let initrd_range = parse_initrd(dtb.as_ref().ok());
 
     klog!(
         "Kernel physical range: {:#x} - {:#x}",
@@ -103,5 +202,10 @@ pub fn boot_info() -> ratto_kernel::arch::aarch64::boot::BootInfo {
         memory_map,
         kernel_phys_start: kernel_range.0,
         kernel_phys_end: kernel_range.1,
+        dtb_phys_start: dtb_phys,
+        dtb_phys_end: dtb_phys + dtb_size,
+        initrd_phys_start: initrd_range.0,
+        initrd_phys_end: initrd_range.1,
+        cpus,
     }
 }
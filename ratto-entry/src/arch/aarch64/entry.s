@@ -19,6 +19,11 @@ _start:
     cmp     x0, x1
     b.ne    .L_parking_loop
 
+    bl      .L_drop_to_el1
+
+    // No per-CPU area until the kernel installs one
+    msr     TPIDR_EL1, xzr
+
     //--------------------------------------------------------------------------
     // Store DTB pointer for Rust
     //--------------------------------------------------------------------------
@@ -42,13 +47,23 @@ _start:
     b       .L_bss_init_loop
 
     //--------------------------------------------------------------------------
-    // Prepare jump to Rust
+    // Turn on the MMU and continue in the upper half, where the kernel is
+    // linked
     //--------------------------------------------------------------------------
 .L_prepare_rust:
-    // Set up the stack (boot core only)
-    adrp    x0, __boot_core_stack_end_exclusive
-    add     x0, x0, :lo12:__boot_core_stack_end_exclusive
-    mov     sp, x0
+    bl      .L_build_boot_tables
+    bl      .L_enable_mmu
+    ldr     x0, =.L_boot_core_high
+    br      x0
+
+.L_boot_core_high:
+    bl      .L_drop_identity_map
+
+    //--------------------------------------------------------------------------
+    // Prepare jump to Rust
+    //--------------------------------------------------------------------------
+    // Set up the stack
+    bl      .L_set_core_stack
 
     // Pass DTB pointer to Rust as argument (optional but nice)
     mov     x0, x20
@@ -57,10 +72,173 @@ _start:
     b       _start_rust
 
     //--------------------------------------------------------------------------
-    // Non-boot cores park here
+    // Cores that enter here without being released by the kernel park forever
     //--------------------------------------------------------------------------
 .L_parking_loop:
     wfe
     b       .L_parking_loop
 
 .size _start, . - _start
+
+//------------------------------------------------------------------------------
+// Secondary core entry, released by the kernel through the spin table or PSCI
+//------------------------------------------------------------------------------
+.global _start_secondary
+.type _start_secondary, function
+
+_start_secondary:
+    bl      .L_drop_to_el1
+    msr     TPIDR_EL1, xzr
+
+    // The boot core has built the tables already
+    bl      .L_enable_mmu
+    ldr     x0, =.L_secondary_high
+    br      x0
+
+.L_secondary_high:
+    bl      .L_drop_identity_map
+    bl      .L_set_core_stack
+    b       _start_secondary_rust
+
+.size _start_secondary, . - _start_secondary
+
+//------------------------------------------------------------------------------
+// Drop from EL2 to EL1 (exceptions and IRQs are taken at EL1), returning to
+// the caller. Clobbers x0.
+//------------------------------------------------------------------------------
+.L_drop_to_el1:
+    mrs     x0, CurrentEL
+    cmp     x0, {CONST_CURRENT_EL_EL2}
+    b.ne    .L_drop_to_el1_done
+
+    // Let EL1 use the physical timer and counter
+    mrs     x0, CNTHCTL_EL2
+    orr     x0, x0, #0b11
+    msr     CNTHCTL_EL2, x0
+    msr     CNTVOFF_EL2, xzr
+
+    // EL1 runs AArch64
+    mov     x0, {CONST_HCR_EL2_RW}
+    msr     HCR_EL2, x0
+
+    // "Return" to the caller in EL1h with all exceptions masked
+    mov     x0, {CONST_SPSR_EL1H_MASKED}
+    msr     SPSR_EL2, x0
+    msr     ELR_EL2, x30
+    eret
+
+.L_drop_to_el1_done:
+    ret
+
+//------------------------------------------------------------------------------
+// Fill in the boot tables, which map the first two GiB of physical memory
+// both at 0, for the switch to the upper half, and at the upper half itself.
+// Both halves use the same level 1 table. Clobbers x0-x7.
+//------------------------------------------------------------------------------
+.L_build_boot_tables:
+    adrp    x0, __boot_l1
+    adrp    x1, __boot_l2
+
+    // First GiB: RAM, then the peripherals, in 2 MiB blocks of __boot_l2
+    orr     x2, x1, {CONST_TABLE_DESC}
+    str     x2, [x0]
+
+    // Second GiB: the local interrupt controller, as a single block
+    ldr     x2, ={CONST_BLOCK_DEVICE}
+    orr     x2, x2, #0x40000000
+    str     x2, [x0, #8]
+
+    mov     x3, xzr
+    ldr     x4, ={CONST_MMIO_START}
+    ldr     x5, ={CONST_BLOCK_NORMAL}
+    ldr     x6, ={CONST_BLOCK_DEVICE}
+    mov     x7, #0x40000000
+
+.L_build_l2_loop:
+    cmp     x3, x4
+    csel    x2, x5, x6, lo
+    orr     x2, x2, x3
+    str     x2, [x1], #8
+    add     x3, x3, #0x200000
+    cmp     x3, x7
+    b.lo    .L_build_l2_loop
+
+    ret
+
+//------------------------------------------------------------------------------
+// Turn on the MMU and caches with the boot tables in both TTBR0_EL1 and
+// TTBR1_EL1, returning to the caller, which is still identity mapped.
+// Clobbers x0-x1.
+//------------------------------------------------------------------------------
+.L_enable_mmu:
+    ldr     x0, ={CONST_MAIR}
+    msr     MAIR_EL1, x0
+    ldr     x0, ={CONST_TCR}
+    msr     TCR_EL1, x0
+
+    adrp    x0, __boot_l1
+    msr     TTBR0_EL1, x0
+    msr     TTBR1_EL1, x0
+
+    tlbi    vmalle1
+    dsb     nsh
+    isb
+
+    mrs     x0, SCTLR_EL1
+    ldr     x1, ={CONST_SCTLR_MMU_ON}
+    orr     x0, x0, x1
+    msr     SCTLR_EL1, x0
+    isb
+
+    ret
+
+//------------------------------------------------------------------------------
+// Once running in the upper half, point TTBR0_EL1 at __empty_l1 so user
+// addresses fault until a process is activated. Clobbers x0-x1.
+//------------------------------------------------------------------------------
+.L_drop_identity_map:
+    adrp    x0, __empty_l1
+    ldr     x1, ={CONST_KERNEL_VIRT_BASE}
+    sub     x0, x0, x1
+    msr     TTBR0_EL1, x0
+    isb
+
+    // The identity map's entries are global, so would outlive the switch
+    tlbi    vmalle1
+    dsb     nsh
+    isb
+
+    ret
+
+//------------------------------------------------------------------------------
+// Point SP at the top of this core's stack in .core_stacks. Clobbers x0-x2.
+//------------------------------------------------------------------------------
+.L_set_core_stack:
+    mrs     x0, MPIDR_EL1
+    and     x0, x0, {CONST_CORE_ID_MASK}
+    add     x0, x0, #1
+
+    adrp    x1, __core_stacks_start
+    add     x1, x1, :lo12:__core_stacks_start
+    mov     x2, {CONST_CORE_STACK_SIZE}
+    madd    x1, x0, x2, x1
+
+    mov     sp, x1
+    ret
+
+//------------------------------------------------------------------------------
+// Translation tables, zeroed along with the rest of .bss. Empty entries are
+// invalid.
+//------------------------------------------------------------------------------
+.section .bss.boot_page_tables, "aw", @nobits
+.balign 4096
+__boot_l1:
+    .space 4096
+
+__boot_l2:
+    .space 4096
+
+// Used as TTBR0_EL1 by cores running no user process
+.global __empty_l1
+__empty_l1:
+    .space 4096
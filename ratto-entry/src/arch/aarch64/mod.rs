@@ -1,5 +1,6 @@
 use core::arch::global_asm;
 
+use ratto_kernel::arch::aarch64::mem;
 use ratto_kernel::{Kernel, KernelArgs, console::Console};
 
 mod boot;
@@ -7,7 +8,19 @@ mod cpu;
 
 global_asm!(
     include_str!("entry.s"),
-    CONST_CORE_ID_MASK = const 0b11
+    CONST_CORE_ID_MASK = const 0b11,
+    CONST_CURRENT_EL_EL2 = const 0b1000,
+    CONST_HCR_EL2_RW = const 1 << 31,
+    CONST_SPSR_EL1H_MASKED = const 0x3c5,
+    CONST_CORE_STACK_SIZE = const ratto_kernel::arch::aarch64::smp::CORE_STACK_SIZE,
+    CONST_KERNEL_VIRT_BASE = const mem::KERNEL_VIRT_BASE,
+    CONST_MMIO_START = const mem::MMIO_START,
+    CONST_TABLE_DESC = const mem::BOOT_TABLE_DESC,
+    CONST_BLOCK_NORMAL = const mem::BOOT_BLOCK_NORMAL,
+    CONST_BLOCK_DEVICE = const mem::BOOT_BLOCK_DEVICE,
+    CONST_MAIR = const mem::MAIR,
+    CONST_TCR = const mem::TCR,
+    CONST_SCTLR_MMU_ON = const mem::SCTLR_MMU_ON,
 );
 
 #[unsafe(no_mangle)]
@@ -35,3 +48,8 @@ pub unsafe fn _start_rust() -> ! {
     Kernel::init2(args);
     Kernel::run();
 }
+
+#[unsafe(no_mangle)]
+pub unsafe fn _start_secondary_rust() -> ! {
+    Kernel::secondary_main();
+}
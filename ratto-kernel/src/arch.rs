@@ -4,23 +4,146 @@ pub mod aarch64;
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::AArch64 as Impl;
 
+use core::ops::Range;
+
+use ratto_core::mem::PhysicalAddress;
+
 pub trait ArchImpl {
     type Cpu: ratto_core::cpu::CpuOps;
     type BootInfo: ratto_core::boot::BootInfo;
     type MemoryMapper: ratto_core::mem::MemoryMapper;
     type FrameAllocator: ratto_core::mem::FrameAllocator;
+    type Context: ContextOps;
+    type Uart: UartOps;
+
+    /// Upper bound on the number of cores the kernel will ever run on.
+    const MAX_CPUS: usize;
+
+    /// User addresses are below this.
+    const USER_END: usize;
+
+    /// `e_machine` of the ELF executables the cores run.
+    const ELF_MACHINE: u16;
+
+    /// Install the exception vectors for the executing core.
+    fn init_exceptions();
+
+    /// Release the other cores, which enter `Kernel::secondary_main`.
+    fn start_secondaries(boot_info: &Self::BootInfo);
+
+    /// Start the periodic scheduler tick on the executing core, `hz` times a
+    /// second. Interrupts stay masked until the caller unmasks them.
+    fn start_tick(hz: u32);
+
+    /// Let the executing core receive [`send_ipi`](ArchImpl::send_ipi).
+    fn enable_ipi();
+
+    /// Interrupt `cpu`, which services it as [`Irq::Ipi`](crate::irq::Irq).
+    fn send_ipi(cpu: usize);
+
+    /// The interrupt the executing core should service next, if any.
+    fn pending_irq() -> Option<crate::irq::Irq>;
+
+    /// Quiet the source of `irq` so it does not fire again straight away.
+    fn ack_irq(irq: crate::irq::Irq);
+
+    /// Frame pointer of the caller, the start of a frame record chain.
+    fn frame_pointer() -> usize;
+
+    /// Bounds of the kernel stack containing `addr`, if it is on one.
+    fn stack_bounds(addr: usize) -> Option<Range<usize>>;
+
+    /// The kernel's address of physical memory at `addr`.
+    fn phys_to_virt(addr: PhysicalAddress) -> usize;
+
+    /// Make code written at `addr..addr + len` visible to instruction
+    /// fetches through any address it is mapped at.
+    fn sync_instruction_cache(addr: usize, len: usize);
+
+    /// Restart the machine.
+    fn reset() -> !;
+
+    /// Drop to user mode at `entry`, with the stack pointer at `stack_top`
+    /// and `arg` as the first argument. Exceptions taken from user mode use
+    /// the rest of the current stack, and return to it where they left off.
+    ///
+    /// # Safety
+    /// The active address space must map `entry` and the stack for user
+    /// mode, and the current stack must stay with the process.
+    unsafe fn enter_user(entry: usize, stack_top: usize, arg: usize) -> !;
 
     fn init_memory(
         boot_info: &Self::BootInfo,
     ) -> Result<(Self::MemoryMapper, Self::FrameAllocator), &'static str>;
 }
 
+/// Register state of a task that is not running.
+pub trait ContextOps: Sized {
+    /// A context that has never run, to be overwritten by its first switch.
+    const EMPTY: Self;
+
+    /// A context that, once switched to, calls `entry(arg)` on the stack
+    /// ending at `stack_top`.
+    fn new(stack_top: usize, entry: extern "C" fn(usize) -> !, arg: usize) -> Self;
+
+    /// Save the executing task's registers into `prev` and resume `next`.
+    /// Returns when something switches back to `prev`.
+    ///
+    /// # Safety
+    /// Interrupts must be masked, and `next` must hold a context saved by a
+    /// previous switch or made by [`ContextOps::new`] whose stack is still
+    /// live. Neither may be in use by another core.
+    unsafe fn switch(prev: *mut Self, next: *const Self);
+}
+
+/// The serial port behind [`io::uart`](crate::io::uart), driven by its
+/// interrupt. Received bytes and room to send are signalled as
+/// [`Irq::Uart`](crate::irq::Irq::Uart), after which the UART's interrupts
+/// stay masked until [`listen`](UartOps::listen) is called again.
+pub trait UartOps {
+    /// Route the UART's interrupt to the kernel, with every source masked.
+    fn init();
+
+    /// Take a received byte, if one is waiting.
+    fn try_read() -> Option<u8>;
+
+    /// Queue `byte` for sending, unless the transmitter is full.
+    fn try_write(byte: u8) -> bool;
+
+    /// Interrupt once a byte has arrived (`rx`) or there is room to send
+    /// (`tx`).
+    fn listen(rx: bool, tx: bool);
+
+    /// Mask and clear every interrupt source of the UART.
+    fn mask_interrupts();
+}
+
 pub type Cpu = <Impl as ArchImpl>::Cpu;
 pub type MemoryMapper = <Impl as ArchImpl>::MemoryMapper;
 pub type FrameAllocator = <Impl as ArchImpl>::FrameAllocator;
 pub type BootInfo = <Impl as ArchImpl>::BootInfo;
+pub type Context = <Impl as ArchImpl>::Context;
+pub type Uart = <Impl as ArchImpl>::Uart;
+
+pub const MAX_CPUS: usize = <Impl as ArchImpl>::MAX_CPUS;
+pub const USER_END: usize = <Impl as ArchImpl>::USER_END;
 
 pub mod sync {
     pub type SpinLock<T> = ratto_core::sync::SpinLock<T, super::Cpu>;
+    pub type RawSpinLock<T> = ratto_core::sync::RawSpinLock<T, super::Cpu>;
+    pub type SpinLockIrq<T> = ratto_core::sync::SpinLockIrq<T, super::Cpu>;
+    pub type SpinLockBh<T> = ratto_core::sync::SpinLockBh<T, super::Cpu>;
     pub type OnceLock<T> = ratto_core::sync::OnceLock<T, super::Cpu>;
+    pub type Lazy<T, F = fn() -> T> = ratto_core::sync::Lazy<T, super::Cpu, F>;
+    pub type RwSpinLock<T> = ratto_core::sync::RwSpinLock<T, super::Cpu>;
+    pub type TicketLock<T> = ratto_core::sync::TicketLock<T, super::Cpu>;
+    pub type McsLock<T> = ratto_core::sync::McsLock<T, super::Cpu, { super::MAX_CPUS }>;
+
+    pub type WaitQueue = ratto_core::sync::WaitQueue<crate::sched::Sched>;
+    pub type Mutex<T> = ratto_core::sync::Mutex<T, crate::sched::Sched>;
+    pub type MutexGuard<'a, T> = ratto_core::sync::MutexGuard<'a, T, crate::sched::Sched>;
+    pub type Semaphore = ratto_core::sync::Semaphore<crate::sched::Sched>;
+    pub type Condvar = ratto_core::sync::Condvar<crate::sched::Sched>;
+    pub type Event = ratto_core::sync::Event<crate::sched::Sched>;
+    pub type Completion = ratto_core::sync::Completion<crate::sched::Sched>;
 }
@@ -1,4 +1,7 @@
+use core::ops::Range;
+
 use crate::arch::aarch64::mem::MemoryRegion;
+use crate::arch::aarch64::smp::CpuInfo;
 
 #[derive(Debug, Clone)]
 pub struct BootInfo {
@@ -8,6 +11,22 @@ pub struct BootInfo {
     /// Kernel physical base
     pub kernel_phys_start: u64,
     pub kernel_phys_end: u64,
+
+    /// Where the DTB was loaded, which stays in use
+    pub dtb_phys_start: u64,
+    pub dtb_phys_end: u64,
+
+    /// Where the bootloader loaded the initramfs, both zero without one
+    pub initrd_phys_start: u64,
+    pub initrd_phys_end: u64,
+
+    /// Cores described by the DTB, including the boot core
+    pub cpus: &'static [CpuInfo],
 }
 
-impl ratto_core::boot::BootInfo for BootInfo {}
+impl ratto_core::boot::BootInfo for BootInfo {
+    fn initrd(&self) -> Option<Range<u64>> {
+        (self.initrd_phys_start < self.initrd_phys_end)
+            .then_some(self.initrd_phys_start..self.initrd_phys_end)
+    }
+}
@@ -0,0 +1,50 @@
+use core::arch::global_asm;
+
+use crate::arch::ContextOps;
+
+global_asm!(include_str!("context.s"));
+
+unsafe extern "C" {
+    fn __switch_context(prev: *mut Context, next: *const Context);
+    unsafe static __task_trampoline: u8;
+}
+
+/// Registers preserved across a switch, in the order `context.s` stores them.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Context {
+    /// Callee-saved x19..=x28
+    x: [u64; 10],
+    fp: u64,
+    lr: u64,
+    sp: u64,
+    tpidr_el0: u64,
+}
+
+impl ContextOps for Context {
+    const EMPTY: Self = Context {
+        x: [0; 10],
+        fp: 0,
+        lr: 0,
+        sp: 0,
+        tpidr_el0: 0,
+    };
+
+    fn new(stack_top: usize, entry: extern "C" fn(usize) -> !, arg: usize) -> Self {
+        let mut x = [0; 10];
+        x[0] = entry as usize as u64;
+        x[1] = arg as u64;
+
+        Context {
+            x,
+            fp: 0,
+            lr: unsafe { &__task_trampoline as *const _ as u64 },
+            sp: stack_top as u64 & !0xf,
+            tpidr_el0: 0,
+        }
+    }
+
+    unsafe fn switch(prev: *mut Self, next: *const Self) {
+        unsafe { __switch_context(prev, next) }
+    }
+}
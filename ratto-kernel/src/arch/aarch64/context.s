@@ -0,0 +1,50 @@
+//------------------------------------------------------------------------------
+// Task context switch
+//------------------------------------------------------------------------------
+// __switch_context(prev: *mut Context, next: *const Context)
+//
+// Saves the callee-saved registers, SP and TPIDR_EL0 of the calling task into
+// `prev` and resumes `next` where it last called __switch_context. The caller
+// has already spilled everything else, as for any other call.
+
+.section .text.context
+.global __switch_context
+.type __switch_context, function
+__switch_context:
+    stp     x19, x20, [x0, #16 * 0]
+    stp     x21, x22, [x0, #16 * 1]
+    stp     x23, x24, [x0, #16 * 2]
+    stp     x25, x26, [x0, #16 * 3]
+    stp     x27, x28, [x0, #16 * 4]
+    stp     x29, x30, [x0, #16 * 5]
+    mov     x9, sp
+    mrs     x10, TPIDR_EL0
+    stp     x9, x10, [x0, #16 * 6]
+
+    ldp     x19, x20, [x1, #16 * 0]
+    ldp     x21, x22, [x1, #16 * 1]
+    ldp     x23, x24, [x1, #16 * 2]
+    ldp     x25, x26, [x1, #16 * 3]
+    ldp     x27, x28, [x1, #16 * 4]
+    ldp     x29, x30, [x1, #16 * 5]
+    ldp     x9, x10, [x1, #16 * 6]
+    mov     sp, x9
+    msr     TPIDR_EL0, x10
+    ret
+
+.size __switch_context, . - __switch_context
+
+//------------------------------------------------------------------------------
+// First return of a new task's context: call entry(arg), which never returns
+//------------------------------------------------------------------------------
+// x19 = entry, x20 = arg, set up by Context::new. x29 is zero so that
+// backtraces stop here.
+
+.global __task_trampoline
+.type __task_trampoline, function
+__task_trampoline:
+    mov     x0, x20
+    blr     x19
+    brk     #0
+
+.size __task_trampoline, . - __task_trampoline
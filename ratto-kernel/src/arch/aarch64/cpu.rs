@@ -1,6 +1,10 @@
 use core::arch::asm;
 
 use ratto_core::cpu::CpuOps;
+use ratto_core::percpu::PerCpuArea;
+
+/// DAIF.I, the IRQ mask bit.
+const DAIF_IRQ: u64 = 1 << 7;
 
 pub struct Cpu;
 
@@ -31,6 +35,73 @@ impl CpuOps for Cpu {
         }
     }
 
+    fn interrupts_were_enabled(flags: Self::InterruptState) -> bool {
+        flags & DAIF_IRQ == 0
+    }
+
+    fn unmask_interrupts() -> Self::InterruptState {
+        let flags: u64;
+        unsafe {
+            asm!(
+                "mrs {0}, daif",
+                "msr daifclr, #2",
+                out(reg) flags,
+                options(nomem, nostack)
+            );
+        }
+
+        flags
+    }
+
+    fn monotonic_ns() -> u64 {
+        let (count, frequency): (u64, u64);
+        unsafe {
+            asm!(
+                "isb",
+                "mrs {0}, cntpct_el0",
+                "mrs {1}, cntfrq_el0",
+                out(reg) count,
+                out(reg) frequency,
+                options(nomem, nostack)
+            );
+        }
+
+        (count as u128 * 1_000_000_000 / frequency.max(1) as u128) as u64
+    }
+
+    fn current_cpu_id() -> usize {
+        let mpidr: u64;
+        unsafe {
+            asm!("mrs {0}, mpidr_el1", out(reg) mpidr, options(nomem, nostack));
+        }
+
+        (mpidr & 0xff) as usize
+    }
+
+    fn per_cpu_area() -> Option<&'static PerCpuArea> {
+        let area: usize;
+        unsafe {
+            asm!("mrs {0}, tpidr_el1", out(reg) area, options(nomem, nostack));
+            (area as *const PerCpuArea).as_ref()
+        }
+    }
+
+    unsafe fn set_per_cpu_area(area: &'static PerCpuArea) {
+        unsafe {
+            asm!(
+                "msr tpidr_el1, {0}",
+                in(reg) area as *const PerCpuArea as usize,
+                options(nomem, nostack)
+            );
+        }
+    }
+
+    fn wait_for_interrupt() {
+        unsafe {
+            asm!("dsb sy", "wfi", options(nomem, nostack));
+        }
+    }
+
     fn wait_forever() -> ! {
         use core::arch::asm;
 
@@ -41,3 +112,27 @@ impl CpuOps for Cpu {
         }
     }
 }
+
+/// Clean the data cache over `addr..addr + len` to the point where
+/// instruction fetches see it, then drop every core's instruction cache,
+/// which may hold the code under another address.
+pub fn sync_instruction_cache(addr: usize, len: usize) {
+    let ctr: u64;
+    unsafe {
+        asm!("mrs {0}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+    }
+
+    // CTR_EL0.DminLine: log2 of the smallest data cache line, in words
+    let line: usize = 4 << ((ctr >> 16) & 0xf);
+    let mut line_addr = addr & !(line - 1);
+    while line_addr < addr + len {
+        unsafe {
+            asm!("dc cvau, {0}", in(reg) line_addr, options(nostack));
+        }
+        line_addr += line;
+    }
+
+    unsafe {
+        asm!("dsb ish", "ic ialluis", "dsb ish", "isb", options(nostack));
+    }
+}
@@ -0,0 +1,241 @@
+use core::arch::{asm, global_asm};
+use core::fmt::Debug;
+
+use ratto_core::cpu::CpuOps;
+
+use crate::arch::Cpu;
+use crate::ksyms::Symbolized;
+use crate::{irq, process, syscall};
+
+global_asm!(
+    include_str!("exception.s"),
+    CONST_TRAP_FRAME_SIZE = const core::mem::size_of::<TrapFrame>(),
+    CONST_KIND_SYNC_SP0 = const ExceptionKind::SyncSp0 as u64,
+    CONST_KIND_IRQ_SP0 = const ExceptionKind::IrqSp0 as u64,
+    CONST_KIND_FIQ_SP0 = const ExceptionKind::FiqSp0 as u64,
+    CONST_KIND_SERROR_SP0 = const ExceptionKind::SErrorSp0 as u64,
+    CONST_KIND_SYNC_SPX = const ExceptionKind::SyncSpx as u64,
+    CONST_KIND_IRQ_SPX = const ExceptionKind::IrqSpx as u64,
+    CONST_KIND_FIQ_SPX = const ExceptionKind::FiqSpx as u64,
+    CONST_KIND_SERROR_SPX = const ExceptionKind::SErrorSpx as u64,
+    CONST_KIND_SYNC_LOWER64 = const ExceptionKind::SyncLower64 as u64,
+    CONST_KIND_IRQ_LOWER64 = const ExceptionKind::IrqLower64 as u64,
+    CONST_KIND_FIQ_LOWER64 = const ExceptionKind::FiqLower64 as u64,
+    CONST_KIND_SERROR_LOWER64 = const ExceptionKind::SErrorLower64 as u64,
+    CONST_KIND_SYNC_LOWER32 = const ExceptionKind::SyncLower32 as u64,
+    CONST_KIND_IRQ_LOWER32 = const ExceptionKind::IrqLower32 as u64,
+    CONST_KIND_FIQ_LOWER32 = const ExceptionKind::FiqLower32 as u64,
+    CONST_KIND_SERROR_LOWER32 = const ExceptionKind::SErrorLower32 as u64,
+);
+
+unsafe extern "C" {
+    unsafe static __exception_vectors: u8;
+    unsafe static __exception_return: u8;
+}
+
+/// `SPSR_EL1` of a return to EL0, using `SP_EL0`, with every exception
+/// unmasked.
+const SPSR_EL0T: u64 = 0;
+
+/// `ESR_EL1` exception class of an `svc` from AArch64.
+const EC_SVC64: u64 = 0x15;
+
+/// Register state saved by the vector stubs in `exception.s`.
+#[repr(C)]
+pub struct TrapFrame {
+    /// General purpose registers x0..=x30
+    pub x: [u64; 31],
+    pub sp_el0: u64,
+    pub elr: u64,
+    pub spsr: u64,
+}
+
+impl Debug for TrapFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TrapFrame")
+            .field("elr", &format_args!("{:#018x}", self.elr))
+            .field("spsr", &format_args!("{:#010x}", self.spsr))
+            .field("sp_el0", &format_args!("{:#018x}", self.sp_el0))
+            .field("fp", &format_args!("{:#018x}", self.x[29]))
+            .field("lr", &format_args!("{:#018x}", self.x[30]))
+            .finish()
+    }
+}
+
+/// Which of the sixteen vector table entries was taken.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u64)]
+pub enum ExceptionKind {
+    SyncSp0 = 0,
+    IrqSp0,
+    FiqSp0,
+    SErrorSp0,
+    SyncSpx,
+    IrqSpx,
+    FiqSpx,
+    SErrorSpx,
+    SyncLower64,
+    IrqLower64,
+    FiqLower64,
+    SErrorLower64,
+    SyncLower32,
+    IrqLower32,
+    FiqLower32,
+    SErrorLower32,
+}
+
+impl ExceptionKind {
+    fn from_raw(raw: u64) -> Option<Self> {
+        use ExceptionKind::*;
+        const KINDS: [ExceptionKind; 16] = [
+            SyncSp0,
+            IrqSp0,
+            FiqSp0,
+            SErrorSp0,
+            SyncSpx,
+            IrqSpx,
+            FiqSpx,
+            SErrorSpx,
+            SyncLower64,
+            IrqLower64,
+            FiqLower64,
+            SErrorLower64,
+            SyncLower32,
+            IrqLower32,
+            FiqLower32,
+            SErrorLower32,
+        ];
+
+        KINDS.get(raw as usize).copied()
+    }
+
+    /// Whether the exception was taken from EL0.
+    fn is_from_user(&self) -> bool {
+        *self as u64 >= ExceptionKind::SyncLower64 as u64
+    }
+
+    fn is_irq(&self) -> bool {
+        matches!(
+            self,
+            ExceptionKind::IrqSp0
+                | ExceptionKind::IrqSpx
+                | ExceptionKind::IrqLower64
+                | ExceptionKind::IrqLower32
+        )
+    }
+}
+
+/// Point `VBAR_EL1` at the vector table in `exception.s`.
+pub fn install_vectors() {
+    unsafe {
+        asm!(
+            "msr vbar_el1, {0}",
+            "isb",
+            in(reg) &__exception_vectors as *const _ as u64,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Drop to EL0 at `entry`, with `stack_top` in `SP_EL0` and `arg` in `x0`.
+/// The rest of the current stack takes the exceptions from EL0.
+///
+/// # Safety
+/// `entry` and `stack_top` must be mapped for EL0 in the active address
+/// space.
+pub unsafe fn enter_user(entry: usize, stack_top: usize, arg: usize) -> ! {
+    #[repr(C, align(16))]
+    struct AlignedFrame(TrapFrame);
+
+    let mut x = [0; 31];
+    x[0] = arg as u64;
+    let frame = AlignedFrame(TrapFrame {
+        x,
+        sp_el0: stack_top as u64,
+        elr: entry as u64,
+        spsr: SPSR_EL0T,
+    });
+
+    // Restore the frame as if returning from an exception taken from EL0,
+    // with IRQs masked until the `eret`
+    unsafe {
+        asm!(
+            "msr daifset, #2",
+            "mov sp, {frame}",
+            "br {exception_return}",
+            frame = in(reg) &raw const frame,
+            exception_return = in(reg) &raw const __exception_return,
+            options(noreturn)
+        );
+    }
+}
+
+fn read_esr() -> u64 {
+    let esr: u64;
+    unsafe {
+        asm!("mrs {0}, esr_el1", out(reg) esr, options(nomem, nostack));
+    }
+
+    esr
+}
+
+fn read_far() -> u64 {
+    let far: u64;
+    unsafe {
+        asm!("mrs {0}, far_el1", out(reg) far, options(nomem, nostack));
+    }
+
+    far
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn handle_exception(frame: &mut TrapFrame, kind: u64) {
+    let kind = ExceptionKind::from_raw(kind).expect("Invalid exception kind");
+
+    if kind.is_irq() {
+        irq::irq_enter();
+        irq::dispatch();
+        irq::irq_exit();
+        return;
+    }
+
+    let esr = read_esr();
+    if kind == ExceptionKind::SyncLower64 && (esr >> 26) & 0x3f == EC_SVC64 {
+        handle_syscall(frame);
+        return;
+    }
+
+    if kind.is_from_user() {
+        process::kill_current(format_args!(
+            "{:?} (ESR: {:#x}, EC: {:#x}, FAR: {:#x}) at {:#x}",
+            kind,
+            esr,
+            (esr >> 26) & 0x3f,
+            read_far(),
+            frame.elr
+        ));
+    }
+
+    panic!(
+        "Unhandled exception: {:?} (ESR: {:#x}, EC: {:#x}, FAR: {:#x})\nAt: {}\n{:#?}",
+        kind,
+        esr,
+        (esr >> 26) & 0x3f,
+        read_far(),
+        Symbolized(frame.elr as usize),
+        frame
+    );
+}
+
+/// Run the system call numbered by `x8`, with the arguments in `x0` to
+/// `x5`, and return its result in `x0`. The call runs with IRQs unmasked,
+/// so it can block and be preempted like any other code of its task.
+fn handle_syscall(frame: &mut TrapFrame) {
+    let args = core::array::from_fn(|index| frame.x[index] as usize);
+
+    let irq_state = Cpu::unmask_interrupts();
+    let result = syscall::dispatch(frame.x[8] as usize, args);
+    Cpu::enable_interrupts(irq_state);
+
+    frame.x[0] = result as u64;
+}
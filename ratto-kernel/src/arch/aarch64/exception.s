@@ -0,0 +1,108 @@
+//------------------------------------------------------------------------------
+// Exception vector table
+//------------------------------------------------------------------------------
+// Every entry spills x0/x1, loads its kind into x1 and branches to the common
+// path, which saves the rest of the trap frame and calls into Rust.
+
+.macro VECTOR_ENTRY kind
+.balign 0x80
+    sub     sp, sp, #{CONST_TRAP_FRAME_SIZE}
+    stp     x0, x1, [sp, #16 * 0]
+    mov     x1, #\kind
+    b       __exception_common
+.endm
+
+.section .text.exceptions
+.balign 0x800
+.global __exception_vectors
+__exception_vectors:
+    // Current EL with SP_EL0
+    VECTOR_ENTRY {CONST_KIND_SYNC_SP0}
+    VECTOR_ENTRY {CONST_KIND_IRQ_SP0}
+    VECTOR_ENTRY {CONST_KIND_FIQ_SP0}
+    VECTOR_ENTRY {CONST_KIND_SERROR_SP0}
+
+    // Current EL with SP_ELx
+    VECTOR_ENTRY {CONST_KIND_SYNC_SPX}
+    VECTOR_ENTRY {CONST_KIND_IRQ_SPX}
+    VECTOR_ENTRY {CONST_KIND_FIQ_SPX}
+    VECTOR_ENTRY {CONST_KIND_SERROR_SPX}
+
+    // Lower EL using AArch64
+    VECTOR_ENTRY {CONST_KIND_SYNC_LOWER64}
+    VECTOR_ENTRY {CONST_KIND_IRQ_LOWER64}
+    VECTOR_ENTRY {CONST_KIND_FIQ_LOWER64}
+    VECTOR_ENTRY {CONST_KIND_SERROR_LOWER64}
+
+    // Lower EL using AArch32
+    VECTOR_ENTRY {CONST_KIND_SYNC_LOWER32}
+    VECTOR_ENTRY {CONST_KIND_IRQ_LOWER32}
+    VECTOR_ENTRY {CONST_KIND_FIQ_LOWER32}
+    VECTOR_ENTRY {CONST_KIND_SERROR_LOWER32}
+
+__exception_common:
+    //--------------------------------------------------------------------------
+    // Save the remaining general purpose registers
+    //--------------------------------------------------------------------------
+    stp     x2, x3, [sp, #16 * 1]
+    stp     x4, x5, [sp, #16 * 2]
+    stp     x6, x7, [sp, #16 * 3]
+    stp     x8, x9, [sp, #16 * 4]
+    stp     x10, x11, [sp, #16 * 5]
+    stp     x12, x13, [sp, #16 * 6]
+    stp     x14, x15, [sp, #16 * 7]
+    stp     x16, x17, [sp, #16 * 8]
+    stp     x18, x19, [sp, #16 * 9]
+    stp     x20, x21, [sp, #16 * 10]
+    stp     x22, x23, [sp, #16 * 11]
+    stp     x24, x25, [sp, #16 * 12]
+    stp     x26, x27, [sp, #16 * 13]
+    stp     x28, x29, [sp, #16 * 14]
+
+    //--------------------------------------------------------------------------
+    // Save x30, SP_EL0, ELR_EL1 and SPSR_EL1
+    //--------------------------------------------------------------------------
+    mrs     x2, SP_EL0
+    stp     x30, x2, [sp, #16 * 15]
+    mrs     x2, ELR_EL1
+    mrs     x3, SPSR_EL1
+    stp     x2, x3, [sp, #16 * 16]
+
+    //--------------------------------------------------------------------------
+    // Call into Rust: handle_exception(frame, kind)
+    //--------------------------------------------------------------------------
+    mov     x0, sp
+    bl      handle_exception
+
+    //--------------------------------------------------------------------------
+    // Restore state; the handler may have modified the frame. Entered
+    // directly, with SP at a frame, to drop to EL0 for the first time.
+    //--------------------------------------------------------------------------
+.global __exception_return
+__exception_return:
+    ldp     x2, x3, [sp, #16 * 16]
+    msr     ELR_EL1, x2
+    msr     SPSR_EL1, x3
+    ldp     x30, x2, [sp, #16 * 15]
+    msr     SP_EL0, x2
+
+    ldp     x0, x1, [sp, #16 * 0]
+    ldp     x2, x3, [sp, #16 * 1]
+    ldp     x4, x5, [sp, #16 * 2]
+    ldp     x6, x7, [sp, #16 * 3]
+    ldp     x8, x9, [sp, #16 * 4]
+    ldp     x10, x11, [sp, #16 * 5]
+    ldp     x12, x13, [sp, #16 * 6]
+    ldp     x14, x15, [sp, #16 * 7]
+    ldp     x16, x17, [sp, #16 * 8]
+    ldp     x18, x19, [sp, #16 * 9]
+    ldp     x20, x21, [sp, #16 * 10]
+    ldp     x22, x23, [sp, #16 * 11]
+    ldp     x24, x25, [sp, #16 * 12]
+    ldp     x26, x27, [sp, #16 * 13]
+    ldp     x28, x29, [sp, #16 * 14]
+
+    add     sp, sp, #{CONST_TRAP_FRAME_SIZE}
+    eret
+
+.size __exception_vectors, . - __exception_vectors
@@ -0,0 +1,27 @@
+//! The BCM2835 interrupt controller, which collects the peripherals' IRQs
+//! and hands them to core 0 through the [`local_intc`](super::local_intc).
+
+use super::mem::phys_to_virt;
+
+const BASE: usize = phys_to_virt(0x3f00_b200);
+
+/// Pending bits of IRQs 0..32, then of IRQs 32..64
+const PENDING: [usize; 2] = [BASE + 0x04, BASE + 0x08];
+
+/// Write-to-enable registers of IRQs 0..32, then of IRQs 32..64
+const ENABLE: [usize; 2] = [BASE + 0x10, BASE + 0x14];
+
+fn bank(irq: usize) -> (usize, u32) {
+    assert!(irq < 64, "No GPU IRQ {}", irq);
+    (irq / 32, 1 << (irq % 32))
+}
+
+pub fn enable(irq: usize) {
+    let (bank, bit) = bank(irq);
+    unsafe { (ENABLE[bank] as *mut u32).write_volatile(bit) };
+}
+
+pub fn is_pending(irq: usize) -> bool {
+    let (bank, bit) = bank(irq);
+    unsafe { (PENDING[bank] as *const u32).read_volatile() & bit != 0 }
+}
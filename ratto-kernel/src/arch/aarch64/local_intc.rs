@@ -0,0 +1,89 @@
+//! The BCM2836 per-core interrupt routing block of the Raspberry Pi 2 and 3,
+//! which sits in front of the generic timer's IRQ lines and provides the
+//! mailboxes used for inter-processor interrupts.
+
+use super::mem::phys_to_virt;
+use super::{gpu_intc, pl011};
+use crate::irq::Irq;
+
+const BASE: usize = phys_to_virt(0x4000_0000);
+
+/// Per-core timer interrupt control, one word per core
+const TIMER_CONTROL: usize = BASE + 0x40;
+
+/// Per-core mailbox interrupt control, one word per core
+const MAILBOX_CONTROL: usize = BASE + 0x50;
+
+/// Per-core IRQ source, one word per core
+const IRQ_SOURCE: usize = BASE + 0x60;
+
+/// Write-to-set register of each core's mailbox 0, four words per core
+const MAILBOX0_SET: usize = BASE + 0x80;
+
+/// Read and write-to-clear register of each core's mailbox 0, four words
+/// per core
+const MAILBOX0_CLEAR: usize = BASE + 0xc0;
+
+/// The EL1 physical timer's line (CNTPNSIRQ), in the timer control and IRQ
+/// source registers
+const CNTPNS: u32 = 1 << 1;
+
+/// Mailbox 0's line, in the mailbox control register
+const MAILBOX0_IRQ: u32 = 1 << 0;
+
+/// Mailbox 0's line, in the IRQ source register
+const MAILBOX0_SOURCE: u32 = 1 << 4;
+
+/// The GPU interrupt controller's line, in the IRQ source register
+const GPU_SOURCE: u32 = 1 << 8;
+
+fn register(block: usize, cpu: usize) -> *mut u32 {
+    (block + 4 * cpu) as *mut u32
+}
+
+fn mailbox0(block: usize, cpu: usize) -> *mut u32 {
+    (block + 16 * cpu) as *mut u32
+}
+
+/// Route the physical timer of `cpu` to its IRQ line.
+pub fn enable_timer(cpu: usize) {
+    unsafe {
+        let control = register(TIMER_CONTROL, cpu);
+        control.write_volatile(control.read_volatile() | CNTPNS);
+    }
+}
+
+/// Let mailbox 0 of `cpu` interrupt it.
+pub fn enable_ipi(cpu: usize) {
+    unsafe {
+        let control = register(MAILBOX_CONTROL, cpu);
+        control.write_volatile(control.read_volatile() | MAILBOX0_IRQ);
+    }
+}
+
+/// Interrupt `cpu` through its mailbox 0.
+pub fn send_ipi(cpu: usize) {
+    unsafe { mailbox0(MAILBOX0_SET, cpu).write_volatile(1) };
+}
+
+/// Clear the IPIs pending in the mailbox 0 of `cpu`.
+pub fn clear_ipi(cpu: usize) {
+    unsafe {
+        let mailbox = mailbox0(MAILBOX0_CLEAR, cpu);
+        mailbox.write_volatile(mailbox.read_volatile());
+    }
+}
+
+/// The interrupt `cpu` should service next, if any.
+pub fn pending(cpu: usize) -> Option<Irq> {
+    let source = unsafe { register(IRQ_SOURCE, cpu).read_volatile() };
+    if source & CNTPNS != 0 {
+        Some(Irq::Tick)
+    } else if source & MAILBOX0_SOURCE != 0 {
+        Some(Irq::Ipi)
+    } else if source & GPU_SOURCE != 0 && gpu_intc::is_pending(pl011::GPU_IRQ) {
+        Some(Irq::Uart)
+    } else {
+        None
+    }
+}
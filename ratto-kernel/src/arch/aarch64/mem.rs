@@ -1,4 +1,128 @@
+//! Physical memory and the translation tables.
+//!
+//! The kernel is linked in the upper half of the address space, which
+//! `TTBR1_EL1` translates through the boot tables built by `ratto-entry`:
+//! the first [`LINEAR_MAP_SIZE`] bytes of physical memory are mapped at
+//! [`KERNEL_VIRT_BASE`], RAM as normal memory and the peripherals from
+//! [`MMIO_START`] as device memory. Every core shares them.
+//!
+//! User address spaces are the lower half, translated through `TTBR0_EL1`.
+//! Each has its own tables, reached through the linear map, and its own
+//! ASID, so switching between them needs no TLB maintenance. Cores running
+//! no user process point `TTBR0_EL1` at a table that maps nothing.
+
+use core::arch::asm;
+use core::ops::Range;
+
+use ratto_core::mem::{MapFlags, PhysicalAddress, PhysicalFrame, VirtualAddress};
+
 use crate::arch::aarch64::boot::BootInfo;
+use crate::arch::sync::SpinLockIrq;
+
+/// Bits of virtual address translated through each of the two halves, with
+/// 4 KiB pages and three levels of tables.
+pub const VA_BITS: u32 = 39;
+
+/// Start of the upper half, where physical address `0` is mapped.
+pub const KERNEL_VIRT_BASE: u64 = !0 << VA_BITS;
+
+/// User addresses are below this.
+pub const USER_VIRT_END: u64 = 1 << VA_BITS;
+
+/// Physical memory mapped at [`KERNEL_VIRT_BASE`] by the boot tables: RAM,
+/// then the peripherals, up to the end of the local interrupt controller.
+pub const LINEAR_MAP_SIZE: u64 = 2 << 30;
+
+/// Start of the peripherals, which are mapped as device memory.
+pub const MMIO_START: u64 = 0x3f00_0000;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Entries of every table.
+const TABLE_ENTRIES: usize = 512;
+
+/// Descriptor bits of every level.
+const DESC_VALID: u64 = 1 << 0;
+/// A table descriptor at levels 1 and 2, a page descriptor at level 3.
+/// Clear for a block descriptor.
+const DESC_TABLE: u64 = 1 << 1;
+/// Index of the attributes in `MAIR_EL1`
+const DESC_ATTR_NORMAL: u64 = 0 << 2;
+const DESC_ATTR_DEVICE: u64 = 1 << 2;
+/// AP[1]: accessible from EL0
+const DESC_AP_EL0: u64 = 1 << 6;
+/// AP[2]: read-only
+const DESC_AP_RO: u64 = 1 << 7;
+const DESC_SH_INNER: u64 = 0b11 << 8;
+/// Access flag, which must be set or the first access faults
+const DESC_AF: u64 = 1 << 10;
+/// Not global: the entry only applies to its ASID
+const DESC_NG: u64 = 1 << 11;
+/// Not executable at EL1
+const DESC_PXN: u64 = 1 << 53;
+/// Not executable at EL0
+const DESC_UXN: u64 = 1 << 54;
+
+/// Bits of a descriptor holding the address of the frame or table
+const DESC_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+/// A level 1 or 2 descriptor pointing to the next table, with its address
+/// still to be added.
+pub const BOOT_TABLE_DESC: u64 = DESC_VALID | DESC_TABLE;
+
+/// Block descriptor of the boot tables' RAM, with its address still to be
+/// added.
+pub const BOOT_BLOCK_NORMAL: u64 =
+    DESC_VALID | DESC_ATTR_NORMAL | DESC_SH_INNER | DESC_AF | DESC_UXN;
+
+/// Block descriptor of the boot tables' peripherals, with its address still
+/// to be added.
+pub const BOOT_BLOCK_DEVICE: u64 = DESC_VALID | DESC_ATTR_DEVICE | DESC_AF | DESC_PXN | DESC_UXN;
+
+/// Attribute 0 is normal write-back memory, attribute 1 is device-nGnRnE.
+pub const MAIR: u64 = 0x00_ff;
+
+/// Both halves use 4 KiB pages and [`VA_BITS`], with inner shareable
+/// write-back table walks; ASIDs are 8 bits and come from `TTBR0_EL1`.
+pub const TCR: u64 = {
+    let t0sz = 64 - VA_BITS as u64;
+    let t1sz = 64 - VA_BITS as u64;
+    let walk0 = (0b01 << 8) | (0b01 << 10) | (0b11 << 12);
+    let walk1 = (0b01 << 24) | (0b01 << 26) | (0b11 << 28);
+    let tg1_4k = 0b10 << 30;
+    t0sz | walk0 | (t1sz << 16) | walk1 | tg1_4k
+};
+
+/// `SCTLR_EL1` bits turning on the MMU and the data and instruction caches.
+pub const SCTLR_MMU_ON: u64 = (1 << 0) | (1 << 2) | (1 << 12);
+
+/// Number of ASIDs. ASID 0 belongs to the empty table and is never handed
+/// out.
+const ASID_COUNT: usize = 256;
+
+static ASIDS: SpinLockIrq<[u64; ASID_COUNT / 64]> = SpinLockIrq::new([1, 0, 0, 0]);
+
+unsafe extern "C" {
+    /// Level 1 table mapping nothing, in `ratto-entry`, for cores running
+    /// no user process.
+    unsafe static __empty_l1: u8;
+}
+
+/// The kernel's address of physical address `phys`, in the linear map.
+pub const fn phys_to_virt(phys: u64) -> usize {
+    (KERNEL_VIRT_BASE | phys) as usize
+}
+
+/// The physical address of `virt`, which must be in the linear map.
+pub fn virt_to_phys(virt: usize) -> u64 {
+    let virt = virt as u64;
+    assert!(
+        virt >= KERNEL_VIRT_BASE && virt - KERNEL_VIRT_BASE < LINEAR_MAP_SIZE,
+        "{:#x} is not in the linear map",
+        virt
+    );
+    virt - KERNEL_VIRT_BASE
+}
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MemoryRegionType {
@@ -23,52 +147,431 @@ impl MemoryMapper {
     }
 }
 
+/// A user address space: its level 1 table and ASID.
+#[derive(Debug)]
+pub struct AddressSpace {
+    root: PhysicalFrame,
+    asid: u16,
+}
+
 impl ratto_core::mem::MemoryMapper for MemoryMapper {
-    type AddressSpace = ();
+    type AddressSpace = AddressSpace;
 
     fn new_address_space(&mut self) -> Result<Self::AddressSpace, &'static str> {
-        todo!()
+        let asid = alloc_asid().ok_or("Out of ASIDs")?;
+        let Some(root) = crate::mem::alloc_zeroed_frame() else {
+            free_asid(asid);
+            return Err("Out of memory for page tables");
+        };
+
+        Ok(AddressSpace { root, asid })
     }
 
     unsafe fn map_page(
         &mut self,
-        _space: &mut Self::AddressSpace,
-        _virtual_address: ratto_core::mem::VirtualAddress,
-        _physical_frame: ratto_core::mem::PhysicalFrame,
-        _flags: ratto_core::mem::MapFlags,
+        space: &mut Self::AddressSpace,
+        virtual_address: VirtualAddress,
+        physical_frame: PhysicalFrame,
+        flags: MapFlags,
     ) -> Result<(), &'static str> {
-        todo!()
+        let virt = check_user_page(virtual_address)?;
+        let entry = unsafe { leaf_entry(space.root, virt, true)? }.expect("Tables not created");
+
+        if unsafe { *entry } & DESC_VALID != 0 {
+            return Err("Address already mapped");
+        }
+
+        unsafe { *entry = page_descriptor(physical_frame, flags) };
+        dsb_ishst();
+        Ok(())
     }
 
     unsafe fn unmap_page(
         &mut self,
-        _space: &mut Self::AddressSpace,
-        _virt: ratto_core::mem::VirtualAddress,
-    ) -> Result<ratto_core::mem::PhysicalFrame, &'static str> {
-        todo!()
+        space: &mut Self::AddressSpace,
+        virt: VirtualAddress,
+    ) -> Result<PhysicalFrame, &'static str> {
+        let virt = check_user_page(virt)?;
+        let entry = unsafe { leaf_entry(space.root, virt, false)? }
+            .filter(|entry| unsafe { **entry } & DESC_VALID != 0)
+            .ok_or("Address not mapped")?;
+
+        let frame = frame_of(unsafe { *entry });
+        unsafe { *entry = 0 };
+        flush_page(space.asid, virt);
+        Ok(frame)
+    }
+
+    fn translate(
+        &self,
+        space: &Self::AddressSpace,
+        virt: VirtualAddress,
+    ) -> Option<(PhysicalFrame, MapFlags)> {
+        let virt = check_user_page(VirtualAddress(virt.0 & !(PAGE_SIZE - 1))).ok()?;
+        let entry = unsafe { leaf_entry(space.root, virt, false) }.ok()??;
+        let desc = unsafe { *entry };
+        if desc & DESC_VALID == 0 {
+            return None;
+        }
+
+        Some((frame_of(desc), descriptor_flags(desc)))
+    }
+
+    unsafe fn activate(&self, space: &Self::AddressSpace) {
+        set_ttbr0(space.root.addr.0 | (space.asid as u64) << 48);
+    }
+
+    unsafe fn deactivate(&self) {
+        set_ttbr0(virt_to_phys(unsafe { &__empty_l1 as *const _ as usize }));
+    }
+
+    unsafe fn destroy_address_space(
+        &mut self,
+        space: Self::AddressSpace,
+        unmapped: &mut dyn FnMut(VirtualAddress, PhysicalFrame),
+    ) {
+        flush_asid(space.asid);
+        unsafe { free_table(space.root, 1, 0, unmapped) };
+        free_asid(space.asid);
+    }
+}
+
+/// The virtual address of `virt`, if it is a page in the user half.
+fn check_user_page(virt: VirtualAddress) -> Result<u64, &'static str> {
+    if virt.0 >= USER_VIRT_END || !virt.0.is_multiple_of(PAGE_SIZE) {
+        return Err("Not a page-aligned user address");
+    }
+
+    Ok(virt.0)
+}
+
+/// Index of `virt` in a table of `level`, from 1 to 3.
+fn table_index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * (3 - level))) as usize) % TABLE_ENTRIES
+}
+
+fn table_entries(table: PhysicalFrame) -> *mut u64 {
+    phys_to_virt(table.addr.0) as *mut u64
+}
+
+fn frame_of(desc: u64) -> PhysicalFrame {
+    PhysicalFrame {
+        addr: PhysicalAddress(desc & DESC_ADDR_MASK),
+    }
+}
+
+/// The level 3 entry of `virt` below the level 1 table `root`. Missing
+/// tables on the way are created if `create` is set, else there is no entry.
+///
+/// # Safety
+/// `root` must be the level 1 table of an address space the caller may
+/// change.
+unsafe fn leaf_entry(
+    root: PhysicalFrame,
+    virt: u64,
+    create: bool,
+) -> Result<Option<*mut u64>, &'static str> {
+    let mut table = root;
+    for level in 1..3 {
+        let entry = unsafe { table_entries(table).add(table_index(virt, level)) };
+
+        if unsafe { *entry } & DESC_VALID == 0 {
+            if !create {
+                return Ok(None);
+            }
+
+            let next = crate::mem::alloc_zeroed_frame().ok_or("Out of memory for page tables")?;
+            // The zeroes must be visible to the table walker before the table
+            dsb_ishst();
+            unsafe { *entry = next.addr.0 | BOOT_TABLE_DESC };
+        }
+
+        table = frame_of(unsafe { *entry });
+    }
+
+    Ok(Some(unsafe {
+        table_entries(table).add(table_index(virt, 3))
+    }))
+}
+
+/// Free `table`, at `level` and covering the addresses from `base`, with
+/// every table below it, passing the pages mapped in them to `unmapped`.
+///
+/// # Safety
+/// No core may walk the table any more.
+unsafe fn free_table(
+    table: PhysicalFrame,
+    level: u32,
+    base: u64,
+    unmapped: &mut dyn FnMut(VirtualAddress, PhysicalFrame),
+) {
+    let entry_size = 1 << (12 + 9 * (3 - level));
+    for index in 0..TABLE_ENTRIES {
+        let desc = unsafe { *table_entries(table).add(index) };
+        if desc & DESC_VALID == 0 {
+            continue;
+        }
+
+        let virt = base + index as u64 * entry_size;
+        if level == 3 {
+            unmapped(VirtualAddress(virt), frame_of(desc));
+        } else {
+            unsafe { free_table(frame_of(desc), level + 1, virt, unmapped) };
+        }
+    }
+
+    unsafe { crate::mem::free_frame(table) };
+}
+
+fn page_descriptor(frame: PhysicalFrame, flags: MapFlags) -> u64 {
+    let mut desc =
+        frame.addr.0 | DESC_VALID | DESC_TABLE | DESC_ATTR_NORMAL | DESC_SH_INNER | DESC_AF;
+
+    // The kernel never runs user code, and user code never runs kernel code
+    if flags.contains(MapFlags::USER) {
+        desc |= DESC_AP_EL0 | DESC_PXN;
+        if !flags.contains(MapFlags::EXEC) {
+            desc |= DESC_UXN;
+        }
+    } else {
+        desc |= DESC_UXN;
+        if !flags.contains(MapFlags::EXEC) {
+            desc |= DESC_PXN;
+        }
+    }
+
+    if !flags.contains(MapFlags::WRITE) {
+        desc |= DESC_AP_RO;
+    }
+    if !flags.contains(MapFlags::GLOBAL) {
+        desc |= DESC_NG;
+    }
+
+    desc
+}
+
+/// The flags `page_descriptor` made `desc` from.
+fn descriptor_flags(desc: u64) -> MapFlags {
+    let mut flags = MapFlags::READ;
+    let user = desc & DESC_AP_EL0 != 0;
+    if user {
+        flags |= MapFlags::USER;
+    }
+    if desc & DESC_AP_RO == 0 {
+        flags |= MapFlags::WRITE;
+    }
+    if desc & if user { DESC_UXN } else { DESC_PXN } == 0 {
+        flags |= MapFlags::EXEC;
+    }
+    if desc & DESC_NG == 0 {
+        flags |= MapFlags::GLOBAL;
+    }
+
+    flags
+}
+
+fn alloc_asid() -> Option<u16> {
+    ASIDS.lock_with(|asids| {
+        let (word, bits) = asids
+            .iter_mut()
+            .enumerate()
+            .find(|(_, bits)| **bits != u64::MAX)?;
+        let bit = bits.trailing_ones() as usize;
+        *bits |= 1 << bit;
+        Some((word * 64 + bit) as u16)
+    })
+}
+
+fn free_asid(asid: u16) {
+    let (word, bit) = (asid as usize / 64, asid % 64);
+    ASIDS.lock_with(|asids| asids[word] &= !(1 << bit));
+}
+
+fn set_ttbr0(value: u64) {
+    unsafe {
+        asm!("msr ttbr0_el1, {0}", "isb", in(reg) value, options(nostack));
+    }
+}
+
+fn dsb_ishst() {
+    unsafe {
+        asm!("dsb ishst", options(nostack));
+    }
+}
+
+/// Drop the translation of the user page `virt` of `asid` from every core's
+/// TLB.
+fn flush_page(asid: u16, virt: u64) {
+    let operand = (asid as u64) << 48 | virt >> 12;
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vae1is, {0}",
+            "dsb ish",
+            "isb",
+            in(reg) operand,
+            options(nostack)
+        );
     }
+}
 
-    unsafe fn activate(&self, _space: &Self::AddressSpace) {
-        todo!()
+/// Drop every translation of `asid` from every core's TLB.
+fn flush_asid(asid: u16) {
+    let operand = (asid as u64) << 48;
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi aside1is, {0}",
+            "dsb ish",
+            "isb",
+            in(reg) operand,
+            options(nostack)
+        );
     }
 }
 
-pub struct FrameAllocator;
+/// Hands out the usable frames of the memory map, other than those of the
+/// firmware, the kernel image, the DTB and the initramfs.
+///
+/// Frames are taken in address order until the memory map is used up, after
+/// which freed frames are reused. Each freed frame holds the address of the
+/// one freed before it.
+pub struct FrameAllocator {
+    /// Regions in address order
+    regions: &'static [MemoryRegion],
+    reserved: [Range<u64>; 3],
+    /// Index of the region holding `next`
+    region: usize,
+    /// The next frame never handed out
+    next: u64,
+    /// Last freed frame, if any
+    free_list: Option<PhysicalFrame>,
+    /// Usable frames outside the reserved ranges
+    total: u64,
+    /// Frames handed out and not freed
+    allocated: u64,
+}
 
 impl FrameAllocator {
-    pub fn new(_boot_info: &BootInfo) -> Result<Self, &'static str> {
-        Ok(FrameAllocator)
+    pub fn new(boot_info: &BootInfo) -> Result<Self, &'static str> {
+        if !boot_info
+            .memory_map
+            .iter()
+            .any(|region| region.kind == MemoryRegionType::Usable)
+        {
+            return Err("No usable memory");
+        }
+
+        // Everything below the kernel belongs to the firmware
+        let reserved = [
+            0..boot_info.kernel_phys_end,
+            boot_info.dtb_phys_start..boot_info.dtb_phys_end,
+            boot_info.initrd_phys_start..boot_info.initrd_phys_end,
+        ];
+
+        Ok(FrameAllocator {
+            regions: boot_info.memory_map,
+            total: count_frames(boot_info.memory_map, &reserved),
+            reserved,
+            region: 0,
+            next: 0,
+            free_list: None,
+            allocated: 0,
+        })
+    }
+
+    /// The next frame of the memory map never handed out.
+    fn take_unused(&mut self) -> Option<PhysicalFrame> {
+        loop {
+            let region = self.regions.get(self.region)?;
+            let start = self.next.max(region.start).next_multiple_of(PAGE_SIZE);
+            let end = region.start + region.size;
+
+            if region.kind != MemoryRegionType::Usable || start + PAGE_SIZE > end {
+                self.region += 1;
+                continue;
+            }
+
+            let frame = start..start + PAGE_SIZE;
+            if let Some(reserved) = self
+                .reserved
+                .iter()
+                .find(|reserved| reserved.start < frame.end && frame.start < reserved.end)
+            {
+                self.next = reserved.end;
+                continue;
+            }
+
+            self.next = frame.end;
+            return Some(PhysicalFrame {
+                addr: PhysicalAddress(start),
+            });
+        }
     }
 }
 
+/// Number of frames [`FrameAllocator::take_unused`] hands out in all: the
+/// whole frames of the usable regions that overlap none of `reserved`.
+fn count_frames(regions: &[MemoryRegion], reserved: &[Range<u64>]) -> u64 {
+    regions
+        .iter()
+        .filter(|region| region.kind == MemoryRegionType::Usable)
+        .map(|region| {
+            let start = region.start.next_multiple_of(PAGE_SIZE);
+            let end = region.start + region.size;
+            (start..end.saturating_sub(PAGE_SIZE - 1))
+                .step_by(PAGE_SIZE as usize)
+                .filter(|frame| {
+                    !reserved
+                        .iter()
+                        .any(|reserved| reserved.start < frame + PAGE_SIZE && *frame < reserved.end)
+                })
+                .count() as u64
+        })
+        .sum()
+}
+
+/// The frame freed before `frame`.
+///
+/// # Safety
+/// `frame` must be on the free list.
+unsafe fn next_free(frame: PhysicalFrame) -> Option<PhysicalFrame> {
+    let next = unsafe { *(phys_to_virt(frame.addr.0) as *const u64) };
+    (next != 0).then_some(PhysicalFrame {
+        addr: PhysicalAddress(next),
+    })
+}
+
 impl ratto_core::mem::FrameAllocator for FrameAllocator {
-    const FRAME_SIZE: u64 = 0x1000;
+    const FRAME_SIZE: u64 = PAGE_SIZE;
 
     fn alloc_frame(&mut self) -> Option<ratto_core::mem::PhysicalFrame> {
-        todo!()
+        let frame = match self.free_list {
+            Some(frame) => {
+                self.free_list = unsafe { next_free(frame) };
+                frame
+            }
+            None => self.take_unused()?,
+        };
+
+        self.allocated += 1;
+        Some(frame)
+    }
+
+    unsafe fn free_frame(&mut self, frame: ratto_core::mem::PhysicalFrame) {
+        // Frame 0 is the firmware's, so never on the list
+        let next = self.free_list.map_or(0, |next| next.addr.0);
+        unsafe { *(phys_to_virt(frame.addr.0) as *mut u64) = next };
+
+        self.free_list = Some(frame);
+        self.allocated -= 1;
+    }
+
+    fn total_frames(&self) -> usize {
+        self.total as usize
     }
 
-    unsafe fn free_frame(&mut self, _frame: ratto_core::mem::PhysicalFrame) {
-        todo!()
+    fn free_frames(&self) -> usize {
+        (self.total - self.allocated) as usize
     }
 }
@@ -1,8 +1,29 @@
-use crate::arch::ArchImpl;
+use core::arch::asm;
+use core::ops::Range;
+
+use ratto_core::cpu::CpuOps;
+use ratto_core::mem::PhysicalAddress;
+
+use crate::arch::{ArchImpl, UartOps};
+use crate::irq::Irq;
+use crate::percpu;
 
 pub mod boot;
+pub mod context;
 pub mod cpu;
+pub mod exception;
+pub mod gpu_intc;
+pub mod local_intc;
 pub mod mem;
+pub mod pl011;
+pub mod smp;
+pub mod timer;
+pub mod watchdog;
+
+unsafe extern "C" {
+    unsafe static __core_stacks_start: u8;
+    unsafe static __core_stacks_end_exclusive: u8;
+}
 
 pub struct AArch64;
 
@@ -11,6 +32,87 @@ impl ArchImpl for AArch64 {
     type MemoryMapper = mem::MemoryMapper;
     type FrameAllocator = mem::FrameAllocator;
     type BootInfo = boot::BootInfo;
+    type Context = context::Context;
+    type Uart = pl011::Pl011;
+
+    const MAX_CPUS: usize = 4;
+    const USER_END: usize = mem::USER_VIRT_END as usize;
+
+    const ELF_MACHINE: u16 = ratto_core::elf::EM_AARCH64;
+
+    fn init_exceptions() {
+        exception::install_vectors();
+    }
+
+    fn start_secondaries(boot_info: &Self::BootInfo) {
+        smp::start_secondaries(boot_info);
+    }
+
+    fn start_tick(hz: u32) {
+        local_intc::enable_timer(percpu::cpu_id());
+        timer::start(hz);
+    }
+
+    fn enable_ipi() {
+        local_intc::enable_ipi(percpu::cpu_id());
+    }
+
+    fn send_ipi(cpu: usize) {
+        local_intc::send_ipi(cpu);
+    }
+
+    fn pending_irq() -> Option<Irq> {
+        local_intc::pending(percpu::cpu_id())
+    }
+
+    fn ack_irq(irq: Irq) {
+        match irq {
+            Irq::Tick => timer::rearm(),
+            Irq::Ipi => local_intc::clear_ipi(percpu::cpu_id()),
+            Irq::Uart => <pl011::Pl011 as UartOps>::mask_interrupts(),
+        }
+    }
+
+    #[inline(always)]
+    fn frame_pointer() -> usize {
+        let fp: usize;
+        unsafe {
+            asm!("mov {0}, x29", out(reg) fp, options(nomem, nostack));
+        }
+
+        fp
+    }
+
+    fn stack_bounds(addr: usize) -> Option<Range<usize>> {
+        let core_stacks = unsafe {
+            &__core_stacks_start as *const _ as usize
+                ..&__core_stacks_end_exclusive as *const _ as usize
+        };
+
+        if !core_stacks.contains(&addr) {
+            return None;
+        }
+
+        let start = addr - (addr - core_stacks.start) % smp::CORE_STACK_SIZE;
+        Some(start..start + smp::CORE_STACK_SIZE)
+    }
+
+    fn phys_to_virt(addr: PhysicalAddress) -> usize {
+        mem::phys_to_virt(addr.0)
+    }
+
+    fn sync_instruction_cache(addr: usize, len: usize) {
+        cpu::sync_instruction_cache(addr, len);
+    }
+
+    fn reset() -> ! {
+        watchdog::reset();
+        cpu::Cpu::wait_forever()
+    }
+
+    unsafe fn enter_user(entry: usize, stack_top: usize, arg: usize) -> ! {
+        unsafe { exception::enter_user(entry, stack_top, arg) }
+    }
 
     fn init_memory(
         boot_info: &Self::BootInfo,
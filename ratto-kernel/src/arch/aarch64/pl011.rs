@@ -0,0 +1,84 @@
+//! The PL011 UART of the Raspberry Pi 3, which QEMU connects to its first
+//! serial port. Its interrupt reaches the cores through the
+//! [`gpu_intc`](super::gpu_intc).
+
+use super::mem::phys_to_virt;
+use crate::arch::UartOps;
+
+const BASE: usize = phys_to_virt(0x3f20_1000);
+
+/// Data register
+const DR: usize = 0x00;
+/// Flag register
+const FR: usize = 0x18;
+/// Interrupt mask set/clear register
+const IMSC: usize = 0x38;
+/// Interrupt clear register
+const ICR: usize = 0x44;
+
+/// FR: receive FIFO empty
+const FR_RXFE: u32 = 1 << 4;
+/// FR: transmit FIFO full
+const FR_TXFF: u32 = 1 << 5;
+
+/// Receive interrupt
+const INT_RX: u32 = 1 << 4;
+/// Transmit interrupt
+const INT_TX: u32 = 1 << 5;
+/// Receive timeout interrupt, for bytes left below the FIFO trigger level
+const INT_RT: u32 = 1 << 6;
+
+/// Interrupt number of the UART on the GPU interrupt controller.
+pub const GPU_IRQ: usize = 57;
+
+fn register(offset: usize) -> *mut u32 {
+    (BASE + offset) as *mut u32
+}
+
+fn read(offset: usize) -> u32 {
+    unsafe { register(offset).read_volatile() }
+}
+
+fn write(offset: usize, value: u32) {
+    unsafe { register(offset).write_volatile(value) }
+}
+
+pub struct Pl011;
+
+impl UartOps for Pl011 {
+    fn init() {
+        write(IMSC, 0);
+        write(ICR, 0x7ff);
+        super::gpu_intc::enable(GPU_IRQ);
+    }
+
+    fn try_read() -> Option<u8> {
+        (read(FR) & FR_RXFE == 0).then(|| read(DR) as u8)
+    }
+
+    fn try_write(byte: u8) -> bool {
+        if read(FR) & FR_TXFF != 0 {
+            return false;
+        }
+
+        write(DR, u32::from(byte));
+        true
+    }
+
+    fn listen(rx: bool, tx: bool) {
+        let mut mask = 0;
+        if rx {
+            mask |= INT_RX | INT_RT;
+        }
+        if tx {
+            mask |= INT_TX;
+        }
+
+        write(IMSC, read(IMSC) | mask);
+    }
+
+    fn mask_interrupts() {
+        write(IMSC, 0);
+        write(ICR, INT_RX | INT_TX | INT_RT);
+    }
+}
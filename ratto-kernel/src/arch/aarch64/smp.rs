@@ -0,0 +1,108 @@
+use core::arch::asm;
+
+use crate::arch;
+use crate::arch::aarch64::boot::BootInfo;
+use crate::arch::aarch64::mem;
+use crate::{kerr, klog, percpu, smp};
+
+/// Size of each core's boot stack in the linker script's `.core_stacks`.
+pub const CORE_STACK_SIZE: usize = 0x4000;
+
+const PSCI_CPU_ON_64: u64 = 0xc400_0003;
+
+unsafe extern "C" {
+    /// Secondary entry point in `ratto-entry`, which sets up the core's
+    /// stack and calls `Kernel::secondary_main`.
+    unsafe static _start_secondary: u8;
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CpuInfo {
+    /// Affinity value from the `reg` property, matching `MPIDR_EL1`
+    pub mpidr: u64,
+    pub enable_method: EnableMethod,
+}
+
+/// How a core is released, from the DTB `enable-method` property.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EnableMethod {
+    /// Write the entry point to `release_addr` and send an event
+    SpinTable { release_addr: u64 },
+    /// Call PSCI `CPU_ON` through the given conduit
+    Psci { conduit: PsciConduit },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PsciConduit {
+    Hvc,
+    Smc,
+}
+
+impl CpuInfo {
+    /// Index of the core, as returned by `percpu::cpu_id()`.
+    pub fn index(&self) -> usize {
+        (self.mpidr & 0xff) as usize
+    }
+}
+
+pub fn start_secondaries(boot_info: &BootInfo) {
+    let boot_cpu = percpu::cpu_id();
+    // The core starts with its MMU off
+    let entry = unsafe { mem::virt_to_phys(&_start_secondary as *const _ as usize) };
+
+    for cpu in boot_info.cpus {
+        let index = cpu.index();
+        if index == boot_cpu || index >= arch::MAX_CPUS {
+            continue;
+        }
+
+        klog!("Starting core {} via {:?}", index, cpu.enable_method);
+        let started = match cpu.enable_method {
+            EnableMethod::SpinTable { release_addr } => {
+                release_spin_table(release_addr, entry);
+                true
+            }
+            EnableMethod::Psci { conduit } => psci_cpu_on(conduit, cpu.mpidr, entry) == 0,
+        };
+
+        if !started || !smp::wait_online(index) {
+            kerr!("Core {} failed to come online", index);
+        }
+    }
+}
+
+fn release_spin_table(release_addr: u64, entry: u64) {
+    let release = mem::phys_to_virt(release_addr) as *mut u64;
+    unsafe {
+        core::ptr::write_volatile(release, entry);
+        // The waiting core reads memory with its caches off
+        asm!("dc civac, {0}", "dsb sy", "sev", in(reg) release, options(nostack));
+    }
+}
+
+fn psci_cpu_on(conduit: PsciConduit, mpidr: u64, entry: u64) -> i64 {
+    let result: i64;
+    // Not `nomem`: the new core reads what was stored before the call
+    unsafe {
+        match conduit {
+            PsciConduit::Hvc => asm!(
+                "hvc #0",
+                inout("x0") PSCI_CPU_ON_64 => result,
+                in("x1") mpidr,
+                in("x2") entry,
+                in("x3") 0u64,
+                options(nostack)
+            ),
+            PsciConduit::Smc => asm!(
+                "smc #0",
+                inout("x0") PSCI_CPU_ON_64 => result,
+                in("x1") mpidr,
+                in("x2") entry,
+                in("x3") 0u64,
+                options(nostack)
+            ),
+        }
+    }
+
+    result
+}
@@ -0,0 +1,43 @@
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Counter ticks between two scheduler ticks, set by [`start`].
+static INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+/// CNTP_CTL_EL0.ENABLE; IMASK is left clear so the timer raises its IRQ.
+const CTL_ENABLE: u64 = 1;
+
+fn frequency() -> u64 {
+    let frequency: u64;
+    unsafe {
+        asm!("mrs {0}, cntfrq_el0", out(reg) frequency, options(nomem, nostack));
+    }
+
+    frequency
+}
+
+/// Fire the EL1 physical timer every `1 / hz` seconds on the executing core.
+pub fn start(hz: u32) {
+    assert!(hz > 0, "Tick rate must be non-zero");
+
+    let interval = (frequency() / u64::from(hz)).max(1);
+    INTERVAL.store(interval, Ordering::Relaxed);
+
+    rearm();
+    unsafe {
+        asm!(
+            "msr cntp_ctl_el0, {0}",
+            "isb",
+            in(reg) CTL_ENABLE,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Schedule the next tick, which also clears the pending one.
+pub fn rearm() {
+    let interval = INTERVAL.load(Ordering::Relaxed);
+    unsafe {
+        asm!("msr cntp_tval_el0, {0}", "isb", in(reg) interval, options(nomem, nostack));
+    }
+}
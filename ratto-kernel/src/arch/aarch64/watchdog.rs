@@ -0,0 +1,33 @@
+//! The power management block of the BCM2837, whose watchdog is the way to
+//! reset a Raspberry Pi 3.
+
+use core::ptr;
+
+use super::mem::phys_to_virt;
+
+const BASE: usize = phys_to_virt(0x3f10_0000);
+
+/// Reset control
+const RSTC: usize = BASE + 0x1c;
+
+/// Watchdog countdown, in ticks of about 16 µs
+const WDOG: usize = BASE + 0x24;
+
+/// Writes are ignored unless they carry this in the top byte
+const PASSWORD: u32 = 0x5a00_0000;
+
+/// The reset kind in RSTC
+const RSTC_WRCFG_MASK: u32 = 0x30;
+const RSTC_WRCFG_FULL_RESET: u32 = 0x20;
+
+/// Reset the whole board, shortly after this returns.
+pub fn reset() {
+    unsafe {
+        let rstc = ptr::read_volatile(RSTC as *const u32);
+        ptr::write_volatile(WDOG as *mut u32, PASSWORD | 10);
+        ptr::write_volatile(
+            RSTC as *mut u32,
+            PASSWORD | (rstc & !RSTC_WRCFG_MASK) | RSTC_WRCFG_FULL_RESET,
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use core::ops::Range;
+
+use crate::arch::{self, ArchImpl};
+use crate::task;
+
+/// Unwinding stops after this many frames, in case of a cycle we fail to spot.
+const MAX_FRAMES: usize = 32;
+
+/// Iterator over the return addresses of a frame pointer chain.
+///
+/// Each frame record is a pair of words, the caller's frame pointer followed by
+/// the return address, which requires building with frame pointers forced on.
+/// A record is only read if it lies entirely within the stack the walk started
+/// on, and frame pointers must strictly increase, so a corrupt chain ends the
+/// walk instead of faulting or looping.
+pub struct Backtrace {
+    fp: usize,
+    stack: Range<usize>,
+    depth: usize,
+}
+
+impl Backtrace {
+    /// Walk the chain starting at the frame record `fp`.
+    pub fn new(fp: usize) -> Self {
+        Backtrace {
+            fp,
+            stack: arch::Impl::stack_bounds(fp)
+                .or_else(|| task::stack_bounds(fp))
+                .unwrap_or(0..0),
+            depth: 0,
+        }
+    }
+
+    /// Walk the chain of the calling function.
+    #[inline(always)]
+    pub fn current() -> Self {
+        Self::new(arch::Impl::frame_pointer())
+    }
+
+    fn is_valid_record(&self, fp: usize) -> bool {
+        let record_size = 2 * core::mem::size_of::<usize>();
+        fp != 0
+            && fp.is_multiple_of(core::mem::align_of::<usize>())
+            && fp >= self.stack.start
+            && fp
+                .checked_add(record_size)
+                .is_some_and(|end| end <= self.stack.end)
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.depth >= MAX_FRAMES || !self.is_valid_record(self.fp) {
+            return None;
+        }
+
+        let record = self.fp as *const usize;
+        let (caller_fp, return_address) = unsafe {
+            (
+                core::ptr::read_volatile(record),
+                core::ptr::read_volatile(record.add(1)),
+            )
+        };
+
+        if return_address == 0 {
+            return None;
+        }
+
+        // Callers live higher up the stack; anything else is corruption
+        self.fp = if caller_fp > self.fp { caller_fp } else { 0 };
+        self.depth += 1;
+
+        Some(return_address)
+    }
+}
@@ -0,0 +1,80 @@
+//! Deferred work: things an interrupt handler wants done, but not in hard-IRQ
+//! context.
+//!
+//! * [`softirq`] - a small, fixed set of per-CPU vectors run at IRQ exit.
+//! * [`tasklet`] - dynamically scheduled callbacks that run from the tasklet
+//!   softirq, never concurrently with themselves.
+//! * [`workqueue`] - work items serviced by kernel worker threads, which may
+//!   take as long as they like.
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::task;
+use workqueue::SYSTEM_WORKQUEUE;
+
+pub mod softirq;
+pub mod tasklet;
+pub mod workqueue;
+
+pub fn init() {
+    softirq::register(softirq::SoftIrq::Tasklet, tasklet::run_pending);
+    task::spawn(SYSTEM_WORKQUEUE.name(), workqueue::system_worker)
+        .expect("Failed to start the system workqueue worker");
+}
+
+/// An item that can sit on a [`Queue`] through an embedded link.
+trait Queued: Sized + Sync + 'static {
+    fn next(&self) -> &AtomicPtr<Self>;
+}
+
+/// Allocation-free FIFO of `'static` items, threaded through their links.
+struct Queue<T: Queued> {
+    head: Option<&'static T>,
+    tail: Option<&'static T>,
+}
+
+impl<T: Queued> Queue<T> {
+    const fn new() -> Self {
+        Queue {
+            head: None,
+            tail: None,
+        }
+    }
+
+    fn push(&mut self, item: &'static T) {
+        item.next().store(ptr::null_mut(), Ordering::Relaxed);
+        match self.tail {
+            Some(tail) => tail
+                .next()
+                .store(item as *const T as *mut T, Ordering::Relaxed),
+            None => self.head = Some(item),
+        }
+
+        self.tail = Some(item);
+    }
+
+    fn pop(&mut self) -> Option<&'static T> {
+        let head = self.head?;
+        let next = head.next().swap(ptr::null_mut(), Ordering::Relaxed);
+
+        // Safe because only `'static` items are ever linked into the queue.
+        self.head = unsafe { next.as_ref() };
+        if self.head.is_none() {
+            self.tail = None;
+        }
+
+        Some(head)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    fn take(&mut self) -> Self {
+        Queue {
+            head: self.head.take(),
+            tail: self.tail.take(),
+        }
+    }
+}
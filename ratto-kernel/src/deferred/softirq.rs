@@ -0,0 +1,110 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use ratto_core::cpu::CpuOps;
+
+use crate::arch::{self, Cpu, sync::OnceLock};
+use crate::percpu::{self, PerCpu};
+
+/// Softirq processing restarts at most this many times per IRQ exit, so a
+/// storm of raises cannot livelock the interrupted context.
+const MAX_RESTARTS: usize = 10;
+
+/// The fixed set of softirq vectors, in priority order.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum SoftIrq {
+    Timer = 0,
+    Serial,
+    Block,
+    Tasklet,
+    Sched,
+}
+
+impl SoftIrq {
+    pub const COUNT: usize = 5;
+
+    const ALL: [SoftIrq; Self::COUNT] = [
+        SoftIrq::Timer,
+        SoftIrq::Serial,
+        SoftIrq::Block,
+        SoftIrq::Tasklet,
+        SoftIrq::Sched,
+    ];
+
+    fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+static HANDLERS: [OnceLock<fn()>; SoftIrq::COUNT] = [const { OnceLock::new() }; SoftIrq::COUNT];
+
+/// Raised-but-not-yet-run vectors of each core.
+static PENDING: PerCpu<AtomicU32> = PerCpu::new([const { AtomicU32::new(0) }; arch::MAX_CPUS]);
+
+/// Whether each core is currently running softirq handlers.
+static ACTIVE: PerCpu<AtomicBool> = PerCpu::new([const { AtomicBool::new(false) }; arch::MAX_CPUS]);
+
+/// Install the handler for a vector. Each vector can be registered once.
+pub fn register(vector: SoftIrq, handler: fn()) {
+    let installed = HANDLERS[vector as usize].set(handler).is_ok();
+    assert!(installed, "Softirq {:?} registered more than once", vector);
+}
+
+/// Mark a vector pending on the executing core. It runs at the next IRQ exit.
+pub fn raise(vector: SoftIrq) {
+    let _irq = percpu::irq_save();
+    PENDING.with(|pending| pending.fetch_or(vector.mask(), Ordering::Relaxed));
+}
+
+/// Whether any vector is pending on the executing core.
+pub fn has_pending() -> bool {
+    PENDING.with(|pending| pending.load(Ordering::Relaxed)) != 0
+}
+
+/// Run the pending vectors of the executing core with interrupts enabled.
+///
+/// Called at IRQ exit once the outermost handler has returned. An IRQ that
+/// arrives while handlers are running only raises vectors; they are picked up
+/// by the restart loop here rather than by a nested call. Nothing runs while
+/// the core holds a [`SpinLockBh`](crate::arch::sync::SpinLockBh), and the
+/// core cannot be preempted until they are done.
+pub fn run_pending() {
+    if !percpu::bh_enabled() {
+        return;
+    }
+
+    // Handlers run with interrupts on, and the IRQ exit of one that arrives
+    // must not switch tasks in the middle of them
+    let _preempt = percpu::preempt_disable();
+    let state = Cpu::disable_interrupts();
+    let cpu = percpu::cpu_id();
+
+    if ACTIVE.for_cpu(cpu).swap(true, Ordering::Relaxed) {
+        Cpu::enable_interrupts(state);
+        return;
+    }
+
+    for _ in 0..MAX_RESTARTS {
+        let pending = PENDING.for_cpu(cpu).swap(0, Ordering::Relaxed);
+        if pending == 0 {
+            break;
+        }
+
+        Cpu::unmask_interrupts();
+        for vector in SoftIrq::ALL {
+            if pending & vector.mask() == 0 {
+                continue;
+            }
+
+            if let Some(handler) = HANDLERS[vector as usize].get() {
+                handler();
+            }
+        }
+
+        Cpu::disable_interrupts();
+    }
+
+    // Anything still pending waits for the next IRQ exit
+    ACTIVE.for_cpu(cpu).store(false, Ordering::Relaxed);
+    Cpu::enable_interrupts(state);
+}
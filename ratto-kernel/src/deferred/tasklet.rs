@@ -0,0 +1,75 @@
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+
+use super::softirq::{self, SoftIrq};
+use super::{Queue, Queued};
+use crate::arch::{self, sync::SpinLock};
+use crate::percpu::PerCpu;
+
+const STATE_SCHEDULED: u8 = 1 << 0;
+const STATE_RUNNING: u8 = 1 << 1;
+
+/// Tasklets scheduled on each core, waiting for the tasklet softirq.
+static QUEUES: PerCpu<SpinLock<Queue<Tasklet>>> =
+    PerCpu::new([const { SpinLock::new(Queue::new()) }; arch::MAX_CPUS]);
+
+/// A callback scheduled from IRQ context and run from the tasklet softirq.
+///
+/// A tasklet is never run concurrently with itself: if it is scheduled again
+/// while running on another core it is deferred until that run finishes.
+/// Scheduling an already-scheduled tasklet is a no-op.
+pub struct Tasklet {
+    func: fn(usize),
+    data: usize,
+    state: AtomicU8,
+    next: AtomicPtr<Tasklet>,
+}
+
+impl Tasklet {
+    pub const fn new(func: fn(usize), data: usize) -> Self {
+        Tasklet {
+            func,
+            data,
+            state: AtomicU8::new(0),
+            next: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Queue the tasklet on the executing core.
+    pub fn schedule(&'static self) {
+        if self.state.fetch_or(STATE_SCHEDULED, Ordering::AcqRel) & STATE_SCHEDULED != 0 {
+            return;
+        }
+
+        QUEUES.with(|queue| queue.lock_with(|queue| queue.push(self)));
+        softirq::raise(SoftIrq::Tasklet);
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        self.state.load(Ordering::Acquire) & STATE_SCHEDULED != 0
+    }
+}
+
+impl Queued for Tasklet {
+    fn next(&self) -> &AtomicPtr<Self> {
+        &self.next
+    }
+}
+
+/// Handler for [`SoftIrq::Tasklet`].
+pub(super) fn run_pending() {
+    let mut pending = QUEUES.with(|queue| queue.lock_with(|queue| queue.take()));
+
+    while let Some(tasklet) = pending.pop() {
+        if tasklet.state.fetch_or(STATE_RUNNING, Ordering::Acquire) & STATE_RUNNING != 0 {
+            // Running on another core; try again on the next pass
+            QUEUES.with(|queue| queue.lock_with(|queue| queue.push(tasklet)));
+            softirq::raise(SoftIrq::Tasklet);
+            continue;
+        }
+
+        // Cleared before the call so the tasklet may reschedule itself
+        tasklet.state.fetch_and(!STATE_SCHEDULED, Ordering::AcqRel);
+        (tasklet.func)(tasklet.data);
+        tasklet.state.fetch_and(!STATE_RUNNING, Ordering::Release);
+    }
+}
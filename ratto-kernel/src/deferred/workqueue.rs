@@ -0,0 +1,106 @@
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use super::{Queue, Queued};
+use crate::arch::sync::{SpinLock, WaitQueue};
+
+/// The shared queue for work that does not need a dedicated worker.
+pub static SYSTEM_WORKQUEUE: WorkQueue = WorkQueue::new("events");
+
+/// A unit of work for a [`WorkQueue`].
+///
+/// Work items are `'static` and can be queued at most once at a time; queuing
+/// a pending item is a no-op. The callback receives the item itself so that it
+/// can find its surrounding state.
+pub struct Work {
+    func: fn(&'static Work),
+    pending: AtomicBool,
+    next: AtomicPtr<Work>,
+}
+
+impl Work {
+    pub const fn new(func: fn(&'static Work)) -> Self {
+        Work {
+            func,
+            pending: AtomicBool::new(false),
+            next: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+}
+
+impl Queued for Work {
+    fn next(&self) -> &AtomicPtr<Self> {
+        &self.next
+    }
+}
+
+/// A FIFO of work items serviced in process context by worker threads.
+pub struct WorkQueue {
+    name: &'static str,
+    queue: SpinLock<Queue<Work>>,
+    /// Idle workers, sleeping until work is queued
+    workers: WaitQueue,
+}
+
+impl WorkQueue {
+    #[track_caller]
+    pub const fn new(name: &'static str) -> Self {
+        WorkQueue {
+            name,
+            queue: SpinLock::new(Queue::new()),
+            workers: WaitQueue::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Queue a work item. Returns `false` if it was already pending.
+    pub fn queue(&self, work: &'static Work) -> bool {
+        if work.pending.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+
+        self.queue.lock_with(|queue| queue.push(work));
+        self.workers.wake_one();
+        true
+    }
+
+    /// Run queued work until the queue is empty, returning how many items ran.
+    /// Must be called from process context.
+    pub fn run_pending(&self) -> usize {
+        let mut count = 0;
+        while let Some(work) = self.queue.lock_with(|queue| queue.pop()) {
+            // Cleared before the call so the item may requeue itself
+            work.pending.store(false, Ordering::Release);
+            (work.func)(work);
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Body of a worker thread servicing this queue, which sleeps while the
+    /// queue is empty.
+    pub fn worker(&self) -> ! {
+        loop {
+            self.workers
+                .wait_until(|| self.queue.lock_with(|queue| !queue.is_empty()));
+            self.run_pending();
+        }
+    }
+}
+
+/// Queue work on [`SYSTEM_WORKQUEUE`].
+pub fn schedule_work(work: &'static Work) -> bool {
+    SYSTEM_WORKQUEUE.queue(work)
+}
+
+/// Body of the worker thread servicing [`SYSTEM_WORKQUEUE`].
+pub(super) fn system_worker() {
+    SYSTEM_WORKQUEUE.worker()
+}
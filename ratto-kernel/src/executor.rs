@@ -0,0 +1,111 @@
+//! The kernel's async executor; see [`ratto_core::executor`].
+//!
+//! [`start`] runs it on a kernel thread of its own, next to the preemptive
+//! threads. A core with nothing else to do can instead run it directly with
+//! [`run`]. Futures [`sleep`] on timers that expire with the scheduler tick.
+//!
+//! A thread can also wait for a single future with [`block_on`], without
+//! going through the executor.
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use core::time::Duration;
+
+use ratto_core::executor::TimerQueue;
+use ratto_core::sched::SchedOps;
+
+use crate::arch::Cpu;
+use crate::deferred::softirq::{self, SoftIrq};
+use crate::sched::Sched;
+use crate::task::{self, Task, TaskId};
+
+/// Most spawned futures that can be pending at once.
+pub const MAX_FUTURES: usize = 16;
+
+/// Most [`sleep`]s that can be pending at once. Further ones poll until
+/// there is room.
+pub const MAX_TIMERS: usize = 32;
+
+pub type Executor = ratto_core::executor::Executor<Sched, MAX_FUTURES>;
+pub type Sleep = ratto_core::executor::Sleep<'static, Cpu, MAX_TIMERS>;
+pub type Signal<T> = ratto_core::executor::Signal<T, Cpu>;
+pub type WakerCell = ratto_core::executor::WakerCell<Cpu>;
+
+static EXECUTOR: Executor = Executor::new();
+
+static TIMERS: TimerQueue<Cpu, MAX_TIMERS> = TimerQueue::new();
+
+pub fn init() {
+    softirq::register(SoftIrq::Timer, expire_timers);
+}
+
+/// Run the executor on a kernel thread of its own.
+pub fn start() -> Result<TaskId, &'static str> {
+    task::spawn("executor", executor_thread)
+}
+
+/// Run the executor on the calling thread, in a kernel that does not
+/// [`start`] it on its own thread.
+pub fn run() -> ! {
+    EXECUTOR.run()
+}
+
+/// Start running `future` on the executor.
+pub fn spawn<F>(future: F) -> Result<(), &'static str>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    EXECUTOR.spawn(future)
+}
+
+/// A future that completes once `duration` has passed, give or take a tick.
+pub fn sleep(duration: Duration) -> Sleep {
+    TIMERS.sleep(duration)
+}
+
+/// Poll `future` on the calling thread, parking it whenever the future is
+/// pending, until it completes.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    const TASK_WAKER: RawWakerVTable =
+        RawWakerVTable::new(clone_task, wake_task, wake_task, |_| {});
+
+    fn task_waker(task: &'static Task) -> RawWaker {
+        RawWaker::new(core::ptr::from_ref(task).cast(), &TASK_WAKER)
+    }
+
+    unsafe fn clone_task(task: *const ()) -> RawWaker {
+        task_waker(unsafe { &*task.cast::<Task>() })
+    }
+
+    // Tasks live in a static table, so a waker outliving the wait at worst
+    // wakes a later task spuriously, which parking allows for
+    unsafe fn wake_task(task: *const ()) {
+        Sched::unpark(&unsafe { &*task.cast::<Task>() });
+    }
+
+    let mut future = pin!(future);
+    let waker = unsafe { Waker::from_raw(task_waker(task::current())) };
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            return output;
+        }
+
+        Sched::park();
+    }
+}
+
+/// Called from the scheduler tick of every core.
+pub(crate) fn tick() {
+    if !TIMERS.is_empty() {
+        softirq::raise(SoftIrq::Timer);
+    }
+}
+
+fn executor_thread() {
+    EXECUTOR.run()
+}
+
+fn expire_timers() {
+    TIMERS.expire();
+}
@@ -0,0 +1,70 @@
+//! The initial filesystem, read from the initramfs the bootloader loaded.
+//!
+//! `cargo xtask` packs a directory tree and the user programs into a newc
+//! cpio archive, which QEMU loads with `-initrd` and describes in the DTB's
+//! `/chosen` node. The archive stays where it was loaded, kept from the
+//! frame allocator, and the files borrow their contents from it.
+
+use core::ops::Range;
+
+use ratto_core::boot::BootInfo as _;
+use ratto_core::cpio::{Cpio, CpioError};
+use ratto_core::mem::PhysicalAddress;
+use ratto_core::ramfs::{RamFs, RamFsError};
+
+use crate::arch::sync::OnceLock;
+use crate::arch::{self, ArchImpl};
+use crate::{kerr, klog};
+
+/// Most files and directories the initial filesystem holds.
+pub const MAX_NODES: usize = 128;
+
+pub type InitFs = RamFs<'static, MAX_NODES>;
+
+static INITRAMFS: OnceLock<InitFs> = OnceLock::new();
+
+/// Read the initramfs, if the bootloader loaded one. A malformed archive
+/// leaves the filesystem empty.
+pub fn init(boot_info: &arch::BootInfo) {
+    let fs = match boot_info.initrd() {
+        Some(range) => match unsafe { unpack(range) } {
+            Ok(fs) => {
+                klog!("Initramfs holds {} files and directories", fs.len() - 1);
+                fs
+            }
+            Err(error) => {
+                kerr!("Ignoring the initramfs: {}", error);
+                InitFs::new()
+            }
+        },
+        None => {
+            klog!("No initramfs");
+            InitFs::new()
+        }
+    };
+
+    assert!(
+        INITRAMFS.set(fs).is_ok(),
+        "initramfs::init() called more than once"
+    );
+}
+
+/// # Safety
+/// `range` must be memory that stays untouched for the kernel's life.
+unsafe fn unpack(range: Range<u64>) -> Result<InitFs, &'static str> {
+    let start = arch::Impl::phys_to_virt(PhysicalAddress(range.start)) as *const u8;
+    let size = (range.end - range.start) as usize;
+    let data = unsafe { core::slice::from_raw_parts(start, size) };
+
+    let cpio = Cpio::new(data).map_err(CpioError::as_str)?;
+    InitFs::from_cpio(&cpio).map_err(RamFsError::as_str)
+}
+
+pub fn get() -> &'static InitFs {
+    INITRAMFS.get().expect("initramfs::init() not called yet")
+}
+
+/// The contents of the file at `path`.
+pub fn read(path: &str) -> Option<&'static [u8]> {
+    get().read(path)
+}
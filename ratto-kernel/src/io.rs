@@ -0,0 +1,11 @@
+//! Async device I/O, for use with the [`executor`](crate::executor).
+//!
+//! * [`uart`] - the serial port, driven by its interrupt.
+//! * [`block`] - block devices, and a RAM disk.
+
+pub mod block;
+pub mod uart;
+
+pub fn init() {
+    uart::init();
+}
@@ -0,0 +1,104 @@
+//! Async block devices.
+//!
+//! Drivers for interrupt-driven controllers start a transfer and then await
+//! a [`BlockSignal`] that their interrupt handler signals on completion.
+
+use core::future::Future;
+
+use crate::arch::sync::SpinLock;
+use crate::executor::Signal;
+
+/// Size of a block, the unit of every transfer.
+pub const BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockError {
+    /// The transfer runs past the end of the device
+    OutOfRange,
+    /// The buffer is not a whole number of blocks
+    UnalignedBuffer,
+    /// The device reported an error
+    Device,
+}
+
+/// Completion of a transfer, signalled by a driver's interrupt handler.
+pub type BlockSignal = Signal<Result<(), BlockError>>;
+
+/// A device read and written in whole [`BLOCK_SIZE`] blocks. Each transfer
+/// covers as many consecutive blocks, starting at `block`, as fit in `buf`.
+pub trait BlockDevice: Sync {
+    fn block_count(&self) -> u64;
+
+    fn read<'a>(
+        &'a self,
+        block: u64,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = Result<(), BlockError>> + Send + 'a;
+
+    fn write<'a>(
+        &'a self,
+        block: u64,
+        buf: &'a [u8],
+    ) -> impl Future<Output = Result<(), BlockError>> + Send + 'a;
+}
+
+/// Check a transfer of `len` bytes at `block` against a device of
+/// `block_count` blocks, returning the byte range it covers.
+pub fn transfer_range(
+    block_count: u64,
+    block: u64,
+    len: usize,
+) -> Result<core::ops::Range<usize>, BlockError> {
+    if !len.is_multiple_of(BLOCK_SIZE) {
+        return Err(BlockError::UnalignedBuffer);
+    }
+
+    let blocks = (len / BLOCK_SIZE) as u64;
+    let end = block.checked_add(blocks).ok_or(BlockError::OutOfRange)?;
+    if end > block_count {
+        return Err(BlockError::OutOfRange);
+    }
+
+    let start = block as usize * BLOCK_SIZE;
+    Ok(start..start + len)
+}
+
+/// A block device backed by memory, whose transfers complete at once.
+pub struct RamDisk {
+    data: SpinLock<&'static mut [u8]>,
+    block_count: u64,
+}
+
+impl RamDisk {
+    /// A disk holding `data`, whose length must be a whole number of blocks.
+    pub fn new(data: &'static mut [u8]) -> Self {
+        assert!(
+            data.len().is_multiple_of(BLOCK_SIZE),
+            "RAM disk size is not a whole number of blocks"
+        );
+
+        RamDisk {
+            block_count: (data.len() / BLOCK_SIZE) as u64,
+            data: SpinLock::new(data),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    async fn read(&self, block: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let range = transfer_range(self.block_count, block, buf.len())?;
+        self.data
+            .lock_with(|data| buf.copy_from_slice(&data[range]));
+        Ok(())
+    }
+
+    async fn write(&self, block: u64, buf: &[u8]) -> Result<(), BlockError> {
+        let range = transfer_range(self.block_count, block, buf.len())?;
+        self.data.lock_with(|data| data[range].copy_from_slice(buf));
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Async reads and writes on the serial port. Futures wait for the UART
+//! interrupt rather than poll, so one reader and one writer may wait at a
+//! time.
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+use crate::arch::{Uart, UartOps};
+use crate::executor::WakerCell;
+
+static READER: WakerCell = WakerCell::new();
+
+static WRITER: WakerCell = WakerCell::new();
+
+pub fn init() {
+    Uart::init();
+}
+
+/// Called for [`Irq::Uart`](crate::irq::Irq::Uart), with the UART's
+/// interrupts masked again.
+pub(crate) fn handle_irq() {
+    READER.wake();
+    WRITER.wake();
+}
+
+/// Read the bytes that have arrived into `buf`, waiting for at least one.
+/// Returns how many were read.
+pub async fn read(buf: &mut [u8]) -> usize {
+    poll_fn(|cx| {
+        let mut count = 0;
+        while count < buf.len()
+            && let Some(byte) = Uart::try_read()
+        {
+            buf[count] = byte;
+            count += 1;
+        }
+
+        if count > 0 || buf.is_empty() {
+            return Poll::Ready(count);
+        }
+
+        // The interrupt is level triggered, so a byte that arrived since
+        // the check above still raises it
+        READER.register(cx.waker());
+        Uart::listen(true, false);
+        Poll::Pending
+    })
+    .await
+}
+
+pub async fn read_byte() -> u8 {
+    let mut byte = [0];
+    read(&mut byte).await;
+    byte[0]
+}
+
+/// Write all of `buf`, waiting for room in the transmitter as needed.
+pub async fn write(buf: &[u8]) {
+    let mut written = 0;
+    poll_fn(|cx| {
+        while written < buf.len() && Uart::try_write(buf[written]) {
+            written += 1;
+        }
+
+        if written == buf.len() {
+            return Poll::Ready(());
+        }
+
+        WRITER.register(cx.waker());
+        Uart::listen(false, true);
+        Poll::Pending
+    })
+    .await
+}
@@ -0,0 +1,70 @@
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::arch::{self, ArchImpl};
+use crate::percpu::{self, PerCpu};
+use crate::{deferred::softirq, executor, io, kerr, sched};
+
+/// The interrupts the kernel knows how to service.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Irq {
+    /// The core's scheduler tick
+    Tick,
+    /// Another core asked this one to look at its run queue
+    Ipi,
+    /// The serial port received a byte or can take more
+    Uart,
+}
+
+/// Hard-IRQ nesting depth of each core.
+static IRQ_DEPTH: PerCpu<AtomicUsize> =
+    PerCpu::new([const { AtomicUsize::new(0) }; arch::MAX_CPUS]);
+
+/// IRQs taken by each core since boot.
+static IRQ_COUNT: PerCpu<AtomicU64> = PerCpu::new([const { AtomicU64::new(0) }; arch::MAX_CPUS]);
+
+/// Called by the arch exception code on entry to an IRQ handler.
+pub fn irq_enter() {
+    IRQ_DEPTH.with(|depth| depth.fetch_add(1, Ordering::Relaxed));
+    IRQ_COUNT.with(|count| count.fetch_add(1, Ordering::Relaxed));
+}
+
+/// Called by the arch exception code on the way out of an IRQ handler. When
+/// the outermost handler returns, pending softirqs are run before the
+/// interrupted context resumes.
+pub fn irq_exit() {
+    let depth = IRQ_DEPTH.with(|depth| depth.fetch_sub(1, Ordering::Relaxed));
+    assert!(depth > 0, "irq_exit() without a matching irq_enter()");
+
+    if depth == 1 {
+        softirq::run_pending();
+        sched::preempt_on_irq_exit();
+    }
+}
+
+/// Whether the executing core is servicing a hard IRQ.
+pub fn in_irq() -> bool {
+    IRQ_DEPTH.with(|depth| depth.load(Ordering::Relaxed)) > 0
+}
+
+/// IRQs taken by `cpu` since boot.
+pub fn irq_count(cpu: usize) -> u64 {
+    IRQ_COUNT.for_cpu(cpu).load(Ordering::Relaxed)
+}
+
+/// Route the pending interrupt to its driver.
+pub fn dispatch() {
+    let Some(irq) = arch::Impl::pending_irq() else {
+        kerr!("Spurious IRQ on core {}", percpu::cpu_id());
+        return;
+    };
+
+    arch::Impl::ack_irq(irq);
+    match irq {
+        Irq::Tick => {
+            sched::tick();
+            executor::tick();
+        }
+        Irq::Ipi => sched::resched_ipi(),
+        Irq::Uart => io::uart::handle_irq(),
+    }
+}
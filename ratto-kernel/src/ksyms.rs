@@ -0,0 +1,62 @@
+use core::fmt::Display;
+
+use ratto_core::ksyms::{Symbol, SymbolTable};
+
+/// Space reserved for the symbol table. `ratto-xtask` fails the build if the
+/// table does not fit.
+const KSYMS_SIZE: usize = 128 * 1024;
+
+#[used]
+#[unsafe(link_section = ".ksyms")]
+static KSYMS_RESERVED: [u8; KSYMS_SIZE] = [0; KSYMS_SIZE];
+
+unsafe extern "C" {
+    unsafe static __ksyms_start: u8;
+    unsafe static __ksyms_end: u8;
+}
+
+/// The symbol table embedded in the kernel image, if the build filled it in.
+pub fn table() -> Option<SymbolTable<'static>> {
+    // Read through the linker symbols so the compiler cannot assume the
+    // reserved section still holds zeros
+    let bytes = unsafe {
+        let start = &__ksyms_start as *const u8;
+        let end = &__ksyms_end as *const u8;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+
+    SymbolTable::parse(bytes)
+}
+
+/// Find the function containing `addr`.
+pub fn lookup(addr: usize) -> Option<Symbol<'static>> {
+    table()?.lookup(addr as u64)
+}
+
+/// Formats an address followed by its symbol, when one is known.
+pub struct Symbolized(pub usize);
+
+impl Display for Symbolized {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#018x}", self.0)?;
+        match lookup(self.0) {
+            Some(symbol) => write!(f, " <{}>", symbol),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Formats a return address with the symbol of its call site. The address
+/// itself may already belong to the next function if the call was the last
+/// instruction of the caller.
+pub struct SymbolizedReturn(pub usize);
+
+impl Display for SymbolizedReturn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#018x}", self.0)?;
+        match lookup(self.0.wrapping_sub(1)) {
+            Some(symbol) => write!(f, " <{}+{:#x}>", symbol.name, symbol.offset + 1),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,22 +1,42 @@
 #![cfg_attr(not(test), no_std)]
-#![feature(sync_unsafe_cell)]
 #![feature(format_args_nl)]
 
-use core::cell::SyncUnsafeCell;
 use core::fmt::Debug;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use ratto_core::boot::BootInfo;
 
 use crate::arch::ArchImpl;
+use crate::arch::sync::OnceLock;
+use crate::backtrace::Backtrace;
 use crate::console::Console;
 
 pub mod arch;
+pub mod backtrace;
 pub mod console;
+pub mod deferred;
+pub mod executor;
+pub mod initramfs;
+pub mod io;
+pub mod irq;
+pub mod ksyms;
+pub mod mem;
+pub mod percpu;
 pub mod print;
+pub mod process;
+pub mod sched;
+pub mod smp;
+pub mod syscall;
+pub mod task;
+
+/// The first user process, in the initial filesystem.
+pub const INIT_PATH: &str = "/init";
 
 static KERNEL_INSTANCE: KernelCell = KernelCell::new();
 
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 pub struct Kernel {
     console: Option<&'static dyn Console>,
 }
@@ -32,39 +52,59 @@ impl Debug for Kernel {
 impl Kernel {
     /// Setup a console logger that can be used for early initialization messages.
     pub fn init1(console: Option<&'static dyn Console>) {
-        assert!(
-            matches!(kernel(), KernelState::Uninit),
-            "Kernel::init() called more than once"
-        );
+        percpu::init_this_cpu();
+        sched::init_this_cpu();
+        ratto_core::sync::lockdep::set_reporter(|args| kerr!("{}", args));
 
-        KERNEL_INSTANCE.promote(KernelState::Init1(console));
+        KERNEL_INSTANCE.enter_init1(console);
     }
 
     /// Complete kernel initialization.
     pub fn init2(args: KernelArgs<arch::BootInfo>) {
-        assert!(
-            matches!(kernel(), KernelState::Init1(..)),
-            "Kernel::init() called more than once"
-        );
-
-        KERNEL_INSTANCE.promote(KernelState::Init2(args.clone()));
+        let args = KERNEL_INSTANCE.enter_init2(args);
         klog!("Kernel initialization started...");
         klog!("Boot info: {:#?}", args.boot_info);
 
-        let (memory_mapper, frame_allocator) =
-            arch::Impl::init_memory(&args.boot_info).expect("Failed to initialize memory");
+        arch::Impl::init_exceptions();
+        sched::init();
+        deferred::init();
+        executor::init();
+        executor::start().expect("Failed to start the executor");
+        io::init();
 
-        // Suppress unused variable warnings for now
-        (_, _) = (memory_mapper, frame_allocator);
+        mem::init(&args.boot_info);
+        initramfs::init(&args.boot_info);
 
         let kernel = Kernel {
             console: args.console,
         };
 
-        KERNEL_INSTANCE.promote(KernelState::Ready(kernel));
+        KERNEL_INSTANCE.enter_ready(kernel);
         klog!("Kernel initialization completed.");
+
+        smp::mark_online(percpu::cpu_id());
+        arch::Impl::start_secondaries(&args.boot_info);
+        klog!("{} core(s) online", smp::online_count());
+    }
+
+    /// Entry point of the secondary cores, once they have a stack.
+    pub fn secondary_main() -> ! {
+        assert!(
+            kernel().is_ready(),
+            "Kernel::secondary_main() called before Kernel::init()"
+        );
+
+        percpu::init_this_cpu();
+        sched::init_this_cpu();
+        let cpu = percpu::cpu_id();
+        arch::Impl::init_exceptions();
+        smp::mark_online(cpu);
+        klog!("Core {} online", cpu);
+
+        sched::idle();
     }
 
+    /// Start [`INIT_PATH`] and become the boot core's idle task.
     pub fn run() -> ! {
         assert!(
             kernel().is_ready(),
@@ -72,10 +112,27 @@ impl Kernel {
         );
 
         klog!("Kernel main loop starting...");
-        panic!("Unimplemented: Kernel main loop");
+        let started = initramfs::read(INIT_PATH)
+            .ok_or("Not in the initramfs")
+            .and_then(|image| process::spawn_elf("init", image, &[INIT_PATH], &[]));
+        match started {
+            Ok(pid) => klog!("Started {} as process {}", INIT_PATH, pid.0),
+            Err(error) => kerr!("Unable to start {}: {}", INIT_PATH, error),
+        }
+
+        sched::idle();
+    }
+
+    /// Restart the machine.
+    pub fn reboot() -> ! {
+        klog!("Rebooting...");
+        arch::Impl::reset()
     }
 
     pub fn panic_dump(info: &PanicInfo) {
+        PANICKING.store(true, Ordering::Relaxed);
+        ratto_core::sync::lockdep::turn_off();
+
         if kernel().console().is_none() {
             // No console available; cannot print panic information
             return;
@@ -89,6 +146,11 @@ impl Kernel {
 
         kraw!("Reason: {}", info.message());
         kraw!("Kernel State: {:#?}", kernel());
+
+        kraw!("Backtrace:");
+        for (i, return_address) in Backtrace::current().enumerate() {
+            kraw!("  #{:<2} {}", i, ksyms::SymbolizedReturn(return_address));
+        }
     }
 }
 
@@ -98,36 +160,39 @@ pub struct KernelArgs<B: BootInfo> {
     pub console: Option<&'static dyn Console>,
 }
 
-#[derive(Debug)]
+/// Snapshot of the kernel's initialization stage. Each stage's data lives
+/// in [`KernelCell`] for the rest of the kernel's life, so the references in
+/// a snapshot stay valid after the kernel moves on.
+#[derive(Debug, Clone, Copy)]
 pub enum KernelState {
     Uninit,
     Init1(Option<&'static dyn Console>),
-    Init2(KernelArgs<arch::BootInfo>),
-    Ready(Kernel),
+    Init2(&'static KernelArgs<arch::BootInfo>),
+    Ready(&'static Kernel),
 }
 
 impl KernelState {
-    pub fn console(&self) -> Option<&dyn Console> {
+    pub fn console(self) -> Option<&'static dyn Console> {
         match self {
             KernelState::Uninit => None,
-            KernelState::Init1(console) => *console,
+            KernelState::Init1(console) => console,
             KernelState::Init2(args) => args.console,
             KernelState::Ready(kernel) => kernel.console,
         }
     }
 
-    pub fn is_ready(&self) -> bool {
+    pub fn is_ready(self) -> bool {
         matches!(self, KernelState::Ready(..))
     }
 
-    pub fn as_ready(&self) -> &Kernel {
+    pub fn as_ready(self) -> &'static Kernel {
         match self {
             KernelState::Ready(kernel) => kernel,
             _ => panic!("Kernel is not ready"),
         }
     }
 
-    pub fn try_as_ready(&self) -> Option<&Kernel> {
+    pub fn try_as_ready(self) -> Option<&'static Kernel> {
         match self {
             KernelState::Ready(kernel) => Some(kernel),
             _ => None,
@@ -135,30 +200,92 @@ impl KernelState {
     }
 }
 
+const STAGE_UNINIT: u8 = 0;
+const STAGE_INIT1: u8 = 1;
+const STAGE_INIT2: u8 = 2;
+const STAGE_READY: u8 = 3;
+
+/// The kernel's state, moving through `Uninit → Init1 → Init2 → Ready`.
+///
+/// Each stage's data is written once into its own slot before the stage
+/// counter is advanced, and is never moved or dropped afterwards, so any core
+/// or interrupt handler can read the state at any time.
 pub struct KernelCell {
-    inner: SyncUnsafeCell<KernelState>,
+    stage: AtomicU8,
+    console: OnceLock<Option<&'static dyn Console>>,
+    args: OnceLock<KernelArgs<arch::BootInfo>>,
+    kernel: OnceLock<Kernel>,
 }
 
 impl KernelCell {
     pub const fn new() -> Self {
         KernelCell {
-            inner: SyncUnsafeCell::new(KernelState::Uninit),
+            stage: AtomicU8::new(STAGE_UNINIT),
+            console: OnceLock::new(),
+            args: OnceLock::new(),
+            kernel: OnceLock::new(),
         }
     }
 
-    pub fn get(&self) -> &KernelState {
-        unsafe { &*self.inner.get() }
+    pub fn get(&'static self) -> KernelState {
+        match self.stage.load(Ordering::Acquire) {
+            STAGE_UNINIT => KernelState::Uninit,
+            STAGE_INIT1 => KernelState::Init1(*self.console.get().unwrap()),
+            STAGE_INIT2 => KernelState::Init2(self.args.get().unwrap()),
+            _ => KernelState::Ready(self.kernel.get().unwrap()),
+        }
     }
 
-    pub fn promote(&self, state: KernelState) {
-        unsafe {
-            let ptr = self.inner.get();
-            core::ptr::write(ptr, state);
-        }
+    pub fn enter_init1(&self, console: Option<&'static dyn Console>) {
+        let stored = self.console.set(console).is_ok();
+        assert!(stored, "Kernel::init() called more than once");
+        self.advance(STAGE_UNINIT, STAGE_INIT1);
     }
+
+    pub fn enter_init2(
+        &'static self,
+        args: KernelArgs<arch::BootInfo>,
+    ) -> &'static KernelArgs<arch::BootInfo> {
+        assert!(
+            self.stage.load(Ordering::Acquire) == STAGE_INIT1,
+            "Kernel::init2() called out of order"
+        );
+        let stored = self.args.set(args).is_ok();
+        assert!(stored, "Kernel::init() called more than once");
+        self.advance(STAGE_INIT1, STAGE_INIT2);
+        self.args.get().unwrap()
+    }
+
+    pub fn enter_ready(&self, kernel: Kernel) {
+        assert!(
+            self.stage.load(Ordering::Acquire) == STAGE_INIT2,
+            "Kernel marked ready out of order"
+        );
+        let stored = self.kernel.set(kernel).is_ok();
+        assert!(stored, "Kernel marked ready more than once");
+        self.advance(STAGE_INIT2, STAGE_READY);
+    }
+
+    /// Publish the next stage. Its data must already be in place.
+    fn advance(&self, from: u8, to: u8) {
+        let result = self
+            .stage
+            .compare_exchange(from, to, Ordering::Release, Ordering::Relaxed);
+        assert!(
+            result.is_ok(),
+            "Kernel state moved from stage {} to {} concurrently",
+            from,
+            to
+        );
+    }
+}
+
+/// Whether a panic is being reported. Consoles should then write without
+/// taking their locks, which the panicking core may already hold.
+pub fn panicking() -> bool {
+    PANICKING.load(Ordering::Relaxed)
 }
 
-pub fn kernel() -> &'static KernelState {
-    let kernel_ptr = KERNEL_INSTANCE.get();
-    &*kernel_ptr
+pub fn kernel() -> KernelState {
+    KERNEL_INSTANCE.get()
 }
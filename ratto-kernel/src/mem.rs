@@ -0,0 +1,91 @@
+//! Physical frames and user address spaces.
+//!
+//! The architecture's [`FrameAllocator`] and [`MemoryMapper`] are set up
+//! once by [`init`] and shared by every core behind their own locks. When
+//! both are needed, the mapper is locked first, as it allocates the frames
+//! of page tables.
+
+use ratto_core::mem::{FrameAllocator as _, MemoryMapper as _, PhysicalFrame};
+
+use crate::arch::sync::{OnceLock, SpinLockIrq};
+use crate::arch::{self, ArchImpl, FrameAllocator, MemoryMapper};
+
+pub type AddressSpace = <MemoryMapper as ratto_core::mem::MemoryMapper>::AddressSpace;
+
+/// Size of a frame, and of a page.
+pub const PAGE_SIZE: usize = FrameAllocator::FRAME_SIZE as usize;
+
+static FRAME_ALLOCATOR: OnceLock<SpinLockIrq<FrameAllocator>> = OnceLock::new();
+
+static MEMORY_MAPPER: OnceLock<SpinLockIrq<MemoryMapper>> = OnceLock::new();
+
+pub fn init(boot_info: &arch::BootInfo) {
+    let (mapper, frame_allocator) =
+        arch::Impl::init_memory(boot_info).expect("Failed to initialize memory");
+
+    let stored = FRAME_ALLOCATOR
+        .set(SpinLockIrq::new(frame_allocator))
+        .is_ok()
+        && MEMORY_MAPPER.set(SpinLockIrq::new(mapper)).is_ok();
+    assert!(stored, "mem::init() called more than once");
+}
+
+fn frame_allocator() -> &'static SpinLockIrq<FrameAllocator> {
+    FRAME_ALLOCATOR.get().expect("mem::init() not called yet")
+}
+
+/// Run `f` with the memory mapper locked.
+pub fn with_mapper<R>(f: impl FnOnce(&mut MemoryMapper) -> R) -> R {
+    MEMORY_MAPPER
+        .get()
+        .expect("mem::init() not called yet")
+        .lock_with(f)
+}
+
+pub fn alloc_frame() -> Option<PhysicalFrame> {
+    frame_allocator().lock_with(|frames| frames.alloc_frame())
+}
+
+/// Number of frames the allocator manages, in use or not.
+pub fn total_frames() -> usize {
+    frame_allocator().lock_with(|frames| frames.total_frames())
+}
+
+/// Number of frames free to allocate.
+pub fn free_frames() -> usize {
+    frame_allocator().lock_with(|frames| frames.free_frames())
+}
+
+/// Allocate a frame and fill it with zeroes.
+pub fn alloc_zeroed_frame() -> Option<PhysicalFrame> {
+    let frame = alloc_frame()?;
+    unsafe { frame_ptr(frame).write_bytes(0, PAGE_SIZE) };
+    Some(frame)
+}
+
+/// Free a frame from [`alloc_frame`].
+///
+/// # Safety
+/// The frame must no longer be mapped or otherwise in use.
+pub unsafe fn free_frame(frame: PhysicalFrame) {
+    frame_allocator().lock_with(|frames| unsafe { frames.free_frame(frame) });
+}
+
+/// Where the kernel reaches the contents of `frame`.
+pub fn frame_ptr(frame: PhysicalFrame) -> *mut u8 {
+    arch::Impl::phys_to_virt(frame.addr) as *mut u8
+}
+
+/// Translate user addresses through `space` on the executing core, or
+/// through nothing for `None`.
+///
+/// # Safety
+/// `space` must stay alive until another address space is activated.
+pub unsafe fn activate(space: Option<&AddressSpace>) {
+    with_mapper(|mapper| unsafe {
+        match space {
+            Some(space) => mapper.activate(space),
+            None => mapper.deactivate(),
+        }
+    });
+}
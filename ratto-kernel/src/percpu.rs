@@ -0,0 +1,57 @@
+use ratto_core::cpu::CpuOps;
+use ratto_core::percpu::PerCpuArea;
+
+use crate::arch::{self, Cpu};
+
+pub use ratto_core::percpu::{BhGuard, IrqGuard, PerCpuRef, PreemptGuard};
+
+/// A variable with one instance per core; see [`ratto_core::percpu::PerCpu`].
+pub type PerCpu<T> = ratto_core::percpu::PerCpu<T, Cpu, { arch::MAX_CPUS }>;
+
+static AREAS: [PerCpuArea; arch::MAX_CPUS] = {
+    let mut areas = [const { PerCpuArea::new(0) }; arch::MAX_CPUS];
+    let mut cpu = 0;
+    while cpu < arch::MAX_CPUS {
+        areas[cpu] = PerCpuArea::new(cpu);
+        cpu += 1;
+    }
+    areas
+};
+
+/// Install the executing core's per-CPU area. Must run before anything on the
+/// core uses [`PerCpu`] or preemption control.
+pub fn init_this_cpu() {
+    let cpu = Cpu::current_cpu_id();
+    assert!(cpu < arch::MAX_CPUS, "Core {} exceeds MAX_CPUS", cpu);
+
+    unsafe { Cpu::set_per_cpu_area(&AREAS[cpu]) };
+}
+
+/// Index of the executing core, in `0..MAX_CPUS`.
+pub fn cpu_id() -> usize {
+    ratto_core::percpu::cpu_id::<Cpu>()
+}
+
+/// Disable preemption until the returned guard is dropped.
+pub fn preempt_disable() -> PreemptGuard<Cpu> {
+    ratto_core::percpu::preempt_disable::<Cpu>()
+}
+
+pub fn preemptible() -> bool {
+    ratto_core::percpu::preemptible::<Cpu>()
+}
+
+/// Mask interrupts until the returned guard is dropped. Guards nest and may
+/// be dropped in any order.
+pub fn irq_save() -> IrqGuard<Cpu> {
+    ratto_core::percpu::irq_save::<Cpu>()
+}
+
+/// Hold off softirqs and preemption until the returned guard is dropped.
+pub fn bh_disable() -> BhGuard<Cpu> {
+    ratto_core::percpu::bh_disable::<Cpu>()
+}
+
+pub fn bh_enabled() -> bool {
+    ratto_core::percpu::bh_enabled::<Cpu>()
+}
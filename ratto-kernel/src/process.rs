@@ -0,0 +1,593 @@
+//! User processes.
+//!
+//! A process is a task running at user level in an address space of its
+//! own, which maps the process's pages below [`USER_END`](arch::USER_END).
+//! The kernel's half of the address space is the same in every process.
+//!
+//! Processes live in a fixed table, like tasks. They are set up with
+//! [`Process::create`] and [`Process::map`], and started with
+//! [`Process::start`], or all at once from an ELF executable with
+//! [`spawn_elf`] or from a flat image with [`spawn`]. Every page mapped
+//! into a process belongs to it, and is freed with its page tables when the
+//! process exits or is killed.
+//!
+//! A process started by another is its child. An exited child keeps its
+//! slot, as a [`ProcessState::Zombie`] holding the exit code, until the
+//! parent [waits](Process::wait_child) for it. Children of an exited parent,
+//! like processes the kernel starts, have no parent and are freed as soon
+//! as they exit.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Debug};
+use core::ptr;
+use core::sync::atomic::{AtomicI32, AtomicU8, AtomicUsize, Ordering};
+
+use ratto_core::cpu::CpuOps;
+use ratto_core::mem::{MapFlags, MemoryMapper as _, VirtualAddress};
+
+use crate::arch::sync::{SpinLockIrq, WaitQueue};
+use crate::arch::{self, ArchImpl, Cpu};
+use crate::mem::{self, AddressSpace, PAGE_SIZE};
+use crate::task::{self, TaskId};
+use crate::{kerr, klog};
+
+mod files;
+mod loader;
+
+pub use files::*;
+pub use loader::*;
+
+/// Most processes that can exist at once.
+pub const MAX_PROCESSES: usize = 8;
+
+/// Where [`spawn`] loads flat images.
+pub const USER_IMAGE_BASE: usize = 0x40_0000;
+
+/// Top of the stack [`spawn`] and [`spawn_elf`] map, at the end of the
+/// user half.
+pub const USER_STACK_TOP: usize = arch::USER_END;
+
+/// Size of the stack [`spawn`] and [`spawn_elf`] map.
+pub const USER_STACK_SIZE: usize = 0x1_0000;
+
+/// Where [`Process::map_anywhere`] starts placing mappings, well above
+/// any image and growing towards the stack.
+pub const USER_MMAP_BASE: usize = 0x10_0000_0000;
+
+/// Exit code of processes killed by the kernel.
+pub const KILLED_EXIT_CODE: i32 = -1;
+
+static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+/// Taken to change which processes are whose children, and to turn exited
+/// processes into zombies or free them, so that the two never race.
+static FAMILY: SpinLockIrq<()> = SpinLockIrq::new(());
+
+/// Woken whenever a process becomes a zombie.
+static ZOMBIES: WaitQueue = WaitQueue::new();
+
+static PROCESSES: [Process; MAX_PROCESSES] = [const { Process::new() }; MAX_PROCESSES];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Pid(pub usize);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ProcessState {
+    /// The slot is unused
+    Free,
+    /// Being set up, not running yet
+    New,
+    /// Its task runs the process's code
+    Running,
+    /// Its address space is being torn down
+    Exiting,
+    /// Exited, its exit code kept until its parent waits for it
+    Zombie,
+}
+
+impl ProcessState {
+    fn from_raw(raw: u8) -> Self {
+        use ProcessState::*;
+        const STATES: [ProcessState; 5] = [Free, New, Running, Exiting, Zombie];
+
+        STATES[raw as usize]
+    }
+}
+
+pub struct Process {
+    /// 0 while the slot is free
+    pid: AtomicUsize,
+    state: AtomicU8,
+    /// Pid of the parent, or 0. Only changed under [`FAMILY`].
+    parent: AtomicUsize,
+    /// Set before the process becomes a zombie
+    exit_code: AtomicI32,
+    /// Only written by [`Process::create`] while the slot is
+    /// [`ProcessState::New`].
+    name: UnsafeCell<&'static str>,
+    /// `None` once the process is torn down
+    space: SpinLockIrq<Option<AddressSpace>>,
+    /// Where the process's task enters user mode
+    entry: AtomicUsize,
+    stack_top: AtomicUsize,
+    /// Where the next [`Process::map_anywhere`] goes
+    mmap_next: AtomicUsize,
+    files: SpinLockIrq<FileTable>,
+}
+
+// See the field docs for who may touch the cells
+unsafe impl Sync for Process {}
+
+impl Process {
+    const fn new() -> Self {
+        Process {
+            pid: AtomicUsize::new(0),
+            state: AtomicU8::new(ProcessState::Free as u8),
+            parent: AtomicUsize::new(0),
+            exit_code: AtomicI32::new(0),
+            name: UnsafeCell::new(""),
+            space: SpinLockIrq::new(None),
+            entry: AtomicUsize::new(0),
+            stack_top: AtomicUsize::new(0),
+            mmap_next: AtomicUsize::new(USER_MMAP_BASE),
+            files: SpinLockIrq::new(FileTable::new()),
+        }
+    }
+
+    /// Claim a slot for a process with an empty address space, as a child
+    /// of the current process, if any.
+    pub fn create(name: &'static str) -> Result<&'static Process, &'static str> {
+        let process = PROCESSES
+            .iter()
+            .find(|process| {
+                process
+                    .state
+                    .compare_exchange(
+                        ProcessState::Free as u8,
+                        ProcessState::New as u8,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            })
+            .ok_or("Too many processes")?;
+
+        let space = match mem::with_mapper(|mapper| mapper.new_address_space()) {
+            Ok(space) => space,
+            Err(error) => {
+                process.set_state(ProcessState::Free);
+                return Err(error);
+            }
+        };
+
+        let pid = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+        let parent = current().map_or(0, |parent| parent.pid().0);
+        FAMILY.lock_with(|_| {
+            process.pid.store(pid, Ordering::Relaxed);
+            process.parent.store(parent, Ordering::Relaxed);
+        });
+        process.mmap_next.store(USER_MMAP_BASE, Ordering::Relaxed);
+        process.files.lock_with(FileTable::clear);
+        unsafe { *process.name.get() = name };
+        process.space.lock_with(|slot| *slot = Some(space));
+        Ok(process)
+    }
+
+    pub fn pid(&self) -> Pid {
+        Pid(self.pid.load(Ordering::Relaxed))
+    }
+
+    pub fn name(&self) -> &'static str {
+        unsafe { *self.name.get() }
+    }
+
+    /// The process that started this one, while it has not exited.
+    pub fn parent(&self) -> Option<Pid> {
+        match self.parent.load(Ordering::Relaxed) {
+            0 => None,
+            pid => Some(Pid(pid)),
+        }
+    }
+
+    pub fn state(&self) -> ProcessState {
+        ProcessState::from_raw(self.state.load(Ordering::Acquire))
+    }
+
+    fn set_state(&self, state: ProcessState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Run `f` with the process's open files.
+    pub fn with_files<R>(&self, f: impl FnOnce(&mut FileTable) -> R) -> R {
+        self.files.lock_with(f)
+    }
+
+    /// Wait for the child `pid` to exit, free it and return its exit code.
+    pub fn wait_child(&self, pid: Pid) -> Result<i32, &'static str> {
+        let child = FAMILY
+            .lock_with(|_| {
+                PROCESSES.iter().find(|process| {
+                    process.state() != ProcessState::Free
+                        && process.pid() == pid
+                        && process.parent() == Some(self.pid())
+                })
+            })
+            .ok_or("No such child")?;
+
+        // Only this process frees its children, so the slot stays the child's
+        ZOMBIES.wait_until(|| child.state() == ProcessState::Zombie);
+        let code = child.exit_code.load(Ordering::Relaxed);
+        FAMILY.lock_with(|_| child.free());
+        Ok(code)
+    }
+
+    /// Map fresh pages for user mode over the `len` bytes from `start`,
+    /// holding `contents` from `start` and zeroes around it.
+    pub fn map(
+        &self,
+        start: usize,
+        len: usize,
+        flags: MapFlags,
+        contents: &[u8],
+    ) -> Result<(), &'static str> {
+        if contents.len() > len {
+            return Err("Contents larger than the mapping");
+        }
+
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= arch::USER_END)
+            .ok_or("Mapping outside the user half")?;
+        let first_page = start & !(PAGE_SIZE - 1);
+
+        self.space.lock_with(|space| {
+            let space = space.as_mut().ok_or("Process has exited")?;
+
+            for page in (first_page..end).step_by(PAGE_SIZE) {
+                let frame = mem::alloc_zeroed_frame().ok_or("Out of memory")?;
+                // The part of `contents` on this page, and where it goes
+                let skip = start.saturating_sub(page);
+                let contents = contents
+                    .get(page.saturating_sub(start)..)
+                    .unwrap_or_default();
+                let copied = contents.len().min(PAGE_SIZE - skip);
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        contents.as_ptr(),
+                        mem::frame_ptr(frame).add(skip),
+                        copied,
+                    );
+                }
+
+                if flags.contains(MapFlags::EXEC) {
+                    arch::Impl::sync_instruction_cache(mem::frame_ptr(frame) as usize, PAGE_SIZE);
+                }
+
+                let virt = VirtualAddress(page as u64);
+                let flags = flags | MapFlags::USER;
+                if let Err(error) =
+                    mem::with_mapper(|mapper| unsafe { mapper.map_page(space, virt, frame, flags) })
+                {
+                    unsafe { mem::free_frame(frame) };
+                    return Err(error);
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Map `len` bytes of fresh, zeroed pages where nothing is mapped yet,
+    /// returning where. Addresses are not reused once unmapped.
+    pub fn map_anywhere(&self, len: usize, flags: MapFlags) -> Result<usize, &'static str> {
+        let len = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .filter(|len| *len > 0 && *len <= USER_STACK_TOP - USER_STACK_SIZE - USER_MMAP_BASE)
+            .ok_or("Bad mapping size")?;
+
+        let start = self
+            .mmap_next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                Some(next + len).filter(|end| *end <= USER_STACK_TOP - USER_STACK_SIZE)
+            })
+            .map_err(|_| "Out of address space")?;
+
+        // Give back what was mapped before running out
+        self.map(start, len, flags, &[]).inspect_err(|_| {
+            let _ = self.unmap(start, len);
+        })?;
+        Ok(start)
+    }
+
+    /// Unmap and free the pages from the page-aligned `start` over `len`
+    /// bytes. Pages that are not mapped are skipped.
+    pub fn unmap(&self, start: usize, len: usize) -> Result<(), &'static str> {
+        if !start.is_multiple_of(PAGE_SIZE) {
+            return Err("Mapping not page-aligned");
+        }
+
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= arch::USER_END)
+            .ok_or("Mapping outside the user half")?;
+
+        self.space.lock_with(|space| {
+            let space = space.as_mut().ok_or("Process has exited")?;
+
+            for page in (start..end).step_by(PAGE_SIZE) {
+                let virt = VirtualAddress(page as u64);
+                // The page's TLB entries are gone once it is unmapped
+                if let Ok(frame) =
+                    mem::with_mapper(|mapper| unsafe { mapper.unmap_page(space, virt) })
+                {
+                    unsafe { mem::free_frame(frame) };
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Copy the process's memory from `addr` into `buf`. Fails if any of it
+    /// is not mapped for user mode.
+    pub fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+        let mut copied = 0;
+        self.for_each_page(addr, buf.len(), MapFlags::READ, |page, len| {
+            unsafe { ptr::copy_nonoverlapping(page, buf[copied..].as_mut_ptr(), len) };
+            copied += len;
+        })
+    }
+
+    /// Copy `data` into the process's memory at `addr`. Fails if any of it
+    /// is not mapped writable for user mode.
+    pub fn write_memory(&self, addr: usize, data: &[u8]) -> Result<(), &'static str> {
+        let mut copied = 0;
+        self.for_each_page(addr, data.len(), MapFlags::WRITE, |page, len| {
+            unsafe { ptr::copy_nonoverlapping(data[copied..].as_ptr(), page, len) };
+            copied += len;
+        })
+    }
+
+    /// Whether `len` bytes from `addr` are all mapped for user mode with
+    /// `access`.
+    pub fn can_access(&self, addr: usize, len: usize, access: MapFlags) -> bool {
+        self.for_each_page(addr, len, access, |_, _| {}).is_ok()
+    }
+
+    /// Check that `len` bytes from `addr` are mapped for user mode with
+    /// `access`, then pass each page's part of them to `f` in order, as
+    /// the kernel reaches it. Nothing is passed if the check fails.
+    fn for_each_page(
+        &self,
+        addr: usize,
+        len: usize,
+        access: MapFlags,
+        mut f: impl FnMut(*mut u8, usize),
+    ) -> Result<(), &'static str> {
+        let end = addr
+            .checked_add(len)
+            .filter(|end| *end <= arch::USER_END)
+            .ok_or("Address outside the user half")?;
+        if len == 0 {
+            return Ok(());
+        }
+
+        let pages = (addr & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE);
+
+        self.space.lock_with(|space| {
+            let space = space.as_ref().ok_or("Process has exited")?;
+            let translate = |page: usize| {
+                mem::with_mapper(|mapper| mapper.translate(space, VirtualAddress(page as u64)))
+                    .filter(|(_, flags)| flags.contains(access | MapFlags::USER))
+                    .map(|(frame, _)| frame)
+            };
+
+            if !pages.clone().all(|page| translate(page).is_some()) {
+                return Err("Address not mapped");
+            }
+
+            for page in pages {
+                let frame = translate(page).expect("Page unmapped while copying");
+                let start = addr.max(page);
+                let len = end.min(page + PAGE_SIZE) - start;
+                f(unsafe { mem::frame_ptr(frame).add(start - page) }, len);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Run the process from `entry`, with its stack pointer at `stack_top`,
+    /// on a task of its own.
+    pub fn start(&'static self, entry: usize, stack_top: usize) -> Result<TaskId, &'static str> {
+        assert_eq!(
+            self.state(),
+            ProcessState::New,
+            "Process started more than once"
+        );
+
+        self.entry.store(entry, Ordering::Relaxed);
+        self.stack_top.store(stack_top, Ordering::Relaxed);
+        self.set_state(ProcessState::Running);
+
+        task::spawn_process(self.name(), process_main, self).inspect_err(|_| {
+            self.set_state(ProcessState::New);
+        })
+    }
+
+    /// Free a process that was never started, with everything mapped in it.
+    pub fn destroy(&self) {
+        assert_eq!(
+            self.state(),
+            ProcessState::New,
+            "Only unstarted processes can be destroyed"
+        );
+        self.set_state(ProcessState::Exiting);
+        self.release();
+        FAMILY.lock_with(|_| self.free());
+    }
+
+    /// Translate user addresses through the process's address space on the
+    /// executing core.
+    pub(crate) fn activate(&self) {
+        self.space.lock_with(|space| {
+            let space = space
+                .as_ref()
+                .expect("Activating a process that has exited");
+            unsafe { mem::activate(Some(space)) };
+        });
+    }
+
+    /// Free the address space, with every page and table in it, and close
+    /// the open files.
+    fn release(&self) {
+        debug_assert_eq!(self.state(), ProcessState::Exiting);
+
+        if let Some(space) = self.space.lock_with(Option::take) {
+            mem::with_mapper(|mapper| unsafe {
+                mapper.destroy_address_space(space, &mut |_, frame| mem::free_frame(frame));
+            });
+        }
+
+        self.files.lock_with(FileTable::clear);
+    }
+
+    /// Give the slot back. Must be called under [`FAMILY`].
+    fn free(&self) {
+        self.pid.store(0, Ordering::Relaxed);
+        self.parent.store(0, Ordering::Relaxed);
+        self.set_state(ProcessState::Free);
+    }
+}
+
+impl Debug for Process {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Process")
+            .field("pid", &self.pid())
+            .field("parent", &self.parent())
+            .field("name", &self.name())
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// Start a process running `image`, which must be position independent
+/// code. It is loaded at [`USER_IMAGE_BASE`] and entered at its first byte,
+/// with a stack of [`USER_STACK_SIZE`] below [`USER_STACK_TOP`].
+pub fn spawn(name: &'static str, image: &[u8]) -> Result<Pid, &'static str> {
+    let process = Process::create(name)?;
+
+    let started = process
+        .map(
+            USER_IMAGE_BASE,
+            image.len(),
+            MapFlags::READ | MapFlags::EXEC,
+            image,
+        )
+        .and_then(|()| {
+            process.map(
+                USER_STACK_TOP - USER_STACK_SIZE,
+                USER_STACK_SIZE,
+                MapFlags::READ | MapFlags::WRITE,
+                &[],
+            )
+        })
+        .and_then(|()| process.start(USER_IMAGE_BASE, USER_STACK_TOP));
+
+    match started {
+        Ok(_) => Ok(process.pid()),
+        Err(error) => {
+            process.destroy();
+            Err(error)
+        }
+    }
+}
+
+/// Every process that exists, zombies included, in slot order.
+pub fn all() -> impl Iterator<Item = &'static Process> {
+    PROCESSES
+        .iter()
+        .filter(|process| process.state() != ProcessState::Free)
+}
+
+/// The process the current task runs, if any.
+pub fn current() -> Option<&'static Process> {
+    task::current().process()
+}
+
+/// End the current process with `code`.
+pub fn exit(code: i32) -> ! {
+    let process = current().expect("process::exit() called outside a process");
+    klog!(
+        "Process {} ({}) exited with code {}",
+        process.pid().0,
+        process.name(),
+        code
+    );
+
+    exit_current(process, code)
+}
+
+/// End the current process because its user code did something the
+/// kernel does not handle, described by `reason`.
+pub(crate) fn kill_current(reason: fmt::Arguments) -> ! {
+    let process = current().expect("User exception outside a process");
+    kerr!(
+        "Process {} ({}) killed: {}",
+        process.pid().0,
+        process.name(),
+        reason
+    );
+
+    exit_current(process, KILLED_EXIT_CODE)
+}
+
+fn exit_current(process: &'static Process, code: i32) -> ! {
+    process.set_state(ProcessState::Exiting);
+
+    // The task's process and the core's address space change together, so
+    // nothing switches back to the process's tables
+    let irq_state = Cpu::disable_interrupts();
+    task::current().set_process(None);
+    unsafe { mem::activate(None) };
+    Cpu::enable_interrupts(irq_state);
+
+    process.release();
+    process.exit_code.store(code, Ordering::Relaxed);
+
+    let zombie = FAMILY.lock_with(|_| {
+        // Orphans have no one to wait for them
+        for child in PROCESSES.iter() {
+            if child.state() != ProcessState::Free && child.parent() == Some(process.pid()) {
+                child.parent.store(0, Ordering::Relaxed);
+                if child.state() == ProcessState::Zombie {
+                    child.free();
+                }
+            }
+        }
+
+        if process.parent().is_some() {
+            process.set_state(ProcessState::Zombie);
+            true
+        } else {
+            process.free();
+            false
+        }
+    });
+
+    if zombie {
+        ZOMBIES.wake_all();
+    }
+    task::exit()
+}
+
+/// Body of every process's task.
+fn process_main() {
+    let process = current().expect("Process task without a process");
+    let entry = process.entry.load(Ordering::Relaxed);
+    let stack_top = process.stack_top.load(Ordering::Relaxed);
+
+    // The switch to this task activated the process's address space
+    unsafe { arch::Impl::enter_user(entry, stack_top, 0) }
+}
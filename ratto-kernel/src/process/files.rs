@@ -0,0 +1,64 @@
+//! The files a process has open, all in the initial filesystem.
+
+use ratto_abi::fd;
+use ratto_core::ramfs::NodeId;
+
+/// Most files a process can have open at once.
+pub const MAX_OPEN_FILES: usize = 8;
+
+/// Number of the first file opened, after the standard ones.
+const FIRST_FD: usize = fd::STDERR + 1;
+
+#[derive(Debug, Copy, Clone)]
+pub struct OpenFile {
+    pub node: NodeId,
+    /// Bytes of a file, or entries of a directory, read so far
+    pub offset: usize,
+}
+
+#[derive(Debug)]
+pub struct FileTable {
+    files: [Option<OpenFile>; MAX_OPEN_FILES],
+}
+
+impl FileTable {
+    pub const fn new() -> Self {
+        FileTable {
+            files: [None; MAX_OPEN_FILES],
+        }
+    }
+
+    /// Open `node` from its start, returning its descriptor, or `None` if
+    /// the table is full.
+    pub fn open(&mut self, node: NodeId) -> Option<usize> {
+        let (index, slot) = self
+            .files
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())?;
+        *slot = Some(OpenFile { node, offset: 0 });
+        Some(FIRST_FD + index)
+    }
+
+    pub fn close(&mut self, fd: usize) -> Option<OpenFile> {
+        self.slot(fd)?.take()
+    }
+
+    pub fn get_mut(&mut self, fd: usize) -> Option<&mut OpenFile> {
+        self.slot(fd)?.as_mut()
+    }
+
+    pub fn clear(&mut self) {
+        self.files = [None; MAX_OPEN_FILES];
+    }
+
+    fn slot(&mut self, fd: usize) -> Option<&mut Option<OpenFile>> {
+        self.files.get_mut(fd.checked_sub(FIRST_FD)?)
+    }
+}
+
+impl Default for FileTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
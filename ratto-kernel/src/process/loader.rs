@@ -0,0 +1,107 @@
+//! Loading ELF executables into new processes.
+
+use core::slice;
+
+use ratto_abi::auxv::{AT_ENTRY, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM};
+use ratto_core::elf::{Elf, ElfError, PHDR_SIZE, initial_stack};
+use ratto_core::mem::MapFlags;
+
+use super::{Pid, Process, USER_STACK_SIZE, USER_STACK_TOP};
+use crate::arch::{self, ArchImpl};
+use crate::mem::{self, PAGE_SIZE};
+
+/// Start a process running the static ELF executable `image`. Its
+/// `PT_LOAD` segments are mapped where they ask to be, with a stack of
+/// [`USER_STACK_SIZE`] below [`USER_STACK_TOP`] that holds `argv`, `envp`
+/// and the auxiliary vector. It starts at the image's entry point.
+pub fn spawn_elf(
+    name: &'static str,
+    image: &[u8],
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<Pid, &'static str> {
+    let elf = check_elf(image)?;
+    let process = Process::create(name)?;
+    match load(process, &elf, argv, envp) {
+        Ok(()) => Ok(process.pid()),
+        Err(error) => {
+            process.destroy();
+            Err(error)
+        }
+    }
+}
+
+/// Check that `image` is a static ELF executable the cores can run.
+pub fn check_elf(image: &[u8]) -> Result<Elf<'_>, &'static str> {
+    let elf = Elf::new(image).map_err(ElfError::as_str)?;
+    if elf.machine() != arch::Impl::ELF_MACHINE {
+        return Err("ELF image for another machine");
+    }
+
+    Ok(elf)
+}
+
+fn load(
+    process: &'static Process,
+    elf: &Elf<'_>,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<(), &'static str> {
+    for segment in elf.segments() {
+        // Pages past the segment's data start out zeroed, which clears .bss
+        let start = usize::try_from(segment.vaddr).map_err(|_| "Segment outside the user half")?;
+        let len = usize::try_from(segment.mem_size).map_err(|_| "Segment outside the user half")?;
+        process.map(start, len, segment.map_flags(), segment.data)?;
+    }
+
+    process.map(
+        USER_STACK_TOP - USER_STACK_SIZE,
+        USER_STACK_SIZE,
+        MapFlags::READ | MapFlags::WRITE,
+        &[],
+    )?;
+
+    let stack_pointer = push_arguments(process, elf, argv, envp)?;
+    process.start(elf.entry() as usize, stack_pointer)?;
+    Ok(())
+}
+
+/// Lay out `argv`, `envp` and the auxiliary vector at the top of the
+/// process's stack, returning the stack pointer to start with.
+fn push_arguments(
+    process: &Process,
+    elf: &Elf<'_>,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<usize, &'static str> {
+    let mut auxv = [
+        (AT_PHENT, PHDR_SIZE as u64),
+        (AT_PHNUM, elf.program_header_count() as u64),
+        (AT_PAGESZ, PAGE_SIZE as u64),
+        (AT_ENTRY, elf.entry()),
+        (AT_PHDR, 0),
+    ];
+    let auxv = match elf.program_headers_vaddr() {
+        Some(phdr) => {
+            auxv[4].1 = phdr;
+            &auxv[..]
+        }
+        None => &auxv[..4],
+    };
+
+    // Built in a spare frame, so the arguments are limited to a page
+    let scratch = mem::alloc_zeroed_frame().ok_or("Out of memory")?;
+    let stack = unsafe { slice::from_raw_parts_mut(mem::frame_ptr(scratch), PAGE_SIZE) };
+    let top = USER_STACK_TOP as u64;
+
+    let pushed = initial_stack(stack, top, argv, envp, auxv)
+        .ok_or("Arguments too large")
+        .and_then(|stack_pointer| {
+            let used = &stack[PAGE_SIZE - (top - stack_pointer) as usize..];
+            process.write_memory(stack_pointer as usize, used)?;
+            Ok(stack_pointer as usize)
+        });
+
+    unsafe { mem::free_frame(scratch) };
+    pushed
+}
@@ -0,0 +1,525 @@
+//! Task scheduling.
+//!
+//! Every core has its own run queue of ready tasks, ordered by a
+//! [`Scheduler`] policy from `ratto-core`. Tasks switch away when they
+//! block, yield or exit, and are preempted at IRQ exit when the scheduler
+//! tick finds that another task should run.
+//!
+//! Woken and new tasks go to the core they last ran on if it is idle, else
+//! to the least loaded core they may run on, which is sent an IPI if it is
+//! not the caller. A core that runs out of work steals from the others, and
+//! each core periodically pulls tasks over from the busiest one.
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering, fence};
+
+use ratto_core::cpu::CpuOps;
+use ratto_core::sched::{FairShare, SchedOps, Scheduler};
+
+use crate::arch::sync::SpinLockIrq;
+use crate::arch::{self, ArchImpl, ContextOps, Cpu};
+use crate::deferred::softirq::{self, SoftIrq};
+use crate::mem;
+use crate::percpu::{self, PerCpu};
+use crate::smp;
+use crate::task::{self, Task, TaskState};
+
+/// Scheduler ticks per second.
+pub const TICK_HZ: u32 = 100;
+
+/// Ticks between two load balancing passes of a core.
+const BALANCE_INTERVAL: u32 = 10;
+
+/// The policy ordering the run queues. Any [`Scheduler`] will do.
+type Policy = FairShare<'static>;
+
+static RUN_QUEUES: PerCpu<RunQueue> = PerCpu::new([const { RunQueue::new() }; arch::MAX_CPUS]);
+
+static CPU_SCHED: PerCpu<CpuSched> = PerCpu::new([const { CpuSched::new() }; arch::MAX_CPUS]);
+
+/// The tasks ready to run on one core.
+struct RunQueue {
+    policy: SpinLockIrq<Policy>,
+    /// Queued tasks, readable without taking the lock
+    len: AtomicUsize,
+}
+
+impl RunQueue {
+    #[track_caller]
+    const fn new() -> Self {
+        RunQueue {
+            policy: SpinLockIrq::new(Policy::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn lock_with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Policy) -> R,
+    {
+        self.policy.lock_with(|policy| {
+            let result = f(policy);
+            self.len.store(policy.len(), Ordering::Relaxed);
+            result
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+/// What each core knows about the tasks it runs.
+struct CpuSched {
+    current: AtomicPtr<Task>,
+    /// The task the core just switched away from, for [`finish_switch`].
+    prev: AtomicPtr<Task>,
+    /// Set when the current task should make way for another.
+    need_resched: AtomicBool,
+    /// Ticks left until the next load balancing pass
+    balance_in: AtomicU32,
+}
+
+impl CpuSched {
+    const fn new() -> Self {
+        CpuSched {
+            current: AtomicPtr::new(ptr::null_mut()),
+            prev: AtomicPtr::new(ptr::null_mut()),
+            need_resched: AtomicBool::new(false),
+            balance_in: AtomicU32::new(BALANCE_INTERVAL),
+        }
+    }
+
+    /// Whether the core is running a task other than its idle task.
+    fn is_busy(&self) -> bool {
+        let current = self.current.load(Ordering::Acquire);
+        unsafe { current.as_ref() }.is_some_and(|task| !task.is_idle())
+    }
+}
+
+/// Set up the periodic load balancing.
+pub fn init() {
+    softirq::register(SoftIrq::Sched, balance);
+}
+
+/// Make the executing core's idle task its current task. Must run once on
+/// each core, after `percpu::init_this_cpu`.
+pub fn init_this_cpu() {
+    let cpu = percpu::cpu_id();
+    let idle = task::init_idle_task(cpu);
+    idle.set_state(TaskState::Running);
+    idle.on_cpu.store(true, Ordering::Relaxed);
+
+    let current = ptr::from_ref(idle).cast_mut();
+    CPU_SCHED
+        .for_cpu(cpu)
+        .current
+        .store(current, Ordering::Release);
+}
+
+/// The task running on the executing core.
+pub fn current() -> &'static Task {
+    let current = CPU_SCHED.with(|sched| sched.current.load(Ordering::Acquire));
+    unsafe { current.as_ref() }.expect("No scheduler on this core yet")
+}
+
+/// Let the other ready tasks run before the current one continues.
+pub fn yield_now() {
+    switch_away(TaskState::Ready);
+}
+
+/// Body of each core's idle task, run once the core is initialized. Starts
+/// the core's tick and enables interrupts, so tasks get preempted from here
+/// on, then runs whatever is ready and sleeps in `wfi` while nothing is.
+pub fn idle() -> ! {
+    assert!(current().is_idle(), "idle() called from a spawned task");
+
+    arch::Impl::enable_ipi();
+    arch::Impl::start_tick(TICK_HZ);
+    Cpu::unmask_interrupts();
+
+    loop {
+        yield_now();
+        wait_for_work();
+    }
+}
+
+/// Sleep until the next interrupt, unless work has turned up for this core.
+fn wait_for_work() {
+    let irq_state = Cpu::disable_interrupts();
+    let cpu = percpu::cpu_id();
+
+    // Tasks queued here from now on come with an IPI, which ends the wait
+    let need_resched = CPU_SCHED.for_cpu(cpu).need_resched.load(Ordering::Relaxed);
+    if RUN_QUEUES.for_cpu(cpu).len() == 0 && !need_resched {
+        Cpu::wait_for_interrupt();
+    }
+
+    Cpu::enable_interrupts(irq_state);
+}
+
+/// Put a new task on a run queue.
+pub(crate) fn enqueue(task: &'static Task) {
+    queue_on(select_cpu(task), task, false);
+}
+
+pub(crate) fn exit_current() -> ! {
+    assert!(!current().is_idle(), "The idle task cannot exit");
+
+    switch_away(TaskState::Exited);
+    unreachable!("Exited task was resumed");
+}
+
+/// Move `task` off its core if its new affinity no longer allows it there.
+pub(crate) fn affinity_changed(task: &'static Task) {
+    let cpu = task.cpu();
+    if task.may_run_on(cpu) {
+        return;
+    }
+
+    match task.state() {
+        TaskState::Ready => {
+            let queued = RUN_QUEUES
+                .for_cpu(cpu)
+                .lock_with(|queue| queue.steal(|entity| ptr::eq(entity, &task.sched)));
+
+            // Otherwise a core has just picked it, and requeues it elsewhere
+            // when it switches away
+            if queued.is_some() {
+                queue_on(select_cpu(task), task, false);
+            }
+        }
+        TaskState::Running if ptr::eq(task, current()) => yield_now(),
+        // Its core switches away from it at the IRQ exit
+        TaskState::Running => arch::Impl::send_ipi(cpu),
+        // Anything else picks a core when it is next queued
+        _ => {}
+    }
+}
+
+/// The core `task` should be queued on: the one it last ran on if that is
+/// idle, else the least loaded online core it may run on.
+fn select_cpu(task: &Task) -> usize {
+    let last = task.cpu();
+    let allowed = task.affinity() & smp::online_cpus();
+    if allowed.contains(last) && !CPU_SCHED.for_cpu(last).is_busy() {
+        return last;
+    }
+
+    allowed
+        .iter()
+        .filter(|&cpu| cpu < arch::MAX_CPUS)
+        .min_by_key(|&cpu| (load(cpu), cpu != last))
+        .unwrap_or_else(|| {
+            // Early in boot no allowed core may be online yet
+            let this = percpu::cpu_id();
+            match task.may_run_on(this) {
+                true => this,
+                false => task
+                    .affinity()
+                    .first()
+                    .expect("Task with an empty affinity mask"),
+            }
+        })
+}
+
+/// Tasks queued on or running on `cpu`.
+fn load(cpu: usize) -> usize {
+    RUN_QUEUES.for_cpu(cpu).len() + usize::from(CPU_SCHED.for_cpu(cpu).is_busy())
+}
+
+/// Put `task`, which is new, woken or switching away, on the run queue of
+/// `cpu` and tell that core about it.
+fn queue_on(cpu: usize, task: &'static Task, woken: bool) {
+    RUN_QUEUES.for_cpu(cpu).lock_with(|queue| {
+        task.set_cpu(cpu);
+        task.set_state(TaskState::Ready);
+        match woken {
+            true => queue.task_wakeup(&task.sched),
+            false => queue.enqueue(&task.sched),
+        }
+    });
+
+    if cpu != percpu::cpu_id() && smp::is_online(cpu) {
+        arch::Impl::send_ipi(cpu);
+    }
+}
+
+/// Take a task that may run on `cpu` from the first other core that has
+/// one queued.
+fn steal(cpu: usize) -> Option<&'static Task> {
+    let others = (1..arch::MAX_CPUS).map(|offset| (cpu + offset) % arch::MAX_CPUS);
+    let task = others
+        .filter(|&other| RUN_QUEUES.for_cpu(other).len() > 0)
+        .find_map(|other| {
+            RUN_QUEUES
+                .for_cpu(other)
+                .lock_with(|queue| queue.steal(|entity| Task::of_entity(entity).may_run_on(cpu)))
+        })?;
+
+    let task = Task::of_entity(task);
+    task.set_cpu(cpu);
+    Some(task)
+}
+
+/// Leave the current task in `state` and run the next ready task, or the
+/// idle task if there is none. Returns once the current task runs again,
+/// straight away if it is merely yielding and nothing else is ready.
+fn switch_away(state: TaskState) {
+    assert!(
+        percpu::preemptible(),
+        "Task switch with preemption disabled"
+    );
+
+    let irq_state = Cpu::disable_interrupts();
+    let cpu = percpu::cpu_id();
+    let prev = current();
+
+    CPU_SCHED
+        .for_cpu(cpu)
+        .need_resched
+        .store(false, Ordering::Relaxed);
+
+    if state == TaskState::Blocked {
+        // Pairs with the fence in `unpark`: either it sees the task blocked
+        // and requeues it, or the token is seen here and the task carries on.
+        // If both happen, the task is already queued and must switch away.
+        prev.set_state(TaskState::Blocked);
+        fence(Ordering::SeqCst);
+        if prev.wake_token.swap(false, Ordering::Acquire)
+            && prev.transition(TaskState::Blocked, TaskState::Running)
+        {
+            Cpu::enable_interrupts(irq_state);
+            return;
+        }
+    }
+
+    let local = RUN_QUEUES.for_cpu(cpu).lock_with(|queue| queue.pick_next());
+    let next = match local {
+        Some(next) => Task::of_entity(next),
+        None if state == TaskState::Ready && !prev.is_idle() && prev.may_run_on(cpu) => {
+            Cpu::enable_interrupts(irq_state);
+            return;
+        }
+        // Rather than go idle, take work from another core
+        None => steal(cpu).unwrap_or_else(|| task::idle_task(cpu)),
+    };
+
+    // A blocking task can be woken and queued again in the meantime
+    if ptr::eq(next, prev) {
+        prev.set_state(TaskState::Running);
+        Cpu::enable_interrupts(irq_state);
+        return;
+    }
+
+    match state {
+        TaskState::Ready if !prev.is_idle() => queue_on(select_cpu(prev), prev, false),
+        // A blocked task's state is already set, and it may even be queued
+        TaskState::Blocked => {}
+        state => prev.set_state(state),
+    }
+
+    switch_to(cpu, prev, next);
+    Cpu::enable_interrupts(irq_state);
+}
+
+fn switch_to(cpu: usize, prev: &'static Task, next: &'static Task) {
+    assert!(
+        prev.stack_intact(),
+        "Task {} ({:?}) overflowed its stack",
+        prev.name(),
+        prev.id()
+    );
+
+    // `next` may have been queued by a core still switching away from it
+    while next.on_cpu.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+
+    next.on_cpu.store(true, Ordering::Relaxed);
+    next.set_cpu(cpu);
+    next.set_state(TaskState::Running);
+
+    let sched = CPU_SCHED.for_cpu(cpu);
+    sched
+        .prev
+        .store(ptr::from_ref(prev).cast_mut(), Ordering::Relaxed);
+    sched
+        .current
+        .store(ptr::from_ref(next).cast_mut(), Ordering::Release);
+
+    // The user half belongs to the process switched to, if any
+    if prev.process().map(ptr::from_ref) != next.process().map(ptr::from_ref) {
+        match next.process() {
+            Some(process) => process.activate(),
+            None => unsafe { mem::activate(None) },
+        }
+    }
+
+    unsafe { arch::Context::switch(prev.context.get(), next.context.get()) };
+
+    // Possibly on another core by now
+    finish_switch();
+}
+
+/// Complete a switch on the task that was switched to: the previous task's
+/// registers are saved, so another core may now resume it.
+fn finish_switch() {
+    let sched = CPU_SCHED.for_cpu(percpu::cpu_id());
+    let prev = sched.prev.swap(ptr::null_mut(), Ordering::Relaxed);
+    let prev = unsafe { prev.as_ref() }.expect("Task switch without a previous task");
+
+    prev.on_cpu.store(false, Ordering::Release);
+    if prev.state() == TaskState::Exited {
+        task::release(prev);
+    }
+}
+
+/// First thing a new task runs, in place of the return from [`switch_to`].
+/// Tasks start with interrupts enabled, whatever the task they replace had.
+pub(crate) fn task_started() {
+    finish_switch();
+    Cpu::unmask_interrupts();
+}
+
+/// Whether the current task of `cpu` should make way for another, without
+/// accounting for time.
+fn should_resched(cpu: usize, current: &Task) -> bool {
+    let idle_with_work = current.is_idle() && RUN_QUEUES.for_cpu(cpu).len() > 0;
+    idle_with_work || !current.may_run_on(cpu)
+}
+
+fn request_resched(cpu: usize) {
+    CPU_SCHED
+        .for_cpu(cpu)
+        .need_resched
+        .store(true, Ordering::Relaxed);
+}
+
+/// Account a tick to the executing core's current task, and start a load
+/// balancing pass every [`BALANCE_INTERVAL`] ticks. Called from the tick
+/// IRQ; the switch, if one is due, happens at IRQ exit.
+pub(crate) fn tick() {
+    let cpu = percpu::cpu_id();
+    let current = current();
+    let sched = CPU_SCHED.for_cpu(cpu);
+
+    let preempt = !current.is_idle()
+        && RUN_QUEUES
+            .for_cpu(cpu)
+            .lock_with(|queue| queue.tick(&current.sched));
+    if preempt || should_resched(cpu, current) {
+        request_resched(cpu);
+    }
+
+    if sched.balance_in.fetch_sub(1, Ordering::Relaxed) == 1 {
+        sched.balance_in.store(BALANCE_INTERVAL, Ordering::Relaxed);
+        softirq::raise(SoftIrq::Sched);
+    }
+}
+
+/// Handle a reschedule IPI from another core, which queued a task here or
+/// changed the affinity of the current one.
+pub(crate) fn resched_ipi() {
+    let cpu = percpu::cpu_id();
+    if should_resched(cpu, current()) {
+        request_resched(cpu);
+    }
+}
+
+/// Pull tasks over from the busiest core until the two are about even.
+/// Runs as the [`SoftIrq::Sched`] handler.
+fn balance() {
+    let cpu = percpu::cpu_id();
+    let Some(busiest) = smp::online_cpus()
+        .iter()
+        .filter(|&other| other != cpu && other < arch::MAX_CPUS)
+        .max_by_key(|&other| load(other))
+    else {
+        return;
+    };
+
+    let excess = load(busiest).saturating_sub(load(cpu)) / 2;
+    let mut moved = 0;
+    for _ in 0..excess {
+        let task = RUN_QUEUES
+            .for_cpu(busiest)
+            .lock_with(|queue| queue.steal(|entity| Task::of_entity(entity).may_run_on(cpu)));
+        let Some(task) = task else {
+            break;
+        };
+
+        queue_on(cpu, Task::of_entity(task), false);
+        moved += 1;
+    }
+
+    if moved > 0 && should_resched(cpu, current()) {
+        request_resched(cpu);
+    }
+}
+
+/// Preempt the current task if a tick or IPI asked for it and the
+/// interrupted code allows it. Called at the exit of the outermost IRQ
+/// handler.
+pub(crate) fn preempt_on_irq_exit() {
+    if !percpu::preemptible() {
+        return;
+    }
+
+    if CPU_SCHED.with(|sched| sched.need_resched.load(Ordering::Relaxed)) {
+        switch_away(TaskState::Ready);
+    }
+}
+
+/// Scheduler hooks for the blocking primitives in [`arch::sync`].
+pub struct Sched;
+
+impl SchedOps for Sched {
+    type Cpu = Cpu;
+    type Task = &'static Task;
+
+    fn current_task() -> Self::Task {
+        current()
+    }
+
+    fn park() {
+        let task = current();
+        if !task.is_idle() {
+            switch_away(TaskState::Blocked);
+            return;
+        }
+
+        // The idle task must stay runnable, so it waits in place. Its
+        // wakeups from other cores come with an IPI.
+        loop {
+            let irq_state = Cpu::disable_interrupts();
+            let woken = task.wake_token.swap(false, Ordering::Acquire);
+            if !woken {
+                Cpu::wait_for_interrupt();
+            }
+
+            Cpu::enable_interrupts(irq_state);
+            if woken {
+                return;
+            }
+        }
+    }
+
+    fn unpark(task: &Self::Task) {
+        task.wake_token.store(true, Ordering::Release);
+        fence(Ordering::SeqCst);
+
+        if task.is_idle() {
+            if task.cpu() != percpu::cpu_id() {
+                arch::Impl::send_ipi(task.cpu());
+            }
+            return;
+        }
+
+        // The wakeup is delivered by requeuing, so the token is spent
+        if task.transition(TaskState::Blocked, TaskState::Ready) {
+            task.wake_token.store(false, Ordering::Relaxed);
+            queue_on(select_cpu(task), task, true);
+        }
+    }
+}
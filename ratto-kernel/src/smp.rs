@@ -0,0 +1,38 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ratto_core::sched::CpuMask;
+
+/// How long the boot core waits for a released core to report in.
+const ONLINE_TIMEOUT_SPINS: usize = 10_000_000;
+
+/// Bitmask of the cores that have entered the kernel.
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn mark_online(cpu: usize) {
+    ONLINE.fetch_or(1 << cpu, Ordering::Release);
+}
+
+pub fn is_online(cpu: usize) -> bool {
+    ONLINE.load(Ordering::Acquire) & (1 << cpu) != 0
+}
+
+pub fn online_cpus() -> CpuMask {
+    CpuMask::from_bits(ONLINE.load(Ordering::Acquire) as u64)
+}
+
+pub fn online_count() -> usize {
+    ONLINE.load(Ordering::Acquire).count_ones() as usize
+}
+
+/// Spin until `cpu` is online, giving up after a while.
+pub fn wait_online(cpu: usize) -> bool {
+    for _ in 0..ONLINE_TIMEOUT_SPINS {
+        if is_online(cpu) {
+            return true;
+        }
+
+        core::hint::spin_loop();
+    }
+
+    false
+}
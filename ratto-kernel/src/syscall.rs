@@ -0,0 +1,142 @@
+//! System calls from user processes.
+//!
+//! The exception handler passes every `svc` from user mode to [`dispatch`],
+//! which looks the call up in a table indexed by its [`Syscall`] number.
+//! Numbers, error codes and argument structures come from [`ratto_abi`],
+//! which user programs share.
+//!
+//! Calls run on the calling process's task, with IRQs unmasked, so they may
+//! block. With [`set_tracing`] on, each call is logged as it enters and
+//! returns.
+
+use core::fmt::{self, Display};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use ratto_abi::{Errno, Syscall, SyscallResult, encode_result};
+
+use crate::klog;
+use crate::process::{self, Process};
+
+mod children;
+mod fs;
+mod io;
+mod memory;
+mod system;
+mod task;
+mod time;
+mod user;
+
+/// Arguments of a call, from `x0` to `x5`.
+pub type Args = [usize; 6];
+
+type Handler = fn(&'static Process, Args) -> SyscallResult;
+
+/// Handlers in [`Syscall`] number order.
+const TABLE: [(Syscall, Handler); Syscall::COUNT] = [
+    (Syscall::Exit, task::exit),
+    (Syscall::Write, io::write),
+    (Syscall::Read, io::read),
+    (Syscall::GetPid, task::getpid),
+    (Syscall::Yield, task::yield_now),
+    (Syscall::Sleep, time::sleep),
+    (Syscall::ClockGetTime, time::clock_gettime),
+    (Syscall::AbiVersion, task::abi_version),
+    (Syscall::Mmap, memory::mmap),
+    (Syscall::Munmap, memory::munmap),
+    (Syscall::Spawn, children::spawn),
+    (Syscall::Wait, children::wait),
+    (Syscall::Open, fs::open),
+    (Syscall::Close, fs::close),
+    (Syscall::ReadDir, fs::readdir),
+    (Syscall::ListProcesses, system::list_processes),
+    (Syscall::MemInfo, system::meminfo),
+    (Syscall::Reboot, system::reboot),
+];
+
+const _: () = {
+    let mut number = 0;
+    while number < Syscall::COUNT {
+        assert!(
+            TABLE[number].0 as usize == number,
+            "System call table out of order"
+        );
+        number += 1;
+    }
+};
+
+static TRACING: AtomicBool = AtomicBool::new(false);
+
+/// Log every system call on entry and return, or stop doing so.
+pub fn set_tracing(on: bool) {
+    TRACING.store(on, Ordering::Relaxed);
+}
+
+/// Run system call `number` for the current process and return its
+/// encoded result, for `x0`.
+pub fn dispatch(number: usize, args: Args) -> usize {
+    let process = process::current().expect("System call outside a process");
+    let tracing = TRACING.load(Ordering::Relaxed);
+
+    let Some(&(syscall, handler)) = TABLE.get(number) else {
+        if tracing {
+            klog!(
+                "[{} {}] unknown system call {} -> {}",
+                process.pid().0,
+                process.name(),
+                number,
+                Errno::NoSys
+            );
+        }
+        return encode_result(Err(Errno::NoSys));
+    };
+
+    if tracing {
+        klog!(
+            "[{} {}] {}({})",
+            process.pid().0,
+            process.name(),
+            syscall,
+            TracedArgs(&args[..syscall.arg_count()])
+        );
+    }
+
+    let result = handler(process, args);
+
+    if tracing {
+        klog!(
+            "[{} {}] {} -> {}",
+            process.pid().0,
+            process.name(),
+            syscall,
+            TracedResult(result)
+        );
+    }
+
+    encode_result(result)
+}
+
+struct TracedArgs<'a>(&'a [usize]);
+
+impl Display for TracedArgs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, arg) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{:#x}", arg)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct TracedResult(SyscallResult);
+
+impl Display for TracedResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Ok(value) => write!(f, "{:#x}", value),
+            Err(errno) => write!(f, "{}", errno),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Starting programs as children of the calling process, and waiting for
+//! them to exit.
+
+use ratto_abi::{ARG_MAX, Errno, PATH_MAX, SyscallResult};
+use ratto_core::collections::ArrayVec;
+use ratto_core::ramfs::NodeKind;
+
+use super::{Args, user};
+use crate::initramfs;
+use crate::process::{self, Pid, Process};
+
+/// Most arguments a program can be started with.
+const MAX_ARGS: usize = 32;
+
+/// `spawn(path, path_len, args, args_len)`: start the executable at
+/// `path` with the NUL-ended arguments in `args`.
+pub fn spawn(
+    process: &'static Process,
+    [path, path_len, args, args_len, ..]: Args,
+) -> SyscallResult {
+    let mut path_buf = [0; PATH_MAX];
+    let path = user::read_str(process, path, path_len, &mut path_buf)?;
+    let mut args_buf = [0; ARG_MAX];
+    let args = user::read_str(process, args, args_len, &mut args_buf)?;
+
+    let mut argv = ArrayVec::<&str, MAX_ARGS>::new();
+    if !args.is_empty() {
+        let args = args.strip_suffix('\0').ok_or(Errno::Inval)?;
+        for arg in args.split('\0') {
+            argv.push(arg).map_err(|_| Errno::Inval)?;
+        }
+    }
+
+    let fs = initramfs::get();
+    let node = fs.node(fs.lookup(path).ok_or(Errno::NoEnt)?);
+    let NodeKind::File(image) = node.kind else {
+        return Err(Errno::IsDir);
+    };
+
+    process::check_elf(image).map_err(|_| Errno::NoExec)?;
+    let pid = process::spawn_elf(node.name, image, &argv, &[]).map_err(|_| Errno::NoMem)?;
+    Ok(pid.0)
+}
+
+/// `wait(pid)`: wait for the child `pid` to exit and return its exit code.
+pub fn wait(process: &'static Process, [pid, ..]: Args) -> SyscallResult {
+    let code = process.wait_child(Pid(pid)).map_err(|_| Errno::Child)?;
+    Ok(code as u32 as usize)
+}
@@ -0,0 +1,82 @@
+//! Reading files and listing directories of the initial filesystem.
+
+use ratto_abi::{DirEntry, Errno, PATH_MAX, SyscallResult, file_kind};
+use ratto_core::ramfs::NodeKind;
+
+use super::{Args, user};
+use crate::initramfs;
+use crate::process::{OpenFile, Process};
+
+/// `open(path, path_len)`: open the file or directory at `path`.
+pub fn open(process: &'static Process, [path, path_len, ..]: Args) -> SyscallResult {
+    let mut buf = [0; PATH_MAX];
+    let path = user::read_str(process, path, path_len, &mut buf)?;
+    let node = initramfs::get().lookup(path).ok_or(Errno::NoEnt)?;
+
+    process
+        .with_files(|files| files.open(node))
+        .ok_or(Errno::NoMem)
+}
+
+/// `close(fd)`
+pub fn close(process: &'static Process, [fd, ..]: Args) -> SyscallResult {
+    process
+        .with_files(|files| files.close(fd))
+        .ok_or(Errno::BadFd)?;
+    Ok(0)
+}
+
+/// `read(fd, buf, len)` on an open file: read from where the last read
+/// stopped, returning 0 at the end.
+pub fn read(process: &'static Process, fd: usize, buf: usize, len: usize) -> SyscallResult {
+    let file = open_file(process, fd)?;
+    let NodeKind::File(data) = initramfs::get().node(file.node).kind else {
+        return Err(Errno::IsDir);
+    };
+
+    let data = data.get(file.offset..).unwrap_or_default();
+    let count = len.min(data.len());
+    user::write_bytes(process, buf, &data[..count])?;
+    advance(process, fd, count);
+    Ok(count)
+}
+
+/// `readdir(fd, entry)`: describe the next entry of an open directory.
+pub fn readdir(process: &'static Process, [fd, entry, ..]: Args) -> SyscallResult {
+    let file = open_file(process, fd)?;
+    let fs = initramfs::get();
+    if fs.node(file.node).kind != NodeKind::Dir {
+        return Err(Errno::NotDir);
+    }
+
+    let Some((_, node)) = fs.children(file.node).nth(file.offset) else {
+        return Ok(0);
+    };
+
+    let mut out = DirEntry::new();
+    let name = &node.name.as_bytes()[..node.name.len().min(DirEntry::NAME_MAX)];
+    out.name[..name.len()].copy_from_slice(name);
+    out.name_len = name.len() as u8;
+    (out.kind, out.size) = match node.kind {
+        NodeKind::File(data) => (file_kind::FILE, data.len() as u64),
+        NodeKind::Dir => (file_kind::DIR, 0),
+    };
+
+    user::write(process, entry, &out)?;
+    advance(process, fd, 1);
+    Ok(1)
+}
+
+fn open_file(process: &Process, fd: usize) -> Result<OpenFile, Errno> {
+    process
+        .with_files(|files| files.get_mut(fd).copied())
+        .ok_or(Errno::BadFd)
+}
+
+fn advance(process: &Process, fd: usize, count: usize) {
+    process.with_files(|files| {
+        if let Some(file) = files.get_mut(fd) {
+            file.offset += count;
+        }
+    });
+}
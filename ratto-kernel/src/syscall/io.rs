@@ -0,0 +1,61 @@
+//! Reads and writes on the standard file descriptors, which are all the
+//! serial console. Reads of other descriptors go to [`fs`](super::fs).
+
+use ratto_abi::{Errno, SyscallResult, fd};
+
+use super::{Args, fs, user};
+use crate::arch::sync::Mutex;
+use crate::executor;
+use crate::io::uart;
+use crate::process::Process;
+
+/// Bytes moved between user memory and the UART at a time.
+const CHUNK_SIZE: usize = 256;
+
+// The UART futures wait for one reader and one writer at a time
+static READER: Mutex<()> = Mutex::new(());
+
+static WRITER: Mutex<()> = Mutex::new(());
+
+/// `write(fd, buf, len)`: write all of `buf` to standard output or error.
+pub fn write(process: &'static Process, [fd, buf, len, ..]: Args) -> SyscallResult {
+    if fd != fd::STDOUT && fd != fd::STDERR {
+        return Err(Errno::BadFd);
+    }
+
+    let mut chunk = [0; CHUNK_SIZE];
+    let _writer = WRITER.lock();
+    for offset in (0..len).step_by(CHUNK_SIZE) {
+        let chunk = &mut chunk[..CHUNK_SIZE.min(len - offset)];
+        let addr = buf.checked_add(offset).ok_or(Errno::Fault)?;
+        match user::read_bytes(process, addr, chunk) {
+            Ok(()) => executor::block_on(uart::write(chunk)),
+            // What was written stays written
+            Err(errno) if offset == 0 => return Err(errno),
+            Err(_) => return Ok(offset),
+        }
+    }
+
+    Ok(len)
+}
+
+/// `read(fd, buf, len)`: read what has arrived on standard input, waiting
+/// for at least one byte, or read an open file.
+pub fn read(process: &'static Process, [fd, buf, len, ..]: Args) -> SyscallResult {
+    if fd != fd::STDIN {
+        return fs::read(process, fd, buf, len);
+    }
+
+    let len = len.min(CHUNK_SIZE);
+    // Input taken from the UART cannot be put back
+    user::check_writable(process, buf, len)?;
+
+    let mut chunk = [0; CHUNK_SIZE];
+    let count = {
+        let _reader = READER.lock();
+        executor::block_on(uart::read(&mut chunk[..len]))
+    };
+
+    user::write_bytes(process, buf, &chunk[..count])?;
+    Ok(count)
+}
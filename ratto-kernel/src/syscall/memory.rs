@@ -0,0 +1,36 @@
+//! Mapping and unmapping pages of the calling process.
+
+use ratto_abi::{Errno, SyscallResult, prot};
+use ratto_core::mem::MapFlags;
+
+use super::Args;
+use crate::mem::PAGE_SIZE;
+use crate::process::Process;
+
+const _: () = assert!(ratto_abi::PAGE_SIZE == PAGE_SIZE);
+
+/// `mmap(len, prot)`: map fresh, zeroed pages somewhere free.
+pub fn mmap(process: &'static Process, [len, access, ..]: Args) -> SyscallResult {
+    if len == 0 || access & !(prot::READ | prot::WRITE | prot::EXEC) != 0 {
+        return Err(Errno::Inval);
+    }
+
+    let mut flags = MapFlags::READ;
+    if access & prot::WRITE != 0 {
+        flags |= MapFlags::WRITE;
+    }
+    if access & prot::EXEC != 0 {
+        flags |= MapFlags::EXEC;
+    }
+
+    process.map_anywhere(len, flags).map_err(|_| Errno::NoMem)
+}
+
+/// `munmap(addr, len)`: unmap the pages in the range.
+pub fn munmap(process: &'static Process, [addr, len, ..]: Args) -> SyscallResult {
+    let len = len
+        .checked_next_multiple_of(PAGE_SIZE)
+        .ok_or(Errno::Inval)?;
+    process.unmap(addr, len).map_err(|_| Errno::Inval)?;
+    Ok(0)
+}
@@ -0,0 +1,59 @@
+//! Calls about the system as a whole.
+
+use ratto_abi::{Errno, MemInfo, ProcessInfo, SyscallResult, process_state};
+
+use super::{Args, user};
+use crate::Kernel;
+use crate::mem::{self, PAGE_SIZE};
+use crate::process::{self, Process, ProcessState};
+
+/// `list_processes(infos, count)`: describe up to `count` processes, and
+/// return how many there are.
+pub fn list_processes(process: &'static Process, [infos, count, ..]: Args) -> SyscallResult {
+    let entry_size = size_of::<ProcessInfo>();
+    let mut total = 0;
+    for other in process::all() {
+        if total < count {
+            let mut info = ProcessInfo::new();
+            info.pid = other.pid().0 as u64;
+            info.parent = other.parent().map_or(0, |parent| parent.0 as u64);
+            info.state = match other.state() {
+                ProcessState::New => process_state::STARTING,
+                ProcessState::Running => process_state::RUNNING,
+                _ => process_state::EXITED,
+            };
+
+            let name = other.name().as_bytes();
+            let name = &name[..name.len().min(ProcessInfo::NAME_MAX)];
+            info.name[..name.len()].copy_from_slice(name);
+            info.name_len = name.len() as u8;
+
+            let addr = total
+                .checked_mul(entry_size)
+                .and_then(|offset| infos.checked_add(offset))
+                .ok_or(Errno::Fault)?;
+            user::write(process, addr, &info)?;
+        }
+
+        total += 1;
+    }
+
+    Ok(total)
+}
+
+/// `meminfo(info)`
+pub fn meminfo(process: &'static Process, [info, ..]: Args) -> SyscallResult {
+    let out = MemInfo {
+        page_size: PAGE_SIZE as u64,
+        total_pages: mem::total_frames() as u64,
+        free_pages: mem::free_frames() as u64,
+    };
+
+    user::write(process, info, &out)?;
+    Ok(0)
+}
+
+/// `reboot()`: never returns.
+pub fn reboot(_process: &'static Process, _args: Args) -> SyscallResult {
+    Kernel::reboot()
+}
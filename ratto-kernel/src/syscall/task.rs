@@ -0,0 +1,25 @@
+//! Calls about the calling process itself.
+
+use ratto_abi::{ABI_VERSION, SyscallResult};
+
+use super::Args;
+use crate::process::{self, Process};
+use crate::sched;
+
+/// `exit(code)`: never returns.
+pub fn exit(_process: &'static Process, [code, ..]: Args) -> SyscallResult {
+    process::exit(code as i32)
+}
+
+pub fn getpid(process: &'static Process, _args: Args) -> SyscallResult {
+    Ok(process.pid().0)
+}
+
+pub fn yield_now(_process: &'static Process, _args: Args) -> SyscallResult {
+    sched::yield_now();
+    Ok(0)
+}
+
+pub fn abi_version(_process: &'static Process, _args: Args) -> SyscallResult {
+    Ok(ABI_VERSION as usize)
+}
@@ -0,0 +1,28 @@
+//! Sleeping and reading the clock.
+
+use core::time::Duration;
+
+use ratto_abi::{Errno, SyscallResult, Timespec};
+use ratto_core::cpu::CpuOps;
+
+use super::{Args, user};
+use crate::arch::Cpu;
+use crate::executor;
+use crate::process::Process;
+
+/// `sleep(duration: *const Timespec)`
+pub fn sleep(process: &'static Process, [duration, ..]: Args) -> SyscallResult {
+    let duration = user::read::<Timespec>(process, duration)?
+        .to_duration()
+        .ok_or(Errno::Inval)?;
+
+    executor::block_on(executor::sleep(duration));
+    Ok(0)
+}
+
+/// `clock_gettime(now: *mut Timespec)`, with the time since boot.
+pub fn clock_gettime(process: &'static Process, [now, ..]: Args) -> SyscallResult {
+    let time = Timespec::from_duration(Duration::from_nanos(Cpu::monotonic_ns()));
+    user::write(process, now, &time)?;
+    Ok(0)
+}
@@ -0,0 +1,69 @@
+//! Reading and writing the memory of the calling process, through the
+//! kernel's own mapping of its pages. Addresses the process has not mapped
+//! for the access fail with [`Errno::Fault`] rather than fault the kernel.
+
+use core::mem::{MaybeUninit, size_of};
+
+use ratto_abi::{DirEntry, Errno, MemInfo, ProcessInfo, Timespec};
+use ratto_core::mem::MapFlags;
+
+use crate::process::Process;
+
+/// Types user memory may hold any bytes of, without padding.
+///
+/// # Safety
+/// Every bit pattern of `size_of::<Self>()` bytes must be a valid value,
+/// and the type must have no padding.
+pub unsafe trait UserData: Copy {}
+
+unsafe impl UserData for Timespec {}
+unsafe impl UserData for DirEntry {}
+unsafe impl UserData for ProcessInfo {}
+unsafe impl UserData for MemInfo {}
+
+pub fn read_bytes(process: &Process, addr: usize, buf: &mut [u8]) -> Result<(), Errno> {
+    process.read_memory(addr, buf).map_err(|_| Errno::Fault)
+}
+
+pub fn write_bytes(process: &Process, addr: usize, data: &[u8]) -> Result<(), Errno> {
+    process.write_memory(addr, data).map_err(|_| Errno::Fault)
+}
+
+/// Copy the `len` bytes of UTF-8 text at `addr` into `buf`, which bounds
+/// their length.
+pub fn read_str<'a>(
+    process: &Process,
+    addr: usize,
+    len: usize,
+    buf: &'a mut [u8],
+) -> Result<&'a str, Errno> {
+    let buf = buf.get_mut(..len).ok_or(Errno::Inval)?;
+    read_bytes(process, addr, buf)?;
+    core::str::from_utf8(buf).map_err(|_| Errno::Inval)
+}
+
+/// Fail unless `len` bytes from `addr` can be written, before doing
+/// something that cannot be undone.
+pub fn check_writable(process: &Process, addr: usize, len: usize) -> Result<(), Errno> {
+    if process.can_access(addr, len, MapFlags::WRITE) {
+        Ok(())
+    } else {
+        Err(Errno::Fault)
+    }
+}
+
+pub fn read<T: UserData>(process: &Process, addr: usize) -> Result<T, Errno> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr().cast::<u8>(), size_of::<T>()) };
+    bytes.fill(0);
+    read_bytes(process, addr, bytes)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+pub fn write<T: UserData>(process: &Process, addr: usize, value: &T) -> Result<(), Errno> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(core::ptr::from_ref(value).cast::<u8>(), size_of::<T>())
+    };
+    write_bytes(process, addr, bytes)
+}
@@ -0,0 +1,366 @@
+//! Kernel threads.
+//!
+//! Tasks live in a fixed table, each with its own stack, and are started
+//! with [`spawn`]. Every core also has an idle task, which runs on the core's
+//! boot stack whenever nothing else is ready; see [`sched`](crate::sched).
+//!
+//! Below each stack is a guard region filled with [`GUARD_WORD`], checked
+//! whenever the task is switched out, so an overflow is only caught once the
+//! task switches and may already have corrupted the stack below. The guard
+//! is not unmapped: the stacks live in the kernel's linear map, which the
+//! boot tables map in 2 MiB blocks that the mapper, which only builds user
+//! tables, cannot split. A fault on it would also leave the exception
+//! handlers no stack to report it on, since they run on the faulting one.
+
+use core::cell::UnsafeCell;
+use core::fmt::Debug;
+use core::ops::Range;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+
+use ratto_core::sched::{AtomicCpuMask, CpuMask, SchedEntity};
+
+use crate::arch::{self, ContextOps};
+use crate::process::Process;
+use crate::sched;
+
+/// Most tasks, not counting the idle tasks, that can exist at once.
+pub const MAX_TASKS: usize = 16;
+
+/// Usable size of each task's stack.
+pub const TASK_STACK_SIZE: usize = 0x4000;
+
+/// Size of the guard region below each stack.
+const GUARD_SIZE: usize = 0x1000;
+
+const GUARD_WORD: u64 = 0x57ac_6a2d_57ac_6a2d;
+
+/// Id of the idle tasks, which are never spawned.
+const IDLE_ID: usize = 0;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(IDLE_ID + 1);
+
+static STACKS: [TaskStack; MAX_TASKS] = [const { TaskStack::new() }; MAX_TASKS];
+
+static TASKS: [Task; MAX_TASKS] = {
+    let mut tasks = [const { Task::new("", None) }; MAX_TASKS];
+    let mut slot = 0;
+    while slot < MAX_TASKS {
+        tasks[slot] = Task::new("", Some(&STACKS[slot]));
+        slot += 1;
+    }
+    tasks
+};
+
+static IDLE_TASKS: [Task; arch::MAX_CPUS] = [const { Task::new("idle", None) }; arch::MAX_CPUS];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TaskId(pub usize);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum TaskState {
+    /// The slot is unused
+    Free,
+    /// Being set up by [`spawn`]
+    New,
+    /// Waiting on the run queue
+    Ready,
+    Running,
+    /// Parked until something wakes it
+    Blocked,
+    /// Finished, its slot is freed once it is switched out
+    Exited,
+}
+
+impl TaskState {
+    fn from_raw(raw: u8) -> Self {
+        use TaskState::*;
+        const STATES: [TaskState; 6] = [Free, New, Ready, Running, Blocked, Exited];
+
+        STATES[raw as usize]
+    }
+}
+
+pub struct Task {
+    id: AtomicUsize,
+    state: AtomicU8,
+    /// Set while a core runs on the task's stack, which includes the
+    /// switch away from it. No other core may resume the task until then.
+    pub(crate) on_cpu: AtomicBool,
+    pub(crate) wake_token: AtomicBool,
+    /// The core the task runs on, or whose run queue it was last put on
+    cpu: AtomicUsize,
+    affinity: AtomicCpuMask,
+    /// The process the task runs, whose address space is active while the
+    /// task is. Null for kernel threads.
+    process: AtomicPtr<Process>,
+    /// Only written by [`spawn`] while the slot is [`TaskState::New`].
+    name: UnsafeCell<&'static str>,
+    /// Only touched by the core switching the task in or out.
+    pub(crate) context: UnsafeCell<arch::Context>,
+    stack: Option<&'static TaskStack>,
+    pub(crate) sched: SchedEntity,
+}
+
+// See the field docs for who may touch the cells
+unsafe impl Sync for Task {}
+
+impl Task {
+    const fn new(name: &'static str, stack: Option<&'static TaskStack>) -> Self {
+        Task {
+            id: AtomicUsize::new(IDLE_ID),
+            state: AtomicU8::new(TaskState::Free as u8),
+            on_cpu: AtomicBool::new(false),
+            wake_token: AtomicBool::new(false),
+            cpu: AtomicUsize::new(0),
+            affinity: AtomicCpuMask::new(CpuMask::ALL),
+            process: AtomicPtr::new(ptr::null_mut()),
+            name: UnsafeCell::new(name),
+            context: UnsafeCell::new(arch::Context::EMPTY),
+            stack,
+            sched: SchedEntity::new(),
+        }
+    }
+
+    /// The task embedding `entity`, which must be the `sched` field of a
+    /// [`Task`], as are all entities the scheduler is given.
+    pub(crate) fn of_entity(entity: &'static SchedEntity) -> &'static Task {
+        let offset = core::mem::offset_of!(Task, sched);
+        unsafe { &*ptr::from_ref(entity).byte_sub(offset).cast::<Task>() }
+    }
+
+    pub fn id(&self) -> TaskId {
+        TaskId(self.id.load(Ordering::Relaxed))
+    }
+
+    pub fn name(&self) -> &'static str {
+        unsafe { *self.name.get() }
+    }
+
+    pub fn state(&self) -> TaskState {
+        TaskState::from_raw(self.state.load(Ordering::Acquire))
+    }
+
+    pub(crate) fn set_state(&self, state: TaskState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Move the task from state `from` to `to`, if it is still in `from`.
+    pub(crate) fn transition(&self, from: TaskState, to: TaskState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// The core the task runs on, or last ran or was queued on.
+    pub fn cpu(&self) -> usize {
+        self.cpu.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_cpu(&self, cpu: usize) {
+        self.cpu.store(cpu, Ordering::Relaxed);
+    }
+
+    /// The cores the task may run on.
+    pub fn affinity(&self) -> CpuMask {
+        self.affinity.load()
+    }
+
+    /// Restrict the task to the cores in `mask`, moving it off its current
+    /// core if that is no longer allowed. When the task is the caller, it
+    /// yields to do so.
+    pub fn set_affinity(&'static self, mask: CpuMask) -> Result<(), &'static str> {
+        if (mask & CpuMask::first_n(arch::MAX_CPUS)).is_empty() {
+            return Err("No usable core in the affinity mask");
+        }
+        if self.is_idle() {
+            return Err("Idle tasks are bound to their core");
+        }
+
+        self.affinity.store(mask);
+        sched::affinity_changed(self);
+        Ok(())
+    }
+
+    pub(crate) fn may_run_on(&self, cpu: usize) -> bool {
+        self.affinity().contains(cpu)
+    }
+
+    /// The process the task runs, if it is not a kernel thread.
+    pub fn process(&self) -> Option<&'static Process> {
+        unsafe { self.process.load(Ordering::Acquire).as_ref() }
+    }
+
+    /// Change the task's process. Only the task itself may, with interrupts
+    /// masked, as the address space of the core must change along with it.
+    pub(crate) fn set_process(&self, process: Option<&'static Process>) {
+        let process = process.map_or(ptr::null_mut(), |process| ptr::from_ref(process).cast_mut());
+        self.process.store(process, Ordering::Release);
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.sched.priority()
+    }
+
+    /// Change the task's priority, `0` being the most important. Takes
+    /// effect the next time it is queued.
+    pub fn set_priority(&self, priority: u8) {
+        self.sched.set_priority(priority);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.stack.is_none()
+    }
+
+    /// Whether the task's stack guard is intact. Idle tasks run on the boot
+    /// stacks, which have no guard.
+    pub(crate) fn stack_intact(&self) -> bool {
+        self.stack.is_none_or(TaskStack::guard_intact)
+    }
+}
+
+impl Debug for Task {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Task")
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .field("state", &self.state())
+            .field("priority", &self.priority())
+            .field("cpu", &self.cpu())
+            .field("affinity", &self.affinity())
+            .finish()
+    }
+}
+
+/// A task's stack, with its guard region at the bottom.
+#[repr(C, align(4096))]
+struct TaskStack(UnsafeCell<[u8; GUARD_SIZE + TASK_STACK_SIZE]>);
+
+// Only ever used by the task owning it
+unsafe impl Sync for TaskStack {}
+
+impl TaskStack {
+    const fn new() -> Self {
+        TaskStack(UnsafeCell::new([0; GUARD_SIZE + TASK_STACK_SIZE]))
+    }
+
+    /// The part of the stack the task may use.
+    fn bounds(&self) -> Range<usize> {
+        let base = self.0.get() as usize;
+        base + GUARD_SIZE..base + GUARD_SIZE + TASK_STACK_SIZE
+    }
+
+    fn guard(&self) -> *mut u64 {
+        self.0.get().cast()
+    }
+
+    fn fill_guard(&self) {
+        for word in 0..GUARD_SIZE / size_of::<u64>() {
+            unsafe { self.guard().add(word).write_volatile(GUARD_WORD) };
+        }
+    }
+
+    fn guard_intact(&self) -> bool {
+        (0..GUARD_SIZE / size_of::<u64>())
+            .all(|word| unsafe { self.guard().add(word).read_volatile() } == GUARD_WORD)
+    }
+}
+
+/// The idle task of `cpu`.
+pub(crate) fn idle_task(cpu: usize) -> &'static Task {
+    &IDLE_TASKS[cpu]
+}
+
+/// Bind the idle task of `cpu` to it.
+pub(crate) fn init_idle_task(cpu: usize) -> &'static Task {
+    let idle = idle_task(cpu);
+    idle.set_cpu(cpu);
+    idle.affinity.store(CpuMask::single(cpu));
+    idle
+}
+
+/// The task running on the executing core.
+pub fn current() -> &'static Task {
+    sched::current()
+}
+
+/// Start a kernel thread running `entry`. The thread exits when `entry`
+/// returns, or by calling [`exit`].
+pub fn spawn(name: &'static str, entry: fn()) -> Result<TaskId, &'static str> {
+    spawn_in(name, entry, None)
+}
+
+/// Start a task for `process`, which runs `entry` with the process's
+/// address space active.
+pub(crate) fn spawn_process(
+    name: &'static str,
+    entry: fn(),
+    process: &'static Process,
+) -> Result<TaskId, &'static str> {
+    spawn_in(name, entry, Some(process))
+}
+
+fn spawn_in(
+    name: &'static str,
+    entry: fn(),
+    process: Option<&'static Process>,
+) -> Result<TaskId, &'static str> {
+    let task = TASKS
+        .iter()
+        .find(|task| {
+            task.state
+                .compare_exchange(
+                    TaskState::Free as u8,
+                    TaskState::New as u8,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        })
+        .ok_or("Too many tasks")?;
+
+    let id = TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let stack = task.stack.expect("Task slot without a stack");
+    stack.fill_guard();
+
+    task.id.store(id.0, Ordering::Relaxed);
+    task.wake_token.store(false, Ordering::Relaxed);
+    task.sched.reset();
+    task.affinity.store(CpuMask::ALL);
+    task.set_process(process);
+    unsafe {
+        *task.name.get() = name;
+        *task.context.get() = arch::Context::new(stack.bounds().end, task_start, entry as usize);
+    }
+
+    sched::enqueue(task);
+    Ok(id)
+}
+
+/// End the current task. Must not be called from an idle task.
+pub fn exit() -> ! {
+    sched::exit_current()
+}
+
+/// Free the slot of a task that has exited and been switched out.
+pub(crate) fn release(task: &Task) {
+    debug_assert_eq!(task.state(), TaskState::Exited);
+    task.set_state(TaskState::Free);
+}
+
+/// Bounds of the task stack containing `addr`, if it is on one.
+pub fn stack_bounds(addr: usize) -> Option<Range<usize>> {
+    STACKS
+        .iter()
+        .map(TaskStack::bounds)
+        .find(|bounds| bounds.contains(&addr))
+}
+
+extern "C" fn task_start(entry: usize) -> ! {
+    sched::task_started();
+
+    let entry: fn() = unsafe { core::mem::transmute(entry) };
+    entry();
+    exit()
+}
@@ -0,0 +1,87 @@
+//! Allocates, checks and frees blocks of many sizes and alignments in a
+//! scrambled order, so the heap has to split, merge and grow.
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+
+use ratto_user::println;
+
+ratto_user::entry!(main);
+
+const ROUNDS: usize = 8;
+const LIVE: usize = 256;
+
+fn main() -> i32 {
+    let mut random = Random(0x9e37_79b9_7f4a_7c15);
+    let mut live: Vec<Option<Vec<u8>>> = (0..LIVE).map(|_| None).collect();
+    let mut allocated = 0;
+
+    for round in 0..ROUNDS {
+        for _ in 0..LIVE * 4 {
+            let slot = random.next() as usize % LIVE;
+            if let Some(block) = live[slot].take() {
+                check(&block, slot);
+                continue;
+            }
+
+            let len = match random.next() % 16 {
+                0 => 16 * 1024 + random.next() as usize % (64 * 1024),
+                _ => 1 + random.next() as usize % 512,
+            };
+            live[slot] = Some((0..len).map(|index| pattern(slot, index)).collect());
+            allocated += len;
+        }
+
+        println!("round {}: {} KiB allocated so far", round, allocated / 1024);
+    }
+
+    for (slot, block) in live.iter().enumerate() {
+        if let Some(block) = block {
+            check(block, slot);
+        }
+    }
+    drop(live);
+
+    // Over-aligned and large allocations take their own paths
+    for align in [32, 256, 4096] {
+        let layout = Layout::from_size_align(align * 3, align).unwrap();
+        let block = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        assert!(!block.is_null() && (block as usize).is_multiple_of(align));
+        unsafe { alloc::alloc::dealloc(block, layout) };
+    }
+
+    let big = Box::new([0xa5u8; 1 << 20]);
+    assert!(big.iter().all(|&byte| byte == 0xa5));
+
+    println!("alloc-stress: ok");
+    0
+}
+
+fn pattern(slot: usize, index: usize) -> u8 {
+    (slot * 31 + index) as u8
+}
+
+fn check(block: &[u8], slot: usize) {
+    let intact = block
+        .iter()
+        .enumerate()
+        .all(|(index, &byte)| byte == pattern(slot, index));
+    assert!(intact, "Block in slot {} was overwritten", slot);
+}
+
+/// xorshift64, as good as this needs.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
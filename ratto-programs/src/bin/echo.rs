@@ -0,0 +1,19 @@
+//! Prints its arguments, separated by spaces.
+#![no_std]
+#![no_main]
+
+use ratto_user::{env, print, println};
+
+ratto_user::entry!(main);
+
+fn main() -> i32 {
+    for (index, arg) in env::args().skip(1).enumerate() {
+        if index > 0 {
+            print!(" ");
+        }
+        print!("{}", arg);
+    }
+
+    println!();
+    0
+}
@@ -0,0 +1,20 @@
+//! Greets the console, to show a program can run and make system calls.
+#![no_std]
+#![no_main]
+
+use ratto_user::abi::ABI_VERSION;
+use ratto_user::{println, syscall};
+
+ratto_user::entry!(main);
+
+fn main() -> i32 {
+    println!("Hello from user space!");
+    println!(
+        "pid {}, ABI version {} (built for {}), {:?} since boot",
+        syscall::getpid(),
+        syscall::abi_version(),
+        ABI_VERSION,
+        syscall::clock_gettime()
+    );
+    0
+}
@@ -0,0 +1,28 @@
+//! The first process the kernel starts. Shows the message of the day, then
+//! keeps a shell running on the console.
+#![no_std]
+#![no_main]
+
+use ratto_user::abi::fd;
+use ratto_user::{eprintln, fs, io, process};
+
+ratto_user::entry!(main);
+
+const MOTD: &str = "/etc/motd";
+const SHELL: &str = "/bin/sh";
+
+fn main() -> i32 {
+    if let Ok(motd) = fs::read(MOTD) {
+        let _ = io::write_all(fd::STDOUT, &motd);
+    }
+
+    loop {
+        match process::run(SHELL, &["sh"]) {
+            Ok(code) => eprintln!("init: {} exited with code {}, restarting it", SHELL, code),
+            Err(errno) => {
+                eprintln!("init: unable to run {}: {}", SHELL, errno);
+                return 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! An interactive shell on the serial console.
+//!
+//! Lines are edited with backspace, dropped with Ctrl-C and run on Enter.
+//! The first word names a builtin, or else a program: the file at that
+//! path if it holds a `/`, or `/bin/<name>`. The shell waits for programs
+//! to exit.
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use ratto_user::abi::{Errno, fd, process_state};
+use ratto_user::{eprintln, fs, io, print, println, process, syscall};
+
+ratto_user::entry!(main);
+
+const PROMPT: &str = "$ ";
+
+/// Longest line the shell takes; further input is dropped.
+const MAX_LINE: usize = 256;
+
+struct Builtin {
+    name: &'static str,
+    usage: &'static str,
+    /// Takes the words of the line, the name first, and returns a status
+    run: fn(&[&str]) -> i32,
+}
+
+/// In the order `help` lists them.
+const BUILTINS: &[Builtin] = &[
+    builtin("cat", "cat FILE...", cat),
+    builtin("echo", "echo [ARG...]", echo),
+    builtin("exit", "exit [CODE]", exit),
+    builtin("help", "help", help),
+    builtin("ls", "ls [DIR...]", ls),
+    builtin("meminfo", "meminfo", meminfo),
+    builtin("ps", "ps", ps),
+    builtin("reboot", "reboot", reboot),
+];
+
+const fn builtin(name: &'static str, usage: &'static str, run: fn(&[&str]) -> i32) -> Builtin {
+    Builtin { name, usage, run }
+}
+
+fn main() -> i32 {
+    println!("Ratto shell. Type `help` for the commands.");
+
+    let mut input = LineReader::new();
+    let mut line = String::new();
+    loop {
+        print!("{}", PROMPT);
+        if let Err(errno) = input.read_line(&mut line) {
+            eprintln!("sh: unable to read the console: {}", errno);
+            return 1;
+        }
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if let Some(&command) = args.first() {
+            run(command, &args);
+        }
+    }
+}
+
+fn run(command: &str, args: &[&str]) {
+    if let Some(builtin) = BUILTINS.iter().find(|builtin| builtin.name == command) {
+        (builtin.run)(args);
+        return;
+    }
+
+    let path = if command.contains('/') {
+        String::from(command)
+    } else {
+        format!("/bin/{}", command)
+    };
+
+    match process::run(&path, args) {
+        Ok(0) => {}
+        Ok(code) => eprintln!("sh: {} exited with code {}", command, code),
+        Err(Errno::NoEnt) => eprintln!("sh: {}: command not found", command),
+        Err(errno) => eprintln!("sh: {}: {}", command, errno),
+    }
+}
+
+fn cat(args: &[&str]) -> i32 {
+    let mut status = 0;
+    for path in &args[1..] {
+        match fs::read(path) {
+            Ok(contents) => {
+                let _ = io::write_all(fd::STDOUT, &contents);
+            }
+            Err(errno) => {
+                eprintln!("cat: {}: {}", path, errno);
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
+
+fn echo(args: &[&str]) -> i32 {
+    println!("{}", args[1..].join(" "));
+    0
+}
+
+fn exit(args: &[&str]) -> i32 {
+    let code = match args.get(1).map(|code| code.parse()) {
+        None => 0,
+        Some(Ok(code)) => code,
+        Some(Err(_)) => {
+            eprintln!("exit: not a number: {}", args[1]);
+            return 1;
+        }
+    };
+
+    syscall::exit(code)
+}
+
+fn help(_args: &[&str]) -> i32 {
+    println!("Builtins:");
+    for builtin in BUILTINS {
+        println!("  {}", builtin.usage);
+    }
+    println!("Anything else runs /bin/<name>, or the program at a path.");
+    0
+}
+
+fn ls(args: &[&str]) -> i32 {
+    let dirs = if args.len() > 1 {
+        &args[1..]
+    } else {
+        &["/"][..]
+    };
+
+    let mut status = 0;
+    for (index, dir) in dirs.iter().enumerate() {
+        if dirs.len() > 1 {
+            if index > 0 {
+                println!();
+            }
+            println!("{}:", dir);
+        }
+
+        let listed = fs::read_dir(dir).and_then(|entries| {
+            for entry in entries {
+                let entry = entry?;
+                if entry.is_dir() {
+                    println!("{:>8}  {}/", "-", entry.name());
+                } else {
+                    println!("{:>8}  {}", entry.size, entry.name());
+                }
+            }
+            Ok(())
+        });
+
+        if let Err(errno) = listed {
+            eprintln!("ls: {}: {}", dir, errno);
+            status = 1;
+        }
+    }
+
+    status
+}
+
+fn meminfo(_args: &[&str]) -> i32 {
+    let info = match syscall::meminfo() {
+        Ok(info) => info,
+        Err(errno) => {
+            eprintln!("meminfo: {}", errno);
+            return 1;
+        }
+    };
+
+    let kib = |pages: u64| pages * info.page_size / 1024;
+    let used = info.total_pages - info.free_pages;
+    println!("Page size: {} bytes", info.page_size);
+    println!(
+        "Total: {:>8} pages {:>8} KiB",
+        info.total_pages,
+        kib(info.total_pages)
+    );
+    println!("Used:  {:>8} pages {:>8} KiB", used, kib(used));
+    println!(
+        "Free:  {:>8} pages {:>8} KiB",
+        info.free_pages,
+        kib(info.free_pages)
+    );
+    0
+}
+
+fn ps(_args: &[&str]) -> i32 {
+    let processes = match process::list() {
+        Ok(processes) => processes,
+        Err(errno) => {
+            eprintln!("ps: {}", errno);
+            return 1;
+        }
+    };
+
+    println!("{:>5} {:>5} {:<8} NAME", "PID", "PPID", "STATE");
+    for info in &processes {
+        println!(
+            "{:>5} {:>5} {:<8} {}",
+            info.pid,
+            info.parent,
+            process_state::name(info.state),
+            info.name()
+        );
+    }
+    0
+}
+
+fn reboot(_args: &[&str]) -> i32 {
+    println!("Rebooting...");
+    syscall::reboot()
+}
+
+/// Reads lines from the console, echoing what is typed.
+struct LineReader {
+    buf: [u8; 64],
+    start: usize,
+    end: usize,
+    /// Whether the last line ended with a carriage return, so that a line
+    /// feed right after it is part of the same line ending
+    after_cr: bool,
+}
+
+impl LineReader {
+    const fn new() -> Self {
+        LineReader {
+            buf: [0; 64],
+            start: 0,
+            end: 0,
+            after_cr: false,
+        }
+    }
+
+    /// Read the next line into `line`, without its ending.
+    fn read_line(&mut self, line: &mut String) -> Result<(), Errno> {
+        line.clear();
+        loop {
+            let byte = self.next_byte()?;
+            let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    println!();
+                    return Ok(());
+                }
+                // Backspace and delete
+                0x08 | 0x7f => {
+                    if line.pop().is_some() {
+                        print!("\x08 \x08");
+                    }
+                }
+                // Ctrl-C
+                0x03 => {
+                    println!("^C");
+                    line.clear();
+                    return Ok(());
+                }
+                b' '..=b'~' if line.len() < MAX_LINE => {
+                    line.push(byte as char);
+                    print!("{}", byte as char);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<u8, Errno> {
+        while self.start == self.end {
+            self.end = io::read(&mut self.buf)?;
+            self.start = 0;
+        }
+
+        let byte = self.buf[self.start];
+        self.start += 1;
+        Ok(byte)
+    }
+}
@@ -1,5 +1,6 @@
 #![no_std]
 
+use ratto_kernel::arch::aarch64::mem::phys_to_virt;
 use ratto_kernel::{arch::sync::SpinLock, console};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -64,6 +65,7 @@ pub struct SerialConsole {
 }
 
 impl SerialConsole {
+    #[track_caller]
     pub const fn new() -> Self {
         SerialConsole {
             lock: SpinLock::new(()),
@@ -73,13 +75,19 @@ impl SerialConsole {
 
 impl console::Console for SerialConsole {
     fn write_str(&self, s: &str) -> core::fmt::Result {
-        self.lock.lock_with(|_| {
+        let write = || {
             for c in s.chars() {
                 unsafe {
-                    core::ptr::write_volatile(0x3F20_1000 as *mut u8, c as u8);
+                    core::ptr::write_volatile(phys_to_virt(0x3F20_1000) as *mut u8, c as u8);
                 }
             }
-        });
+        };
+
+        if ratto_kernel::panicking() {
+            write();
+        } else {
+            self.lock.lock_with(|_| write());
+        }
 
         Ok(())
     }
@@ -0,0 +1,38 @@
+use core::arch::{asm, global_asm};
+
+// The kernel starts programs with the stack pointer on `argc`
+global_asm!(
+    ".section .text._start",
+    ".global _start",
+    "_start:",
+    "    mov x0, sp",
+    "    mov x29, #0",
+    "    mov x30, #0",
+    "    bl {start}",
+    "    brk #0",
+    start = sym crate::rt::start,
+);
+
+/// Make system call `number` with `args` in `x0` to `x5`, returning `x0`.
+///
+/// # Safety
+/// The arguments must be valid for the call, such as pointers to memory it
+/// may read or write.
+pub unsafe fn syscall(number: usize, args: [usize; 6]) -> usize {
+    let result;
+    unsafe {
+        asm!(
+            "svc #0",
+            inlateout("x0") args[0] => result,
+            in("x1") args[1],
+            in("x2") args[2],
+            in("x3") args[3],
+            in("x4") args[4],
+            in("x5") args[5],
+            in("x8") number,
+            options(nostack)
+        );
+    }
+
+    result
+}
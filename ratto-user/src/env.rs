@@ -0,0 +1,57 @@
+//! The arguments and environment the program was started with.
+
+use core::ffi::CStr;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(ptr::null_mut());
+
+static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(ptr::null_mut());
+
+/// Record the lists from the initial stack.
+///
+/// # Safety
+/// `argv` must hold `argc` pointers to strings, and `envp` pointers to
+/// strings up to a null, all of which stay valid.
+pub(crate) unsafe fn init(argc: usize, argv: *const *const u8, envp: *const *const u8) {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv.cast_mut(), Ordering::Relaxed);
+    ENVP.store(envp.cast_mut(), Ordering::Relaxed);
+}
+
+/// The arguments, starting with the program's name. Ones that are not
+/// UTF-8 are empty.
+pub fn args() -> impl ExactSizeIterator<Item = &'static str> {
+    let argv = ARGV.load(Ordering::Relaxed);
+    (0..ARGC.load(Ordering::Relaxed)).map(move |index| unsafe { string(*argv.add(index)) })
+}
+
+/// The `NAME=value` strings of the environment.
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let envp = ENVP.load(Ordering::Relaxed);
+    (0..)
+        .map_while(move |index| {
+            let var = (!envp.is_null()).then(|| unsafe { envp.add(index).read() })?;
+            (!var.is_null()).then_some(var)
+        })
+        .map(|var| {
+            let var = unsafe { string(var) };
+            var.split_once('=').unwrap_or((var, ""))
+        })
+}
+
+pub fn var(name: &str) -> Option<&'static str> {
+    vars().find(|(var, _)| *var == name).map(|(_, value)| value)
+}
+
+/// The name the program was started as.
+pub fn program_name() -> &'static str {
+    args().next().unwrap_or("?")
+}
+
+unsafe fn string(ptr: *const u8) -> &'static str {
+    let bytes = unsafe { CStr::from_ptr(ptr.cast()) }.to_bytes();
+    core::str::from_utf8(bytes).unwrap_or_default()
+}
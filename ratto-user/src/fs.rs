@@ -0,0 +1,77 @@
+//! Files and directories of the initial filesystem, which is read-only.
+
+use alloc::vec::Vec;
+
+use ratto_abi::{DirEntry, Errno};
+
+use crate::syscall;
+
+/// A file open for reading, closed when dropped.
+pub struct File {
+    fd: usize,
+}
+
+impl File {
+    pub fn open(path: &str) -> Result<Self, Errno> {
+        syscall::open(path).map(|fd| File { fd })
+    }
+
+    /// Read from where the last read stopped, returning 0 at the end.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Errno> {
+        syscall::read(self.fd, buf)
+    }
+
+    /// Append the rest of the file to `buf`.
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, Errno> {
+        let start = buf.len();
+        let mut chunk = [0; 512];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start),
+                count => buf.extend_from_slice(&chunk[..count]),
+            }
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = syscall::close(self.fd);
+    }
+}
+
+/// The whole contents of the file at `path`.
+pub fn read(path: &str) -> Result<Vec<u8>, Errno> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// The entries of the directory at `path`, closed when dropped.
+pub struct ReadDir {
+    fd: usize,
+}
+
+/// List the directory at `path`.
+pub fn read_dir(path: &str) -> Result<ReadDir, Errno> {
+    syscall::open(path).map(|fd| ReadDir { fd })
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry, Errno>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut entry = DirEntry::new();
+        match syscall::readdir(self.fd, &mut entry) {
+            Ok(true) => Some(Ok(entry)),
+            Ok(false) => None,
+            Err(errno) => Some(Err(errno)),
+        }
+    }
+}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        let _ = syscall::close(self.fd);
+    }
+}
@@ -0,0 +1,187 @@
+//! The heap behind `alloc`, in pages from [`mmap`](syscall::mmap).
+//!
+//! Free memory is a list of blocks sorted by address, which merge with
+//! their neighbours when freed. Every block and allocation is a multiple of
+//! [`BLOCK_ALIGN`] in size and address, so splitting a block never leaves a
+//! piece too small to hold a block header. The heap grows by at least
+//! [`GROW_SIZE`] at a time and never shrinks.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use ratto_abi::{PAGE_SIZE, prot};
+
+use crate::syscall;
+
+/// Alignment and size granule of blocks, and the smallest block.
+pub const BLOCK_ALIGN: usize = 16;
+
+/// Least the heap grows by when it runs out.
+pub const GROW_SIZE: usize = 64 * 1024;
+
+#[global_allocator]
+static HEAP: LockedHeap = LockedHeap {
+    locked: AtomicBool::new(false),
+    heap: UnsafeCell::new(Heap { free: None }),
+};
+
+/// Header of a free block, at its start.
+#[repr(C, align(16))]
+struct Block {
+    size: usize,
+    next: Option<NonNull<Block>>,
+}
+
+struct Heap {
+    free: Option<NonNull<Block>>,
+}
+
+/// Programs run a single thread, so the heap is only ever found locked when
+/// an allocation, say from the panic handler, starts during another. Waiting
+/// would hang, so that allocation fails instead.
+struct LockedHeap {
+    locked: AtomicBool,
+    heap: UnsafeCell<Heap>,
+}
+
+unsafe impl Sync for LockedHeap {}
+
+impl LockedHeap {
+    /// Run `f` on the heap, or return `None` if it is already in use.
+    fn try_lock_with<R>(&self, f: impl FnOnce(&mut Heap) -> R) -> Option<R> {
+        if self.locked.swap(true, Ordering::Acquire) {
+            return None;
+        }
+
+        let result = f(unsafe { &mut *self.heap.get() });
+        self.locked.store(false, Ordering::Release);
+        Some(result)
+    }
+}
+
+unsafe impl GlobalAlloc for LockedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = block_layout(layout);
+        self.try_lock_with(|heap| {
+            heap.take(size, align).or_else(|| {
+                heap.grow(size + align)?;
+                heap.take(size, align)
+            })
+        })
+        .flatten()
+        .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = block_layout(layout);
+        // Memory freed during another allocation is leaked
+        self.try_lock_with(|heap| unsafe { heap.insert(ptr, size) });
+    }
+}
+
+/// Size and alignment of the block serving `layout`.
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let size = layout.size().max(1).next_multiple_of(BLOCK_ALIGN);
+    (size, layout.align().max(BLOCK_ALIGN))
+}
+
+impl Heap {
+    /// Carve `size` bytes aligned to `align` out of the first free block
+    /// they fit in.
+    fn take(&mut self, size: usize, align: usize) -> Option<NonNull<u8>> {
+        let mut link = &mut self.free;
+        while let Some(block) = *link {
+            let start = block.as_ptr() as usize;
+            let block_size = unsafe { block.as_ref().size };
+            let next = unsafe { block.as_ref().next };
+
+            let aligned = start.next_multiple_of(align);
+            let end = start + block_size;
+            if aligned + size > end {
+                link = unsafe { &mut (*block.as_ptr()).next };
+                continue;
+            }
+
+            // Whatever is left before and after stays free, in order
+            let after = aligned + size;
+            let rest = if after < end {
+                let tail = after as *mut Block;
+                unsafe {
+                    tail.write(Block {
+                        size: end - after,
+                        next,
+                    })
+                };
+                NonNull::new(tail)
+            } else {
+                next
+            };
+
+            if aligned > start {
+                unsafe {
+                    (*block.as_ptr()).size = aligned - start;
+                    (*block.as_ptr()).next = rest;
+                }
+            } else {
+                *link = rest;
+            }
+
+            return NonNull::new(aligned as *mut u8);
+        }
+
+        None
+    }
+
+    /// Map at least `size` more bytes into the heap.
+    fn grow(&mut self, size: usize) -> Option<()> {
+        let size = size.max(GROW_SIZE).checked_next_multiple_of(PAGE_SIZE)?;
+        let pages = syscall::mmap(size, prot::READ | prot::WRITE).ok()?;
+        unsafe { self.insert(pages, size) };
+        Some(())
+    }
+
+    /// Add the `size` bytes at `ptr` to the free list, merged with the
+    /// blocks either side if they touch.
+    ///
+    /// # Safety
+    /// The memory must be unused, and aligned and sized for a block.
+    unsafe fn insert(&mut self, ptr: *mut u8, size: usize) {
+        let start = ptr as usize;
+
+        // The last block before `ptr`, if any
+        let mut previous: Option<NonNull<Block>> = None;
+        let mut next = self.free;
+        while let Some(block) = next
+            && (block.as_ptr() as usize) < start
+        {
+            previous = Some(block);
+            next = unsafe { block.as_ref().next };
+        }
+
+        let block = ptr.cast::<Block>();
+        unsafe { block.write(Block { size, next }) };
+        let mut block = unsafe { NonNull::new_unchecked(block) };
+
+        if let Some(following) = next
+            && start + size == following.as_ptr() as usize
+        {
+            unsafe {
+                block.as_mut().size += following.as_ref().size;
+                block.as_mut().next = following.as_ref().next;
+            }
+        }
+
+        match previous {
+            Some(mut previous)
+                if previous.as_ptr() as usize + unsafe { previous.as_ref().size } == start =>
+            unsafe {
+                previous.as_mut().size += block.as_ref().size;
+                previous.as_mut().next = block.as_ref().next;
+            },
+            Some(mut previous) => unsafe { previous.as_mut().next = Some(block) },
+            None => self.free = Some(block),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Standard input and output, which are the serial console.
+
+use core::fmt::{self, Write};
+
+use ratto_abi::{Errno, fd};
+
+use crate::syscall;
+
+pub struct Stdout;
+
+pub struct Stderr;
+
+impl Write for Stdout {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        write_all(fd::STDOUT, string.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Write for Stderr {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        write_all(fd::STDERR, string.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+pub fn write_all(fd: usize, mut buf: &[u8]) -> Result<(), Errno> {
+    while !buf.is_empty() {
+        let written = syscall::write(fd, buf)?;
+        buf = &buf[written..];
+    }
+
+    Ok(())
+}
+
+/// Read what has arrived on standard input, waiting for at least one byte.
+pub fn read(buf: &mut [u8]) -> Result<usize, Errno> {
+    syscall::read(fd::STDIN, buf)
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let _ = Stdout.write_fmt(args);
+}
+
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments) {
+    let _ = Stderr.write_fmt(args);
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::io::_print(format_args!("{}\n", format_args!($($arg)*))));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_eprint(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::io::_eprint(format_args!("{}\n", format_args!($($arg)*))));
+}
@@ -0,0 +1,33 @@
+//! The runtime of Ratto's user programs.
+//!
+//! It provides the program's entry point, which gathers the arguments and
+//! calls the function named with [`entry!`], and the panic handler. On top
+//! of the system calls in [`syscall`], it offers [print macros](println),
+//! the [arguments and environment](env), a [heap](heap) for `alloc`,
+//! [files](fs) and [child processes](process).
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! ratto_user::entry!(main);
+//!
+//! fn main() -> i32 {
+//!     ratto_user::println!("Hello");
+//!     0
+//! }
+//! ```
+#![no_std]
+
+pub extern crate alloc;
+
+mod arch;
+pub mod env;
+pub mod fs;
+pub mod heap;
+pub mod io;
+pub mod process;
+mod rt;
+pub mod syscall;
+
+pub use ratto_abi as abi;
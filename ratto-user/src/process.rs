@@ -0,0 +1,47 @@
+//! Starting other programs and waiting for them.
+
+use alloc::vec::Vec;
+
+use ratto_abi::{Errno, ProcessInfo};
+
+use crate::syscall;
+
+/// Start the executable at `path` with the arguments `args`, which by
+/// convention start with the program's name. Returns its pid.
+pub fn spawn(path: &str, args: &[&str]) -> Result<usize, Errno> {
+    let mut packed = Vec::new();
+    for arg in args {
+        if arg.contains('\0') {
+            return Err(Errno::Inval);
+        }
+
+        packed.extend_from_slice(arg.as_bytes());
+        packed.push(0);
+    }
+
+    syscall::spawn(path, &packed)
+}
+
+/// Wait for the child `pid` to exit, returning its exit code.
+pub fn wait(pid: usize) -> Result<i32, Errno> {
+    syscall::wait(pid)
+}
+
+/// Start `path` and wait for it to exit, returning its exit code.
+pub fn run(path: &str, args: &[&str]) -> Result<i32, Errno> {
+    wait(spawn(path, args)?)
+}
+
+/// Every process there is.
+pub fn list() -> Result<Vec<ProcessInfo>, Errno> {
+    let mut infos = Vec::new();
+    loop {
+        let total = syscall::list_processes(&mut infos)?;
+        if total <= infos.len() {
+            infos.truncate(total);
+            return Ok(infos);
+        }
+
+        infos.resize(total, ProcessInfo::new());
+    }
+}
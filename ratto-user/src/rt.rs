@@ -0,0 +1,37 @@
+use core::panic::PanicInfo;
+
+use crate::{env, eprintln, syscall};
+
+/// Name the function a program starts in, which returns its exit code.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[unsafe(no_mangle)]
+        fn __ratto_user_main() -> i32 {
+            let main: fn() -> i32 = $main;
+            main()
+        }
+    };
+}
+
+unsafe extern "Rust" {
+    safe fn __ratto_user_main() -> i32;
+}
+
+/// Called by `_start` with the initial stack pointer.
+pub(crate) unsafe extern "C" fn start(stack: *const usize) -> ! {
+    unsafe {
+        let argc = *stack;
+        let argv = stack.add(1).cast::<*const u8>();
+        let envp = argv.add(argc + 1);
+        env::init(argc, argv, envp);
+    }
+
+    syscall::exit(__ratto_user_main())
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    eprintln!("{}: {}", env::program_name(), info);
+    syscall::exit(101)
+}
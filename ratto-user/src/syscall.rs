@@ -0,0 +1,141 @@
+//! Wrappers of the system calls described in [`ratto_abi`].
+
+use core::ptr;
+use core::time::Duration;
+
+use ratto_abi::{DirEntry, Errno, MemInfo, ProcessInfo, Syscall, Timespec, decode_result};
+
+use crate::arch;
+
+/// Make system call `syscall` with up to six arguments.
+///
+/// # Safety
+/// The arguments must be valid for the call, such as pointers to memory it
+/// may read or write.
+pub unsafe fn raw(syscall: Syscall, args: &[usize]) -> Result<usize, Errno> {
+    let mut all = [0; 6];
+    all[..args.len()].copy_from_slice(args);
+    decode_result(unsafe { arch::syscall(syscall.number(), all) })
+}
+
+pub fn exit(code: i32) -> ! {
+    unsafe {
+        let _ = raw(Syscall::Exit, &[code as usize]);
+    }
+    unreachable!("exit() returned")
+}
+
+/// Write some of `buf` to `fd`, returning how much.
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize, Errno> {
+    unsafe { raw(Syscall::Write, &[fd, buf.as_ptr() as usize, buf.len()]) }
+}
+
+/// Read what has arrived on `fd` into `buf`, waiting for at least one byte.
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { raw(Syscall::Read, &[fd, buf.as_mut_ptr() as usize, buf.len()]) }
+}
+
+pub fn getpid() -> usize {
+    unsafe { raw(Syscall::GetPid, &[]) }.unwrap_or(0)
+}
+
+pub fn yield_now() {
+    unsafe {
+        let _ = raw(Syscall::Yield, &[]);
+    }
+}
+
+pub fn sleep(duration: Duration) {
+    let duration = Timespec::from_duration(duration);
+    unsafe {
+        let _ = raw(Syscall::Sleep, &[ptr::from_ref(&duration) as usize]);
+    }
+}
+
+/// Time since boot.
+pub fn clock_gettime() -> Duration {
+    let mut now = Timespec::default();
+    unsafe {
+        let _ = raw(Syscall::ClockGetTime, &[ptr::from_mut(&mut now) as usize]);
+    }
+    now.to_duration().unwrap_or_default()
+}
+
+/// Version of the interface the kernel implements, to compare with
+/// [`ABI_VERSION`](ratto_abi::ABI_VERSION).
+pub fn abi_version() -> u32 {
+    unsafe { raw(Syscall::AbiVersion, &[]) }.unwrap_or(0) as u32
+}
+
+/// Map `len` bytes of fresh, zeroed pages with `prot` access.
+pub fn mmap(len: usize, prot: usize) -> Result<*mut u8, Errno> {
+    unsafe { raw(Syscall::Mmap, &[len, prot]) }.map(|addr| addr as *mut u8)
+}
+
+/// Unmap the pages over `len` bytes from `addr`.
+///
+/// # Safety
+/// Nothing may use the pages any more.
+pub unsafe fn munmap(addr: *mut u8, len: usize) -> Result<(), Errno> {
+    unsafe { raw(Syscall::Munmap, &[addr as usize, len]) }.map(|_| ())
+}
+
+/// Start the executable at `path` with `args`, each ended by a NUL,
+/// returning its pid.
+pub fn spawn(path: &str, args: &[u8]) -> Result<usize, Errno> {
+    unsafe {
+        raw(
+            Syscall::Spawn,
+            &[
+                path.as_ptr() as usize,
+                path.len(),
+                args.as_ptr() as usize,
+                args.len(),
+            ],
+        )
+    }
+}
+
+/// Wait for the child `pid` to exit, returning its exit code.
+pub fn wait(pid: usize) -> Result<i32, Errno> {
+    unsafe { raw(Syscall::Wait, &[pid]) }.map(|code| code as i32)
+}
+
+/// Open the file or directory at `path`, returning its descriptor.
+pub fn open(path: &str) -> Result<usize, Errno> {
+    unsafe { raw(Syscall::Open, &[path.as_ptr() as usize, path.len()]) }
+}
+
+pub fn close(fd: usize) -> Result<(), Errno> {
+    unsafe { raw(Syscall::Close, &[fd]) }.map(|_| ())
+}
+
+/// Fill `entry` with the next entry of the directory open as `fd`.
+/// Returns `false` after the last one.
+pub fn readdir(fd: usize, entry: &mut DirEntry) -> Result<bool, Errno> {
+    unsafe { raw(Syscall::ReadDir, &[fd, ptr::from_mut(entry) as usize]) }.map(|more| more != 0)
+}
+
+/// Describe as many processes as fit in `infos`, returning how many there
+/// are in all.
+pub fn list_processes(infos: &mut [ProcessInfo]) -> Result<usize, Errno> {
+    unsafe {
+        raw(
+            Syscall::ListProcesses,
+            &[infos.as_mut_ptr() as usize, infos.len()],
+        )
+    }
+}
+
+pub fn meminfo() -> Result<MemInfo, Errno> {
+    let mut info = MemInfo::default();
+    unsafe { raw(Syscall::MemInfo, &[ptr::from_mut(&mut info) as usize]) }?;
+    Ok(info)
+}
+
+pub fn reboot() -> ! {
+    unsafe {
+        let _ = raw(Syscall::Reboot, &[]);
+    }
+    unreachable!("reboot() returned")
+}
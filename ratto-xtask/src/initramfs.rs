@@ -0,0 +1,200 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use ratto_core::cpio::{Cpio, HEADER_SIZE, MAGIC, S_IFDIR, S_IFMT, S_IFREG, TRAILER};
+
+/// Builds a newc cpio archive in the format read by `ratto_core::cpio`.
+/// Parent directories are added ahead of the entries in them.
+pub struct Archive {
+    data: Vec<u8>,
+    dirs: BTreeSet<String>,
+    next_inode: u32,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Archive {
+            data: Vec::new(),
+            dirs: BTreeSet::new(),
+            next_inode: 1,
+        }
+    }
+
+    /// Add every directory and regular file under `root`, in name order,
+    /// with the paths relative to it.
+    pub fn add_tree(&mut self, root: &Path) -> anyhow::Result<()> {
+        self.add_tree_at(root, "")
+    }
+
+    fn add_tree_at(&mut self, dir: &Path, prefix: &str) -> anyhow::Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .context(format!("Unable to read directory: {}", dir.display()))?
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().into_string().map_err(|name| {
+                anyhow::anyhow!("File name is not UTF-8: {}", name.to_string_lossy())
+            })?;
+            let path = format!("{}{}", prefix, name);
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                self.add_dir(&path)?;
+                self.add_tree_at(&entry.path(), &format!("{}/", path))?;
+            } else if file_type.is_file() {
+                let contents = fs::read(entry.path())
+                    .context(format!("Unable to read file: {}", entry.path().display()))?;
+                self.add_file(&path, file_mode(&entry.metadata()?), &contents)?;
+            } else {
+                println!("Skipping {} in the initramfs", entry.path().display());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_dir(&mut self, path: &str) -> anyhow::Result<()> {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.add_dir(parent)?;
+        }
+
+        if self.dirs.insert(path.to_owned()) {
+            self.add_entry(path, S_IFDIR | 0o755, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Add a regular file with the permission bits `mode`.
+    pub fn add_file(&mut self, path: &str, mode: u32, contents: &[u8]) -> anyhow::Result<()> {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.add_dir(parent)?;
+        }
+
+        self.add_entry(path, S_IFREG | (mode & 0o7777), contents)
+    }
+
+    /// The archive, ended by its trailer and checked by the kernel's reader.
+    pub fn finish(mut self) -> anyhow::Result<Vec<u8>> {
+        self.add_entry(TRAILER, 0, &[])?;
+        Cpio::new(&self.data).map_err(|error| anyhow::anyhow!(error.as_str()))?;
+        Ok(self.data)
+    }
+
+    fn add_entry(&mut self, name: &str, mode: u32, contents: &[u8]) -> anyhow::Result<()> {
+        let file_size =
+            u32::try_from(contents.len()).context(format!("File too large: {}", name))?;
+        let inode = if name == TRAILER { 0 } else { self.next_inode };
+        self.next_inode += 1;
+
+        let fields = [
+            inode,
+            mode,
+            0, // uid
+            0, // gid
+            if mode & S_IFMT == S_IFDIR { 2 } else { 1 },
+            0, // mtime, left out for reproducible archives
+            file_size,
+            0, // devmajor
+            0, // devminor
+            0, // rdevmajor
+            0, // rdevminor
+            name.len() as u32 + 1,
+            0, // check
+        ];
+
+        let start = self.data.len();
+        self.data.extend_from_slice(MAGIC);
+        for field in fields {
+            self.data
+                .extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        debug_assert_eq!(self.data.len() - start, HEADER_SIZE);
+
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.pad();
+        self.data.extend_from_slice(contents);
+        self.pad();
+        Ok(())
+    }
+
+    fn pad(&mut self) {
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+    }
+}
+
+impl Default for Archive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> u32 {
+    0o644
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn archives_read_back() {
+        let mut archive = Archive::new();
+        archive.add_file("a/b/c", 0o755, b"contents").unwrap();
+        archive.add_dir("a/d").unwrap();
+        archive.add_file("a/e", 0o644, b"").unwrap();
+        archive.add_dir("a/b").unwrap();
+        archive.add_file("f", 0o600, b"odd length").unwrap();
+        let data = archive.finish().unwrap();
+
+        let cpio = Cpio::new(&data).unwrap();
+        let entries: Vec<_> = cpio
+            .entries()
+            .map(|entry| (entry.name, entry.mode, entry.data))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("a", S_IFDIR | 0o755, &b""[..]),
+                ("a/b", S_IFDIR | 0o755, b""),
+                ("a/b/c", S_IFREG | 0o755, b"contents"),
+                ("a/d", S_IFDIR | 0o755, b""),
+                ("a/e", S_IFREG | 0o644, b""),
+                ("f", S_IFREG | 0o600, b"odd length"),
+            ]
+        );
+
+        // Every directory once, ahead of what is in it
+        for (index, (name, _, _)) in entries.iter().enumerate() {
+            if let Some((parent, _)) = name.rsplit_once('/') {
+                let dirs: Vec<_> = entries
+                    .iter()
+                    .filter(|(name, mode, _)| *name == parent && mode & S_IFMT == S_IFDIR)
+                    .collect();
+                assert_eq!(dirs.len(), 1, "{}", parent);
+                assert!(entries[..index].contains(dirs[0]), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn file_modes_keep_only_permissions() {
+        let mut archive = Archive::new();
+        archive.add_file("x", S_IFDIR | 0o4755, b"x").unwrap();
+        let data = archive.finish().unwrap();
+
+        let entry = Cpio::new(&data).unwrap().entries().next().unwrap();
+        assert_eq!(entry.mode, S_IFREG | 0o4755);
+        assert!(entry.is_file());
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use anyhow::Context;
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use ratto_core::ksyms::{ENTRY_SIZE, HEADER_SIZE, MAGIC};
+
+const KSYMS_SECTION: &str = ".ksyms";
+
+struct FunctionSymbol {
+    address: u64,
+    size: u64,
+    name: String,
+}
+
+/// Fill the kernel's reserved `.ksyms` section with a sorted table of its
+/// function symbols, in the format read by `ratto_core::ksyms`. The ELF is
+/// patched in place so that the later `objcopy` carries the table into the
+/// flat image.
+pub fn embed_symbol_table(kernel_elf: &Path) -> anyhow::Result<()> {
+    let mut image = std::fs::read(kernel_elf).context(format!(
+        "Unable to read kernel ELF: {}",
+        kernel_elf.display()
+    ))?;
+
+    let (table, section_offset, section_size) = {
+        let elf = object::File::parse(&*image).context("Unable to parse kernel ELF")?;
+
+        let section = elf
+            .section_by_name(KSYMS_SECTION)
+            .context("Kernel ELF has no .ksyms section")?;
+
+        let (section_offset, section_size) = section
+            .file_range()
+            .context("The .ksyms section has no file contents")?;
+
+        let mut symbols = elf
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+            .filter_map(|symbol| {
+                Some(FunctionSymbol {
+                    address: symbol.address(),
+                    size: symbol.size(),
+                    name: format!("{:#}", rustc_demangle::demangle(symbol.name().ok()?)),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        symbols.sort_by_key(|symbol| symbol.address);
+        symbols.dedup_by_key(|symbol| symbol.address);
+
+        (encode(&symbols)?, section_offset, section_size)
+    };
+
+    if table.len() as u64 > section_size {
+        return Err(anyhow::anyhow!(
+            "Symbol table ({} bytes) does not fit in .ksyms ({} bytes)",
+            table.len(),
+            section_size
+        ));
+    }
+
+    let start = section_offset as usize;
+    image[start..start + table.len()].copy_from_slice(&table);
+
+    std::fs::write(kernel_elf, &image).context(format!(
+        "Unable to write kernel ELF: {}",
+        kernel_elf.display()
+    ))?;
+
+    Ok(())
+}
+
+fn encode(symbols: &[FunctionSymbol]) -> anyhow::Result<Vec<u8>> {
+    let base = symbols.first().map_or(0, |symbol| symbol.address);
+
+    let mut entries = Vec::with_capacity(symbols.len() * ENTRY_SIZE);
+    let mut names = Vec::new();
+    for symbol in symbols {
+        let offset = u32::try_from(symbol.address - base).context("Kernel text too large")?;
+        let size = u32::try_from(symbol.size).context("Function too large")?;
+
+        entries.extend_from_slice(&offset.to_le_bytes());
+        entries.extend_from_slice(&size.to_le_bytes());
+        entries.extend_from_slice(&(names.len() as u32).to_le_bytes());
+        entries.extend_from_slice(&(symbol.name.len() as u32).to_le_bytes());
+        names.extend_from_slice(symbol.name.as_bytes());
+    }
+
+    let mut table = Vec::with_capacity(HEADER_SIZE + entries.len() + names.len());
+    table.extend_from_slice(&MAGIC);
+    table.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    table.extend_from_slice(&base.to_le_bytes());
+    table.extend_from_slice(&entries);
+    table.extend_from_slice(&names);
+
+    Ok(table)
+}
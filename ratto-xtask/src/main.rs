@@ -1,9 +1,17 @@
-use std::{env, fmt::Display, path::PathBuf};
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 
+mod initramfs;
+mod ksyms;
+
 #[derive(Parser)]
 #[command(version, about, long_about = None, bin_name = "cargo xtask")]
 struct Cli {
@@ -74,6 +82,7 @@ impl VirtualizationProvider {
     fn run_with_kernel(
         &self,
         kernel_image: &PathBuf,
+        initramfs: &PathBuf,
         config: &Config,
         args: &[String],
     ) -> anyhow::Result<()> {
@@ -84,11 +93,11 @@ impl VirtualizationProvider {
                     Architecture::AArch64 => {
                         let mut cmd = std::process::Command::new("qemu-system-aarch64");
                         if let Some(machine_type) = &config.machine_type {
-                            cmd.args(&["-M", machine_type]);
+                            cmd.args(["-M", machine_type]);
                         }
 
                         // -semihosting -semihosting-config enable=on,target=native
-                        cmd.args(&[
+                        cmd.args([
                             "-semihosting",
                             "-semihosting-config",
                             "enable=on,target=native",
@@ -106,9 +115,11 @@ impl VirtualizationProvider {
 
                 cmd.arg("-kernel")
                     .arg(kernel_image)
+                    .arg("-initrd")
+                    .arg(initramfs)
                     .arg("-nographic")
-                    .args(&["-serial", "mon:stdio"])
-                    .args(&["-audio", "none"])
+                    .args(["-serial", "mon:stdio"])
+                    .args(["-audio", "none"])
                     .status()
                     .unwrap()
             }
@@ -162,7 +173,11 @@ impl Architecture {
 struct Config {
     arch: Architecture,
     ld_path: PathBuf,
+    user_ld_path: PathBuf,
     machine_type: Option<String>,
+    /// Directory packed into the initramfs along with the user programs
+    #[serde(default)]
+    rootfs_path: Option<PathBuf>,
 }
 
 fn main() {
@@ -183,6 +198,7 @@ fn try_main() -> anyhow::Result<()> {
         }) => {
             let config = load_config(config_path)?;
             build_kernel(&config, *profile, *virtualization)?;
+            build_initramfs(&config, *profile)?;
         }
         Some(Commands::Run {
             config_path,
@@ -192,8 +208,9 @@ fn try_main() -> anyhow::Result<()> {
         }) => {
             let config = load_config(config_path)?;
             let kernel_image = build_kernel(&config, Profile::Debug, Some(*provider))?;
+            let initramfs = build_initramfs(&config, Profile::Debug)?;
 
-            provider.run_with_kernel(&kernel_image, &config, args)?;
+            provider.run_with_kernel(&kernel_image, &initramfs, &config, args)?;
         }
         None => {
             println!("No command provided. Use --help for more information.");
@@ -222,41 +239,19 @@ fn build_kernel(
     profile: Profile,
     virtualization: Option<VirtualizationProvider>,
 ) -> anyhow::Result<PathBuf> {
-    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
-    let workspace_root = {
-        let root_from_env =
-            env::var("CARGO_WORKSPACE_DIR").context("Unable to determine cargo workspace root")?;
-        PathBuf::from(root_from_env)
-    };
+    let cargo = cargo();
+    let workspace_root = workspace_root()?;
 
-    let (ld_path, ld_script) = {
-        let qualified_path = if config.ld_path.is_absolute() {
-            config.ld_path.clone()
-        } else {
-            PathBuf::from(&workspace_root).join(&config.ld_path)
-        };
-
-        let ld_dir = qualified_path
-            .parent()
-            .context("Unable to determine linker script directory")?
-            .to_owned();
-
-        let ld_script = qualified_path
-            .file_name()
-            .context("Unable to determine linker script file name")?
-            .to_owned();
-
-        (ld_dir, ld_script)
-    };
+    let (ld_path, ld_script) = split_linker_script(&workspace_root, &config.ld_path)?;
 
     let arch_triple = config.arch.target_triple();
 
     match config.arch {
         Architecture::AArch64 => {
-            let mut cmd = std::process::Command::new(cargo);
+            let mut cmd = std::process::Command::new(&cargo);
 
             cmd.arg("build")
-                .args(&["--package", "ratto-entry"])
+                .args(["--package", "ratto-entry"])
                 .arg("--target")
                 .arg(arch_triple);
 
@@ -269,9 +264,10 @@ fn build_kernel(
                 cmd.arg(provider.to_string());
             }
 
-            // no_std binaries require panic=abort and a panic handler
+            // no_std binaries require panic=abort and a panic handler, and the
+            // kernel unwinds its own stack for backtraces using frame pointers
             let rust_flags = format!(
-                "-C panic=abort -C link-arg=--library-path={} -C link-arg=--script={}",
+                "-C panic=abort -C force-frame-pointers=yes -C link-arg=--library-path={} -C link-arg=--script={}",
                 ld_path.display(),
                 ld_script.display()
             );
@@ -292,6 +288,8 @@ fn build_kernel(
         .join(profile.as_str())
         .join("ratto-entry");
 
+    ksyms::embed_symbol_table(&kernel_elf)?;
+
     let kernel_bin = kernel_elf
         .parent()
         .context("Unable to determine kernel.bin path")?
@@ -299,7 +297,7 @@ fn build_kernel(
 
     let status = std::process::Command::new("rust-objcopy")
         .arg("--strip-all")
-        .args(&["-O", "binary"])
+        .args(["-O", "binary"])
         .arg(&kernel_elf)
         .arg(&kernel_bin)
         .status()
@@ -311,3 +309,149 @@ fn build_kernel(
 
     Ok(kernel_bin)
 }
+
+/// The user program the kernel starts first, packed as `/init`.
+const INIT_PROGRAM: &str = "init";
+
+/// Pack the configured root file system and the user programs, as
+/// `bin/<name>` apart from [`INIT_PROGRAM`], into a cpio archive for the
+/// kernel to load at boot.
+fn build_initramfs(config: &Config, profile: Profile) -> anyhow::Result<PathBuf> {
+    let workspace_root = workspace_root()?;
+    let programs = build_user_programs(config, profile, &workspace_root, &cargo())?;
+
+    let mut archive = initramfs::Archive::new();
+    if let Some(rootfs_path) = &config.rootfs_path {
+        archive.add_tree(&workspace_root.join(rootfs_path))?;
+    }
+
+    for (name, path) in &programs {
+        let image = fs::read(path).context(format!("Unable to read user program: {}", name))?;
+        let path = if name == INIT_PROGRAM {
+            name.clone()
+        } else {
+            format!("bin/{}", name)
+        };
+        archive.add_file(&path, 0o755, &image)?;
+    }
+
+    let initramfs = workspace_root
+        .join("target")
+        .join(config.arch.target_triple())
+        .join(profile.as_str())
+        .join("ratto-initramfs.cpio");
+    fs::create_dir_all(initramfs.parent().unwrap())?;
+    fs::write(&initramfs, archive.finish()?).context(format!(
+        "Unable to write initramfs: {}",
+        initramfs.display()
+    ))?;
+
+    Ok(initramfs)
+}
+
+/// Build the programs in `ratto-programs` for user mode. Returns the name
+/// and path of each one.
+fn build_user_programs(
+    config: &Config,
+    profile: Profile,
+    workspace_root: &Path,
+    cargo: &str,
+) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let (ld_path, ld_script) = split_linker_script(workspace_root, &config.user_ld_path)?;
+    let arch_triple = config.arch.target_triple();
+
+    // Apart from the kernel's, as the flags differ
+    let target_dir = workspace_root.join("target").join("user");
+
+    let mut cmd = std::process::Command::new(cargo);
+    cmd.arg("build")
+        .args(["--package", "ratto-programs"])
+        .arg("--bins")
+        .arg("--target")
+        .arg(arch_triple)
+        .arg("--target-dir")
+        .arg(&target_dir);
+
+    if profile == Profile::Release {
+        cmd.arg("--release");
+    }
+
+    // The loader only takes static executables, at the address in the
+    // linker script
+    let rust_flags = format!(
+        "-C panic=abort -C relocation-model=static -C strip=debuginfo -C link-arg=--library-path={} -C link-arg=--script={}",
+        ld_path.display(),
+        ld_script.display()
+    );
+
+    cmd.env("RUSTFLAGS", &rust_flags);
+    if !cmd.status().unwrap().success() {
+        return Err(anyhow::anyhow!("User programs build failed"));
+    }
+
+    let built_dir = target_dir.join(arch_triple).join(profile.as_str());
+    let programs = user_program_names(workspace_root)?
+        .into_iter()
+        .map(|name| {
+            let path = built_dir.join(&name);
+            (name, path)
+        })
+        .collect();
+
+    Ok(programs)
+}
+
+/// Names of the binaries of `ratto-programs`, one per file in `src/bin`.
+fn user_program_names(workspace_root: &Path) -> anyhow::Result<Vec<String>> {
+    let bin_dir = workspace_root
+        .join("ratto-programs")
+        .join("src")
+        .join("bin");
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&bin_dir).context(format!(
+        "Unable to read user programs: {}",
+        bin_dir.display()
+    ))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "rs")
+            && let Some(name) = path.file_stem()
+        {
+            names.push(name.to_string_lossy().into_owned());
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+fn cargo() -> String {
+    env::var("CARGO").unwrap_or_else(|_| "cargo".to_string())
+}
+
+fn workspace_root() -> anyhow::Result<PathBuf> {
+    let root_from_env =
+        env::var("CARGO_WORKSPACE_DIR").context("Unable to determine cargo workspace root")?;
+    Ok(PathBuf::from(root_from_env))
+}
+
+/// The directory and file name of the linker script at `path`, relative to
+/// the workspace unless absolute.
+fn split_linker_script(workspace_root: &Path, path: &Path) -> anyhow::Result<(PathBuf, PathBuf)> {
+    let qualified_path = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        workspace_root.join(path)
+    };
+
+    let ld_dir = qualified_path
+        .parent()
+        .context("Unable to determine linker script directory")?
+        .to_owned();
+
+    let ld_script = qualified_path
+        .file_name()
+        .context("Unable to determine linker script file name")?
+        .into();
+
+    Ok((ld_dir, ld_script))
+}
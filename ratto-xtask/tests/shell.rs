@@ -0,0 +1,226 @@
+//! Boot Ratto in QEMU and drive its shell through the serial console.
+//!
+//! These need `qemu-system-aarch64` and the aarch64 toolchain, so they only
+//! run when asked for:
+//!
+//! ```sh
+//! cargo test -p ratto-xtask --test shell -- --ignored
+//! ```
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PROMPT: &str = "$ ";
+
+/// Long enough for the first test to build everything.
+const BOOT_TIMEOUT: Duration = Duration::from_secs(600);
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Sessions share the build directory and images, so they run one at a time.
+static SESSIONS: Mutex<()> = Mutex::new(());
+
+/// A booted system, up to its first shell prompt.
+struct Session {
+    xtask: Child,
+    stdin: ChildStdin,
+    output: Receiver<Vec<u8>>,
+    /// Everything read from the console
+    transcript: String,
+    /// How much of the transcript earlier expectations consumed
+    consumed: usize,
+}
+
+impl Session {
+    fn boot() -> Self {
+        let workspace_root = workspace_root();
+        let mut xtask = Command::new(env!("CARGO_BIN_EXE_ratto-xtask"))
+            .args(["run", "--config-path", "config/raspi3.config"])
+            // Reset, as `reboot` does, ends QEMU
+            .args(["--", "-no-reboot"])
+            .current_dir(&workspace_root)
+            .env("CARGO_WORKSPACE_DIR", &workspace_root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Unable to run ratto-xtask");
+
+        let stdin = xtask.stdin.take().unwrap();
+        let mut stdout = xtask.stdout.take().unwrap();
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(count @ 1..) = stdout.read(&mut buf) {
+                if sender.send(buf[..count].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut session = Session {
+            xtask,
+            stdin,
+            output,
+            transcript: String::new(),
+            consumed: 0,
+        };
+        session.expect(PROMPT, BOOT_TIMEOUT);
+        session
+    }
+
+    /// Type `line` at the prompt, and return what was printed before the
+    /// next prompt, the echoed line included.
+    fn run(&mut self, line: &str) -> String {
+        self.send(line);
+        self.expect(PROMPT, COMMAND_TIMEOUT)
+    }
+
+    fn send(&mut self, line: &str) {
+        self.stdin
+            .write_all(format!("{}\n", line).as_bytes())
+            .and_then(|()| self.stdin.flush())
+            .expect("Unable to write to the serial console");
+    }
+
+    /// Wait for `needle` to be printed, returning everything up to it since
+    /// the last expectation.
+    fn expect(&mut self, needle: &str, timeout: Duration) -> String {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(position) = self.transcript[self.consumed..].find(needle) {
+                let start = self.consumed;
+                self.consumed += position + needle.len();
+                return self.transcript[start..start + position].to_owned();
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.output.recv_timeout(left) {
+                Ok(bytes) => self.transcript.push_str(&String::from_utf8_lossy(&bytes)),
+                Err(RecvTimeoutError::Timeout) => {
+                    panic!("Timed out waiting for {:?}:\n{}", needle, self.transcript)
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    panic!(
+                        "Console closed waiting for {:?}:\n{}",
+                        needle, self.transcript
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Ctrl-A x quits QEMU through its monitor
+        let _ = self.stdin.write_all(b"\x01x");
+        let _ = self.stdin.flush();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.xtask.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = self.xtask.kill();
+    }
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_owned()
+}
+
+fn session() -> (std::sync::MutexGuard<'static, ()>, Session) {
+    let guard = SESSIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    (guard, Session::boot())
+}
+
+#[test]
+#[ignore = "needs QEMU and the aarch64 toolchain"]
+fn builtins() {
+    let (_guard, mut shell) = session();
+
+    assert!(shell.run("echo hello   world").contains("hello world"));
+    assert!(shell.run("cat /etc/motd").contains("Welcome to Ratto!"));
+    assert!(shell.run("cat /nothing").contains("cat: /nothing: ENOENT"));
+
+    let root = shell.run("ls");
+    for entry in ["bin/", "etc/", "init"] {
+        assert!(root.contains(entry), "{} missing from:\n{}", entry, root);
+    }
+    let bin = shell.run("ls /bin");
+    for program in ["sh", "hello", "echo"] {
+        assert!(bin.contains(program), "{} missing from:\n{}", program, bin);
+    }
+
+    let processes = shell.run("ps");
+    assert!(processes.contains("PID"));
+    assert!(processes.contains("init"));
+    assert!(processes.contains("sh"));
+
+    let memory = shell.run("meminfo");
+    assert!(memory.contains("Total:"));
+    assert!(memory.contains("Free:"));
+
+    assert!(shell.run("help").contains("meminfo"));
+}
+
+#[test]
+#[ignore = "needs QEMU and the aarch64 toolchain"]
+fn runs_programs() {
+    let (_guard, mut shell) = session();
+
+    assert!(shell.run("hello").contains("Hello from user space!"));
+    assert!(shell.run("/bin/echo from a path").contains("from a path"));
+    assert!(shell.run("alloc-stress").contains("alloc-stress: ok"));
+    assert!(
+        shell
+            .run("missing")
+            .contains("sh: missing: command not found")
+    );
+    assert!(shell.run("/etc").contains("sh: /etc: EISDIR"));
+    assert!(shell.run("/etc/motd").contains("sh: /etc/motd: ENOEXEC"));
+
+    // init starts a new shell
+    let restart = shell.run("exit 3");
+    assert!(restart.contains("exited with code 3"), "{}", restart);
+    assert!(restart.contains("Ratto shell"), "{}", restart);
+    assert!(shell.run("echo again").contains("again"));
+}
+
+#[test]
+#[ignore = "needs QEMU and the aarch64 toolchain"]
+fn line_editing() {
+    let (_guard, mut shell) = session();
+
+    assert!(shell.run("echo abx\x7fc").contains("abc"));
+    // The line ending sent after Ctrl-C gives an empty line, and a second
+    // prompt
+    shell.send("echo dropped\x03");
+    assert!(shell.expect(PROMPT, COMMAND_TIMEOUT).contains("^C"));
+    shell.expect(PROMPT, COMMAND_TIMEOUT);
+    let output = shell.run("echo kept");
+    assert!(output.contains("kept"));
+    assert!(!output.contains("dropped"));
+}
+
+#[test]
+#[ignore = "needs QEMU and the aarch64 toolchain"]
+fn reboot() {
+    let (_guard, mut shell) = session();
+
+    shell.send("reboot");
+    shell.expect("Rebooting...", COMMAND_TIMEOUT);
+    shell.expect("Virtualization session ended", COMMAND_TIMEOUT);
+}